@@ -0,0 +1,74 @@
+use crate::dsp::fft;
+use crate::processor::gain_to_db;
+use ringbuf::HeapCons;
+use ringbuf::traits::{Consumer, Observer};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const FFT_SIZE: usize = 2048;
+/// Number of new samples between two analyses.
+const HOP_SIZE: usize = FFT_SIZE / 4;
+/// Number of log-spaced bands between 20 Hz and 20 kHz.
+pub const BANDS: usize = 48;
+/// Band levels at or below this are shown as silence.
+pub const MIN_DB: f32 = -90.0;
+/// How fast a band falls when the signal drops, per analysis.
+const FALL_DB: f32 = 1.5;
+
+/// Spawns the thread that turns samples from the analyzer tap into band levels in dB.
+pub fn spawn(mut receiver: HeapCons<f32>, sample_rate: usize) -> Arc<Mutex<Vec<f32>>> {
+    let spectrum = Arc::new(Mutex::new(vec![MIN_DB; BANDS]));
+    let shared = spectrum.clone();
+
+    std::thread::spawn(move || {
+        let window: Vec<f32> = (0..FFT_SIZE)
+            .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / FFT_SIZE as f32).cos())
+            .collect();
+        // Scales the magnitude of a full-scale sine to 1
+        let scale = 2.0 / window.iter().sum::<f32>();
+        let bins: Vec<(usize, usize)> = (0..BANDS)
+            .map(|band| {
+                let frequency = |band: usize| 20.0 * 1000.0_f32.powf(band as f32 / BANDS as f32);
+                let bin = |frequency: f32| frequency * FFT_SIZE as f32 / sample_rate as f32;
+                let low = (bin(frequency(band)).floor() as usize).min(FFT_SIZE / 2 - 1);
+                let high = (bin(frequency(band + 1)).ceil() as usize).clamp(low + 1, FFT_SIZE / 2);
+                (low, high)
+            })
+            .collect();
+
+        let mut samples = vec![0.0; FFT_SIZE];
+        let mut re = vec![0.0; FFT_SIZE];
+        let mut im = vec![0.0; FFT_SIZE];
+        let mut levels = vec![MIN_DB; BANDS];
+
+        loop {
+            if receiver.occupied_len() < HOP_SIZE {
+                // The processor is gone, e.g. after the JACK server shut down
+                if !receiver.write_is_held() {
+                    return;
+                }
+                std::thread::sleep(Duration::from_millis(5));
+                continue;
+            }
+            samples.copy_within(HOP_SIZE.., 0);
+            receiver.pop_slice(&mut samples[FFT_SIZE - HOP_SIZE..]);
+
+            for (i, sample) in samples.iter().enumerate() {
+                re[i] = sample * window[i];
+                im[i] = 0.0;
+            }
+            fft(&mut re, &mut im);
+
+            for (level, (low, high)) in levels.iter_mut().zip(&bins) {
+                let magnitude = (*low..*high)
+                    .map(|bin| (re[bin] * re[bin] + im[bin] * im[bin]).sqrt())
+                    .fold(0.0, f32::max);
+                let db = gain_to_db(magnitude * scale).max(MIN_DB);
+                *level = db.max(*level - FALL_DB);
+            }
+            shared.lock().unwrap().copy_from_slice(&levels);
+        }
+    });
+
+    spectrum
+}
@@ -0,0 +1,422 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Sender};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Time a request waits for the instance to carry it out, which takes longest for sessions
+/// with many plugins.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Time between feedback of the meters and of changes to parameters, bypass and volume.
+const FEEDBACK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What `rake ctl`, OSC and the web API can ask of a running instance. Plugins are given by id
+/// or by name.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    Status,
+    Load {
+        path: PathBuf,
+    },
+    /// Sets a plugin's bypass, or toggles it.
+    Bypass {
+        plugin: String,
+        #[serde(default)]
+        bypass: Option<bool>,
+    },
+    /// Sets a parameter to a value in its own range, from `min` to `max` in its status.
+    Param {
+        plugin: String,
+        param: ParamRef,
+        value: f32,
+    },
+    /// Sets the master volume in dB.
+    Volume {
+        volume: f32,
+    },
+    Next,
+    Previous,
+}
+
+/// Parameter given by index or by name.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum ParamRef {
+    Index(usize),
+    Name(String),
+}
+
+impl Request {
+    /// Reads a request from the words of a `rake ctl` command.
+    pub fn parse(words: &[String]) -> Result<Self, String> {
+        let number = |word: &str| -> Result<f32, String> {
+            word.parse().map_err(|_| format!("{} isn't a number", word))
+        };
+        Ok(match words {
+            [command] if command == "status" => Request::Status,
+            [command, path] if command == "load" => Request::Load {
+                path: PathBuf::from(path),
+            },
+            [command, plugin, setting @ ..] if command == "bypass" && setting.len() <= 1 => {
+                let bypass = match setting {
+                    [] => None,
+                    [on] if on == "on" || on == "1" => Some(true),
+                    [off] if off == "off" || off == "0" => Some(false),
+                    _ => return Err("Bypass is either on or off".to_string()),
+                };
+                Request::Bypass {
+                    plugin: plugin.clone(),
+                    bypass,
+                }
+            }
+            [command, plugin, param, value] if command == "param" => Request::Param {
+                plugin: plugin.clone(),
+                param: match param.parse() {
+                    Ok(index) => ParamRef::Index(index),
+                    Err(_) => ParamRef::Name(param.clone()),
+                },
+                value: number(value)?,
+            },
+            [command, volume] if command == "volume" => Request::Volume {
+                volume: number(volume)?,
+            },
+            [command] if command == "next" => Request::Next,
+            [command] if command == "previous" => Request::Previous,
+            _ => return Err(format!("Unknown command {}", words.join(" "))),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "reply", rename_all = "snake_case")]
+pub enum Reply {
+    Status(Status),
+    Done { message: String },
+}
+
+impl fmt::Display for Reply {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reply::Status(status) => write!(f, "{}", status),
+            Reply::Done { message } => write!(f, "{}", message),
+        }
+    }
+}
+
+/// The session, its chains and their plugins with every parameter.
+#[derive(Debug, Clone, Serialize)]
+pub struct Status {
+    /// Session file, unless it hasn't been saved.
+    pub session: Option<PathBuf>,
+    pub setlist_entry: Option<String>,
+    pub snapshot: Option<String>,
+    /// Master volume in dB.
+    pub volume: f32,
+    pub chains: Vec<ChainStatus>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChainStatus {
+    pub id: Uuid,
+    pub name: String,
+    pub nodes: Vec<NodeStatus>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub returns: Vec<ChainStatus>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NodeStatus {
+    Plugin(PluginStatus),
+    Split {
+        id: Uuid,
+        branches: Vec<Vec<PluginStatus>>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PluginStatus {
+    pub id: Uuid,
+    pub name: String,
+    pub bypass: bool,
+    pub params: Vec<ParamStatus>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ParamStatus {
+    pub index: usize,
+    pub name: String,
+    /// Value in the parameter's own range, from `min` to `max`, like every parameter value in
+    /// the API.
+    pub value: f32,
+    pub min: f32,
+    pub max: f32,
+    pub unit: String,
+}
+
+impl Status {
+    /// Every plugin of every chain and return chain.
+    pub fn plugins(&self) -> impl Iterator<Item = &PluginStatus> {
+        fn chain_plugins(chain: &ChainStatus) -> Vec<&PluginStatus> {
+            let mut plugins = Vec::new();
+            for node in &chain.nodes {
+                match node {
+                    NodeStatus::Plugin(plugin) => plugins.push(plugin),
+                    NodeStatus::Split { branches, .. } => plugins.extend(branches.iter().flatten()),
+                }
+            }
+            for aux_return in &chain.returns {
+                plugins.extend(chain_plugins(aux_return));
+            }
+            plugins
+        }
+        self.chains.iter().flat_map(chain_plugins)
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.session {
+            Some(path) => writeln!(f, "Session: {}", path.display())?,
+            None => writeln!(f, "Session: unsaved")?,
+        }
+        if let Some(entry) = &self.setlist_entry {
+            writeln!(f, "Setlist: {}", entry)?;
+        }
+        if let Some(snapshot) = &self.snapshot {
+            writeln!(f, "Snapshot: {}", snapshot)?;
+        }
+        write!(f, "Volume: {:+.1} dB", self.volume)?;
+        for chain in &self.chains {
+            write!(f, "\nChain {}", chain.name)?;
+            write_nodes(f, &chain.nodes)?;
+            for aux_return in &chain.returns {
+                write!(f, "\nReturn {}", aux_return.name)?;
+                write_nodes(f, &aux_return.nodes)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_nodes(f: &mut fmt::Formatter<'_>, nodes: &[NodeStatus]) -> fmt::Result {
+    let line = |f: &mut fmt::Formatter<'_>, plugin: &PluginStatus, indent: &str| {
+        write!(
+            f,
+            "\n{}{} {}{}",
+            indent,
+            plugin.id,
+            plugin.name,
+            if plugin.bypass { " (bypassed)" } else { "" }
+        )
+    };
+    for node in nodes {
+        match node {
+            NodeStatus::Plugin(plugin) => line(f, plugin, "  ")?,
+            NodeStatus::Split { branches, .. } => {
+                for (i, branch) in branches.iter().enumerate() {
+                    write!(f, "\n  Branch {}", i + 1)?;
+                    for plugin in branch {
+                        line(f, plugin, "    ")?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A request waiting to be carried out on the GUI thread.
+pub struct Pending {
+    pub request: Request,
+    reply: Sender<Result<Reply, String>>,
+}
+
+impl Pending {
+    pub fn reply(self, reply: Result<Reply, String>) {
+        // The client may have given up waiting
+        let _ = self.reply.send(reply);
+    }
+}
+
+/// Passes a request on to the GUI thread and waits for its reply.
+pub fn ask(requests: &Sender<Pending>, request: Request) -> Result<Reply, String> {
+    let (reply, reply_receiver) = mpsc::channel();
+    requests
+        .send(Pending { request, reply })
+        .map_err(|_| "Rake is exiting".to_string())?;
+    reply_receiver
+        .recv_timeout(REPLY_TIMEOUT)
+        .unwrap_or_else(|_| Err("No reply from Rake".to_string()))
+}
+
+/// Change sent to remote clients as it happens.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// Peak levels at a plugin's input and output.
+    Meter {
+        plugin: Uuid,
+        input: f32,
+        output: f32,
+    },
+    Param {
+        plugin: Uuid,
+        index: usize,
+        value: f32,
+    },
+    Bypass {
+        plugin: Uuid,
+        bypass: bool,
+    },
+    /// Master volume in dB.
+    Volume {
+        volume: f32,
+    },
+    /// Plugins were added, removed or moved, or another session was opened, so the status is
+    /// out of date.
+    Chains,
+}
+
+/// Values last sent to a group of clients, so only changes are sent.
+#[derive(Default)]
+pub struct Feedback {
+    plugins: Vec<Uuid>,
+    params: HashMap<(Uuid, usize), f32>,
+    bypass: HashMap<Uuid, bool>,
+    volume: Option<f32>,
+    last: Option<Instant>,
+}
+
+impl Feedback {
+    /// Forgets what was sent, so every value is sent again, e.g. to a new client.
+    pub fn reset(&mut self) {
+        *self = Feedback {
+            last: self.last,
+            ..Feedback::default()
+        };
+    }
+
+    /// Whether it's time for the next feedback, which is then counted as sent.
+    pub fn due(&mut self) -> bool {
+        if self
+            .last
+            .is_some_and(|time| time.elapsed() < FEEDBACK_INTERVAL)
+        {
+            return false;
+        }
+        self.last = Some(Instant::now());
+        true
+    }
+
+    /// Events for the values of `status` that changed since they were last sent, preceded by
+    /// `Event::Chains` if the plugins did.
+    pub fn changes(&mut self, status: &Status) -> Vec<Event> {
+        let mut events = Vec::new();
+        let plugins: Vec<Uuid> = status.plugins().map(|plugin| plugin.id).collect();
+        if plugins != self.plugins {
+            events.push(Event::Chains);
+            self.plugins = plugins;
+        }
+        for plugin in status.plugins() {
+            if self.bypass.insert(plugin.id, plugin.bypass) != Some(plugin.bypass) {
+                events.push(Event::Bypass {
+                    plugin: plugin.id,
+                    bypass: plugin.bypass,
+                });
+            }
+            for param in &plugin.params {
+                if self.params.insert((plugin.id, param.index), param.value) != Some(param.value) {
+                    events.push(Event::Param {
+                        plugin: plugin.id,
+                        index: param.index,
+                        value: param.value,
+                    });
+                }
+            }
+        }
+        if self.volume.replace(status.volume) != Some(status.volume) {
+            events.push(Event::Volume {
+                volume: status.volume,
+            });
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(command: &str) -> Result<Request, String> {
+        let words: Vec<String> = command.split_whitespace().map(String::from).collect();
+        Request::parse(&words)
+    }
+
+    #[test]
+    fn commands() {
+        assert!(matches!(parse("status"), Ok(Request::Status)));
+        assert!(matches!(parse("next"), Ok(Request::Next)));
+        assert!(matches!(parse("previous"), Ok(Request::Previous)));
+        assert!(matches!(
+            parse("load set.yaml"),
+            Ok(Request::Load { path }) if path == std::path::Path::new("set.yaml")
+        ));
+        assert!(matches!(
+            parse("volume -6.5"),
+            Ok(Request::Volume { volume }) if volume == -6.5
+        ));
+    }
+
+    #[test]
+    fn bypass() {
+        assert!(matches!(
+            parse("bypass Reverb"),
+            Ok(Request::Bypass { plugin, bypass: None }) if plugin == "Reverb"
+        ));
+        assert!(matches!(
+            parse("bypass Reverb on"),
+            Ok(Request::Bypass {
+                bypass: Some(true),
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse("bypass Reverb 0"),
+            Ok(Request::Bypass {
+                bypass: Some(false),
+                ..
+            })
+        ));
+        assert!(parse("bypass Reverb maybe").is_err());
+        assert!(parse("bypass Reverb on off").is_err());
+    }
+
+    #[test]
+    fn param_by_index_or_name() {
+        assert!(matches!(
+            parse("param Reverb 3 0.5"),
+            Ok(Request::Param { param: ParamRef::Index(3), value, .. }) if value == 0.5
+        ));
+        assert!(matches!(
+            parse("param Reverb Size 20"),
+            Ok(Request::Param { param: ParamRef::Name(name), .. }) if name == "Size"
+        ));
+        assert_eq!(
+            parse("param Reverb Size big").unwrap_err(),
+            "big isn't a number"
+        );
+    }
+
+    #[test]
+    fn unknown_commands() {
+        assert_eq!(parse("play now").unwrap_err(), "Unknown command play now");
+        assert!(parse("status now").is_err());
+        assert!(parse("volume").is_err());
+        assert!(parse("").is_err());
+    }
+}
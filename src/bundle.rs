@@ -0,0 +1,96 @@
+use crate::{LoadedUnit, SESSION_VERSION, Session};
+use rack::prelude::*;
+use serde::Serialize;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use zip::ZipWriter;
+use zip::write::SimpleFileOptions;
+
+/// Directory of the impulse responses in a bundle.
+const FILES_DIR: &str = "files";
+
+/// What a bundled session needs besides the files in the bundle.
+#[derive(Serialize)]
+struct Manifest {
+    rake_version: &'static str,
+    session_version: u32,
+    plugins: Vec<RequiredPlugin>,
+}
+
+#[derive(Serialize)]
+struct RequiredPlugin {
+    name: String,
+    vendor: String,
+    version: String,
+    unique_id: String,
+}
+
+/// Writes a session to a zip file along with the impulse responses it uses, which the session
+/// refers to relative to itself there, and a manifest of the plugins it needs.
+pub fn export(path: &Path, mut session: Session) -> Result<()> {
+    let mut plugins: Vec<RequiredPlugin> = Vec::new();
+    // Source of each impulse response and its path in the bundle
+    let mut files: Vec<(PathBuf, String)> = Vec::new();
+    for plugin in session.plugins_mut() {
+        match &mut plugin.unit {
+            LoadedUnit::Plugin { info, .. } => {
+                if !plugins
+                    .iter()
+                    .any(|required| required.unique_id == info.unique_id)
+                {
+                    plugins.push(RequiredPlugin {
+                        name: info.name.clone(),
+                        vendor: info.manufacturer.clone(),
+                        version: info.version.to_string(),
+                        unique_id: info.unique_id.clone(),
+                    });
+                }
+            }
+            LoadedUnit::Convolver { ir } => {
+                let bundled = match files.iter().find(|(source, _)| source == ir) {
+                    Some((_, bundled)) => bundled.clone(),
+                    None => {
+                        let file_name = ir.file_name().unwrap_or_default().to_string_lossy();
+                        let mut bundled = format!("{}/{}", FILES_DIR, file_name);
+                        // Impulse responses from different directories can have the same name
+                        if files.iter().any(|(_, other)| *other == bundled) {
+                            bundled = format!("{}/{}-{}", FILES_DIR, files.len(), file_name);
+                        }
+                        files.push((ir.clone(), bundled.clone()));
+                        bundled
+                    }
+                };
+                *ir = PathBuf::from(bundled);
+            }
+            LoadedUnit::Looper { .. } => {}
+        }
+    }
+    let manifest = Manifest {
+        rake_version: env!("CARGO_PKG_VERSION"),
+        session_version: SESSION_VERSION,
+        plugins,
+    };
+
+    let mut zip = ZipWriter::new(File::create(path)?);
+    let options = SimpleFileOptions::default();
+    for (source, bundled) in &files {
+        let mut file = File::open(source).map_err(|e| {
+            rack::Error::Other(format!("Error reading {}: {}", source.display(), e))
+        })?;
+        zip.start_file(bundled.as_str(), options)
+            .map_err(zip_error)?;
+        std::io::copy(&mut file, &mut zip)?;
+    }
+    zip.start_file("session.yaml", options).map_err(zip_error)?;
+    zip.write_all(serde_yaml_ng::to_string(&session).unwrap().as_bytes())?;
+    zip.start_file("manifest.yaml", options)
+        .map_err(zip_error)?;
+    zip.write_all(serde_yaml_ng::to_string(&manifest).unwrap().as_bytes())?;
+    zip.finish().map_err(zip_error)?;
+    Ok(())
+}
+
+fn zip_error(e: zip::result::ZipError) -> rack::Error {
+    rack::Error::Other(e.to_string())
+}
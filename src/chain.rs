@@ -0,0 +1,950 @@
+use crate::convolver::Convolver;
+use crate::dsp::{Level, Routing, mix_down};
+use crate::log;
+use crate::looper::Looper;
+use crate::midi::ControlChange;
+use jack::{AudioOut, Client, Port, ProcessScope};
+use rack::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::time::Instant;
+use uuid::Uuid;
+
+/// Time over which parameter changes are ramped to avoid zipper noise.
+pub const PARAM_RAMP_SECONDS: f32 = 0.01;
+
+/// Weight of the latest cycle in the smoothed processing time of a slot.
+const LOAD_SMOOTHING: f32 = 0.05;
+
+/// Maximum number of return chains per chain, so slots never grow their list of sends.
+pub const MAX_RETURNS: usize = 8;
+
+/// Maximum number of plugins and splits in a chain, and of plugins in a split branch, so the
+/// process thread never grows them.
+pub const MAX_SLOTS: usize = 32;
+
+/// Stereo output buses a plugin may have besides its main one.
+pub const MAX_EXTRA_BUSES: usize = 3;
+
+/// Output channels handed to a plugin: the main bus and every extra one.
+const MAX_CHANNELS: usize = 2 + 2 * MAX_EXTRA_BUSES;
+
+/// Moves a parameter towards its target value over several process cycles.
+struct ParamRamp {
+    index: usize,
+    value: f32,
+    step: f32,
+    target: f32,
+    cycles: usize,
+}
+
+/// Scratch buffers shared by every slot of a chain, allocated once for the buffer size.
+pub struct Buffers {
+    scratch: Scratch,
+    l_branch: Vec<f32>,
+    r_branch: Vec<f32>,
+    /// Mono copy of the signal at the analyzer tap, if it was reached this cycle.
+    pub tap: Vec<f32>,
+    pub tapped: bool,
+}
+
+impl Buffers {
+    pub fn new(size: usize) -> Self {
+        Buffers {
+            scratch: Scratch {
+                l_vec: vec![0.0; size],
+                r_vec: vec![0.0; size],
+                l_dry: vec![0.0; size],
+                r_dry: vec![0.0; size],
+            },
+            l_branch: vec![0.0; size],
+            r_branch: vec![0.0; size],
+            tap: vec![0.0; size],
+            tapped: false,
+        }
+    }
+}
+
+/// Buffers a slot works in while it runs.
+struct Scratch {
+    /// Input of the unit.
+    l_vec: Vec<f32>,
+    r_vec: Vec<f32>,
+    /// Input of a slot that is fading in or out, which it crossfades with.
+    l_dry: Vec<f32>,
+    r_dry: Vec<f32>,
+}
+
+/// MIDI received this cycle.
+pub struct Midi {
+    pub events: Vec<ControlChange>,
+}
+
+/// What a slot runs: a hosted plugin or one of the built-in processors.
+pub enum Unit {
+    Plugin(Plugin),
+    /// A mono plugin run as one instance per channel.
+    DualMono(Box<[Plugin; 2]>),
+    Convolver(Box<Convolver>),
+    Looper(Box<Looper>),
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Unit::Plugin(plugin) => write!(f, "{}", plugin.info()),
+            Unit::DualMono(plugins) => write!(f, "{}", plugins[0].info()),
+            Unit::Convolver(_) => write!(f, "IR convolver"),
+            Unit::Looper(_) => write!(f, "Looper"),
+        }
+    }
+}
+
+impl Unit {
+    /// Hosted plugin instances, which all get the same parameters.
+    pub fn plugins(&self) -> &[Plugin] {
+        match self {
+            Unit::Plugin(plugin) => std::slice::from_ref(plugin),
+            Unit::DualMono(plugins) => &plugins[..],
+            Unit::Convolver(_) | Unit::Looper(_) => &[],
+        }
+    }
+
+    pub fn plugins_mut(&mut self) -> &mut [Plugin] {
+        match self {
+            Unit::Plugin(plugin) => std::slice::from_mut(plugin),
+            Unit::DualMono(plugins) => &mut plugins[..],
+            Unit::Convolver(_) | Unit::Looper(_) => &mut [],
+        }
+    }
+}
+
+/// Which of a plugin's output buses feeds the rest of the chain.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OutputBus {
+    #[default]
+    Main,
+    /// An extra bus, counting from 1.
+    Extra(usize),
+    /// The sum of all buses.
+    Downmix,
+}
+
+impl fmt::Display for OutputBus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputBus::Main => write!(f, "Bus 1"),
+            OutputBus::Extra(n) => write!(f, "Bus {}", n + 1),
+            OutputBus::Downmix => write!(f, "Downmix"),
+        }
+    }
+}
+
+/// An extra stereo output bus of a plugin, sent to its own JACK ports.
+pub struct Bus {
+    left: Vec<f32>,
+    right: Vec<f32>,
+    left_port: Port<AudioOut>,
+    right_port: Port<AudioOut>,
+}
+
+impl Bus {
+    /// Registers `<name>_left` and `<name>_right` ports for the bus.
+    pub fn register(
+        client: &Client,
+        name: &str,
+        size: usize,
+    ) -> std::result::Result<Self, jack::Error> {
+        Ok(Bus {
+            left: vec![0.0; size],
+            right: vec![0.0; size],
+            left_port: client.register_port(&format!("{}_left", name), AudioOut::default())?,
+            right_port: client.register_port(&format!("{}_right", name), AudioOut::default())?,
+        })
+    }
+
+    pub fn unregister(self, client: &Client) {
+        for result in [
+            client.unregister_port(self.left_port),
+            client.unregister_port(self.right_port),
+        ] {
+            if let Err(e) = result {
+                log::error!("Error unregistering port: {}", e);
+            }
+        }
+    }
+
+    /// Copies the bus to its ports and clears it, so it is silent while the plugin doesn't run.
+    fn write(&mut self, scope: &ProcessScope) {
+        self.left_port
+            .as_mut_slice(scope)
+            .copy_from_slice(&self.left);
+        self.right_port
+            .as_mut_slice(scope)
+            .copy_from_slice(&self.right);
+        self.left.fill(0.0);
+        self.right.fill(0.0);
+    }
+}
+
+/// Change a slot crossfades through, so rearranging the chain doesn't click.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Transition {
+    /// Inserted, and fading from its input to its output.
+    FadeIn,
+    /// Fading out to its input, then moved one position up or down and faded back in.
+    Move { up: bool },
+    /// Fading out to its input, then removed.
+    Remove,
+}
+
+/// Why the process thread bypassed a slot on its own.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// Kept taking most of the time available for a cycle.
+    Overload,
+    /// Produced NaN or infinite samples.
+    NonFinite,
+    /// Panicked, so the plugin can't be trusted to run again.
+    Panic,
+    /// Couldn't be instantiated when a session was loaded, so it was left out.
+    Missing,
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fault::Overload => write!(f, "took too long to process"),
+            Fault::NonFinite => write!(f, "produced NaN or infinite samples"),
+            Fault::Panic => write!(f, "panicked"),
+            Fault::Missing => write!(f, "couldn't be loaded and was left out"),
+        }
+    }
+}
+
+/// Errors on the process thread, sent to the GUI with the id of the slot or chain they concern
+/// instead of being formatted and printed there, which can block.
+#[derive(Clone, Copy, Debug)]
+pub enum ProcessError {
+    /// The plugin failed to process a cycle.
+    Process,
+    /// Setting the parameter with this index failed.
+    SetParameter(usize),
+    /// Re-initializing the plugin for a new buffer size failed.
+    Reinitialize,
+    /// The garbage queue was full, so the removed plugin, output bus, chain or return chain was
+    /// dropped on the process thread.
+    RemovePlugin,
+    RemoveBus,
+    RemoveSplit,
+    RemoveChain,
+    RemoveReturn,
+    /// The finished render couldn't be handed back.
+    FinishRender,
+    /// The recorder fell behind, so a cycle of output was dropped.
+    RecorderFull,
+    /// The fault queue was full.
+    ReportFault(Fault),
+}
+
+pub struct Slot {
+    pub unit: Unit,
+    pub id: Uuid,
+    pub bypass: bool,
+    pub mix: f32,
+    pub input_gain: f32,
+    pub output_gain: f32,
+    pub routing: Routing,
+    /// Return chain ids and the linear level sent to each.
+    pub sends: Vec<(Uuid, f32)>,
+    /// Whether the slot's output feeds the spectrum analyzer.
+    pub tap: bool,
+    /// Levels entering and leaving the slot during the last cycle.
+    pub input_level: Level,
+    pub output_level: Level,
+    /// Smoothed time the unit takes to process a cycle, in seconds.
+    pub process_seconds: f32,
+    /// Fault found this cycle, for the processor to bypass the slot and report it.
+    pub fault: Option<Fault>,
+    /// Error found this cycle, for the processor to report.
+    pub error: Option<ProcessError>,
+    /// Extra output buses of the plugin, filled in order if it has that many outputs.
+    pub buses: Vec<Bus>,
+    pub output_bus: OutputBus,
+    /// The plugin panicked and is never run again, even if the slot is re-enabled.
+    poisoned: bool,
+    transition: Option<Transition>,
+    /// Share of the slot's output in the crossfade with its input, from 0 to 1.
+    fade: f32,
+    fade_step: f32,
+    ramps: Vec<ParamRamp>,
+}
+
+impl Slot {
+    /// Must be called outside the process thread since it allocates the sends and ramps.
+    pub fn new(unit: Unit, id: Uuid) -> Self {
+        let parameter_count = unit
+            .plugins()
+            .first()
+            .map_or(0, |plugin| plugin.parameter_count());
+        Slot {
+            unit,
+            id,
+            bypass: false,
+            mix: 1.0,
+            input_gain: 1.0,
+            output_gain: 1.0,
+            routing: Routing::Stereo,
+            sends: Vec::with_capacity(MAX_RETURNS),
+            tap: false,
+            input_level: Level::default(),
+            output_level: Level::default(),
+            process_seconds: 0.0,
+            fault: None,
+            error: None,
+            buses: Vec::with_capacity(MAX_EXTRA_BUSES),
+            output_bus: OutputBus::Main,
+            poisoned: false,
+            transition: None,
+            fade: 1.0,
+            fade_step: 0.0,
+            ramps: Vec::with_capacity(parameter_count),
+        }
+    }
+
+    /// Re-initializes the unit for a new JACK buffer size. Allocates, so it must only be called
+    /// between cycles.
+    pub fn set_buffer_size(&mut self, sample_rate: usize, size: usize) {
+        match &mut self.unit {
+            Unit::Plugin(_) | Unit::DualMono(_) => {
+                for plugin in self.unit.plugins_mut() {
+                    if plugin.initialize(sample_rate as f64, size).is_err() {
+                        self.error = Some(ProcessError::Reinitialize);
+                    }
+                }
+            }
+            Unit::Convolver(convolver) => convolver.set_size(size),
+            Unit::Looper(_) => (),
+        }
+        for bus in &mut self.buses {
+            bus.left = vec![0.0; size];
+            bus.right = vec![0.0; size];
+        }
+    }
+
+    /// Sends the extra buses to their ports.
+    pub fn write_buses(&mut self, scope: &ProcessScope) {
+        for bus in &mut self.buses {
+            bus.write(scope);
+        }
+    }
+
+    /// Unregisters the ports of the extra buses of a slot the process thread is done with.
+    pub fn unregister_buses(&mut self, client: &Client) {
+        for bus in self.buses.drain(..) {
+            bus.unregister(client);
+        }
+    }
+
+    /// Takes the place of `old`, keeping its settings, and returns it with the replaced unit.
+    /// Doesn't allocate, so it can run in the process thread.
+    pub fn replace(mut self, old: &mut Slot) -> Slot {
+        self.bypass = old.bypass;
+        self.mix = old.mix;
+        self.input_gain = old.input_gain;
+        self.output_gain = old.output_gain;
+        self.routing = old.routing;
+        self.tap = old.tap;
+        self.output_bus = old.output_bus;
+        std::mem::swap(&mut self.sends, &mut old.sends);
+        std::mem::swap(&mut self.buses, &mut old.buses);
+        std::mem::replace(old, self)
+    }
+
+    pub fn start_ramp(&mut self, index: usize, target: f32, cycles: usize) {
+        let Some(plugin) = self.unit.plugins().first() else {
+            return;
+        };
+        if self.poisoned {
+            return;
+        }
+        let Ok(value) = panic::catch_unwind(AssertUnwindSafe(|| plugin.get_parameter(index)))
+        else {
+            self.poison();
+            return;
+        };
+        let value = value.unwrap_or(target);
+        self.ramps.retain(|ramp| ramp.index != index);
+        self.ramps.push(ParamRamp {
+            index,
+            value,
+            step: (target - value) / cycles as f32,
+            target,
+            cycles,
+        });
+    }
+
+    /// Fades the newly inserted slot in by `step` per sample.
+    pub fn start_fade_in(&mut self, step: f32) {
+        self.fade = 0.0;
+        self.start_transition(Transition::FadeIn, step);
+    }
+
+    /// Fades the slot out before `Chain::finish_transitions` moves it.
+    pub fn start_move(&mut self, up: bool, step: f32) {
+        self.start_transition(Transition::Move { up }, step);
+    }
+
+    /// Fades the slot out before `Chain::finish_transitions` removes it.
+    pub fn start_removal(&mut self, step: f32) {
+        self.start_transition(Transition::Remove, step);
+    }
+
+    fn start_transition(&mut self, transition: Transition, step: f32) {
+        self.transition = Some(transition);
+        self.fade_step = step;
+    }
+
+    /// The transition the slot finished fading out for, if any.
+    fn faded_out(&self) -> Option<Transition> {
+        self.transition
+            .filter(|transition| *transition != Transition::FadeIn && self.fade == 0.0)
+    }
+
+    /// Sets the level sent to a return chain, removing the send at 0.
+    pub fn set_send(&mut self, return_id: Uuid, level: f32) {
+        if level == 0.0 {
+            self.sends.retain(|(id, _)| *id != return_id);
+        } else if let Some(send) = self.sends.iter_mut().find(|(id, _)| *id == return_id) {
+            send.1 = level;
+        } else if self.sends.len() < self.sends.capacity() {
+            self.sends.push((return_id, level));
+        }
+    }
+
+    /// Stops running a plugin that panicked and reports it.
+    fn poison(&mut self) {
+        self.poisoned = true;
+        self.ramps.clear();
+        self.fault = Some(Fault::Panic);
+    }
+
+    fn advance_ramps(&mut self) {
+        if self.poisoned {
+            return;
+        }
+        let plugins = self.unit.plugins_mut();
+        for ramp in &mut self.ramps {
+            ramp.cycles -= 1;
+            ramp.value = if ramp.cycles == 0 {
+                ramp.target
+            } else {
+                ramp.value + ramp.step
+            };
+            for plugin in plugins.iter_mut() {
+                let Ok(result) = panic::catch_unwind(AssertUnwindSafe(|| {
+                    plugin.set_parameter(ramp.index, ramp.value)
+                })) else {
+                    self.poisoned = true;
+                    break;
+                };
+                if result.is_err() {
+                    self.error = Some(ProcessError::SetParameter(ramp.index));
+                    ramp.cycles = 0;
+                }
+            }
+            if self.poisoned {
+                break;
+            }
+        }
+        if self.poisoned {
+            self.poison();
+        }
+        self.ramps.retain(|ramp| ramp.cycles > 0);
+    }
+
+    /// Runs the slot's unit in place on `left` and `right`, using `scratch` for its input.
+    /// Returns false if the signal was passed through because the slot is bypassed or failed,
+    /// or silenced because the unit produced samples that aren't finite.
+    fn process(
+        &mut self,
+        left: &mut [f32],
+        right: &mut [f32],
+        scratch: &mut Scratch,
+        midi: &mut Midi,
+    ) -> bool {
+        let Scratch {
+            l_vec,
+            r_vec,
+            l_dry,
+            r_dry,
+        } = scratch;
+        self.input_level = Level::measure(left, right);
+        if let Some(transition) = self.transition {
+            l_dry.copy_from_slice(left);
+            r_dry.copy_from_slice(right);
+            // Nothing to crossfade with while the slot passes its input through
+            if self.bypass || self.poisoned {
+                self.fade = if transition == Transition::FadeIn {
+                    1.0
+                } else {
+                    0.0
+                };
+            }
+        }
+        let processed = self.run(left, right, l_vec, r_vec, midi);
+        if let Some(transition) = self.transition {
+            let step = if transition == Transition::FadeIn {
+                self.fade_step
+            } else {
+                -self.fade_step
+            };
+            for ((l, r), (dry_l, dry_r)) in left
+                .iter_mut()
+                .zip(right.iter_mut())
+                .zip(l_dry.iter().zip(r_dry.iter()))
+            {
+                self.fade = (self.fade + step).clamp(0.0, 1.0);
+                *l = dry_l + (*l - dry_l) * self.fade;
+                *r = dry_r + (*r - dry_r) * self.fade;
+            }
+            if transition == Transition::FadeIn && self.fade == 1.0 {
+                self.transition = None;
+            }
+        }
+        self.output_level = Level::measure(left, right);
+        processed
+    }
+
+    fn run(
+        &mut self,
+        left: &mut [f32],
+        right: &mut [f32],
+        l_vec: &mut [f32],
+        r_vec: &mut [f32],
+        midi: &mut Midi,
+    ) -> bool {
+        self.advance_ramps();
+        if self.bypass || self.poisoned {
+            self.process_seconds = 0.0;
+            return false;
+        }
+
+        l_vec.copy_from_slice(left);
+        r_vec.copy_from_slice(right);
+        if self.input_gain != 1.0 {
+            for sample in l_vec.iter_mut().chain(r_vec.iter_mut()) {
+                *sample *= self.input_gain;
+            }
+        }
+        self.routing.apply_input(l_vec, r_vec);
+
+        let start = Instant::now();
+        let result = match &mut self.unit {
+            // A panic must not unwind into the JACK callback, so the plugin is disabled instead
+            Unit::Plugin(plugin) => panic::catch_unwind(AssertUnwindSafe(|| {
+                let mut outputs: [&mut [f32]; MAX_CHANNELS] = Default::default();
+                let channels = 2 + 2 * self.buses.len();
+                outputs[0] = &mut *left;
+                outputs[1] = &mut *right;
+                for (pair, bus) in outputs[2..].chunks_mut(2).zip(&mut self.buses) {
+                    pair[0] = &mut bus.left;
+                    pair[1] = &mut bus.right;
+                }
+                plugin
+                    .process(&[&*l_vec, &*r_vec], &mut outputs[..channels], l_vec.len())
+                    .map(|_| ())
+            }))
+            .unwrap_or_else(|_| {
+                self.poisoned = true;
+                Ok(())
+            }),
+            // Each instance only gets its own channel
+            Unit::DualMono(plugins) => panic::catch_unwind(AssertUnwindSafe(|| {
+                let [l_plugin, r_plugin] = &mut **plugins;
+                l_plugin.process(&[&*l_vec], &mut [&mut *left], l_vec.len())?;
+                r_plugin.process(&[&*r_vec], &mut [&mut *right], r_vec.len())?;
+                Ok(())
+            }))
+            .unwrap_or_else(|_| {
+                self.poisoned = true;
+                Ok(())
+            }),
+            Unit::Convolver(convolver) => {
+                convolver.process(l_vec, r_vec, left, right);
+                Ok(())
+            }
+            Unit::Looper(looper) => {
+                looper.process(l_vec, r_vec, left, right, &midi.events);
+                Ok(())
+            }
+        };
+        self.process_seconds +=
+            (start.elapsed().as_secs_f32() - self.process_seconds) * LOAD_SMOOTHING;
+
+        if self.poisoned {
+            // The plugin may have been halfway through writing its output
+            left.copy_from_slice(l_vec);
+            right.copy_from_slice(r_vec);
+            self.poison();
+            return false;
+        }
+
+        // One unstable filter would otherwise spread NaN through the rest of the chain for good
+        if result.is_ok()
+            && left
+                .iter()
+                .chain(right.iter())
+                .any(|sample| !sample.is_finite())
+        {
+            left.fill(0.0);
+            right.fill(0.0);
+            self.fault = Some(Fault::NonFinite);
+            return false;
+        }
+
+        match result {
+            Ok(()) => {
+                // Only a plugin run as a single instance fills the extra buses
+                match self.output_bus {
+                    _ if !matches!(self.unit, Unit::Plugin(_)) => (),
+                    OutputBus::Main => (),
+                    OutputBus::Extra(n) => {
+                        if let Some(bus) = self.buses.get(n - 1) {
+                            left.copy_from_slice(&bus.left);
+                            right.copy_from_slice(&bus.right);
+                        }
+                    }
+                    OutputBus::Downmix => {
+                        for bus in &self.buses {
+                            for (sum, sample) in left.iter_mut().zip(&bus.left) {
+                                *sum += sample;
+                            }
+                            for (sum, sample) in right.iter_mut().zip(&bus.right) {
+                                *sum += sample;
+                            }
+                        }
+                    }
+                }
+                // l_vec and r_vec still hold the plugin's input, which is the dry signal
+                if self.mix < 1.0 {
+                    for (wet, dry) in left.iter_mut().zip(l_vec.iter()) {
+                        *wet = dry + (*wet - dry) * self.mix;
+                    }
+                    for (wet, dry) in right.iter_mut().zip(r_vec.iter()) {
+                        *wet = dry + (*wet - dry) * self.mix;
+                    }
+                }
+                self.routing.apply_output(left, right);
+                if self.output_gain != 1.0 {
+                    for sample in left.iter_mut().chain(right.iter_mut()) {
+                        *sample *= self.output_gain;
+                    }
+                }
+                true
+            }
+            Err(_) => {
+                self.error = Some(ProcessError::Process);
+                false
+            }
+        }
+    }
+
+    /// Adds the slot's output to the return chains it sends to.
+    fn send(&self, left: &[f32], right: &[f32], aux: &mut [AuxReturn]) {
+        for (id, level) in &self.sends {
+            if let Some(aux_return) = aux.iter_mut().find(|aux_return| aux_return.id == *id) {
+                for (sum, sample) in aux_return.left.iter_mut().zip(left) {
+                    *sum += sample * level;
+                }
+                for (sum, sample) in aux_return.right.iter_mut().zip(right) {
+                    *sum += sample * level;
+                }
+            }
+        }
+    }
+}
+
+/// Splits the signal into two branches that are processed independently and summed.
+pub struct Split {
+    pub id: Uuid,
+    pub branches: [Vec<Slot>; 2],
+    pub gains: [f32; 2],
+}
+
+impl Split {
+    /// Must be called outside the process thread since it allocates the branches.
+    pub fn new(id: Uuid) -> Self {
+        Split {
+            id,
+            branches: [Vec::with_capacity(MAX_SLOTS), Vec::with_capacity(MAX_SLOTS)],
+            gains: [1.0, 1.0],
+        }
+    }
+}
+
+pub enum Node {
+    Plugin(Slot),
+    Split(Split),
+}
+
+impl Node {
+    pub fn id(&self) -> Uuid {
+        match self {
+            Node::Plugin(slot) => slot.id,
+            Node::Split(split) => split.id,
+        }
+    }
+}
+
+/// A return chain fed by the aux sends of the slots in another chain.
+pub struct AuxReturn {
+    pub id: Uuid,
+    pub chain: Chain,
+    left: Vec<f32>,
+    right: Vec<f32>,
+}
+
+impl AuxReturn {
+    pub fn new(id: Uuid, size: usize) -> Self {
+        AuxReturn {
+            id,
+            chain: Chain::default(),
+            left: vec![0.0; size],
+            right: vec![0.0; size],
+        }
+    }
+
+    pub fn set_buffer_size(&mut self, size: usize) {
+        self.left = vec![0.0; size];
+        self.right = vec![0.0; size];
+    }
+
+    pub fn clear(&mut self) {
+        self.left.fill(0.0);
+        self.right.fill(0.0);
+    }
+
+    /// Runs the return chain on everything sent to it and adds the result to `left` and `right`.
+    pub fn process(
+        &mut self,
+        left: &mut [f32],
+        right: &mut [f32],
+        buffers: &mut Buffers,
+        midi: &mut Midi,
+    ) {
+        self.chain
+            .process(&mut self.left, &mut self.right, buffers, midi, &mut []);
+        for (sum, sample) in left.iter_mut().zip(&self.left) {
+            *sum += sample;
+        }
+        for (sum, sample) in right.iter_mut().zip(&self.right) {
+            *sum += sample;
+        }
+    }
+}
+
+/// Moves the item with the given id one position up or down.
+/// Returns false if the item isn't in `items`.
+pub fn move_by_id<T>(items: &mut [T], id: Uuid, item_id: impl Fn(&T) -> Uuid, up: bool) -> bool {
+    let Some(i) = items.iter().position(|item| item_id(item) == id) else {
+        return false;
+    };
+    if up && i > 0 {
+        items.swap(i - 1, i);
+    } else if !up && i + 1 < items.len() {
+        items.swap(i, i + 1);
+    }
+    true
+}
+
+pub struct Chain {
+    pub nodes: Vec<Node>,
+}
+
+impl Default for Chain {
+    fn default() -> Self {
+        Chain {
+            nodes: Vec::with_capacity(MAX_SLOTS),
+        }
+    }
+}
+
+impl Chain {
+    pub fn slot_mut(&mut self, id: Uuid) -> Option<&mut Slot> {
+        self.nodes.iter_mut().find_map(|node| match node {
+            Node::Plugin(slot) if slot.id == id => Some(slot),
+            Node::Plugin(_) => None,
+            Node::Split(split) => split
+                .branches
+                .iter_mut()
+                .flatten()
+                .find(|slot| slot.id == id),
+        })
+    }
+
+    pub fn slots_mut(&mut self) -> impl Iterator<Item = &mut Slot> {
+        self.nodes.iter_mut().flat_map(|node| match node {
+            Node::Plugin(slot) => std::slice::from_mut(slot).iter_mut().chain(&mut []),
+            Node::Split(split) => {
+                let [a, b] = &mut split.branches;
+                a.iter_mut().chain(b)
+            }
+        })
+    }
+
+    /// Unregisters the ports of every slot's extra buses once the chain is removed.
+    pub fn unregister_buses(&mut self, client: &Client) {
+        for slot in self.slots_mut() {
+            slot.unregister_buses(client);
+        }
+    }
+
+    pub fn split_mut(&mut self, id: Uuid) -> Option<&mut Split> {
+        self.nodes.iter_mut().find_map(|node| match node {
+            Node::Split(split) if split.id == id => Some(split),
+            _ => None,
+        })
+    }
+
+    /// Removes a plugin or a whole split, passing every removed plugin to `removed`.
+    pub fn remove(&mut self, id: Uuid, mut removed: impl FnMut(Slot)) {
+        if let Some(i) = self.nodes.iter().position(|node| node.id() == id) {
+            match self.nodes.remove(i) {
+                Node::Plugin(slot) => removed(slot),
+                Node::Split(split) => split.branches.into_iter().flatten().for_each(removed),
+            }
+            return;
+        }
+        for node in &mut self.nodes {
+            if let Node::Split(split) = node {
+                for branch in &mut split.branches {
+                    if let Some(i) = branch.iter().position(|slot| slot.id == id) {
+                        removed(branch.remove(i));
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Moves or removes the slots that finished fading out for it. Moved slots fade back in.
+    pub fn finish_transitions(&mut self, mut removed: impl FnMut(Slot)) {
+        let is_removed = |slot: &Slot| slot.faded_out() == Some(Transition::Remove);
+        for node in self.nodes.extract_if(
+            ..,
+            |node| matches!(node, Node::Plugin(slot) if is_removed(slot)),
+        ) {
+            if let Node::Plugin(slot) = node {
+                removed(slot);
+            }
+        }
+        for node in &mut self.nodes {
+            if let Node::Split(split) = node {
+                for branch in &mut split.branches {
+                    branch
+                        .extract_if(.., |slot| is_removed(slot))
+                        .for_each(&mut removed);
+                }
+            }
+        }
+
+        loop {
+            let Some((id, up)) = self.slots_mut().find_map(|slot| {
+                let Some(Transition::Move { up }) = slot.faded_out() else {
+                    return None;
+                };
+                slot.transition = Some(Transition::FadeIn);
+                Some((slot.id, up))
+            }) else {
+                break;
+            };
+            self.move_node(id, up);
+        }
+    }
+
+    pub fn clear(&mut self, mut removed: impl FnMut(Slot)) {
+        while let Some(node) = self.nodes.pop() {
+            match node {
+                Node::Plugin(slot) => removed(slot),
+                Node::Split(split) => split.branches.into_iter().flatten().for_each(&mut removed),
+            }
+        }
+    }
+
+    pub fn move_node(&mut self, id: Uuid, up: bool) {
+        if move_by_id(&mut self.nodes, id, Node::id, up) {
+            return;
+        }
+        for node in &mut self.nodes {
+            if let Node::Split(split) = node {
+                for branch in &mut split.branches {
+                    if move_by_id(branch, id, |slot| slot.id, up) {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Runs the whole chain in place on `left` and `right`, feeding the aux sends into `aux`.
+    /// Returns false if no plugin processed the signal.
+    pub fn process(
+        &mut self,
+        left: &mut [f32],
+        right: &mut [f32],
+        buffers: &mut Buffers,
+        midi: &mut Midi,
+        aux: &mut [AuxReturn],
+    ) -> bool {
+        let Buffers {
+            scratch,
+            l_branch,
+            r_branch,
+            tap,
+            tapped,
+        } = buffers;
+
+        let mut processed = false;
+        for node in &mut self.nodes {
+            match node {
+                Node::Plugin(slot) => {
+                    processed |= slot.process(left, right, scratch, midi);
+                    slot.send(left, right, aux);
+                    if slot.tap {
+                        mix_down(tap, left, right);
+                        *tapped = true;
+                    }
+                }
+                Node::Split(split) => {
+                    l_branch.copy_from_slice(left);
+                    r_branch.copy_from_slice(right);
+                    for slot in &mut split.branches[0] {
+                        processed |= slot.process(left, right, scratch, midi);
+                        slot.send(left, right, aux);
+                        if slot.tap {
+                            mix_down(tap, left, right);
+                            *tapped = true;
+                        }
+                    }
+                    for slot in &mut split.branches[1] {
+                        processed |= slot.process(l_branch, r_branch, scratch, midi);
+                        slot.send(l_branch, r_branch, aux);
+                        if slot.tap {
+                            mix_down(tap, l_branch, r_branch);
+                            *tapped = true;
+                        }
+                    }
+                    for (l, b) in left.iter_mut().zip(l_branch.iter()) {
+                        *l = *l * split.gains[0] + b * split.gains[1];
+                    }
+                    for (r, b) in right.iter_mut().zip(r_branch.iter()) {
+                        *r = *r * split.gains[0] + b * split.gains[1];
+                    }
+                }
+            }
+        }
+        processed
+    }
+}
@@ -0,0 +1,34 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Sender};
+use std::thread::JoinHandle;
+
+/// Handle to the thread that drops plugins and chains the process thread is done with, so
+/// slow plugin teardown neither runs in the process callback nor stalls the GUI.
+pub struct Collector {
+    sender: Sender<Box<dyn Send>>,
+    thread: JoinHandle<()>,
+}
+
+impl Collector {
+    pub fn spawn() -> Self {
+        let (sender, receiver) = mpsc::channel::<Box<dyn Send>>();
+        let thread = std::thread::spawn(move || {
+            for garbage in receiver {
+                // A plugin that panicked while processing may well panic again when dropped
+                let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(garbage)));
+            }
+        });
+        Collector { sender, thread }
+    }
+
+    pub fn dispose(&self, garbage: impl Send + 'static) {
+        // The thread only exits once the sender is dropped, so this can't fail
+        let _ = self.sender.send(Box::new(garbage));
+    }
+
+    /// Waits for everything sent so far to be dropped.
+    pub fn join(self) {
+        drop(self.sender);
+        let _ = self.thread.join();
+    }
+}
@@ -0,0 +1,112 @@
+use crate::log;
+use crate::setlist::{ProgramMapping, SetlistEntry};
+use crate::view::ParamView;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+/// Settings kept between runs, in `rake/config.yaml` in the XDG config directory.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Config {
+    /// JACK client name, unless one is given with `--name`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_name: Option<String>,
+    /// Connections of Rake's ports as source and destination, restored whenever the ports exist.
+    #[serde(default)]
+    pub connections: Vec<(String, String)>,
+    /// Unique ids of the plugins listed first.
+    #[serde(default)]
+    pub favorites: Vec<String>,
+    /// Unique ids of the plugins loaded last, most recent first.
+    #[serde(default)]
+    pub recent: Vec<String>,
+    /// Session files opened or saved last, most recent first.
+    #[serde(default)]
+    pub recent_sessions: Vec<PathBuf>,
+    /// Sessions and snapshots played in order, switched with Next and Previous or with MIDI
+    /// program changes.
+    #[serde(default)]
+    pub setlist: Vec<SetlistEntry>,
+    /// Program changes that switch to another setlist entry or to a snapshot.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub program_map: Vec<ProgramMapping>,
+    /// UDP port of the OSC server, which only runs when one is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub osc_port: Option<u16>,
+    /// Address the OSC server listens on, which is only reachable from this machine unless set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub osc_address: Option<IpAddr>,
+    /// TCP port of the web API, which only runs when one is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub web_port: Option<u16>,
+    /// Address the web API listens on, which is only reachable from this machine unless set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub web_address: Option<IpAddr>,
+    /// Token that requests to the web API and its WebSockets have to give, generated when the
+    /// API first starts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub web_token: Option<String>,
+    /// ALSA sequencer port whose MIDI is merged into the MIDI input, as `client:port`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alsa_midi_input: Option<String>,
+    /// Whether the tempo follows that of Link peers on the network.
+    #[serde(default)]
+    pub link: bool,
+    /// Share of the window's width the plugin browser takes, as last resized.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub browser_ratio: Option<f32>,
+    /// File extension of the format listed when a plugin is found in several, e.g. `vst3`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preferred_format: Option<String>,
+    /// Way plugins' parameters are shown, unless set for a plugin.
+    #[serde(default)]
+    pub param_view: ParamView,
+}
+
+/// Rake's directory in the XDG config directory.
+pub fn dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("rake"))
+}
+
+fn path() -> Option<PathBuf> {
+    dir().map(|dir| dir.join("config.yaml"))
+}
+
+impl Config {
+    /// Reads the config, falling back to the defaults if there is none yet.
+    pub fn load() -> Self {
+        let Some(path) = path() else {
+            return Config::default();
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(content) => serde_yaml_ng::from_str(&content).unwrap_or_else(|e| {
+                log::error!("Error reading {}: {}", path.display(), e);
+                Config::default()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Config::default(),
+            Err(e) => {
+                log::error!("Error reading {}: {}", path.display(), e);
+                Config::default()
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        let Some(path) = path() else {
+            return;
+        };
+        if let Some(dir) = path.parent()
+            && let Err(e) = std::fs::create_dir_all(dir)
+        {
+            log::error!("Error creating {}: {}", dir.display(), e);
+            return;
+        }
+        let content = serde_yaml_ng::to_string(self).unwrap();
+        if let Err(e) = std::fs::write(&path, content) {
+            log::error!("Error writing {}: {}", path.display(), e);
+        }
+    }
+}
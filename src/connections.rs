@@ -0,0 +1,137 @@
+use crate::log;
+use jack::{Client, PortFlags};
+use std::fmt;
+
+/// Connections of the client's own ports, as source and destination port names.
+pub fn current(client: &Client) -> Vec<(String, String)> {
+    let prefix = format!("{}:", client.name());
+    let ports = client.ports(None, None, PortFlags::empty());
+    let mut connections = Vec::new();
+    for name in ports.iter().filter(|name| name.starts_with(&prefix)) {
+        let Some(port) = client.port_by_name(name) else {
+            continue;
+        };
+        let output = port.flags().contains(PortFlags::IS_OUTPUT);
+        // The jack crate only lists a port's connections with its metadata feature
+        for other in &ports {
+            if port.is_connected_to(other).unwrap_or(false) {
+                connections.push(if output {
+                    (name.clone(), other.clone())
+                } else {
+                    (other.clone(), name.clone())
+                });
+            }
+        }
+    }
+    connections
+}
+
+/// Connections made while none are remembered: the first physical capture port to both
+/// inputs of the main chain, and its outputs to the first two physical playback ports.
+pub fn default(client: &Client) -> Vec<(String, String)> {
+    let name = client.name();
+    let capture = client.ports(
+        None,
+        Some("audio"),
+        PortFlags::IS_OUTPUT | PortFlags::IS_PHYSICAL,
+    );
+    let playback = client.ports(
+        None,
+        Some("audio"),
+        PortFlags::IS_INPUT | PortFlags::IS_PHYSICAL,
+    );
+    let mut connections = Vec::new();
+    if let Some(port) = capture.first() {
+        connections.push((port.clone(), format!("{}:in_left", name)));
+        connections.push((port.clone(), format!("{}:in_right", name)));
+    }
+    for (port, output) in playback.iter().zip(["out_left", "out_right"]) {
+        connections.push((format!("{}:{}", name, output), port.clone()));
+    }
+    connections
+}
+
+/// Makes the given connections that involve one of the client's ports starting with
+/// `prefix`. Connections of ports that don't exist right now are skipped.
+pub fn restore(client: &Client, connections: &[(String, String)], prefix: &str) {
+    let prefix = format!("{}:{}", client.name(), prefix);
+    for (source, destination) in connections {
+        if !source.starts_with(&prefix) && !destination.starts_with(&prefix) {
+            continue;
+        }
+        let Some(port) = client.port_by_name(source) else {
+            continue;
+        };
+        if client.port_by_name(destination).is_none()
+            || port.is_connected_to(destination).unwrap_or(false)
+        {
+            continue;
+        }
+        if let Err(e) = client.connect_ports_by_name(source, destination) {
+            log::error!("Error connecting {} to {}: {}", source, destination, e);
+        }
+    }
+}
+
+/// Connection of a session whose other port doesn't exist here, e.g. on another machine.
+#[derive(Clone, Debug)]
+pub struct Missing {
+    /// Rake's port.
+    pub port: String,
+    /// Port it was connected to.
+    pub other: String,
+    /// Whether Rake's port is an input, so the other port was a source.
+    pub input: bool,
+    pub audio: bool,
+}
+
+/// Connects the client's ports as a session had them, disconnecting them from everything
+/// else. Returns the connections whose other port doesn't exist right now.
+pub fn apply(client: &Client, connections: &[(String, String)]) -> Vec<Missing> {
+    for (source, destination) in current(client) {
+        if !connections.contains(&(source.clone(), destination.clone()))
+            && let Err(e) = client.disconnect_ports_by_name(&source, &destination)
+        {
+            log::error!("Error disconnecting {} from {}: {}", source, destination, e);
+        }
+    }
+    let prefix = format!("{}:", client.name());
+    let mut missing = Vec::new();
+    for (source, destination) in connections {
+        let input = destination.starts_with(&prefix);
+        let (port, other) = if input {
+            (destination, source)
+        } else {
+            (source, destination)
+        };
+        // Ports of chains that aren't loaded are left out
+        let Some(own) = client.port_by_name(port) else {
+            continue;
+        };
+        if client.port_by_name(other).is_none() {
+            missing.push(Missing {
+                port: port.clone(),
+                other: other.clone(),
+                input,
+                audio: own
+                    .port_type()
+                    .is_ok_and(|port_type| port_type.contains("audio")),
+            });
+        }
+    }
+    restore(client, connections, "");
+    missing
+}
+
+/// Port picked to connect to one of Rake's ports, if any.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PortChoice(pub Option<String>);
+
+impl fmt::Display for PortChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            Some(port) => write!(f, "{}", port),
+            None => write!(f, "None"),
+        }
+    }
+}
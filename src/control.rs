@@ -0,0 +1,132 @@
+use crate::api::{self, Pending, Request};
+use crate::config::Config;
+use crate::log;
+use std::io::{Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+
+/// First argument that runs Rake as a client of a running instance.
+pub const CTL_ARG: &str = "ctl";
+
+/// Start of a reply that reports a failed request.
+const ERROR_PREFIX: &str = "Error: ";
+
+const USAGE: &str = "Usage: rake ctl [--name NAME] COMMAND
+Commands:
+  status                            List the session, chains and plugins
+  load PATH                         Open a session
+  bypass PLUGIN [on|off]            Bypass a plugin, or toggle it
+  param PLUGIN PARAMETER VALUE      Set a parameter, by name or index
+  volume DB                         Set the master volume
+  next, previous                    Switch to the next or previous setlist entry
+Plugins are given by name or by the id listed by status.";
+
+/// Socket of the instance with a JACK client name, in the XDG runtime directory.
+pub fn socket_path(client_name: &str) -> PathBuf {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+        .join(format!("rake-{}.sock", client_name))
+}
+
+/// Listens on the instance's socket in the background, passing on the requests.
+pub fn listen(client_name: &str, requests: Sender<Pending>) -> std::io::Result<()> {
+    let path = socket_path(client_name);
+    if path.exists() {
+        if UnixStream::connect(&path).is_ok() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AddrInUse,
+                format!("{} is in use by another instance", path.display()),
+            ));
+        }
+        // Left behind by an instance that didn't exit cleanly
+        std::fs::remove_file(&path)?;
+    }
+    let listener = UnixListener::bind(&path)?;
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => serve(stream, &requests),
+                Err(e) => log::error!("Error accepting rake ctl connection: {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Passes on one request and writes back the reply.
+fn serve(mut stream: UnixStream, requests: &Sender<Pending>) {
+    let mut content = String::new();
+    if let Err(e) = stream.read_to_string(&mut content) {
+        log::error!("Error reading rake ctl request: {}", e);
+        return;
+    }
+    let reply = serde_yaml_ng::from_str::<Vec<String>>(&content)
+        .map_err(|e| format!("Incorrect request: {}", e))
+        .and_then(|words| Request::parse(&words))
+        .and_then(|request| api::ask(requests, request));
+    let _ = match reply {
+        Ok(reply) => writeln!(stream, "{}", reply),
+        Err(e) => writeln!(stream, "{}{}", ERROR_PREFIX, e),
+    };
+}
+
+/// Removes the instance's socket when it exits.
+pub fn remove(client_name: &str) {
+    let _ = std::fs::remove_file(socket_path(client_name));
+}
+
+/// Runs `rake ctl`: sends the arguments to the running instance and prints its reply.
+pub fn run(args: &[String]) -> ! {
+    let mut name = None;
+    let mut command = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--name" {
+            name = args.next().cloned();
+        } else if let Some(value) = arg.strip_prefix("--name=") {
+            name = Some(value.to_string());
+        } else {
+            command.push(arg.clone());
+        }
+    }
+    if command.is_empty() {
+        eprintln!("{}", USAGE);
+        std::process::exit(2);
+    }
+    // The instance has its own working directory
+    if let [load, path] = &mut command[..]
+        && *load == "load"
+        && let Ok(absolute) = std::path::absolute(Path::new(path))
+    {
+        *path = absolute.to_string_lossy().into_owned();
+    }
+    let name = name
+        .or_else(|| Config::load().client_name)
+        .unwrap_or_else(|| crate::DEFAULT_CLIENT_NAME.to_string());
+
+    let reply = send(&name, &command).unwrap_or_else(|e| {
+        eprintln!("Error connecting to {}: {}", name, e);
+        std::process::exit(1);
+    });
+    if let Some(e) = reply.strip_prefix(ERROR_PREFIX) {
+        eprint!("{}", e);
+        if e.contains("Unknown command") {
+            eprintln!("{}", USAGE);
+        }
+        std::process::exit(1);
+    }
+    print!("{}", reply);
+    std::process::exit(0);
+}
+
+fn send(name: &str, command: &[String]) -> std::io::Result<String> {
+    let mut stream = UnixStream::connect(socket_path(name))?;
+    stream.write_all(serde_yaml_ng::to_string(command).unwrap().as_bytes())?;
+    stream.shutdown(Shutdown::Write)?;
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+    Ok(reply)
+}
@@ -0,0 +1,129 @@
+use crate::dsp::{fft, resample};
+use crate::wav;
+use std::path::Path;
+
+/// Impulse responses are cut off after this, which is plenty for cabinets and rooms.
+pub const MAX_IR_SECONDS: f32 = 10.0;
+
+/// Frequency-domain state of one channel.
+struct Channel {
+    /// Spectra of the impulse response partitions.
+    filters: Vec<(Vec<f32>, Vec<f32>)>,
+    /// Spectra of the most recent input blocks, newest at `Convolver::position`.
+    spectra: Vec<(Vec<f32>, Vec<f32>)>,
+    /// Previous and current input block.
+    input: Vec<f32>,
+}
+
+/// Uniformly partitioned overlap-save convolution with the partition size set to the
+/// buffer size, so it adds no latency.
+pub struct Convolver {
+    /// The impulse response, kept to partition it again when the buffer size changes.
+    ir: [Vec<f32>; 2],
+    size: usize,
+    channels: [Channel; 2],
+    position: usize,
+    re: Vec<f32>,
+    im: Vec<f32>,
+}
+
+impl Convolver {
+    /// Reads an impulse response and prepares it for the given sample rate and buffer size,
+    /// which must be a power of two. Mono impulse responses are used for both channels.
+    pub fn load(path: &Path, sample_rate: usize, size: usize) -> std::io::Result<Self> {
+        let ir = wav::read(path)?;
+        let max_length = (MAX_IR_SECONDS * sample_rate as f32) as usize;
+        let mut channels = ir.channels.iter().map(|samples| {
+            let mut samples = resample(samples, ir.sample_rate as usize, sample_rate);
+            samples.truncate(max_length);
+            samples
+        });
+        let left = channels.next().unwrap_or_default();
+        let right = channels.next().unwrap_or_else(|| left.clone());
+        Ok(Convolver::new([left, right], size))
+    }
+
+    fn new(ir: [Vec<f32>; 2], size: usize) -> Self {
+        Convolver {
+            channels: [Channel::new(&ir[0], size), Channel::new(&ir[1], size)],
+            ir,
+            size,
+            position: 0,
+            re: vec![0.0; size * 2],
+            im: vec![0.0; size * 2],
+        }
+    }
+
+    /// Partitions the impulse response again for a new buffer size. Allocates, so it must only
+    /// be called between cycles.
+    pub fn set_size(&mut self, size: usize) {
+        let ir = std::mem::take(&mut self.ir);
+        *self = Convolver::new(ir, size);
+    }
+
+    /// Convolves `l_in` and `r_in` with the impulse response into `left` and `right`.
+    pub fn process(&mut self, l_in: &[f32], r_in: &[f32], left: &mut [f32], right: &mut [f32]) {
+        if l_in.len() != self.size {
+            left.copy_from_slice(l_in);
+            right.copy_from_slice(r_in);
+            return;
+        }
+        let partitions = self.channels[0].filters.len();
+        self.position = (self.position + 1) % partitions;
+        for (channel, (input, output)) in
+            self.channels.iter_mut().zip([(l_in, left), (r_in, right)])
+        {
+            channel.input.copy_within(self.size.., 0);
+            channel.input[self.size..].copy_from_slice(input);
+
+            let (spectrum_re, spectrum_im) = &mut channel.spectra[self.position];
+            spectrum_re.copy_from_slice(&channel.input);
+            spectrum_im.fill(0.0);
+            fft(spectrum_re, spectrum_im);
+
+            // Each partition of the impulse response meets the input block from as many blocks ago
+            self.re.fill(0.0);
+            self.im.fill(0.0);
+            for (i, (filter_re, filter_im)) in channel.filters.iter().enumerate() {
+                let (spectrum_re, spectrum_im) =
+                    &channel.spectra[(self.position + partitions - i) % partitions];
+                for k in 0..self.size * 2 {
+                    self.re[k] += spectrum_re[k] * filter_re[k] - spectrum_im[k] * filter_im[k];
+                    self.im[k] += spectrum_re[k] * filter_im[k] + spectrum_im[k] * filter_re[k];
+                }
+            }
+
+            // Inverse transform through the forward one by conjugating, only the real part is needed
+            for value in &mut self.im {
+                *value = -*value;
+            }
+            fft(&mut self.re, &mut self.im);
+            // The second half is free of the circular wrap-around
+            let scale = 1.0 / (self.size * 2) as f32;
+            for (out, value) in output.iter_mut().zip(&self.re[self.size..]) {
+                *out = value * scale;
+            }
+        }
+    }
+}
+
+impl Channel {
+    fn new(ir: &[f32], size: usize) -> Self {
+        let partitions = ir.len().div_ceil(size).max(1);
+        let filters = (0..partitions)
+            .map(|i| {
+                let mut re = vec![0.0; size * 2];
+                let mut im = vec![0.0; size * 2];
+                let partition = &ir[(i * size).min(ir.len())..((i + 1) * size).min(ir.len())];
+                re[..partition.len()].copy_from_slice(partition);
+                fft(&mut re, &mut im);
+                (re, im)
+            })
+            .collect();
+        Channel {
+            filters,
+            spectra: vec![(vec![0.0; size * 2], vec![0.0; size * 2]); partitions],
+            input: vec![0.0; size * 2],
+        }
+    }
+}
@@ -0,0 +1,235 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Output level the limiter never exceeds, about -1 dBFS.
+const LIMITER_CEILING: f32 = 0.891;
+const LIMITER_RELEASE_SECONDS: f32 = 0.1;
+
+/// Stereo-linked peak limiter with instant attack, used as a safety net on the master output.
+pub struct Limiter {
+    envelope: f32,
+    release: f32,
+}
+
+impl Limiter {
+    pub fn new(sample_rate: usize) -> Self {
+        Limiter {
+            envelope: 0.0,
+            release: (-1.0 / (LIMITER_RELEASE_SECONDS * sample_rate as f32)).exp(),
+        }
+    }
+
+    pub fn process(&mut self, left: &mut [f32], right: &mut [f32]) {
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            let peak = l.abs().max(r.abs());
+            self.envelope = if peak > self.envelope {
+                peak
+            } else {
+                peak + (self.envelope - peak) * self.release
+            };
+
+            let gain = if self.envelope > LIMITER_CEILING {
+                LIMITER_CEILING / self.envelope
+            } else {
+                1.0
+            };
+            *l = (*l * gain).clamp(-LIMITER_CEILING, LIMITER_CEILING);
+            *r = (*r * gain).clamp(-LIMITER_CEILING, LIMITER_CEILING);
+        }
+    }
+}
+
+const DC_BLOCKER_CUTOFF: f32 = 10.0;
+
+/// First-order high-pass filter that removes DC offset.
+pub struct DcBlocker {
+    coefficient: f32,
+    last_input: f32,
+    last_output: f32,
+}
+
+impl DcBlocker {
+    pub fn new(sample_rate: usize) -> Self {
+        DcBlocker {
+            coefficient: 1.0 - std::f32::consts::TAU * DC_BLOCKER_CUTOFF / sample_rate as f32,
+            last_input: 0.0,
+            last_output: 0.0,
+        }
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        for sample in samples {
+            self.last_output = *sample - self.last_input + self.coefficient * self.last_output;
+            self.last_input = *sample;
+            *sample = self.last_output;
+        }
+    }
+}
+
+/// Peak and RMS level of one process cycle, across both channels.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Level {
+    pub peak: f32,
+    pub rms: f32,
+}
+
+impl Level {
+    pub fn measure(left: &[f32], right: &[f32]) -> Self {
+        let mut peak = 0.0_f32;
+        let mut sum = 0.0;
+        for sample in left.iter().chain(right) {
+            peak = peak.max(sample.abs());
+            sum += sample * sample;
+        }
+        let count = left.len() + right.len();
+        Level {
+            peak,
+            rms: if count == 0 {
+                0.0
+            } else {
+                (sum / count as f32).sqrt()
+            },
+        }
+    }
+}
+
+/// Writes the average of `left` and `right` to `out`.
+pub fn mix_down(out: &mut [f32], left: &[f32], right: &[f32]) {
+    for ((sample, l), r) in out.iter_mut().zip(left).zip(right) {
+        *sample = (l + r) * 0.5;
+    }
+}
+
+/// In-place radix-2 FFT. The length of `re` and `im` must be a power of two.
+pub fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * std::f32::consts::PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let a = start + k;
+                let b = a + len / 2;
+                let tr = re[b] * cos - im[b] * sin;
+                let ti = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - tr;
+                im[b] = im[a] - ti;
+                re[a] += tr;
+                im[a] += ti;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// Linearly interpolates `samples` from one sample rate to another.
+pub fn resample(samples: &[f32], from: usize, to: usize) -> Vec<f32> {
+    if from == to || samples.is_empty() {
+        return samples.to_vec();
+    }
+    let ratio = from as f64 / to as f64;
+    let length = (samples.len() as f64 / ratio) as usize;
+    (0..length)
+        .map(|i| {
+            let position = i as f64 * ratio;
+            let index = position as usize;
+            let fraction = (position - index as f64) as f32;
+            let next = samples.get(index + 1).copied().unwrap_or(0.0);
+            samples[index] + (next - samples[index]) * fraction
+        })
+        .collect()
+}
+
+/// Sets the flush-to-zero (and denormals-are-zero where available) CPU flags for the
+/// calling thread, so decaying plugin tails don't slow down to denormal arithmetic.
+pub fn flush_denormals() {
+    #[cfg(target_arch = "x86_64")]
+    // SAFETY: only the FTZ (bit 15) and DAZ (bit 6) bits of MXCSR are changed
+    unsafe {
+        let mut mxcsr: u32 = 0;
+        std::arch::asm!("stmxcsr [{}]", in(reg) &mut mxcsr);
+        mxcsr |= 0x8040;
+        std::arch::asm!("ldmxcsr [{}]", in(reg) &mxcsr);
+    }
+    #[cfg(target_arch = "aarch64")]
+    // SAFETY: only the FZ bit (24) of FPCR is changed
+    unsafe {
+        let mut fpcr: u64;
+        std::arch::asm!("mrs {}, fpcr", out(reg) fpcr);
+        fpcr |= 1 << 24;
+        std::arch::asm!("msr fpcr, {}", in(reg) fpcr);
+    }
+}
+
+/// Channel transform applied around a plugin slot.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Routing {
+    #[default]
+    Stereo,
+    Swap,
+    Mono,
+    MidSide,
+}
+
+impl Routing {
+    pub const ALL: [Routing; 4] = [
+        Routing::Stereo,
+        Routing::Swap,
+        Routing::Mono,
+        Routing::MidSide,
+    ];
+
+    /// Transforms the channels before they are passed to the plugin.
+    pub fn apply_input(self, left: &mut [f32], right: &mut [f32]) {
+        match self {
+            Routing::Stereo => (),
+            Routing::Swap => left.swap_with_slice(right),
+            Routing::Mono => {
+                for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+                    *l = (*l + *r) * 0.5;
+                    *r = *l;
+                }
+            }
+            Routing::MidSide => {
+                for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+                    (*l, *r) = ((*l + *r) * 0.5, (*l - *r) * 0.5);
+                }
+            }
+        }
+    }
+
+    /// Transforms the plugin's output back into left and right channels.
+    pub fn apply_output(self, left: &mut [f32], right: &mut [f32]) {
+        if self == Routing::MidSide {
+            for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+                (*l, *r) = (*l + *r, *l - *r);
+            }
+        }
+    }
+}
+
+impl fmt::Display for Routing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Routing::Stereo => "Stereo",
+            Routing::Swap => "Swap L/R",
+            Routing::Mono => "Mono",
+            Routing::MidSide => "Mid/Side",
+        })
+    }
+}
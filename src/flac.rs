@@ -0,0 +1,453 @@
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Samples per channel in each frame.
+const BLOCK_SIZE: usize = 4096;
+
+/// Bits per sample the float samples are converted to.
+const BITS: u32 = 24;
+
+/// Highest order of the fixed polynomial predictors.
+const MAX_ORDER: usize = 4;
+
+/// Highest Rice parameter, 15 being the escape code.
+const MAX_RICE_PARAMETER: u32 = 14;
+
+/// Highest number of times the residual is halved into partitions with their own parameter.
+const MAX_PARTITION_ORDER: u32 = 8;
+
+/// Writes interleaved stereo FLAC files with 24-bit samples. Each channel of a frame is predicted
+/// with the best of the fixed polynomial predictors and its residual Rice coded, and the
+/// channels are stored as left/right, left/side, side/right or mid/side, whichever is smallest.
+pub struct FlacWriter {
+    file: BufWriter<File>,
+    sample_rate: u32,
+    /// Samples of the frame being collected, per channel.
+    block: [Vec<i32>; 2],
+    /// Samples per channel written so far.
+    length: u64,
+    frame_number: u32,
+    /// Smallest and largest frame in bytes, 0 while unknown.
+    min_frame_size: u32,
+    max_frame_size: u32,
+}
+
+impl FlacWriter {
+    pub fn create(path: &Path, sample_rate: u32) -> std::io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(b"fLaC")?;
+        // The last metadata block, of type STREAMINFO and 34 bytes long
+        file.write_all(&[0x80, 0, 0, 34])?;
+        let mut writer = FlacWriter {
+            file,
+            sample_rate,
+            block: [
+                Vec::with_capacity(BLOCK_SIZE),
+                Vec::with_capacity(BLOCK_SIZE),
+            ],
+            length: 0,
+            frame_number: 0,
+            min_frame_size: 0,
+            max_frame_size: 0,
+        };
+        // Filled in by finish
+        writer.write_stream_info()?;
+        Ok(writer)
+    }
+
+    pub fn write(&mut self, samples: &[f32]) -> std::io::Result<()> {
+        for frame in samples.chunks_exact(2) {
+            for (channel, sample) in self.block.iter_mut().zip(frame) {
+                let max = ((1 << (BITS - 1)) - 1) as f32;
+                channel.push((sample.clamp(-1.0, 1.0) * max).round() as i32);
+            }
+            if self.block[0].len() == BLOCK_SIZE {
+                self.write_frame()?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> std::io::Result<()> {
+        if !self.block[0].is_empty() {
+            self.write_frame()?;
+        }
+        // STREAMINFO follows the marker and the block header
+        self.file.seek(SeekFrom::Start(8))?;
+        self.write_stream_info()?;
+        self.file.flush()
+    }
+
+    fn write_stream_info(&mut self) -> std::io::Result<()> {
+        let mut bits = BitWriter::default();
+        bits.write(BLOCK_SIZE as u64, 16);
+        bits.write(BLOCK_SIZE as u64, 16);
+        bits.write(self.min_frame_size as u64, 24);
+        bits.write(self.max_frame_size as u64, 24);
+        bits.write(self.sample_rate as u64, 20);
+        bits.write(2 - 1, 3);
+        bits.write(BITS as u64 - 1, 5);
+        bits.write(self.length >> 32, 4);
+        bits.write(self.length, 32);
+        // MD5 signature of the samples, all zero for unknown
+        bits.bytes.extend([0; 16]);
+        self.file.write_all(&bits.bytes)
+    }
+
+    fn write_frame(&mut self) -> std::io::Result<()> {
+        let bytes = encode_frame(&self.block, self.frame_number);
+        self.file.write_all(&bytes)?;
+        let size = bytes.len() as u32;
+        if self.min_frame_size == 0 || size < self.min_frame_size {
+            self.min_frame_size = size;
+        }
+        self.max_frame_size = self.max_frame_size.max(size);
+        self.length += self.block[0].len() as u64;
+        self.frame_number += 1;
+        self.block.iter_mut().for_each(Vec::clear);
+        Ok(())
+    }
+}
+
+fn encode_frame([left, right]: &[Vec<i32>; 2], number: u32) -> Vec<u8> {
+    let mid = left
+        .iter()
+        .zip(right)
+        .map(|(left, right)| (left + right) >> 1)
+        .collect::<Vec<_>>();
+    let side = left
+        .iter()
+        .zip(right)
+        .map(|(left, right)| left - right)
+        .collect::<Vec<_>>();
+    let left = Subframe::new(left, BITS);
+    let right = Subframe::new(right, BITS);
+    let mid = Subframe::new(&mid, BITS);
+    // The difference takes one more bit
+    let side = Subframe::new(&side, BITS + 1);
+    let (assignment, first, second) = [
+        (0b0001, &left, &right),
+        (0b1000, &left, &side),
+        (0b1001, &side, &right),
+        (0b1010, &mid, &side),
+    ]
+    .into_iter()
+    .min_by_key(|(_, first, second)| first.cost + second.cost)
+    .unwrap();
+
+    let mut bits = BitWriter::default();
+    // Sync code, with a fixed block size
+    bits.write(0b1111_1111_1111_1000, 16);
+    // Block size at the end of the header, sample rate from STREAMINFO
+    bits.write(0b0111, 4);
+    bits.write(0b0000, 4);
+    bits.write(assignment, 4);
+    // 24 bits per sample
+    bits.write(0b110, 3);
+    bits.write(0, 1);
+    bits.write_utf8(number);
+    bits.write(left.samples.len() as u64 - 1, 16);
+    let crc = crc8(&bits.bytes);
+    bits.write(crc as u64, 8);
+    first.write(&mut bits);
+    second.write(&mut bits);
+    bits.align();
+    let crc = crc16(&bits.bytes);
+    bits.write(crc as u64, 16);
+    bits.bytes
+}
+
+/// One channel of a frame, coded the smallest way found.
+struct Subframe<'a> {
+    samples: &'a [i32],
+    bits: u32,
+    kind: Kind,
+    /// Estimated length in bits.
+    cost: u64,
+}
+
+enum Kind {
+    Constant,
+    Verbatim,
+    Fixed {
+        order: usize,
+        /// Prediction errors mapped to unsigned, 0, -1, 1, -2 and so on becoming 0, 1, 2, 3.
+        residual: Vec<u32>,
+        partition_order: u32,
+        parameters: Vec<u32>,
+    },
+}
+
+impl<'a> Subframe<'a> {
+    fn new(samples: &'a [i32], bits: u32) -> Self {
+        // Each subframe starts with a byte giving its type
+        if samples.iter().all(|sample| *sample == samples[0]) {
+            return Subframe {
+                samples,
+                bits,
+                kind: Kind::Constant,
+                cost: 8 + bits as u64,
+            };
+        }
+        let mut best = Subframe {
+            samples,
+            bits,
+            kind: Kind::Verbatim,
+            cost: 8 + bits as u64 * samples.len() as u64,
+        };
+        for order in 0..=MAX_ORDER.min(samples.len() - 1) {
+            let residual = fixed_residual(samples, order);
+            let (partition_order, parameters, residual_cost) =
+                rice_partitions(&residual, samples.len(), order);
+            // Warm-up samples, then the coding method and partition order of the residual
+            let cost = 8 + order as u64 * bits as u64 + 2 + 4 + residual_cost;
+            if cost < best.cost {
+                best.kind = Kind::Fixed {
+                    order,
+                    residual,
+                    partition_order,
+                    parameters,
+                };
+                best.cost = cost;
+            }
+        }
+        best
+    }
+
+    fn write(&self, bits: &mut BitWriter) {
+        match &self.kind {
+            Kind::Constant => {
+                bits.write(0b0000_0000, 8);
+                bits.write_signed(self.samples[0], self.bits);
+            }
+            Kind::Verbatim => {
+                bits.write(0b0000_0010, 8);
+                for sample in self.samples {
+                    bits.write_signed(*sample, self.bits);
+                }
+            }
+            Kind::Fixed {
+                order,
+                residual,
+                partition_order,
+                parameters,
+            } => {
+                bits.write(0b0001_0000 | (*order as u64) << 1, 8);
+                for sample in &self.samples[..*order] {
+                    bits.write_signed(*sample, self.bits);
+                }
+                // Rice coding with 4-bit parameters
+                bits.write(0b00, 2);
+                bits.write(*partition_order as u64, 4);
+                let size = self.samples.len() >> partition_order;
+                let mut start = 0;
+                for (i, parameter) in parameters.iter().enumerate() {
+                    // The first partition leaves out the warm-up samples
+                    let end = start + size - if i == 0 { *order } else { 0 };
+                    bits.write(*parameter as u64, 4);
+                    for value in &residual[start..end] {
+                        bits.write_rice(*value, *parameter);
+                    }
+                    start = end;
+                }
+            }
+        }
+    }
+}
+
+/// Errors of predicting each sample after the first `order` from the ones before it with a
+/// polynomial of that order, mapped to unsigned.
+fn fixed_residual(samples: &[i32], order: usize) -> Vec<u32> {
+    let sample = |i: usize| samples[i] as i64;
+    (order..samples.len())
+        .map(|i| {
+            let error = match order {
+                0 => sample(i),
+                1 => sample(i) - sample(i - 1),
+                2 => sample(i) - 2 * sample(i - 1) + sample(i - 2),
+                3 => sample(i) - 3 * sample(i - 1) + 3 * sample(i - 2) - sample(i - 3),
+                _ => {
+                    sample(i) - 4 * sample(i - 1) + 6 * sample(i - 2) - 4 * sample(i - 3)
+                        + sample(i - 4)
+                }
+            };
+            ((error << 1) ^ (error >> 63)) as u32
+        })
+        .collect()
+}
+
+/// Finds the partition order and the Rice parameter of each partition that code `residual`
+/// in the fewest bits, also returning that estimate.
+fn rice_partitions(residual: &[u32], length: usize, order: usize) -> (u32, Vec<u32>, u64) {
+    let mut best: Option<(u32, Vec<u32>, u64)> = None;
+    let mut partition_order = 0;
+    // Partitions are of equal size, and the first must have room for the warm-up samples
+    while partition_order <= MAX_PARTITION_ORDER
+        && length.is_multiple_of(1 << partition_order)
+        && length >> partition_order > order
+    {
+        let size = length >> partition_order;
+        let mut parameters = Vec::with_capacity(1 << partition_order);
+        let mut cost = 0;
+        let mut start = 0;
+        for i in 0..1 << partition_order {
+            let end = start + size - if i == 0 { order } else { 0 };
+            let sum = residual[start..end]
+                .iter()
+                .map(|value| *value as u64)
+                .sum::<u64>();
+            let (parameter, partition_cost) = (0..=MAX_RICE_PARAMETER)
+                .map(|parameter| {
+                    // Each value takes a stop bit and the parameter's low bits after its
+                    // high bits in unary
+                    let count = (end - start) as u64;
+                    (
+                        parameter,
+                        count * (parameter as u64 + 1) + (sum >> parameter),
+                    )
+                })
+                .min_by_key(|(_, cost)| *cost)
+                .unwrap();
+            parameters.push(parameter);
+            cost += 4 + partition_cost;
+            start = end;
+        }
+        if best
+            .as_ref()
+            .is_none_or(|(_, _, best_cost)| cost < *best_cost)
+        {
+            best = Some((partition_order, parameters, cost));
+        }
+        partition_order += 1;
+    }
+    best.unwrap()
+}
+
+/// Packs values of any number of bits into bytes, most significant bit first.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    /// Bits that don't make up a whole byte yet, in the low end.
+    pending: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    /// Appends the low `count` bits of `value`, up to 32.
+    fn write(&mut self, value: u64, count: u32) {
+        self.pending = (self.pending << count) | (value & ((1 << count) - 1));
+        self.count += count;
+        while self.count >= 8 {
+            self.count -= 8;
+            self.bytes.push((self.pending >> self.count) as u8);
+        }
+    }
+
+    fn write_signed(&mut self, value: i32, count: u32) {
+        self.write(value as u32 as u64, count);
+    }
+
+    /// Appends `value` with its bits above `parameter` in unary, as zeros ended by a one.
+    fn write_rice(&mut self, value: u32, parameter: u32) {
+        let high = value >> parameter;
+        for _ in 0..high / 32 {
+            self.write(0, 32);
+        }
+        self.write(1, high % 32 + 1);
+        self.write(value as u64, parameter);
+    }
+
+    /// Appends a number in the variable-length coding of UTF-8, as frame headers store it.
+    fn write_utf8(&mut self, value: u32) {
+        let value = value as u64;
+        if value < 0x80 {
+            self.write(value, 8);
+            return;
+        }
+        // The first byte has 7 - length bits of the value, the others 6
+        let mut length = 2;
+        while value >= 1 << (5 * length + 1) {
+            length += 1;
+        }
+        self.write(
+            ((0xff00 >> length) & 0xff) | (value >> (6 * (length - 1))),
+            8,
+        );
+        for i in (0..length - 1).rev() {
+            self.write(0x80 | ((value >> (6 * i)) & 0x3f), 8);
+        }
+    }
+
+    /// Pads with zeros to a whole byte.
+    fn align(&mut self) {
+        if self.count > 0 {
+            self.write(0, 8 - self.count);
+        }
+    }
+}
+
+/// CRC-8 with the polynomial x^8 + x^2 + x + 1, which protects frame headers.
+fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |crc, byte| {
+        (0..8).fold(crc ^ byte, |crc, _| {
+            if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// CRC-16 with the polynomial x^16 + x^15 + x^2 + 1, which protects whole frames.
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0, |crc, byte| {
+        (0..8).fold(crc ^ ((*byte as u16) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc_check_values() {
+        // Check values of CRC-8 and CRC-16/UMTS for "123456789"
+        assert_eq!(crc8(b"123456789"), 0xf4);
+        assert_eq!(crc16(b"123456789"), 0xfee8);
+    }
+
+    #[test]
+    fn utf8_boundaries() {
+        for value in [0, 0x7f, 0x80, 0x7ff, 0x800, 0xffff, 0x10000, 0x10ffff] {
+            let mut bits = BitWriter::default();
+            bits.write_utf8(value);
+            let mut expected = [0; 4];
+            let expected = char::from_u32(value).unwrap().encode_utf8(&mut expected);
+            assert_eq!(bits.bytes, expected.as_bytes(), "{:#x}", value);
+        }
+        // Frame numbers go past the code points of UTF-8, up to 31 bits in six bytes
+        let mut bits = BitWriter::default();
+        bits.write_utf8(0x7fff_ffff);
+        assert_eq!(bits.bytes, [0xfd, 0xbf, 0xbf, 0xbf, 0xbf, 0xbf]);
+    }
+
+    #[test]
+    fn frame_checksums() {
+        let left = (0..BLOCK_SIZE as i32).map(|i| i * 100).collect();
+        let right = vec![-5; BLOCK_SIZE];
+        let bytes = encode_frame(&[left, right], 300);
+        assert_eq!(bytes[..2], [0xff, 0xf8]);
+        // A CRC over the data followed by the CRC itself comes out zero. The header is the
+        // sync code, two bytes of settings, the frame number in two bytes and the block size.
+        assert_eq!(crc8(&bytes[..9]), 0);
+        assert_eq!(crc16(&bytes), 0);
+    }
+}
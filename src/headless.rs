@@ -0,0 +1,24 @@
+use crate::{Message, boot, update};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// How often the engine is looked after, as the GUI does every frame.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Runs the engine without the GUI until Rake is interrupted or terminated, e.g. on a
+/// pedalboard without a display server. Blocks until then.
+pub fn run() {
+    let stop = Arc::new(AtomicBool::new(false));
+    let handler_stop = stop.clone();
+    if let Err(e) = ctrlc::set_handler(move || handler_stop.store(true, Ordering::Relaxed)) {
+        eprintln!("Error handling signals: {}", e);
+    }
+    let mut state = boot();
+    while !stop.load(Ordering::Relaxed) {
+        // Polls levels, faults, program changes and the JACK server as a frame would
+        let _ = update(&mut state, Message::Frame);
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    let _ = update(&mut state, Message::Exit);
+}
@@ -0,0 +1,317 @@
+use rack::prelude::*;
+use roxmltree::{Document, Node, ParsingOptions};
+use std::path::PathBuf;
+
+/// A plugin of a chain saved by another host, to be matched with a plugin available here.
+pub struct ForeignPlugin {
+    /// Names the plugin may be listed under, most likely first.
+    pub names: Vec<String>,
+    /// Plugin file, if the host saved it.
+    pub path: Option<PathBuf>,
+    /// Parameter values by name or symbol.
+    pub params: Vec<(String, f32)>,
+    pub bypass: bool,
+    pub mix: f32,
+    /// Linear output gain.
+    pub volume: f32,
+}
+
+impl ForeignPlugin {
+    fn new(names: Vec<String>) -> Self {
+        ForeignPlugin {
+            names,
+            path: None,
+            params: Vec::new(),
+            bypass: false,
+            mix: 1.0,
+            volume: 1.0,
+        }
+    }
+
+    /// Name shown when no plugin here matches.
+    pub fn name(&self) -> &str {
+        self.names.first().map_or("Unnamed plugin", String::as_str)
+    }
+}
+
+/// Whether two names are the same apart from case, spaces and punctuation, as plugin names
+/// and parameter symbols differ between formats.
+pub fn same_name(a: &str, b: &str) -> bool {
+    let key = |name: &str| -> String {
+        name.chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect()
+    };
+    key(a) == key(b)
+}
+
+/// Last part of an LV2 URI, which is usually the plugin's name.
+fn uri_name(uri: &str) -> String {
+    uri.rsplit(['/', '#', ':'])
+        .find(|part| !part.is_empty())
+        .unwrap_or(uri)
+        .to_string()
+}
+
+fn child_text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.children()
+        .find(|child| child.has_tag_name(name))
+        .and_then(|child| child.text())
+        .map(str::trim)
+}
+
+/// Reads the plugins of a Carla project in the order Carla's rack processes them.
+pub fn carla(content: &str) -> Result<Vec<ForeignPlugin>> {
+    // Carla writes a DOCTYPE, which roxmltree rejects unless asked not to
+    let options = ParsingOptions {
+        allow_dtd: true,
+        ..ParsingOptions::default()
+    };
+    let document = Document::parse_with_options(content, options)
+        .map_err(|e| rack::Error::Other(format!("Incorrect Carla project: {}", e)))?;
+    let project = document.root_element();
+    if !project.has_tag_name("CARLA-PROJECT") {
+        return Err(rack::Error::Other("Not a Carla project".to_string()));
+    }
+    Ok(project
+        .children()
+        .filter(|node| node.has_tag_name("Plugin"))
+        .map(carla_plugin)
+        .collect())
+}
+
+fn carla_plugin(node: Node) -> ForeignPlugin {
+    let mut plugin = ForeignPlugin::new(Vec::new());
+    if let Some(info) = node.children().find(|child| child.has_tag_name("Info")) {
+        for tag in ["Name", "Label"] {
+            if let Some(name) = child_text(info, tag) {
+                plugin.names.push(name.to_string());
+            }
+        }
+        if let Some(uri) = child_text(info, "URI") {
+            plugin.names.push(uri_name(uri));
+        }
+        if let Some(binary) = child_text(info, "Binary") {
+            let binary = PathBuf::from(binary);
+            if let Some(stem) = binary.file_stem() {
+                plugin.names.push(stem.to_string_lossy().into_owned());
+            }
+            plugin.path = Some(binary);
+        }
+    }
+    if let Some(data) = node.children().find(|child| child.has_tag_name("Data")) {
+        plugin.bypass = child_text(data, "Active") == Some("No");
+        if let Some(mix) = child_text(data, "DryWet").and_then(|mix| mix.parse().ok()) {
+            plugin.mix = mix;
+        }
+        if let Some(volume) = child_text(data, "Volume").and_then(|volume| volume.parse().ok()) {
+            plugin.volume = volume;
+        }
+        for param in data
+            .children()
+            .filter(|child| child.has_tag_name("Parameter"))
+        {
+            let Some(value) = child_text(param, "Value").and_then(|value| value.parse().ok())
+            else {
+                continue;
+            };
+            for tag in ["Name", "Symbol"] {
+                if let Some(name) = child_text(param, tag) {
+                    plugin.params.push((name.to_string(), value));
+                }
+            }
+        }
+    }
+    plugin
+}
+
+/// Reads the plugins added by a mod-host command script. They're ordered by following the
+/// connections from the capture ports, followed by any that aren't connected that way in the
+/// order they were added.
+pub fn mod_host(content: &str) -> Vec<ForeignPlugin> {
+    // Plugins by the name of their instance's ports
+    let mut instances: Vec<(String, ForeignPlugin)> = Vec::new();
+    // Connections between the clients of the ports
+    let mut connections: Vec<(String, String)> = Vec::new();
+    let client = |port: &str| port.split(':').next().unwrap_or(port).to_string();
+    for line in content.lines() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words[..] {
+            ["add", uri, instance] => {
+                let client = format!("effect_{}", instance);
+                instances.retain(|(other, _)| *other != client);
+                instances.push((client, ForeignPlugin::new(vec![uri_name(uri)])));
+            }
+            ["remove", instance] => {
+                instances.retain(|(client, _)| *client != format!("effect_{}", instance));
+            }
+            ["param_set", instance, symbol, value] => {
+                if let Some((_, plugin)) = instances
+                    .iter_mut()
+                    .find(|(client, _)| *client == format!("effect_{}", instance))
+                    && let Ok(value) = value.parse()
+                {
+                    plugin.params.push((symbol.to_string(), value));
+                }
+            }
+            ["bypass", instance, value] => {
+                if let Some((_, plugin)) = instances
+                    .iter_mut()
+                    .find(|(client, _)| *client == format!("effect_{}", instance))
+                {
+                    plugin.bypass = value != "0";
+                }
+            }
+            ["connect", source, destination] => {
+                connections.push((client(source), client(destination)));
+            }
+            _ => {}
+        }
+    }
+
+    let mut order: Vec<&str> = Vec::new();
+    let mut previous = "system";
+    while let Some(next) = connections
+        .iter()
+        .filter(|(source, _)| source == previous)
+        .map(|(_, destination)| destination.as_str())
+        .find(|destination| *destination != "system" && !order.contains(destination))
+    {
+        order.push(next);
+        previous = next;
+    }
+    instances.sort_by_key(|(client, _)| {
+        order
+            .iter()
+            .position(|connected| connected == client)
+            .unwrap_or(usize::MAX)
+    });
+    instances.into_iter().map(|(_, plugin)| plugin).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn carla_project() {
+        let content = r#"<?xml version='1.0' encoding='UTF-8'?>
+<!DOCTYPE CARLA-PROJECT>
+<CARLA-PROJECT VERSION='2.5'>
+ <EngineSettings>
+  <ProcessMode>Continuous Rack</ProcessMode>
+ </EngineSettings>
+ <Plugin>
+  <Info>
+   <Type>LV2</Type>
+   <Name>Dragonfly Room Reverb</Name>
+   <URI>urn:dragonfly:room</URI>
+  </Info>
+  <Data>
+   <Active>No</Active>
+   <DryWet>0.5</DryWet>
+   <Volume>0.8</Volume>
+   <Parameter>
+    <Index>0</Index>
+    <Name>Dry Level</Name>
+    <Symbol>dry_level</Symbol>
+    <Value>40</Value>
+   </Parameter>
+   <Parameter>
+    <Index>1</Index>
+    <Name>Size</Name>
+   </Parameter>
+  </Data>
+ </Plugin>
+ <Plugin>
+  <Info>
+   <Type>VST3</Type>
+   <Name>Compressor</Name>
+   <Binary>/usr/lib/vst3/Compressor.vst3</Binary>
+  </Info>
+  <Data>
+   <Active>Yes</Active>
+  </Data>
+ </Plugin>
+</CARLA-PROJECT>"#;
+        let plugins = carla(content).unwrap();
+        assert_eq!(plugins.len(), 2);
+        assert_eq!(plugins[0].names, ["Dragonfly Room Reverb", "room"]);
+        assert!(plugins[0].bypass);
+        assert_eq!(plugins[0].mix, 0.5);
+        assert_eq!(plugins[0].volume, 0.8);
+        assert_eq!(
+            plugins[0].params,
+            [
+                ("Dry Level".to_string(), 40.0),
+                ("dry_level".to_string(), 40.0)
+            ]
+        );
+        assert_eq!(plugins[1].names, ["Compressor", "Compressor"]);
+        assert_eq!(
+            plugins[1].path.as_deref(),
+            Some(std::path::Path::new("/usr/lib/vst3/Compressor.vst3"))
+        );
+        assert!(!plugins[1].bypass);
+        assert_eq!(plugins[1].mix, 1.0);
+    }
+
+    #[test]
+    fn not_a_carla_project() {
+        assert!(carla("<ardour/>").is_err());
+        assert!(carla("<CARLA-PROJECT>").is_err());
+    }
+
+    #[test]
+    fn mod_host_order() {
+        let content = "\
+add http://guitarix.sourceforge.net/plugins/gx_chorus#_chorus 2
+add http://calf.sourceforge.net/plugins/Reverb 1
+add urn:example:unused 3
+add http://drobilla.net/plugins/mda/Delay 0
+remove 3
+param_set 1 room_size 2.5
+param_set 1 dry oops
+param_set 5 wet 1
+bypass 2 1
+bypass 1 0
+connect system:capture_1 effect_0:in
+connect effect_0:out effect_1:in
+connect effect_1:out_l effect_2:in
+connect effect_2:out system:playback_1
+";
+        let plugins = mod_host(content);
+        let names: Vec<&str> = plugins.iter().map(ForeignPlugin::name).collect();
+        assert_eq!(names, ["Delay", "Reverb", "_chorus"]);
+        assert_eq!(plugins[1].params, [("room_size".to_string(), 2.5)]);
+        assert!(!plugins[1].bypass);
+        assert!(plugins[2].bypass);
+    }
+
+    #[test]
+    fn mod_host_unconnected_last() {
+        let content = "\
+add urn:a 0
+add urn:b 1
+connect system:capture_1 effect_1:in
+connect effect_1:out system:playback_1
+";
+        let names: Vec<String> = mod_host(content)
+            .into_iter()
+            .map(|plugin| plugin.names[0].clone())
+            .collect();
+        assert_eq!(names, ["b", "a"]);
+    }
+
+    #[test]
+    fn names_ignore_case_and_punctuation() {
+        assert!(same_name("Dry Level", "dry_level"));
+        assert!(!same_name("Dry", "Wet"));
+        assert_eq!(
+            uri_name("http://calf.sourceforge.net/plugins/Reverb/"),
+            "Reverb"
+        );
+        assert_eq!(uri_name("urn:dragonfly:room"), "room");
+    }
+}
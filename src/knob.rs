@@ -0,0 +1,248 @@
+use iced::advanced::layout::{self, Layout};
+use iced::advanced::widget::{Tree, Widget, tree};
+use iced::advanced::{Clipboard, Shell, renderer};
+use iced::keyboard::{self, key::Named};
+use iced::{Border, Element, Event, Length, Point, Rectangle, Size, Theme, mouse};
+
+/// Diameter of a knob.
+const SIZE: f32 = 40.0;
+
+/// Pixels of vertical drag that turn a knob through its whole range.
+const DRAG_PIXELS: f32 = 200.0;
+
+/// Share of the drag a knob follows while Shift is held.
+const FINE_SCALE: f32 = 0.1;
+
+/// Step of the mouse wheel and the arrow keys, and with Shift held.
+const STEP: f32 = 0.01;
+const FINE_STEP: f32 = 0.001;
+
+/// Dots around a knob showing its value, from the lower left to the lower right.
+const DOTS: usize = 21;
+
+/// Angle of the lowest and highest values either side of the top, in degrees.
+const SWEEP: f32 = 135.0;
+
+/// Rotary control of a normalized value from 0 to 1, turned by dragging up and down, with the
+/// mouse wheel or with the arrow keys while hovered. Takes much less room than a slider.
+pub struct Knob<'a, Message> {
+    value: f32,
+    on_change: Box<dyn Fn(f32) -> Message + 'a>,
+}
+
+/// Creates a knob turned to `value`, calling `on_change` with each new value.
+pub fn knob<'a, Message>(value: f32, on_change: impl Fn(f32) -> Message + 'a) -> Knob<'a, Message> {
+    Knob {
+        value: value.clamp(0.0, 1.0),
+        on_change: Box::new(on_change),
+    }
+}
+
+#[derive(Default)]
+struct State {
+    /// Pointer height and value the drag is measured from, moved when Shift is pressed or
+    /// released so the knob doesn't jump.
+    drag: Option<(f32, f32)>,
+    last_y: f32,
+    modifiers: keyboard::Modifiers,
+}
+
+impl<Message: Clone, Renderer: renderer::Renderer> Widget<Message, Theme, Renderer>
+    for Knob<'_, Message>
+{
+    fn tag(&self) -> tree::Tag {
+        tree::Tag::of::<State>()
+    }
+
+    fn state(&self) -> tree::State {
+        tree::State::new(State::default())
+    }
+
+    fn size(&self) -> Size<Length> {
+        Size::new(Length::Fixed(SIZE), Length::Fixed(SIZE))
+    }
+
+    fn layout(
+        &mut self,
+        _tree: &mut Tree,
+        _renderer: &Renderer,
+        limits: &layout::Limits,
+    ) -> layout::Node {
+        layout::atomic(limits, SIZE, SIZE)
+    }
+
+    fn update(
+        &mut self,
+        tree: &mut Tree,
+        event: &Event,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        _renderer: &Renderer,
+        _clipboard: &mut dyn Clipboard,
+        shell: &mut Shell<'_, Message>,
+        _viewport: &Rectangle,
+    ) {
+        let state = tree.state.downcast_mut::<State>();
+        let value = self.value;
+        let mut change = |new_value: f32| {
+            let new_value = new_value.clamp(0.0, 1.0);
+            if new_value != self.value {
+                shell.publish((self.on_change)(new_value));
+                self.value = new_value;
+            }
+        };
+        let step = if state.modifiers.shift() {
+            FINE_STEP
+        } else {
+            STEP
+        };
+        match event {
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+                if let Some(position) = cursor.position_over(layout.bounds()) {
+                    state.drag = Some((position.y, value));
+                    state.last_y = position.y;
+                    shell.capture_event();
+                }
+            }
+            Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) => {
+                state.drag = None;
+            }
+            Event::Mouse(mouse::Event::CursorMoved { position }) => {
+                if let Some((start_y, start_value)) = state.drag {
+                    let scale = if state.modifiers.shift() {
+                        FINE_SCALE
+                    } else {
+                        1.0
+                    };
+                    // Dragging up turns the knob up
+                    change(start_value + (start_y - position.y) / DRAG_PIXELS * scale);
+                    state.last_y = position.y;
+                    shell.capture_event();
+                }
+            }
+            Event::Mouse(mouse::Event::WheelScrolled { delta })
+                if cursor.is_over(layout.bounds()) =>
+            {
+                let y = match delta {
+                    mouse::ScrollDelta::Lines { y, .. } | mouse::ScrollDelta::Pixels { y, .. } => {
+                        *y
+                    }
+                };
+                if y != 0.0 {
+                    change(value + step * y.signum());
+                }
+                shell.capture_event();
+            }
+            Event::Keyboard(keyboard::Event::KeyPressed { key, .. })
+                if cursor.is_over(layout.bounds()) =>
+            {
+                match key {
+                    keyboard::Key::Named(Named::ArrowUp | Named::ArrowRight) => {
+                        change(value + step);
+                        shell.capture_event();
+                    }
+                    keyboard::Key::Named(Named::ArrowDown | Named::ArrowLeft) => {
+                        change(value - step);
+                        shell.capture_event();
+                    }
+                    _ => {}
+                }
+            }
+            Event::Keyboard(keyboard::Event::ModifiersChanged(modifiers)) => {
+                state.modifiers = *modifiers;
+                if state.drag.is_some() {
+                    state.drag = Some((state.last_y, value));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn draw(
+        &self,
+        _tree: &Tree,
+        renderer: &mut Renderer,
+        theme: &Theme,
+        _style: &renderer::Style,
+        layout: Layout<'_>,
+        _cursor: mouse::Cursor,
+        _viewport: &Rectangle,
+    ) {
+        let palette = theme.extended_palette();
+        let bounds = layout.bounds();
+        let center = bounds.center();
+        let radius = bounds.width.min(bounds.height) / 2.0;
+        let dot = |renderer: &mut Renderer, angle: f32, distance: f32, size: f32, color| {
+            let (sin, cos) = angle.to_radians().sin_cos();
+            let at = Point::new(center.x + distance * sin, center.y - distance * cos);
+            renderer.fill_quad(
+                renderer::Quad {
+                    bounds: Rectangle::new(
+                        Point::new(at.x - size / 2.0, at.y - size / 2.0),
+                        Size::new(size, size),
+                    ),
+                    border: Border::default().rounded(size / 2.0),
+                    ..renderer::Quad::default()
+                },
+                color,
+            );
+        };
+
+        // Body
+        dot(
+            renderer,
+            0.0,
+            0.0,
+            (radius - 5.0) * 2.0,
+            palette.background.strong.color,
+        );
+        // Scale, lit up to the value
+        for i in 0..DOTS {
+            let position = i as f32 / (DOTS - 1) as f32;
+            let color = if position <= self.value {
+                palette.primary.base.color
+            } else {
+                palette.background.weak.color
+            };
+            dot(renderer, angle(position), radius - 1.5, 3.0, color);
+        }
+        // Pointer
+        dot(
+            renderer,
+            angle(self.value),
+            radius - 11.0,
+            5.0,
+            palette.background.base.text,
+        );
+    }
+
+    fn mouse_interaction(
+        &self,
+        tree: &Tree,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        _viewport: &Rectangle,
+        _renderer: &Renderer,
+    ) -> mouse::Interaction {
+        if tree.state.downcast_ref::<State>().drag.is_some() {
+            mouse::Interaction::Grabbing
+        } else if cursor.is_over(layout.bounds()) {
+            mouse::Interaction::ResizingVertically
+        } else {
+            mouse::Interaction::default()
+        }
+    }
+}
+
+/// Angle of a value from the top, clockwise, in degrees.
+fn angle(value: f32) -> f32 {
+    -SWEEP + value * SWEEP * 2.0
+}
+
+impl<'a, Message: Clone + 'a, Renderer: renderer::Renderer + 'a> From<Knob<'a, Message>>
+    for Element<'a, Message, Theme, Renderer>
+{
+    fn from(knob: Knob<'a, Message>) -> Self {
+        Element::new(knob)
+    }
+}
@@ -0,0 +1,291 @@
+use crate::log;
+use crate::tempo::Tempo;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::{Ipv4Addr, SocketAddrV4, UdpSocket};
+use std::time::{Duration, Instant};
+
+/// Multicast group and port of Ableton Link's peer discovery.
+const GROUP: Ipv4Addr = Ipv4Addr::new(224, 76, 78, 75);
+const PORT: u16 = 20808;
+
+/// Start of every discovery message, with the protocol version.
+const PROTOCOL_HEADER: &[u8; 8] = b"_asdp_v\x01";
+
+const BYE_BYE: u8 = 3;
+
+const TIMELINE_KEY: u32 = u32::from_be_bytes(*b"tmln");
+const SESSION_KEY: u32 = u32::from_be_bytes(*b"sess");
+
+type NodeId = [u8; 8];
+
+/// Tempo and beat position shared by a session.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Timeline {
+    micros_per_beat: i64,
+    /// Beat at the time origin, in millionths of a beat.
+    beat_origin: i64,
+}
+
+impl Timeline {
+    fn bpm(&self) -> f64 {
+        60_000_000.0 / self.micros_per_beat as f64
+    }
+}
+
+struct Peer {
+    session: NodeId,
+    expires: Instant,
+}
+
+/// Listener for the announcements of Ableton Link peers, following the tempo of their session.
+///
+/// This isn't an implementation of Link: Rake never sends anything, so it doesn't show up as a
+/// peer, can't change the session's tempo and doesn't align the beat phase.
+pub struct Link {
+    socket: UdpSocket,
+    /// Session followed and its latest timeline, while any peer is heard.
+    session: Option<(NodeId, Timeline)>,
+    peers: HashMap<NodeId, Peer>,
+    /// Session tempo set last, so one set in Rake, e.g. by tapping, stays until it changes.
+    applied_bpm: Option<f64>,
+}
+
+impl Link {
+    pub fn listen() -> std::io::Result<Self> {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        // Other Link apps on this machine listen on the same port
+        socket.set_reuse_address(true)?;
+        socket.bind(&SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, PORT).into())?;
+        socket.join_multicast_v4(&GROUP, &Ipv4Addr::UNSPECIFIED)?;
+        socket.set_nonblocking(true)?;
+        Ok(Link {
+            socket: socket.into(),
+            session: None,
+            peers: HashMap::new(),
+            applied_bpm: None,
+        })
+    }
+
+    /// Peers in the session followed.
+    pub fn peers(&self) -> usize {
+        self.session.map_or(0, |(session, _)| {
+            self.peers
+                .values()
+                .filter(|peer| peer.session == session)
+                .count()
+        })
+    }
+
+    /// Reads the messages of peers and sets the tempo to their session's when it changes.
+    /// Called every frame.
+    pub fn poll(&mut self, tempo: &Tempo) {
+        let mut buffer = [0; 512];
+        loop {
+            let size = match self.socket.recv(&mut buffer) {
+                Ok(size) => size,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    log::error!("Error receiving Link message: {}", e);
+                    break;
+                }
+            };
+            self.receive(&buffer[..size]);
+        }
+
+        let now = Instant::now();
+        self.peers.retain(|_, peer| peer.expires > now);
+        if self.peers() == 0 {
+            self.session = None;
+        }
+        let bpm = self.session.map(|(_, timeline)| timeline.bpm());
+        if let Some(bpm) = bpm
+            && self.applied_bpm != Some(bpm)
+        {
+            tempo.set_bpm(bpm);
+        }
+        self.applied_bpm = bpm;
+    }
+
+    fn receive(&mut self, message: &[u8]) {
+        let Some((kind, ttl, node, payload)) = decode(message) else {
+            return;
+        };
+        if kind == BYE_BYE {
+            self.peers.remove(&node);
+            return;
+        }
+        let (Some(session), Some(timeline)) = (payload.session, payload.timeline) else {
+            return;
+        };
+        self.peers.insert(
+            node,
+            Peer {
+                session,
+                expires: Instant::now() + Duration::from_secs(ttl as u64),
+            },
+        );
+        // Peers settle on the session that sorts first, and within it on the timeline with the
+        // latest beat origin
+        let follow = match self.session {
+            None => true,
+            Some((current, _)) if session < current => true,
+            Some((current, current_timeline)) => {
+                session == current && timeline.beat_origin > current_timeline.beat_origin
+            }
+        };
+        if follow {
+            self.session = Some((session, timeline));
+        }
+    }
+}
+
+/// Entries of a message that Rake reads.
+#[derive(Default)]
+struct Payload {
+    timeline: Option<Timeline>,
+    session: Option<NodeId>,
+}
+
+/// Reads a message's type, time to live, sender and payload.
+fn decode(message: &[u8]) -> Option<(u8, u8, NodeId, Payload)> {
+    let message = message.strip_prefix(PROTOCOL_HEADER)?;
+    let (&[kind, ttl, _, _], rest) = message.split_first_chunk::<4>()?;
+    let (node, mut entries) = rest.split_first_chunk::<8>()?;
+    let mut payload = Payload::default();
+    while !entries.is_empty() {
+        let (key, rest) = entries.split_first_chunk::<4>()?;
+        let (size, rest) = rest.split_first_chunk::<4>()?;
+        let size = u32::from_be_bytes(*size) as usize;
+        let value = rest.get(..size)?;
+        let word = |i: usize| -> Option<i64> {
+            Some(i64::from_be_bytes(
+                value.get(i * 8..(i + 1) * 8)?.try_into().ok()?,
+            ))
+        };
+        // Entries Rake doesn't read, like the start and stop state, are skipped
+        match u32::from_be_bytes(*key) {
+            TIMELINE_KEY => {
+                payload.timeline = Some(Timeline {
+                    micros_per_beat: word(0).filter(|micros| *micros > 0)?,
+                    beat_origin: word(1)?,
+                })
+            }
+            SESSION_KEY => payload.session = Some(value.try_into().ok()?),
+            _ => {}
+        }
+        entries = &rest[size..];
+    }
+    Some((kind, ttl, *node, payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALIVE: u8 = 1;
+
+    fn message(kind: u8, node: u8, entries: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+        let mut message = PROTOCOL_HEADER.to_vec();
+        message.extend([kind, 5, 0, 0]);
+        message.extend([node; 8]);
+        for (key, value) in entries {
+            message.extend(*key);
+            message.extend((value.len() as u32).to_be_bytes());
+            message.extend(value);
+        }
+        message
+    }
+
+    fn timeline(bpm: f64, beat_origin: i64) -> (&'static [u8; 4], Vec<u8>) {
+        let micros_per_beat = (60_000_000.0 / bpm) as i64;
+        let mut value = micros_per_beat.to_be_bytes().to_vec();
+        value.extend(beat_origin.to_be_bytes());
+        // Time origin
+        value.extend(0_i64.to_be_bytes());
+        (b"tmln", value)
+    }
+
+    fn session(id: u8) -> (&'static [u8; 4], Vec<u8>) {
+        (b"sess", vec![id; 8])
+    }
+
+    fn link() -> Link {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        socket.set_nonblocking(true).unwrap();
+        Link {
+            socket,
+            session: None,
+            peers: HashMap::new(),
+            applied_bpm: None,
+        }
+    }
+
+    #[test]
+    fn decode_entries() {
+        let start_stop = (b"stst", vec![1; 17]);
+        let (kind, ttl, node, payload) = decode(&message(
+            ALIVE,
+            7,
+            &[start_stop, timeline(120.0, 3), session(2)],
+        ))
+        .unwrap();
+        assert_eq!((kind, ttl, node), (ALIVE, 5, [7; 8]));
+        assert_eq!(
+            payload.timeline,
+            Some(Timeline {
+                micros_per_beat: 500_000,
+                beat_origin: 3
+            })
+        );
+        assert_eq!(payload.timeline.unwrap().bpm(), 120.0);
+        assert_eq!(payload.session, Some([2; 8]));
+    }
+
+    #[test]
+    fn decode_incorrect() {
+        let correct = message(ALIVE, 1, &[timeline(120.0, 0), session(1)]);
+        // Cut between entries, a message is still whole
+        for length in (0..correct.len()).filter(|length| *length != 20 && *length != 52) {
+            assert!(decode(&correct[..length]).is_none(), "{} bytes", length);
+        }
+        let mut version = correct.clone();
+        version[7] = 2;
+        assert!(decode(&version).is_none());
+        assert!(decode(&message(ALIVE, 1, &[timeline(-120.0, 0)])).is_none());
+        assert!(decode(&message(ALIVE, 1, &[(b"sess", vec![1; 4])])).is_none());
+    }
+
+    #[test]
+    fn follows_first_session() {
+        let mut link = link();
+        link.receive(&message(ALIVE, 1, &[timeline(120.0, 0), session(5)]));
+        link.receive(&message(ALIVE, 2, &[timeline(90.0, 0), session(3)]));
+        link.receive(&message(ALIVE, 3, &[timeline(140.0, 0), session(4)]));
+        assert_eq!(link.session.unwrap().0, [3; 8]);
+        assert_eq!(link.peers(), 1);
+        // A later beat origin within the session is followed, an earlier one isn't
+        link.receive(&message(ALIVE, 4, &[timeline(100.0, 10), session(3)]));
+        link.receive(&message(ALIVE, 2, &[timeline(80.0, 5), session(3)]));
+        assert_eq!(link.session.unwrap().1.bpm(), 100.0);
+        assert_eq!(link.peers(), 2);
+        link.receive(&message(BYE_BYE, 4, &[]));
+        assert_eq!(link.peers(), 1);
+    }
+
+    #[test]
+    fn tempo_set_in_rake_stays() {
+        let mut link = link();
+        let tempo = Tempo::default();
+        link.receive(&message(ALIVE, 1, &[timeline(120.0, 0), session(1)]));
+        link.poll(&tempo);
+        assert_eq!(tempo.bpm(), 120.0);
+        tempo.set_bpm(90.0);
+        link.poll(&tempo);
+        assert_eq!(tempo.bpm(), 90.0);
+        link.receive(&message(ALIVE, 1, &[timeline(100.0, 1), session(1)]));
+        link.poll(&tempo);
+        assert_eq!(tempo.bpm(), 100.0);
+    }
+}
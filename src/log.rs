@@ -0,0 +1,113 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Errors reported since the GUI last took them, from any thread but the process thread.
+static PENDING: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Errors kept waiting for the GUI, so they don't pile up while it isn't running.
+const MAX_PENDING: usize = 100;
+
+/// Errors kept in the log, oldest dropped first.
+const MAX_ENTRIES: usize = 500;
+
+/// Time an error is shown as a notification.
+pub const TOAST_DURATION: Duration = Duration::from_secs(6);
+
+/// Notifications shown at once, newest first.
+pub const MAX_TOASTS: usize = 3;
+
+/// Prints an error like `eprintln!` and keeps it for the GUI's notifications and log. Formats
+/// and locks, so the process thread sends a `ProcessError` instead.
+macro_rules! error {
+    ($($arg:tt)*) => {
+        $crate::log::report(format!($($arg)*))
+    };
+}
+pub(crate) use error;
+
+/// Prints an error and keeps it for the GUI.
+pub fn report(message: String) {
+    eprintln!("{}", message);
+    if let Ok(mut pending) = PENDING.lock()
+        && pending.len() < MAX_PENDING
+    {
+        pending.push(message);
+    }
+}
+
+pub struct Entry {
+    pub id: u64,
+    pub message: String,
+    /// Times the error was reported in a row.
+    pub count: usize,
+    /// When it was last reported.
+    pub time: Instant,
+    pub dismissed: bool,
+}
+
+/// Errors shown in the GUI, as notifications when they come in and in a log to look back on.
+#[derive(Default)]
+pub struct Log {
+    entries: VecDeque<Entry>,
+    next_id: u64,
+    /// Errors reported since the log was last looked at.
+    pub unread: usize,
+}
+
+impl Log {
+    /// Takes the errors reported since the last call. An error reported again right after
+    /// itself, such as one every cycle, is counted rather than repeated.
+    pub fn receive(&mut self) {
+        let pending = match PENDING.lock() {
+            Ok(mut pending) => std::mem::take(&mut *pending),
+            Err(poisoned) => std::mem::take(&mut *poisoned.into_inner()),
+        };
+        for message in pending {
+            self.unread += 1;
+            if let Some(last) = self.entries.back_mut()
+                && last.message == message
+            {
+                last.count += 1;
+                last.time = Instant::now();
+                last.dismissed = false;
+                continue;
+            }
+            self.entries.push_back(Entry {
+                id: self.next_id,
+                message,
+                count: 1,
+                time: Instant::now(),
+                dismissed: false,
+            });
+            self.next_id += 1;
+            if self.entries.len() > MAX_ENTRIES {
+                self.entries.pop_front();
+            }
+        }
+    }
+
+    /// Every error kept, newest first.
+    pub fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter().rev()
+    }
+
+    /// Errors still shown as notifications, newest first.
+    pub fn toasts(&self) -> impl Iterator<Item = &Entry> {
+        self.entries()
+            .take_while(|entry| entry.time.elapsed() < TOAST_DURATION)
+            .filter(|entry| !entry.dismissed)
+            .take(MAX_TOASTS)
+    }
+
+    pub fn dismiss(&mut self, id: u64) {
+        if let Some(entry) = self.entries.iter_mut().find(|entry| entry.id == id) {
+            entry.dismissed = true;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.unread = 0;
+    }
+}
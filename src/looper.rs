@@ -0,0 +1,309 @@
+use crate::midi::ControlChange;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+/// Loop memory of a new looper.
+pub const LOOP_SECONDS: f32 = 60.0;
+
+/// Samples per channel copied into the undo layer each cycle, on top of the cycle itself.
+/// Spreads the copy over many cycles instead of copying the whole loop at once.
+const UNDO_CHUNK: usize = 16384;
+
+/// Controllers that trigger looper actions on any channel when sent with a value of 64 or more.
+pub const CONTROLLERS: [(u8, LooperAction); 6] = [
+    (80, LooperAction::Record),
+    (81, LooperAction::Overdub),
+    (82, LooperAction::Play),
+    (83, LooperAction::Stop),
+    (84, LooperAction::Undo),
+    (85, LooperAction::Clear),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LooperState {
+    Empty,
+    Recording,
+    Playing,
+    Overdubbing,
+    Stopped,
+}
+
+impl fmt::Display for LooperState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match self {
+            LooperState::Empty => "Empty",
+            LooperState::Recording => "Recording",
+            LooperState::Playing => "Playing",
+            LooperState::Overdubbing => "Overdubbing",
+            LooperState::Stopped => "Stopped",
+        };
+        write!(f, "{}", state)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LooperAction {
+    /// Starts a new loop, or closes the one being recorded and plays it.
+    Record,
+    /// Toggles layering the input onto the loop.
+    Overdub,
+    Play,
+    Stop,
+    /// Swaps the loop with how it was before the last overdub, so a second undo redoes it.
+    Undo,
+    Clear,
+}
+
+impl LooperAction {
+    pub const ALL: [LooperAction; 6] = [
+        LooperAction::Record,
+        LooperAction::Overdub,
+        LooperAction::Play,
+        LooperAction::Stop,
+        LooperAction::Undo,
+        LooperAction::Clear,
+    ];
+}
+
+impl fmt::Display for LooperAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Looper state published by the process thread for the GUI.
+#[derive(Default)]
+pub struct LooperStatus {
+    state: AtomicU8,
+    position: AtomicUsize,
+    length: AtomicUsize,
+}
+
+impl LooperStatus {
+    pub fn state(&self) -> LooperState {
+        match self.state.load(Ordering::Relaxed) {
+            1 => LooperState::Recording,
+            2 => LooperState::Playing,
+            3 => LooperState::Overdubbing,
+            4 => LooperState::Stopped,
+            _ => LooperState::Empty,
+        }
+    }
+
+    /// Play position as a fraction of the loop, or loop memory used while recording.
+    pub fn progress(&self) -> f32 {
+        let length = self.length.load(Ordering::Relaxed);
+        if length == 0 {
+            return 0.0;
+        }
+        self.position.load(Ordering::Relaxed) as f32 / length as f32
+    }
+}
+
+/// Records a loop from its input and plays it back on top of the input.
+/// All loop memory is allocated up front so it can run in the process thread.
+pub struct Looper {
+    left: Vec<f32>,
+    right: Vec<f32>,
+    /// The loop as it was before the last overdub.
+    undo_left: Vec<f32>,
+    undo_right: Vec<f32>,
+    /// Whether there is an overdub to undo.
+    undo_valid: bool,
+    /// Where the last overdub started and how much of the loop from there is in the undo layer.
+    undo_start: usize,
+    undo_filled: usize,
+    /// Undo was requested before the undo layer was complete.
+    undo_pending: bool,
+    length: usize,
+    position: usize,
+    state: LooperState,
+    status: Arc<LooperStatus>,
+}
+
+impl Looper {
+    /// Must be called outside the process thread since it allocates the loop memory.
+    pub fn new(seconds: f32, sample_rate: usize, status: Arc<LooperStatus>) -> Self {
+        let capacity = ((seconds * sample_rate as f32) as usize).max(1);
+        Looper {
+            left: vec![0.0; capacity],
+            right: vec![0.0; capacity],
+            undo_left: vec![0.0; capacity],
+            undo_right: vec![0.0; capacity],
+            undo_valid: false,
+            undo_start: 0,
+            undo_filled: 0,
+            undo_pending: false,
+            length: 0,
+            position: 0,
+            state: LooperState::Empty,
+            status,
+        }
+    }
+
+    pub fn trigger(&mut self, action: LooperAction) {
+        match action {
+            LooperAction::Record if self.state == LooperState::Recording => self.close_loop(),
+            LooperAction::Record => {
+                self.state = LooperState::Recording;
+                self.position = 0;
+                self.length = 0;
+                self.undo_valid = false;
+                self.undo_pending = false;
+            }
+            LooperAction::Overdub => match self.state {
+                LooperState::Overdubbing => self.state = LooperState::Playing,
+                // Starting a new layer would throw away the undo that is still being prepared
+                _ if self.undo_pending => (),
+                LooperState::Recording => {
+                    self.close_loop();
+                    if self.state == LooperState::Playing {
+                        self.start_overdub();
+                    }
+                }
+                LooperState::Stopped => {
+                    self.position = 0;
+                    self.start_overdub();
+                }
+                LooperState::Playing => self.start_overdub(),
+                LooperState::Empty => (),
+            },
+            LooperAction::Play => match self.state {
+                LooperState::Recording => self.close_loop(),
+                LooperState::Overdubbing => self.state = LooperState::Playing,
+                LooperState::Stopped => {
+                    self.position = 0;
+                    self.state = LooperState::Playing;
+                }
+                LooperState::Playing | LooperState::Empty => (),
+            },
+            LooperAction::Stop => {
+                if self.state == LooperState::Recording {
+                    self.close_loop();
+                }
+                if self.state != LooperState::Empty {
+                    self.state = LooperState::Stopped;
+                }
+            }
+            LooperAction::Undo => {
+                if self.state == LooperState::Overdubbing {
+                    self.state = LooperState::Playing;
+                }
+                self.undo_pending = self.undo_valid;
+            }
+            LooperAction::Clear => {
+                self.state = LooperState::Empty;
+                self.position = 0;
+                self.length = 0;
+                self.undo_valid = false;
+                self.undo_pending = false;
+            }
+        }
+    }
+
+    fn close_loop(&mut self) {
+        if self.position == 0 {
+            self.state = LooperState::Empty;
+            return;
+        }
+        self.length = self.position;
+        self.position = 0;
+        self.state = LooperState::Playing;
+    }
+
+    fn start_overdub(&mut self) {
+        self.undo_valid = true;
+        self.undo_start = self.position;
+        self.undo_filled = 0;
+        self.state = LooperState::Overdubbing;
+    }
+
+    /// Copies up to `count` more samples of the loop into the undo layer. Samples ahead of
+    /// the play head haven't been overdubbed yet, so they still hold the previous layer.
+    fn fill_undo(&mut self, count: usize) {
+        let count = count.min(self.length - self.undo_filled);
+        let mut start = (self.undo_start + self.undo_filled) % self.length;
+        let mut remaining = count;
+        while remaining > 0 {
+            let end = (start + remaining).min(self.length);
+            self.undo_left[start..end].copy_from_slice(&self.left[start..end]);
+            self.undo_right[start..end].copy_from_slice(&self.right[start..end]);
+            remaining -= end - start;
+            start = 0;
+        }
+        self.undo_filled += count;
+    }
+
+    /// Runs the looper on `l_in` and `r_in`, writing the input plus the loop to `left` and `right`.
+    pub fn process(
+        &mut self,
+        l_in: &[f32],
+        r_in: &[f32],
+        left: &mut [f32],
+        right: &mut [f32],
+        events: &[ControlChange],
+    ) {
+        for event in events {
+            if event.value >= 64
+                && let Some((_, action)) =
+                    CONTROLLERS.iter().find(|(cc, _)| *cc == event.controller)
+            {
+                self.trigger(*action);
+            }
+        }
+
+        if self.undo_valid && self.undo_filled < self.length {
+            self.fill_undo(UNDO_CHUNK + l_in.len());
+        }
+        if self.undo_pending && self.undo_filled == self.length {
+            std::mem::swap(&mut self.left, &mut self.undo_left);
+            std::mem::swap(&mut self.right, &mut self.undo_right);
+            self.undo_pending = false;
+        }
+
+        left.copy_from_slice(l_in);
+        right.copy_from_slice(r_in);
+        for i in 0..l_in.len() {
+            match self.state {
+                LooperState::Recording => {
+                    self.left[self.position] = l_in[i];
+                    self.right[self.position] = r_in[i];
+                    self.position += 1;
+                    // Out of loop memory, so the loop closes itself
+                    if self.position == self.left.len() {
+                        self.close_loop();
+                    }
+                }
+                LooperState::Playing | LooperState::Overdubbing => {
+                    left[i] += self.left[self.position];
+                    right[i] += self.right[self.position];
+                    if self.state == LooperState::Overdubbing {
+                        self.left[self.position] += l_in[i];
+                        self.right[self.position] += r_in[i];
+                    }
+                    self.position = (self.position + 1) % self.length;
+                }
+                LooperState::Empty | LooperState::Stopped => break,
+            }
+        }
+
+        let state = match self.state {
+            LooperState::Empty => 0,
+            LooperState::Recording => 1,
+            LooperState::Playing => 2,
+            LooperState::Overdubbing => 3,
+            LooperState::Stopped => 4,
+        };
+        self.status.state.store(state, Ordering::Relaxed);
+        self.status.position.store(self.position, Ordering::Relaxed);
+        self.status.length.store(
+            if self.state == LooperState::Recording {
+                self.left.len()
+            } else {
+                self.length
+            },
+            Ordering::Relaxed,
+        );
+    }
+}
@@ -1,16 +1,123 @@
-use iced::{Subscription, Task, window};
+use iced::futures::Stream;
+use iced::widget::pane_grid;
+use iced::{Subscription, Task, keyboard, mouse, window};
 use rack::prelude::*;
 use rfd::FileDialog;
-use ringbuf::{HeapCons, HeapProd, traits::Producer};
+use ringbuf::{
+    HeapCons, HeapProd,
+    traits::{Consumer, Producer},
+};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::net::Ipv4Addr;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 
+mod analyzer;
+mod api;
+mod bundle;
+mod chain;
+mod collector;
+mod config;
+mod connections;
+mod control;
+mod convolver;
+mod dsp;
+mod flac;
+mod headless;
+mod import;
+mod knob;
+mod link;
+mod log;
+mod looper;
+mod metronome;
+mod midi;
+mod migrate;
+mod nsm;
+mod osc;
+mod presets;
 mod processor;
+mod recorder;
+mod render;
+mod scan;
+mod sequencer;
+mod setlist;
+mod tempo;
+mod tuner;
 mod view;
+mod wav;
+mod web;
+use chain::{
+    AuxReturn, Bus, Fault, MAX_RETURNS, MAX_SLOTS, OutputBus, ProcessError, Slot, Split, Unit,
+    move_by_id,
+};
+use collector::Collector;
+use config::Config;
+use connections::PortChoice;
+use convolver::Convolver;
+use dsp::{Level, Routing};
+use looper::{Looper, LooperAction, LooperStatus};
+use metronome::ClickSound;
+use midi::{MidiMapping, MidiTarget};
+use presets::Settings;
 use processor::*;
+use recorder::Recorder;
+use render::Render;
+use setlist::{ProgramMapping, ProgramTarget, SetlistEntry};
+use tempo::{TapController, Tempo};
+use view::{ParamView, SlotColor};
+
+/// Time between automatic attempts to reconnect after the JACK server shut down.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Time between snapshots of the port connections, which are remembered in the config.
+const CONNECTION_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Time between meter updates, about 30 a second.
+const METER_INTERVAL: Duration = Duration::from_millis(33);
+
+/// Share of the slider's movement a parameter follows while Shift is held.
+const FINE_DRAG_SCALE: f32 = 0.1;
+
+/// Time the meters hold the highest peak before falling back to the current one.
+const PEAK_HOLD: Duration = Duration::from_millis(1500);
+
+const DEFAULT_CLIENT_NAME: &str = "Rake";
+
+/// Number of recently loaded plugins remembered for ordering the available list.
+const MAX_RECENT: usize = 20;
+
+/// Number of recently opened or saved sessions listed for reopening.
+const MAX_RECENT_SESSIONS: usize = 10;
 
 fn main() -> iced::Result {
+    // Rake runs itself with --scan OUTPUT to scan for plugins in a child process
+    let args: Vec<PathBuf> = std::env::args_os().skip(1).map(PathBuf::from).collect();
+    if let [arg, output] = &args[..]
+        && arg.as_os_str() == scan::SCAN_ARG
+    {
+        scan::run(output);
+    }
+    if let [arg, ctl_args @ ..] = &args[..]
+        && arg.as_os_str() == control::CTL_ARG
+    {
+        let ctl_args: Vec<String> = ctl_args
+            .iter()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect();
+        control::run(&ctl_args);
+    }
+    if Args::parse().headless {
+        headless::run();
+        return Ok(());
+    }
+
     iced::application(boot, update, view::view)
         .exit_on_close_request(false)
         .subscription(subscription)
@@ -18,326 +125,4908 @@ fn main() -> iced::Result {
         .run()
 }
 
-fn subscription(_state: &AppState) -> Subscription<Message> {
-    window::close_requests().map(|_id| Message::Exit)
+/// Ticks as often as the engine is looked after without the GUI, standing in for frames.
+fn ticks() -> impl Stream<Item = ()> {
+    iced::stream::channel(1, async |mut output| {
+        std::thread::spawn(move || {
+            while !output.is_closed() {
+                let _ = output.try_send(());
+                std::thread::sleep(headless::POLL_INTERVAL);
+            }
+        });
+        iced::futures::future::pending::<()>().await;
+    })
+}
+
+fn subscription(state: &AppState) -> Subscription<Message> {
+    // Frames stop while the window is hidden, and the session manager has to be heard from
+    let ticks = if state.gui_hidden {
+        Subscription::run(ticks).map(|()| Message::Frame)
+    } else {
+        Subscription::none()
+    };
+    // A plugin released anywhere but on a drop zone isn't loaded
+    let drag = if state.dragging.is_some() {
+        iced::event::listen_with(|event, _status, _id| match event {
+            iced::Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) => {
+                Some(Message::EndDrag)
+            }
+            _ => None,
+        })
+    } else {
+        Subscription::none()
+    };
+    Subscription::batch([
+        ticks,
+        drag,
+        window::close_requests().map(|_id| Message::AskConfirm(Confirm::Exit)),
+        window::frames().map(|_instant| Message::Frame),
+        keyboard::listen().filter_map(|event| match event {
+            keyboard::Event::KeyPressed {
+                key,
+                modified_key,
+                modifiers,
+                repeat,
+                ..
+            } => shortcut(key.as_ref(), modified_key.as_ref(), modifiers, repeat),
+            keyboard::Event::ModifiersChanged(modifiers) => {
+                Some(Message::ModifiersChanged(modifiers))
+            }
+            _ => None,
+        }),
+    ])
+}
+
+/// Message for a key pressed outside of text inputs, if it's a shortcut. Shortcuts that toggle
+/// something or open a dialog ignore key repeat. They're listed in `view::SHORTCUTS`.
+fn shortcut(
+    key: keyboard::Key<&str>,
+    modified_key: keyboard::Key<&str>,
+    modifiers: keyboard::Modifiers,
+    repeat: bool,
+) -> Option<Message> {
+    use keyboard::Key::{Character, Named};
+    use keyboard::key::Named::{ArrowDown, ArrowUp, Escape, F1};
+    if modifiers.command() {
+        return match key {
+            Character("z") if modifiers.shift() => Some(Message::Redo),
+            Character("z") => Some(Message::Undo),
+            _ if repeat => None,
+            Character("s") if modifiers.shift() => Some(Message::SaveSessionAs),
+            Character("s") => Some(Message::SaveSession),
+            Character("o") => Some(Message::AskConfirm(Confirm::Open(None))),
+            Character("r") => Some(Message::Scan),
+            _ => None,
+        };
+    }
+    if modifiers.alt() || modifiers.logo() {
+        return None;
+    }
+    match (key, modified_key) {
+        (Named(ArrowUp), _) => Some(Message::SelectPreviousPlugin),
+        (Named(ArrowDown), _) => Some(Message::SelectNextPlugin),
+        _ if repeat => None,
+        (Named(Escape), _) => Some(Message::TogglePanic),
+        (Named(F1), _) | (_, Character("?")) => Some(Message::ToggleShortcuts),
+        (Character("b"), _) => Some(Message::ToggleSelectedBypass),
+        (Character("["), _) => Some(Message::PreviousSnapshot),
+        (Character("]"), _) => Some(Message::NextSnapshot),
+        (Character("m"), _) => Some(Message::ToggleMasterMute),
+        _ => None,
+    }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 struct LoadedPlugin {
     id: Uuid,
-    info: PluginInfo,
+    #[serde(flatten)]
+    unit: LoadedUnit,
     params: Vec<(ParameterInfo, f32)>,
+    #[serde(default)]
+    bypass: bool,
+    #[serde(default = "default_mix")]
+    mix: f32,
+    #[serde(default)]
+    input_gain: f32,
+    #[serde(default)]
+    output_gain: f32,
+    #[serde(default)]
+    routing: Routing,
+    /// Return chain ids and the linear level sent to each.
+    #[serde(default)]
+    sends: Vec<(Uuid, f32)>,
+    /// Stereo output buses of the plugin besides the main one, each on its own JACK ports.
+    #[serde(default)]
+    buses: usize,
+    #[serde(default)]
+    output_bus: OutputBus,
+    /// Name given to the slot in place of its unit's, e.g. "Lead boost". Empty for none.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    label: String,
+    #[serde(default)]
+    color: SlotColor,
+    /// Whether only the header and meters are shown, so long chains fit on screen.
+    #[serde(default)]
+    collapsed: bool,
+    /// Indexes of the parameters that randomizing and nudging leave alone.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    locked: Vec<usize>,
+    /// Way the parameters are shown instead of the one set for every plugin.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    param_view: Option<ParamView>,
+    #[serde(skip)]
+    hide_params: bool,
+    /// Page of the parameters shown, `PARAMS_PER_PAGE` at a time.
+    #[serde(skip)]
+    param_page: usize,
+    /// Text the names of the parameters shown contain, for plugins with dozens of them.
+    #[serde(skip)]
+    param_filter: String,
+    /// Whether side B of the A/B comparison is playing.
+    #[serde(skip)]
+    on_b: bool,
+}
+
+/// Plugin run by a slot, or the settings of a built-in processor.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum LoadedUnit {
+    Plugin {
+        info: PluginInfo,
+        /// Run as one instance per channel, for plugins that only process mono.
+        #[serde(default)]
+        dual_mono: bool,
+    },
+    Convolver {
+        ir: PathBuf,
+    },
+    /// Loops aren't saved, only the loop memory.
+    Looper {
+        loop_seconds: f32,
+        #[serde(skip)]
+        status: Arc<LooperStatus>,
+    },
+}
+
+impl LoadedPlugin {
+    /// A new slot running `unit`, with every setting at its default.
+    fn new(unit: LoadedUnit, params: Vec<(ParameterInfo, f32)>) -> Self {
+        LoadedPlugin {
+            id: Uuid::new_v4(),
+            unit,
+            params,
+            bypass: false,
+            mix: 1.0,
+            input_gain: 0.0,
+            output_gain: 0.0,
+            routing: Routing::Stereo,
+            sends: Vec::new(),
+            buses: 0,
+            output_bus: OutputBus::Main,
+            label: String::new(),
+            color: SlotColor::None,
+            collapsed: false,
+            locked: Vec::new(),
+            param_view: None,
+            hide_params: false,
+            param_page: 0,
+            param_filter: String::new(),
+            on_b: false,
+        }
+    }
+
+    /// The slot's label, or else its unit's name.
+    fn name(&self) -> String {
+        if self.label.is_empty() {
+            self.unit.name()
+        } else {
+            self.label.clone()
+        }
+    }
+}
+
+impl LoadedUnit {
+    fn name(&self) -> String {
+        match self {
+            LoadedUnit::Plugin { info, .. } => info.name.clone(),
+            LoadedUnit::Convolver { ir } => {
+                format!(
+                    "IR: {}",
+                    ir.file_stem().unwrap_or_default().to_string_lossy()
+                )
+            }
+            LoadedUnit::Looper { .. } => "Looper".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for LoadedUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadedUnit::Plugin { info, .. } => write!(f, "{}", info),
+            LoadedUnit::Convolver { ir } => write!(f, "IR {}", ir.display()),
+            LoadedUnit::Looper { .. } => write!(f, "Looper"),
+        }
+    }
+}
+
+/// Plugin type the available plugins are filtered by, or all of them.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+struct Category(Option<PluginType>);
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(plugin_type) => write!(f, "{:?}", plugin_type),
+            None => write!(f, "All"),
+        }
+    }
+}
+
+/// A plugin's chain or return chain, its split branch if it's in one, and its index there.
+type Position = (Uuid, Option<(Uuid, usize)>, usize);
+
+/// Most edits kept for undoing.
+const MAX_UNDO: usize = 100;
+
+/// Edits of the same parameter or of the volume closer together than this, e.g. while dragging
+/// a slider, are undone at once.
+const UNDO_MERGE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A change to the chains or the volume, kept to undo or redo it.
+enum Edit {
+    /// Puts a plugin at `index` of a chain or return chain, or of a split branch.
+    Insert {
+        plugin: Box<LoadedPlugin>,
+        chain_id: Uuid,
+        branch: Option<(Uuid, usize)>,
+        index: usize,
+    },
+    Delete(Uuid),
+    Move {
+        id: Uuid,
+        up: bool,
+    },
+    Param {
+        plugin_id: Uuid,
+        info: ParameterInfo,
+        value: f32,
+    },
+    /// Sets several parameters of a plugin at once, e.g. when randomizing them.
+    Params {
+        plugin_id: Uuid,
+        values: Vec<(ParameterInfo, f32)>,
+    },
+    Volume(f32),
+}
+
+impl Edit {
+    /// Whether this and `other` set the same parameter, or both the volume.
+    fn same_target(&self, other: &Edit) -> bool {
+        match (self, other) {
+            (
+                Edit::Param {
+                    plugin_id, info, ..
+                },
+                Edit::Param {
+                    plugin_id: other_id,
+                    info: other_info,
+                    ..
+                },
+            ) => plugin_id == other_id && info.index == other_info.index,
+            (Edit::Volume(_), Edit::Volume(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+fn default_mix() -> f32 {
+    1.0
+}
+
+/// Two parallel branches of plugins, with branch gains in dB.
+#[derive(Clone, Serialize, Deserialize)]
+struct LoadedSplit {
+    id: Uuid,
+    branches: [Vec<LoadedPlugin>; 2],
+    gains: [f32; 2],
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum ChainNode {
+    Plugin(Box<LoadedPlugin>),
+    Split(LoadedSplit),
+}
+
+impl ChainNode {
+    fn id(&self) -> Uuid {
+        match self {
+            ChainNode::Plugin(plugin) => plugin.id,
+            ChainNode::Split(split) => split.id,
+        }
+    }
+}
+
+/// A chain fed by the aux sends of another chain's plugins and mixed back into its output.
+#[derive(Clone, Serialize, Deserialize)]
+struct LoadedReturn {
+    id: Uuid,
+    name: String,
+    nodes: Vec<ChainNode>,
+}
+
+/// A chain with its own JACK ports, prefixed with its name.
+#[derive(Clone, Serialize, Deserialize)]
+struct LoadedChain {
+    id: Uuid,
+    name: String,
+    nodes: Vec<ChainNode>,
+    #[serde(default)]
+    returns: Vec<LoadedReturn>,
+    #[serde(skip)]
+    mute: bool,
+    #[serde(skip)]
+    solo: bool,
+}
+
+/// Version of the session format, raised whenever sessions gain something older versions of
+/// Rake would lose.
+const SESSION_VERSION: u32 = 3;
+
+/// Parameter values and bypass states of the plugins, switched between live.
+#[derive(Clone, Serialize, Deserialize)]
+struct Snapshot {
+    name: String,
+    /// Settings of each plugin when the snapshot was saved, by plugin id.
+    plugins: HashMap<Uuid, PluginSnapshot>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct PluginSnapshot {
+    /// Parameter values by index.
+    params: Vec<f32>,
+    bypass: bool,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Session {
+    /// `SESSION_VERSION` when saved, or 0 for sessions saved before it was added.
+    #[serde(default)]
+    version: u32,
+    plugins: Vec<ChainNode>,
+    /// Return chains of the main chain.
+    #[serde(default)]
+    returns: Vec<LoadedReturn>,
+    /// Chains besides the main one, whose plugins are in `plugins`.
+    #[serde(default)]
+    chains: Vec<LoadedChain>,
+    #[serde(default)]
+    limiter: bool,
+    #[serde(default)]
+    dc_blocker: bool,
+    /// Master volume in dB.
+    #[serde(default)]
+    volume: f32,
+    /// Connections of Rake's ports as source and destination.
+    #[serde(default)]
+    connections: Vec<(String, String)>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    snapshots: Vec<Snapshot>,
+    /// Controllers mapped with MIDI learn.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    midi_mappings: Vec<MidiMapping>,
+}
+
+impl Session {
+    /// Plugins of every chain and return chain.
+    fn plugins_mut(&mut self) -> impl Iterator<Item = &mut LoadedPlugin> {
+        std::iter::once(&mut self.plugins)
+            .chain(
+                self.returns
+                    .iter_mut()
+                    .map(|aux_return| &mut aux_return.nodes),
+            )
+            .chain(self.chains.iter_mut().flat_map(|chain| {
+                std::iter::once(&mut chain.nodes).chain(
+                    chain
+                        .returns
+                        .iter_mut()
+                        .map(|aux_return| &mut aux_return.nodes),
+                )
+            }))
+            .flatten()
+            .flat_map(|node| match node {
+                ChainNode::Plugin(plugin) => vec![plugin.as_mut()],
+                ChainNode::Split(split) => split.branches.iter_mut().flatten().collect(),
+            })
+    }
+}
+
+/// Units of a session file instantiated in the background ahead of switching to it.
+struct Preload {
+    path: PathBuf,
+    /// Sample rate and buffer size the units were initialized for.
+    format: (usize, usize),
+    /// Units by the ids of their plugins in the file.
+    units: JoinHandle<HashMap<Uuid, Unit>>,
+}
+
+/// Action that loses the session or part of it, confirmed in a dialog first.
+#[derive(Debug, Clone)]
+enum Confirm {
+    ClearSession,
+    DeletePlugin(Uuid),
+    /// Opens a session file, or picks one first if there's none.
+    Open(Option<PathBuf>),
+    NewFromTemplate(String),
+    Exit,
+}
+
+impl Confirm {
+    /// Message that carries out the action.
+    fn message(&self) -> Message {
+        match self {
+            Confirm::ClearSession => Message::ClearSession,
+            Confirm::DeletePlugin(id) => Message::DeletePlugin(*id),
+            Confirm::Open(None) => Message::LoadSession,
+            Confirm::Open(Some(path)) => Message::OpenRecentSession(path.clone()),
+            Confirm::NewFromTemplate(name) => Message::NewFromTemplate(name.clone()),
+            Confirm::Exit => Message::Exit,
+        }
+    }
+}
+
+/// Action waiting in a dialog for the user to confirm it.
+struct Confirmation {
+    action: Confirm,
+    question: String,
+    /// Whether the session has changes since it was last saved, offered to be saved first.
+    unsaved: bool,
+}
+
+/// Value typed for a parameter, in the parameter's own range.
+struct ParamEntry {
+    plugin_id: Uuid,
+    index: usize,
+    text: String,
+    /// Whether the value submitted wasn't a number in the parameter's range.
+    invalid: bool,
 }
 
 #[derive(Default)]
 struct AppState {
     plugin_scanner: Option<Scanner>,
     scanned_plugins: Vec<PluginInfo>,
-    loaded_plugins: Vec<LoadedPlugin>,
+    /// Other paths of the available plugins that were found more than once, by unique id.
+    duplicates: HashMap<String, Vec<PathBuf>>,
+    /// Type of the plugins shown in the available list.
+    category: Category,
+    /// Text the names and vendors of the available plugins are matched against.
+    filter: String,
+    /// Unique id of the available plugin whose details are shown, and its parameter count
+    /// if it could be loaded.
+    details: Option<(String, Option<usize>)>,
+    /// Names of the user's presets of each plugin that was instantiated, by unique id.
+    presets: HashMap<String, Vec<String>>,
+    /// Settings of the side of each plugin's A/B comparison that isn't playing.
+    compare: HashMap<Uuid, Settings>,
+    /// Scan running in the background, in a child process.
+    scanning: Option<JoinHandle<std::result::Result<Vec<PluginInfo>, String>>>,
+    /// The main chain followed by the named chains.
+    chains: Vec<LoadedChain>,
+    selected_chain: usize,
+    /// Return chain of the selected chain that is shown instead of the chain itself.
+    selected_return: Option<usize>,
+    /// Plugin that keyboard shortcuts act on, picked by clicking its name.
+    selected_plugin: Option<Uuid>,
+    /// Keyboard modifiers held, for fine parameter adjustment with Shift.
+    modifiers: keyboard::Modifiers,
+    /// Plugin and index of the parameter dragged with Shift held, its value when the fine
+    /// adjustment started, and the slider's.
+    fine_drag: Option<(Uuid, usize, f32, f32)>,
+    /// Parameter whose value is being typed.
+    param_entry: Option<ParamEntry>,
+    confirmation: Option<Confirmation>,
+    /// Errors shown as notifications and in the log panel.
+    log: log::Log,
+    log_open: bool,
+    /// The session as last saved or opened, to tell whether it has unsaved changes.
+    saved_session: String,
+    /// Slot being renamed and the label typed so far.
+    renaming: Option<(Uuid, String)>,
+    panes: view::Panes,
+    /// Whether the keyboard shortcut cheat sheet is shown.
+    shortcuts_open: bool,
+    /// Name for the next chain or return chain.
+    chain_name: String,
+    /// Split branch that newly loaded plugins are added to, instead of the end of the chain.
+    load_target: Option<(Uuid, usize)>,
+    /// Available plugin being dragged into the chain.
+    dragging: Option<PluginInfo>,
     volume: f32,
+    limiter: bool,
+    dc_blocker: bool,
+    monitor: Monitor,
+    /// Whether every chain is bypassed in an emergency.
+    panic: bool,
+    /// Whether the master output is muted, leaving the volume as it is.
+    master_mute: bool,
+    metronome: bool,
+    /// Click volume in dB.
+    metronome_volume: f32,
+    click_sound: ClickSound,
+    click_to_main: bool,
+    /// Tempo used while no JACK timebase master provides one, set by tapping.
+    tempo: Arc<Tempo>,
+    tap_controller: TapController,
     command_sender: Option<HeapProd<Command>>,
-    _garbage_receiver: Option<HeapCons<(Plugin, Uuid)>>,
+    garbage_receiver: Option<HeapCons<Garbage>>,
+    meter_receiver: Option<HeapCons<(Uuid, Level, Level, f32)>>,
+    fault_receiver: Option<HeapCons<(Uuid, Fault)>>,
+    error_receiver: Option<HeapCons<(Uuid, ProcessError)>>,
+    program_receiver: Option<HeapCons<(u16, u8)>>,
+    /// Whether the program change map is shown.
+    program_map_open: bool,
+    /// Bank and program of the next program change mapped, filled in with the last one
+    /// received.
+    program_bank: String,
+    program_number: String,
+    controller_receiver: Option<HeapCons<(u8, u8)>>,
+    midi_mappings: Vec<MidiMapping>,
+    /// Whether moving a control picks it as the target for the next controller moved.
+    midi_learn: bool,
+    /// Control picked during MIDI learn, waiting for a controller.
+    learn_target: Option<MidiTarget>,
+    /// Requests from `rake ctl`, OSC and the web API.
+    requests: Option<Receiver<api::Pending>>,
+    /// Whether this instance has the `rake ctl` socket, rather than another instance.
+    ctl_socket: bool,
+    osc: Option<osc::Server>,
+    web: Option<web::Server>,
+    /// Listener following the tempo of Link peers, while enabled.
+    link: Option<link::Link>,
+    /// ALSA sequencer client, unless the sequencer isn't available.
+    sequencer: Option<sequencer::Sequencer>,
+    /// MIDI ports of the ALSA sequencer, offered by the ALSA MIDI picker.
+    alsa_devices: Vec<String>,
+    /// Plugins the process thread bypassed, with what went wrong, until re-enabled or dismissed.
+    alerts: Vec<(Uuid, Fault, String)>,
+    /// Latest input and output levels of each plugin and chain, and a plugin's share of the
+    /// cycle time.
+    levels: HashMap<Uuid, (Level, Level, f32)>,
+    /// Highest input and output peaks of each plugin and chain within `PEAK_HOLD`, and when they
+    /// were reached.
+    peak_holds: HashMap<Uuid, [(f32, Instant); 2]>,
+    levels_collected: Option<Instant>,
+    /// Plugin or chain whose output is shown on the spectrum analyzer.
+    analyzer_tap: Option<Uuid>,
+    analyzer: Option<Arc<Mutex<Vec<f32>>>>,
+    /// Band levels in dB, copied from the analyzer thread every frame.
+    spectrum: Vec<f32>,
+    /// Chain whose input the tuner listens to.
+    tuner_input: Option<Uuid>,
+    tuner: Option<Arc<Mutex<Option<f32>>>>,
+    /// Detected frequency in Hz, copied from the tuner thread every frame.
+    pitch: Option<f32>,
+    recorder: Option<Recorder>,
+    collector: Option<Collector>,
+    /// File the main output is being recorded to.
+    recording: Option<PathBuf>,
+    /// Whether the dry input is also recorded, to a `-dry` file next to the output.
+    record_dry: bool,
+    /// File being rendered through the main chain in freewheel mode.
+    rendering: Option<PathBuf>,
     session_path: PathBuf,
-    jack_client: Option<jack::AsyncClient<(), processor::Processor>>,
+    /// Names of the session templates.
+    templates: Vec<String>,
+    /// Edits that revert the last changes, the latest last.
+    undo: Vec<Edit>,
+    /// Edits that make the undone changes again, the latest undone last.
+    redo: Vec<Edit>,
+    last_edit: Option<Instant>,
+    snapshots: Vec<Snapshot>,
+    /// Snapshot recalled or saved last.
+    snapshot: Option<usize>,
+    /// Name for the next snapshot.
+    snapshot_name: String,
+    /// Setlist entry switched to last.
+    setlist_position: Option<usize>,
+    /// Units of the next session in the setlist, instantiated in the background.
+    preload: Option<Preload>,
+    /// Preloaded units of the session being loaded, by plugin id.
+    preloaded: HashMap<Uuid, Unit>,
+    /// Sample rate the plugins were last initialized at.
+    sample_rate: usize,
+    notifications: Arc<Notifications>,
+    /// Name asked for when connecting. JACK may change it if another client already has it.
+    client_name: String,
+    /// The JACK server shut down and the client is waiting to reconnect.
+    disconnected: bool,
+    /// Session manager that launched Rake, if any.
+    nsm: Option<nsm::Nsm>,
+    /// Whether the session manager hid the window.
+    gui_hidden: bool,
+    last_reconnect: Option<Instant>,
+    config: Config,
+    connections_polled: Option<Instant>,
+    /// Audio ports of other clients, offered by the connection pickers.
+    sources: Vec<String>,
+    destinations: Vec<String>,
+    /// Connections of the session last opened whose other port doesn't exist, until each is
+    /// replaced or they're dismissed.
+    missing_ports: Vec<connections::Missing>,
+    jack_client: Option<jack::AsyncClient<processor::Notifier, processor::Processor>>,
+}
+
+impl AppState {
+    /// Nodes of every chain and return chain.
+    fn node_lists_mut(&mut self) -> impl Iterator<Item = &mut Vec<ChainNode>> {
+        self.chains.iter_mut().flat_map(|chain| {
+            std::iter::once(&mut chain.nodes).chain(
+                chain
+                    .returns
+                    .iter_mut()
+                    .map(|aux_return| &mut aux_return.nodes),
+            )
+        })
+    }
+
+    fn nodes_mut(&mut self) -> impl Iterator<Item = &mut ChainNode> {
+        self.node_lists_mut().flat_map(|nodes| nodes.iter_mut())
+    }
+
+    fn plugins_mut(&mut self) -> impl Iterator<Item = &mut LoadedPlugin> {
+        self.nodes_mut().flat_map(|node| match node {
+            ChainNode::Plugin(plugin) => vec![plugin.as_mut()],
+            ChainNode::Split(split) => split.branches.iter_mut().flatten().collect(),
+        })
+    }
+
+    /// Id of the chain or return chain that new plugins are added to.
+    fn selected_id(&self) -> Uuid {
+        let chain = &self.chains[self.selected_chain];
+        match self.selected_return {
+            Some(i) => chain.returns[i].id,
+            None => chain.id,
+        }
+    }
+
+    /// Ids of the plugins shown, in the chain or return chain selected, from top to bottom.
+    fn shown_plugins(&self) -> Vec<Uuid> {
+        let chain = &self.chains[self.selected_chain];
+        let nodes = match self.selected_return {
+            Some(i) => &chain.returns[i].nodes,
+            None => &chain.nodes,
+        };
+        nodes
+            .iter()
+            .flat_map(|node| match node {
+                ChainNode::Plugin(plugin) => vec![plugin.id],
+                ChainNode::Split(split) => split.branches.iter().flatten().map(|p| p.id).collect(),
+            })
+            .collect()
+    }
+
+    fn selected_nodes_mut(&mut self) -> &mut Vec<ChainNode> {
+        let chain = &mut self.chains[self.selected_chain];
+        match self.selected_return {
+            Some(i) => &mut chain.returns[i].nodes,
+            None => &mut chain.nodes,
+        }
+    }
+
+    fn plugin_mut(&mut self, id: Uuid) -> Option<&mut LoadedPlugin> {
+        self.nodes_mut().find_map(|node| match node {
+            ChainNode::Plugin(plugin) if plugin.id == id => Some(plugin.as_mut()),
+            ChainNode::Plugin(_) => None,
+            ChainNode::Split(split) => split
+                .branches
+                .iter_mut()
+                .flatten()
+                .find(|plugin| plugin.id == id),
+        })
+    }
+
+    /// Nodes of a chain or return chain.
+    fn chain_nodes_mut(&mut self, id: Uuid) -> Option<&mut Vec<ChainNode>> {
+        self.chains.iter_mut().find_map(|chain| {
+            if chain.id == id {
+                Some(&mut chain.nodes)
+            } else {
+                chain
+                    .returns
+                    .iter_mut()
+                    .find(|aux_return| aux_return.id == id)
+                    .map(|aux_return| &mut aux_return.nodes)
+            }
+        })
+    }
+
+    /// Whether a chain, or a split branch if one is given, has no room for another node.
+    fn is_full(&mut self, chain_id: Uuid, branch: Option<(Uuid, usize)>) -> bool {
+        match branch {
+            Some((split_id, branch)) => self
+                .split_mut(split_id)
+                .is_some_and(|split| split.branches[branch].len() >= MAX_SLOTS),
+            None => self
+                .chain_nodes_mut(chain_id)
+                .is_some_and(|nodes| nodes.len() >= MAX_SLOTS),
+        }
+    }
+
+    fn position(&self, id: Uuid) -> Option<Position> {
+        let node_lists = self.chains.iter().flat_map(|chain| {
+            std::iter::once((chain.id, &chain.nodes)).chain(
+                chain
+                    .returns
+                    .iter()
+                    .map(|aux_return| (aux_return.id, &aux_return.nodes)),
+            )
+        });
+        for (chain_id, nodes) in node_lists {
+            for (index, node) in nodes.iter().enumerate() {
+                match node {
+                    ChainNode::Plugin(plugin) if plugin.id == id => {
+                        return Some((chain_id, None, index));
+                    }
+                    ChainNode::Plugin(_) => {}
+                    ChainNode::Split(split) => {
+                        for (branch, plugins) in split.branches.iter().enumerate() {
+                            if let Some(index) = plugins.iter().position(|plugin| plugin.id == id) {
+                                return Some((chain_id, Some((split.id, branch)), index));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn split_mut(&mut self, id: Uuid) -> Option<&mut LoadedSplit> {
+        self.nodes_mut().find_map(|node| match node {
+            ChainNode::Split(split) if split.id == id => Some(split),
+            _ => None,
+        })
+    }
+
+    fn remove_node(&mut self, id: Uuid) {
+        for nodes in self.node_lists_mut() {
+            nodes.retain(|node| node.id() != id);
+        }
+        for node in self.nodes_mut() {
+            if let ChainNode::Split(split) = node {
+                for branch in &mut split.branches {
+                    branch.retain(|plugin| plugin.id != id);
+                }
+            }
+        }
+        if self.load_target.is_some_and(|(split_id, _)| split_id == id) {
+            self.load_target = None;
+        }
+        if self.analyzer_tap == Some(id) {
+            self.analyzer_tap = None;
+        }
+    }
+
+    fn move_node(&mut self, id: Uuid, up: bool) {
+        for nodes in self.node_lists_mut() {
+            if move_by_id(nodes, id, ChainNode::id, up) {
+                return;
+            }
+        }
+        for node in self.nodes_mut() {
+            if let ChainNode::Split(split) = node {
+                for branch in &mut split.branches {
+                    if move_by_id(branch, id, |plugin| plugin.id, up) {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Takes the levels sent since the last update, keeping the highest peaks, and holds new
+    /// peaks.
+    fn collect_levels(&mut self) {
+        let mut updated: HashMap<Uuid, (Level, Level, f32)> = HashMap::new();
+        while let Some((id, input, output, load)) = self.meter_receiver.as_mut().unwrap().try_pop()
+        {
+            let levels = updated.entry(id).or_insert((input, output, load));
+            levels.0 = Level {
+                peak: levels.0.peak.max(input.peak),
+                rms: input.rms,
+            };
+            levels.1 = Level {
+                peak: levels.1.peak.max(output.peak),
+                rms: output.rms,
+            };
+            levels.2 = load;
+        }
+        let now = Instant::now();
+        for (id, (input, output, _)) in &updated {
+            let holds = self
+                .peak_holds
+                .entry(*id)
+                .or_insert([(0.0, now), (0.0, now)]);
+            for ((held, since), peak) in holds.iter_mut().zip([input.peak, output.peak]) {
+                if peak >= *held || since.elapsed() >= PEAK_HOLD {
+                    *held = peak;
+                    *since = now;
+                }
+            }
+        }
+        self.levels.extend(updated);
+    }
+
+    /// Logs the errors the process thread reported, naming the plugins they concern.
+    fn collect_errors(&mut self) {
+        while let Some((id, error)) = self.error_receiver.as_mut().unwrap().try_pop() {
+            // A removed plugin is only known by its id
+            let plugin = self
+                .plugin_mut(id)
+                .map_or_else(|| id.to_string(), |plugin| plugin.name());
+            match error {
+                ProcessError::Process => log::error!("Plugin {} failed to process", plugin),
+                ProcessError::SetParameter(index) => {
+                    log::error!("Error setting parameter {} of {}", index, plugin)
+                }
+                ProcessError::Reinitialize => {
+                    log::error!("Error re-initializing plugin {}", plugin)
+                }
+                ProcessError::RemovePlugin => log::error!("Error removing plugin {}", plugin),
+                ProcessError::RemoveBus => log::error!("Error removing output bus"),
+                ProcessError::RemoveSplit => log::error!("Error removing split {}", id),
+                ProcessError::RemoveChain => log::error!("Error removing chain {}", id),
+                ProcessError::RemoveReturn => log::error!("Error removing return chain {}", id),
+                ProcessError::FinishRender => log::error!("Error finishing render"),
+                ProcessError::RecorderFull => log::error!("Recorder buffer full, dropping output"),
+                ProcessError::ReportFault(fault) => {
+                    log::error!("Error reporting that plugin {} {}", plugin, fault)
+                }
+            }
+        }
+    }
+
+    /// Marks the plugins the process thread bypassed and raises an alert for each.
+    fn collect_faults(&mut self) {
+        while let Some((id, fault)) = self.fault_receiver.as_mut().unwrap().try_pop() {
+            let Some(plugin) = self.plugin_mut(id) else {
+                continue;
+            };
+            plugin.bypass = true;
+            let alert = match fault {
+                Fault::Panic => format!("{} {} and was disabled", plugin.name(), fault),
+                _ => format!("{} {} and was bypassed", plugin.name(), fault),
+            };
+            log::error!("{}", alert);
+            self.alerts.retain(|(alert_id, ..)| *alert_id != id);
+            self.alerts.push((id, fault, alert));
+        }
+    }
+
+    /// The chains and output settings, as saved to a session file.
+    fn session(&self) -> Session {
+        Session {
+            plugins: self.chains[0].nodes.clone(),
+            returns: self.chains[0].returns.clone(),
+            chains: self.chains[1..].to_vec(),
+            limiter: self.limiter,
+            dc_blocker: self.dc_blocker,
+            volume: self.volume,
+            connections: self
+                .jack_client
+                .as_ref()
+                .map(|client| connections::current(client.as_client()))
+                .unwrap_or_default(),
+            snapshots: self.snapshots.clone(),
+            midi_mappings: self.midi_mappings.clone(),
+            version: SESSION_VERSION,
+        }
+    }
+
+    /// The session as compared for unsaved changes, without the port connections, which
+    /// other clients change.
+    fn saved_form(&self) -> String {
+        let mut session = self.session();
+        session.connections.clear();
+        serde_yaml_ng::to_string(&session).unwrap()
+    }
+
+    fn unsaved(&self) -> bool {
+        self.saved_form() != self.saved_session
+    }
+
+    /// Takes back the chains of a session that was sent again, without the plugins that
+    /// couldn't be instantiated.
+    fn keep_sent(&mut self, session: Session) {
+        self.chains[0].nodes = session.plugins;
+        self.chains[0].returns = session.returns;
+        for (chain, sent) in self.chains[1..].iter_mut().zip(session.chains) {
+            chain.nodes = sent.nodes;
+            chain.returns = sent.returns;
+        }
+    }
+
+    /// Releases plugins, return chains and ports that the process thread is done with.
+    /// Plugins are dropped on the collector thread.
+    fn collect_garbage(&mut self) {
+        let client = self.jack_client.as_ref().unwrap().as_client();
+        // Gone once exiting
+        let Some(collector) = self.collector.as_ref() else {
+            return;
+        };
+        while let Some(garbage) = self.garbage_receiver.as_mut().unwrap().try_pop() {
+            match garbage {
+                Garbage::Slot(mut slot) => {
+                    slot.unregister_buses(client);
+                    collector.dispose(slot);
+                }
+                Garbage::Bus(bus) => bus.unregister(client),
+                Garbage::Return(mut aux_return) => {
+                    aux_return.chain.unregister_buses(client);
+                    collector.dispose(aux_return);
+                }
+                Garbage::Split(mut split) => {
+                    for slot in split.branches.iter_mut().flatten() {
+                        slot.unregister_buses(client);
+                    }
+                    collector.dispose(split);
+                }
+                Garbage::Strip(strip) => collector.dispose(strip.unregister(client)),
+                Garbage::Render(render) => {
+                    set_freewheel(client, false);
+                    if let Err(e) = render.save(client.sample_rate() as u32) {
+                        log::error!("Error saving {}: {}", render.path.display(), e);
+                    }
+                    self.rendering = None;
+                }
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 enum Message {
     Scan,
+    SetCategory(Category),
+    FilterChanged(String),
+    ToggleFavorite(String),
+    ToggleDetails(PluginInfo),
     LoadPlugin(PluginInfo),
+    /// Loads a plugin dropped at an index of the selected chain.
+    LoadPluginAt(PluginInfo, usize),
+    DragPlugin(PluginInfo),
+    EndDrag,
+    LoadImpulseResponse,
+    AddLooper,
+    LooperAction(Uuid, LooperAction),
     DeletePlugin(Uuid),
+    Undo,
+    Redo,
     MovePluginUp(Uuid),
     MovePluginDown(Uuid),
     ParamChange(Uuid, ParameterInfo, f32),
+    /// Moves a parameter's slider, which moves the parameter less with Shift held.
+    DragParam(Uuid, ParameterInfo, f32),
+    ReleaseParam,
+    EditParam(Uuid, usize),
+    ParamEntryChange(String),
+    SubmitParamEntry,
+    CancelParamEntry,
+    ModifiersChanged(keyboard::Modifiers),
+    SetBypass(Uuid, bool),
+    SelectPlugin(Uuid),
+    SelectPreviousPlugin,
+    SelectNextPlugin,
+    ToggleSelectedBypass,
+    LoadUserPreset(Uuid, String),
+    SaveUserPreset(Uuid),
+    /// Switches a plugin between the two sides of its A/B comparison.
+    FlipCompare(Uuid),
+    /// Copies a plugin's current side of its A/B comparison to the other.
+    CopyCompare(Uuid),
+    ToggleParams(Uuid),
+    SetSlotColor(Uuid, SlotColor),
+    ToggleCollapsed(Uuid),
+    StartRename(Uuid),
+    RenameChange(String),
+    FinishRename,
+    SetParamPage(Uuid, usize),
+    ParamFilterChange(Uuid, String),
+    /// Excludes a parameter from randomizing and nudging, or includes it again.
+    ToggleParamLock(Uuid, usize),
+    /// Shows every plugin's parameters as sliders or knobs, unless set for the plugin.
+    SetParamView(ParamView),
+    SetPluginParamView(Uuid, ParamView),
+    RandomizeParams(Uuid),
+    NudgeParams(Uuid),
+    MixChange(Uuid, f32),
+    InputGainChange(Uuid, f32),
+    OutputGainChange(Uuid, f32),
+    RoutingChange(Uuid, Routing),
+    SetDualMono(Uuid, bool),
+    SetBuses(Uuid, usize),
+    SetOutputBus(Uuid, OutputBus),
+    AddSplit,
+    SelectBranch(Uuid, usize),
+    BranchGainChange(Uuid, usize, f32),
+    SelectChain(usize),
+    SelectReturn(Option<usize>),
+    ChainNameChange(String),
+    AddChain,
+    /// Asks before carrying out an action that loses the session or part of it, or before
+    /// losing unsaved changes.
+    AskConfirm(Confirm),
+    /// Carries out the action asked about, after saving the session if `true`.
+    Confirmed(bool),
+    CancelConfirm,
+    ToggleLog,
+    ClearLog,
+    DismissNotification(u64),
+    DeleteChain(Uuid),
+    SetMute(Uuid, bool),
+    SetSolo(Uuid, bool),
+    AddReturn,
+    DeleteReturn(Uuid),
+    SendChange(Uuid, Uuid, f32),
     ClearSession,
     SaveSession,
+    SaveSessionAs,
     LoadSession,
+    OpenRecentSession(PathBuf),
+    SaveTemplate,
+    ExportBundle,
+    ImportChain,
+    SnapshotNameChange(String),
+    SaveSnapshot,
+    RecallSnapshot(usize),
+    DeleteSnapshot(usize),
+    PreviousSnapshot,
+    NextSnapshot,
+    SetlistNext,
+    SetlistPrevious,
+    GoToEntry(usize),
+    AddSessionToSetlist,
+    AddSnapshotToSetlist,
+    RemoveFromSetlist(usize),
+    ToggleProgramMap,
+    ProgramBankChange(String),
+    ProgramNumberChange(String),
+    /// Maps the bank and program entered to a setlist entry or snapshot.
+    MapProgram(ProgramTarget),
+    UnmapProgram(usize),
+    NewFromTemplate(String),
     VolumeChange(f32),
+    SetLimiter(bool),
+    SetDcBlocker(bool),
+    SetMonitor(Monitor),
+    TogglePanic,
+    ToggleMasterMute,
+    ToggleShortcuts,
+    ResizePanes(pane_grid::ResizeEvent),
+    SetMetronome(bool),
+    MetronomeVolumeChange(f32),
+    SetClickSound(ClickSound),
+    SetClickToMain(bool),
+    Tap,
+    SetTapController(TapController),
+    SetLink(bool),
+    SetAlsaMidiInput(PortChoice),
+    ToggleMidiLearn,
+    /// Removes the mapping of a controller.
+    Unmap(u8),
+    SetAnalyzerTap(Option<Uuid>),
+    SetTuner(Option<Uuid>),
+    ToggleRecording,
+    SetRecordDry(bool),
+    RenderFile,
+    ResetXruns,
+    Reconnect,
+    DismissAlert(Uuid),
+    ReplaceMissingPort(usize, String),
+    DismissMissingPorts,
+    SetConnection(String, bool, PortChoice),
+    Frame,
     Exit,
 }
 
+/// Lets JACK run the process callback as fast as possible instead of in realtime.
+/// The jack crate doesn't wrap this, so it goes through jack_sys.
+fn set_freewheel(client: &jack::Client, enabled: bool) {
+    // SAFETY: the client pointer stays valid for as long as `client` is borrowed
+    if unsafe { jack::jack_sys::jack_set_freewheel(client.raw(), enabled as _) } != 0 {
+        log::error!("Error setting freewheel mode");
+    }
+}
+
+/// Collapses plugins found more than once, with the same vendor, name and version, into the
+/// one in the preferred format, or else the first one found. Also returns the paths of the
+/// others by the unique id of the one kept.
+fn collapse_duplicates(
+    plugins: Vec<PluginInfo>,
+    preferred_format: Option<&str>,
+) -> (Vec<PluginInfo>, HashMap<String, Vec<PathBuf>>) {
+    let preferred = |info: &PluginInfo| {
+        preferred_format.is_some_and(|format| {
+            info.path
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case(format))
+        })
+    };
+    let mut kept: Vec<PluginInfo> = Vec::with_capacity(plugins.len());
+    let mut others: Vec<Vec<PathBuf>> = Vec::with_capacity(plugins.len());
+    for mut info in plugins {
+        let Some(i) = kept.iter().position(|other| {
+            other.manufacturer == info.manufacturer
+                && other.name == info.name
+                && other.version == info.version
+        }) else {
+            kept.push(info);
+            others.push(Vec::new());
+            continue;
+        };
+        if preferred(&info) && !preferred(&kept[i]) {
+            std::mem::swap(&mut kept[i], &mut info);
+        }
+        others[i].push(info.path);
+    }
+    let duplicates = kept
+        .iter()
+        .zip(others)
+        .filter(|(_, paths)| !paths.is_empty())
+        .map(|(info, paths)| (info.unique_id.clone(), paths))
+        .collect();
+    (kept, duplicates)
+}
+
+/// Scans for plugins on a thread of its own, since a large plugin folder takes long enough
+/// to freeze the window. The result is picked up by the frame handler.
+fn start_scan(state: &mut AppState) {
+    if state.scanning.is_some() {
+        return;
+    }
+    state.scanning = Some(std::thread::spawn(|| {
+        scan::scan().map_err(|e| e.to_string())
+    }));
+}
+
 fn create_instance(scanner: &Scanner, info: &PluginInfo, client: &jack::Client) -> Result<Plugin> {
+    create_instance_at(
+        scanner,
+        info,
+        client.sample_rate(),
+        client.buffer_size() as usize,
+    )
+}
+
+/// Creates a plugin instance for a sample rate and buffer size, off the GUI thread too.
+fn create_instance_at(
+    scanner: &Scanner,
+    info: &PluginInfo,
+    sample_rate: usize,
+    buffer_size: usize,
+) -> Result<Plugin> {
     let mut plugin_instance = scanner.load(info)?;
-    let _ =
-        plugin_instance.initialize(client.sample_rate() as f64, client.buffer_size() as usize)?;
+    let _ = plugin_instance.initialize(sample_rate as f64, buffer_size)?;
     Ok(plugin_instance)
 }
 
-fn load_session(state: &mut AppState, path: &std::path::PathBuf) -> Result<Vec<LoadedPlugin>> {
-    let content = std::fs::read_to_string(path)?;
-    let mut saved_plugins = serde_yaml_ng::from_str::<Vec<LoadedPlugin>>(&content)
-        .map_err(|e| rack::Error::Other(format!("Incorrect YAML: {}", e)))?;
+/// Current parameter values of a plugin instance.
+fn read_params(plugin_instance: &Plugin) -> Vec<(ParameterInfo, f32)> {
+    let mut params = Vec::with_capacity(plugin_instance.parameter_count());
+    for i in 0..plugin_instance.parameter_count() {
+        params.push((
+            plugin_instance.parameter_info(i).unwrap(),
+            plugin_instance.get_parameter(i).unwrap(),
+        ));
+    }
+    params
+}
+
+fn create_unit(unit: &LoadedUnit, scanner: &Scanner, client: &jack::Client) -> Result<Unit> {
+    create_unit_at(
+        unit,
+        scanner,
+        client.sample_rate(),
+        client.buffer_size() as usize,
+    )
+}
 
-    for plugin in &mut saved_plugins {
-        plugin.id = Uuid::new_v4();
+/// Creates a unit for a sample rate and buffer size, off the GUI thread too.
+fn create_unit_at(
+    unit: &LoadedUnit,
+    scanner: &Scanner,
+    sample_rate: usize,
+    buffer_size: usize,
+) -> Result<Unit> {
+    match unit {
+        LoadedUnit::Plugin {
+            info,
+            dual_mono: false,
+        } => create_instance_at(scanner, info, sample_rate, buffer_size).map(Unit::Plugin),
+        LoadedUnit::Plugin {
+            info,
+            dual_mono: true,
+        } => Ok(Unit::DualMono(Box::new([
+            create_instance_at(scanner, info, sample_rate, buffer_size)?,
+            create_instance_at(scanner, info, sample_rate, buffer_size)?,
+        ]))),
+        LoadedUnit::Convolver { ir } => Convolver::load(ir, sample_rate, buffer_size)
+            .map(|convolver| Unit::Convolver(Box::new(convolver)))
+            .map_err(|e| rack::Error::Other(format!("Error loading {}: {}", ir.display(), e))),
+        LoadedUnit::Looper {
+            loop_seconds,
+            status,
+        } => Ok(Unit::Looper(Box::new(Looper::new(
+            *loop_seconds,
+            sample_rate,
+            status.clone(),
+        )))),
     }
+}
 
-    let _ = state
-        .command_sender
-        .as_mut()
-        .unwrap()
-        .try_push(Command::ClearSession)
-        .map_err(|_| rack::Error::Other("Error sending command to clear session".to_string()))?;
+/// Instantiates a scanned plugin and adds it to the selected chain, at `index` if given.
+fn load_plugin(state: &mut AppState, info: PluginInfo, index: Option<usize>) {
+    let recent = &mut state.config.recent;
+    recent.retain(|id| *id != info.unique_id);
+    recent.insert(0, info.unique_id.clone());
+    recent.truncate(MAX_RECENT);
+    if let Ok(plugin_instance) = create_instance(
+        state.plugin_scanner.as_ref().unwrap(),
+        &info,
+        state.jack_client.as_ref().unwrap().as_client(),
+    ) {
+        let params = read_params(&plugin_instance);
+        state
+            .presets
+            .entry(info.unique_id.clone())
+            .or_insert_with(|| presets::list(&info));
 
-    for plugin in &saved_plugins {
-        let plugin_instance = create_instance(
-            state.plugin_scanner.as_ref().unwrap(),
-            &plugin.info,
-            state.jack_client.as_ref().unwrap().as_client(),
-        )?;
+        let plugin = LoadedPlugin::new(
+            LoadedUnit::Plugin {
+                info,
+                dual_mono: false,
+            },
+            params,
+        );
 
-        let _ = state
-            .command_sender
-            .as_mut()
-            .unwrap()
-            .try_push(Command::LoadPlugin(plugin_instance, plugin.id))
-            .map_err(|_| rack::Error::Other(format!("Error sending plugin {}", plugin.info)))?;
+        let slot = Slot::new(Unit::Plugin(plugin_instance), plugin.id);
+        add_slot(state, slot, plugin, index);
+    }
+}
 
-        for param in &plugin.params {
-            let _ = state
+/// Sends a new slot to the selected chain, or the selected split branch, and adds it to the GUI.
+/// A slot dropped at an index of the chain goes there instead.
+fn add_slot(state: &mut AppState, slot: Slot, plugin: LoadedPlugin, index: Option<usize>) {
+    let chain_id = state.selected_id();
+    let branch = if index.is_some() {
+        None
+    } else {
+        state.load_target
+    };
+    if state.is_full(chain_id, branch) {
+        log::error!(
+            "Can't have more than {} plugins in a chain or branch",
+            MAX_SLOTS
+        );
+        return;
+    }
+    let command = match (state.load_target, index) {
+        (_, Some(index)) => Command::InsertPlugin(slot, chain_id, None, index),
+        (Some((split_id, branch)), None) => Command::LoadPluginIntoBranch(slot, split_id, branch),
+        (None, None) => Command::LoadPlugin(slot, chain_id),
+    };
+    match state.command_sender.as_mut().unwrap().try_push(command) {
+        Ok(_) => {
+            record(state, Edit::Delete(plugin.id));
+            match (state.load_target, index) {
+                (_, Some(index)) => {
+                    let nodes = state.selected_nodes_mut();
+                    nodes.insert(index.min(nodes.len()), ChainNode::Plugin(Box::new(plugin)));
+                }
+                (Some((split_id, branch)), None) => {
+                    if let Some(split) = state.split_mut(split_id) {
+                        split.branches[branch].push(plugin);
+                    }
+                }
+                (None, None) => state
+                    .selected_nodes_mut()
+                    .push(ChainNode::Plugin(Box::new(plugin))),
+            }
+        }
+        Err(_) => {
+            log::error!("Error sending plugin: {}", plugin.unit);
+        }
+    }
+}
+
+/// Random number from 0 to 1, taken from a random UUID rather than pulling in a generator.
+fn random_unit() -> f32 {
+    (Uuid::new_v4().as_u128() as u32 >> 8) as f32 / (1 << 24) as f32
+}
+
+/// Normalized value of a plugin's parameter.
+fn param_value(state: &mut AppState, plugin_id: Uuid, index: usize) -> Option<f32> {
+    let plugin = state.plugin_mut(plugin_id)?;
+    plugin
+        .params
+        .iter()
+        .find(|(info, _)| info.index == index)
+        .map(|(_, value)| *value)
+}
+
+/// Keeps the edit that reverts a change made from the GUI.
+fn record(state: &mut AppState, undo: Edit) {
+    let merge = state
+        .last_edit
+        .is_some_and(|last| last.elapsed() < UNDO_MERGE_INTERVAL)
+        && state
+            .undo
+            .last()
+            .is_some_and(|last| last.same_target(&undo));
+    state.last_edit = Some(Instant::now());
+    state.redo.clear();
+    // The first of the merged edits reverts them all
+    if !merge {
+        state.undo.push(undo);
+        if state.undo.len() > MAX_UNDO {
+            state.undo.remove(0);
+        }
+    }
+}
+
+/// Makes a change from the GUI that can be undone.
+fn edit(state: &mut AppState, edit: Edit) {
+    match apply_edit(state, edit) {
+        Ok(undo) => record(state, undo),
+        Err(e) => log::error!("{}", e),
+    }
+}
+
+/// Makes an edit in the processor and the GUI, returning the edit that reverts it.
+fn apply_edit(state: &mut AppState, edit: Edit) -> Result<Edit> {
+    match edit {
+        Edit::Insert {
+            plugin,
+            chain_id,
+            branch,
+            index,
+        } => {
+            if state.is_full(chain_id, branch) {
+                return Err(rack::Error::Other(format!(
+                    "Can't have more than {} plugins in a chain or branch",
+                    MAX_SLOTS
+                )));
+            }
+            if !send_plugin(state, &plugin, chain_id, branch, Some(index))? {
+                return Err(rack::Error::Other(format!(
+                    "Error restoring {}",
+                    plugin.unit
+                )));
+            }
+            let id = plugin.id;
+            match branch {
+                Some((split_id, branch)) => {
+                    if let Some(split) = state.split_mut(split_id) {
+                        let plugins = &mut split.branches[branch];
+                        plugins.insert(index.min(plugins.len()), *plugin);
+                    }
+                }
+                None => {
+                    if let Some(nodes) = state.chain_nodes_mut(chain_id) {
+                        nodes.insert(index.min(nodes.len()), ChainNode::Plugin(plugin));
+                    }
+                }
+            }
+            Ok(Edit::Delete(id))
+        }
+        Edit::Delete(id) => {
+            let (chain_id, branch, index) = state
+                .position(id)
+                .ok_or_else(|| rack::Error::Other("Plugin no longer exists".to_string()))?;
+            let mut plugin = state.plugin_mut(id).unwrap().clone();
+            plugin.on_b = false;
+            state
+                .command_sender
+                .as_mut()
+                .unwrap()
+                .try_push(Command::DeletePlugin(id))
+                .map_err(|_| {
+                    rack::Error::Other("Error sending command to delete plugin".to_string())
+                })?;
+            state.remove_node(id);
+            state.compare.remove(&id);
+            Ok(Edit::Insert {
+                plugin: Box::new(plugin),
+                chain_id,
+                branch,
+                index,
+            })
+        }
+        Edit::Move { id, up } => {
+            let command = if up {
+                Command::MovePluginUp(id)
+            } else {
+                Command::MovePluginDown(id)
+            };
+            state
+                .command_sender
+                .as_mut()
+                .unwrap()
+                .try_push(command)
+                .map_err(|_| {
+                    rack::Error::Other("Error sending command to move plugin".to_string())
+                })?;
+            state.move_node(id, up);
+            Ok(Edit::Move { id, up: !up })
+        }
+        Edit::Param {
+            plugin_id,
+            info,
+            value,
+        } => {
+            let old = state
+                .plugin_mut(plugin_id)
+                .map(|plugin| plugin.params[info.index].1)
+                .ok_or_else(|| rack::Error::Other("Plugin no longer exists".to_string()))?;
+            state
+                .command_sender
+                .as_mut()
+                .unwrap()
+                .try_push(Command::ParamChange(plugin_id, info.clone(), value))
+                .map_err(|_| {
+                    rack::Error::Other(format!("Error sending parameter {}", info.name))
+                })?;
+            if let Some(plugin) = state.plugin_mut(plugin_id) {
+                plugin.params[info.index].1 = value;
+            }
+            Ok(Edit::Param {
+                plugin_id,
+                info,
+                value: old,
+            })
+        }
+        Edit::Params { plugin_id, values } => {
+            let mut undo = Vec::new();
+            for (info, value) in values {
+                if let Edit::Param { info, value, .. } = apply_edit(
+                    state,
+                    Edit::Param {
+                        plugin_id,
+                        info,
+                        value,
+                    },
+                )? {
+                    undo.push((info, value));
+                }
+            }
+            Ok(Edit::Params {
+                plugin_id,
+                values: undo,
+            })
+        }
+        Edit::Volume(volume) => {
+            state
+                .command_sender
+                .as_mut()
+                .unwrap()
+                .try_push(Command::VolumeChange(volume_to_gain(volume)))
+                .map_err(|_| {
+                    rack::Error::Other("Error sending command to change volume".to_string())
+                })?;
+            let old = state.volume;
+            state.volume = volume;
+            Ok(Edit::Volume(old))
+        }
+    }
+}
+
+/// Saves the parameter values and bypass states of every plugin as a snapshot, replacing the
+/// one with the same name.
+fn save_snapshot(state: &mut AppState) {
+    let name = state.snapshot_name.trim().to_string();
+    if name.is_empty() {
+        return;
+    }
+    let plugins = state
+        .plugins_mut()
+        .map(|plugin| {
+            (
+                plugin.id,
+                PluginSnapshot {
+                    params: plugin.params.iter().map(|(_, value)| *value).collect(),
+                    bypass: plugin.bypass,
+                },
+            )
+        })
+        .collect();
+    let snapshot = Snapshot { name, plugins };
+    match state
+        .snapshots
+        .iter()
+        .position(|other| other.name == snapshot.name)
+    {
+        Some(i) => {
+            state.snapshots[i] = snapshot;
+            state.snapshot = Some(i);
+        }
+        None => {
+            state.snapshots.push(snapshot);
+            state.snapshot = Some(state.snapshots.len() - 1);
+        }
+    }
+    state.snapshot_name.clear();
+}
+
+/// Switches the plugins to a snapshot's parameter values and bypass states, leaving plugins
+/// added since as they are. Only the changes are sent, so they usually fit in the process
+/// thread's command budget for one cycle and start ramping together.
+fn recall_snapshot(state: &mut AppState, i: usize) {
+    let Some(snapshot) = state.snapshots.get(i).cloned() else {
+        return;
+    };
+    let mut params: Vec<(Uuid, ParameterInfo, f32)> = Vec::new();
+    let mut bypasses: Vec<(Uuid, bool)> = Vec::new();
+    for plugin in state.plugins_mut() {
+        let Some(saved) = snapshot.plugins.get(&plugin.id) else {
+            continue;
+        };
+        for ((info, value), saved_value) in plugin.params.iter().zip(&saved.params) {
+            if value != saved_value {
+                params.push((plugin.id, info.clone(), *saved_value));
+            }
+        }
+        if plugin.bypass != saved.bypass {
+            bypasses.push((plugin.id, saved.bypass));
+        }
+    }
+
+    for (plugin_id, info, value) in params {
+        let index = info.index;
+        match send_waiting(state, Command::ParamChange(plugin_id, info, value)) {
+            Ok(_) => {
+                if let Some(plugin) = state.plugin_mut(plugin_id) {
+                    plugin.params[index].1 = value;
+                }
+            }
+            Err(_) => {
+                log::error!("Error sending snapshot {}", snapshot.name);
+                return;
+            }
+        }
+    }
+    for (plugin_id, bypass) in bypasses {
+        match send_waiting(state, Command::SetBypass(plugin_id, bypass)) {
+            Ok(_) => {
+                if let Some(plugin) = state.plugin_mut(plugin_id) {
+                    plugin.bypass = bypass;
+                }
+            }
+            Err(_) => {
+                log::error!("Error sending snapshot {}", snapshot.name);
+                return;
+            }
+        }
+    }
+    state.snapshot = Some(i);
+}
+
+/// Maps a controller learned for a control, replacing the control's previous controller.
+fn map_controller(state: &mut AppState, mapping: MidiMapping) {
+    state
+        .midi_mappings
+        .retain(|other| other.controller != mapping.controller && other.target != mapping.target);
+    if state.midi_mappings.len() == midi::MAX_MAPPINGS {
+        log::error!(
+            "Error mapping CC {}: too many controllers are mapped",
+            mapping.controller
+        );
+        return;
+    }
+    match state
+        .command_sender
+        .as_mut()
+        .unwrap()
+        .try_push(Command::MapController(mapping))
+    {
+        Ok(_) => state.midi_mappings.push(mapping),
+        Err(_) => log::error!("Error sending command to map controller"),
+    }
+}
+
+/// Shows the value a mapped controller moved its parameter or the volume to.
+fn show_mapped(state: &mut AppState, mapping: MidiMapping, value: u8) {
+    let value = mapping.value(value);
+    match mapping.target {
+        MidiTarget::Param {
+            plugin_id, index, ..
+        } => {
+            if let Some(plugin) = state.plugin_mut(plugin_id)
+                && let Some(param) = plugin
+                    .params
+                    .iter_mut()
+                    .find(|(info, _)| info.index == index)
+            {
+                param.1 = value;
+            }
+        }
+        MidiTarget::Volume => state.volume = value,
+    }
+}
+
+/// Gives a plugin the parameters of `settings`.
+fn apply_settings(state: &mut AppState, plugin_id: Uuid, settings: Settings) -> Result<()> {
+    if state.plugin_mut(plugin_id).is_none() {
+        return Ok(());
+    }
+    let command_sender = state.command_sender.as_mut().unwrap();
+    for (info, value) in &settings.params {
+        if command_sender
+            .try_push(Command::ParamChange(plugin_id, info.clone(), *value))
+            .is_err()
+        {
+            log::error!("Error sending command to change parameter");
+        }
+    }
+    if let Some(plugin) = state.plugin_mut(plugin_id) {
+        plugin.params = settings.params;
+    }
+    Ok(())
+}
+
+/// Time a command waits for room in the queue to the process thread while a session is sent.
+const SEND_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Sends a command, waiting for the process thread to make room if the queue is full, since
+/// it only takes so many commands each cycle.
+fn send_waiting(state: &mut AppState, command: Command) -> std::result::Result<(), ()> {
+    let start = Instant::now();
+    let mut command = command;
+    loop {
+        match state.command_sender.as_mut().unwrap().try_push(command) {
+            Ok(_) => return Ok(()),
+            Err(returned) if start.elapsed() < SEND_TIMEOUT => {
+                command = returned;
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            Err(_) => return Err(()),
+        }
+    }
+}
+
+/// Instantiates a saved plugin and sends it to the processor along with its settings.
+/// Returns false, raising an alert, if the plugin can't be instantiated, e.g. because it
+/// was uninstalled.
+fn send_plugin(
+    state: &mut AppState,
+    plugin: &LoadedPlugin,
+    chain_id: Uuid,
+    branch: Option<(Uuid, usize)>,
+    index: Option<usize>,
+) -> Result<bool> {
+    let created = match state.preloaded.remove(&plugin.id) {
+        Some(unit) => Ok(unit),
+        None => create_unit(
+            &plugin.unit,
+            state.plugin_scanner.as_ref().unwrap(),
+            state.jack_client.as_ref().unwrap().as_client(),
+        ),
+    };
+    let unit = match created {
+        Ok(unit) => unit,
+        Err(e) => {
+            let alert = format!("{} {} ({})", plugin.name(), Fault::Missing, e);
+            log::error!("{}", alert);
+            state.alerts.retain(|(id, ..)| *id != plugin.id);
+            state.alerts.push((plugin.id, Fault::Missing, alert));
+            return Ok(false);
+        }
+    };
+    if let LoadedUnit::Plugin { info, .. } = &plugin.unit {
+        state
+            .presets
+            .entry(info.unique_id.clone())
+            .or_insert_with(|| presets::list(info));
+    }
+
+    let slot = Slot::new(unit, plugin.id);
+    let command = match (branch, index) {
+        (_, Some(index)) => Command::InsertPlugin(slot, chain_id, branch, index),
+        (Some((split_id, branch)), None) => Command::LoadPluginIntoBranch(slot, split_id, branch),
+        (None, None) => Command::LoadPlugin(slot, chain_id),
+    };
+    send_waiting(state, command)
+        .map_err(|_| rack::Error::Other(format!("Error sending plugin {}", plugin.unit)))?;
+
+    for param in &plugin.params {
+        let _ = send_waiting(
+            state,
+            Command::ParamChange(plugin.id, param.0.clone(), param.1),
+        )
+        .map_err(|_| {
+            rack::Error::Other(format!(
+                "Error sending parameter {} of {}",
+                param.0.name, plugin.unit
+            ))
+        })?;
+    }
+
+    if plugin.bypass {
+        send_waiting(state, Command::SetBypass(plugin.id, true))
+            .map_err(|_| rack::Error::Other(format!("Error sending bypass of {}", plugin.unit)))?;
+    }
+
+    if plugin.mix != 1.0 {
+        send_waiting(state, Command::MixChange(plugin.id, plugin.mix))
+            .map_err(|_| rack::Error::Other(format!("Error sending mix of {}", plugin.unit)))?;
+    }
+
+    if plugin.input_gain != 0.0 {
+        send_waiting(
+            state,
+            Command::InputGainChange(plugin.id, db_to_gain(plugin.input_gain)),
+        )
+        .map_err(|_| rack::Error::Other(format!("Error sending input gain of {}", plugin.unit)))?;
+    }
+
+    if plugin.output_gain != 0.0 {
+        send_waiting(
+            state,
+            Command::OutputGainChange(plugin.id, db_to_gain(plugin.output_gain)),
+        )
+        .map_err(|_| rack::Error::Other(format!("Error sending output gain of {}", plugin.unit)))?;
+    }
+
+    if plugin.routing != Routing::Stereo {
+        send_waiting(state, Command::RoutingChange(plugin.id, plugin.routing))
+            .map_err(|_| rack::Error::Other(format!("Error sending routing of {}", plugin.unit)))?;
+    }
+
+    for (return_id, level) in &plugin.sends {
+        send_waiting(state, Command::SetSend(plugin.id, *return_id, *level)).map_err(|_| {
+            rack::Error::Other(format!("Error sending aux send of {}", plugin.unit))
+        })?;
+    }
+
+    for n in 1..=plugin.buses {
+        send_bus(state, plugin, n)?;
+    }
+
+    if plugin.output_bus != OutputBus::Main {
+        send_waiting(state, Command::SetOutputBus(plugin.id, plugin.output_bus)).map_err(|_| {
+            rack::Error::Other(format!("Error sending output bus of {}", plugin.unit))
+        })?;
+    }
+    Ok(true)
+}
+
+/// Name of the ports of a plugin's extra output bus, counting from 1, without the
+/// `_left`/`_right` suffix. Includes part of the plugin's id since names can repeat.
+fn bus_port_name(plugin: &LoadedPlugin, n: usize) -> String {
+    let name: String = plugin
+        .unit
+        .name()
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .collect();
+    format!(
+        "{}_{}_bus{}",
+        name,
+        &plugin.id.simple().to_string()[..8],
+        n + 1
+    )
+}
+
+/// Registers the ports of a plugin's extra output bus and sends the bus to its slot.
+fn send_bus(state: &mut AppState, plugin: &LoadedPlugin, n: usize) -> Result<()> {
+    let client = state.jack_client.as_ref().unwrap().as_client();
+    let name = bus_port_name(plugin, n);
+    let bus = Bus::register(client, &name, client.buffer_size() as usize).map_err(|e| {
+        rack::Error::Other(format!("Error registering ports of {}: {}", plugin.unit, e))
+    })?;
+    connections::restore(client, &state.config.connections, &name);
+    send_waiting(state, Command::AddBus(plugin.id, bus))
+        .map_err(|_| rack::Error::Other(format!("Error sending output bus of {}", plugin.unit)))
+}
+
+/// Sends a chain's plugins and splits, leaving out the plugins that can't be instantiated.
+fn send_nodes(state: &mut AppState, nodes: &mut Vec<ChainNode>, chain_id: Uuid) -> Result<()> {
+    nodes.truncate(MAX_SLOTS);
+    for node in std::mem::take(nodes) {
+        match node {
+            ChainNode::Plugin(plugin) => {
+                if send_plugin(state, &plugin, chain_id, None, None)? {
+                    nodes.push(ChainNode::Plugin(plugin));
+                }
+            }
+            ChainNode::Split(mut split) => {
+                let command = Command::AddSplit(Box::new(Split::new(split.id)), chain_id);
+                send_waiting(state, command)
+                    .map_err(|_| rack::Error::Other("Error sending split".to_string()))?;
+                for (i, branch) in split.branches.iter_mut().enumerate() {
+                    if split.gains[i] != 0.0 {
+                        send_waiting(
+                            state,
+                            Command::BranchGainChange(split.id, i, db_to_gain(split.gains[i])),
+                        )
+                        .map_err(|_| rack::Error::Other("Error sending branch gain".to_string()))?;
+                    }
+                    branch.truncate(MAX_SLOTS);
+                    for plugin in std::mem::take(branch) {
+                        if send_plugin(state, &plugin, chain_id, Some((split.id, i)), None)? {
+                            branch.push(plugin);
+                        }
+                    }
+                }
+                nodes.push(ChainNode::Split(split));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Registers the ports of a named chain and sends it to the processor.
+fn send_chain(state: &mut AppState, chain: &LoadedChain) -> Result<()> {
+    let prefix = port_prefix(chain);
+    let client = state.jack_client.as_ref().unwrap().as_client();
+    // A removed chain with the same name may not have handed back its ports yet
+    let in_left = format!("{}:{}in_left", client.name(), prefix);
+    for _ in 0..50 {
+        if state
+            .jack_client
+            .as_ref()
+            .unwrap()
+            .as_client()
+            .port_by_name(&in_left)
+            .is_none()
+        {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        state.collect_garbage();
+    }
+
+    let client = state.jack_client.as_ref().unwrap().as_client();
+    let ports = ChainPorts::register(client, &prefix).map_err(|e| {
+        rack::Error::Other(format!("Error registering ports of {}: {}", chain.name, e))
+    })?;
+    let strip = Strip::new(chain.id, ports, client.sample_rate());
+    if let Err(Command::AddChain(strip)) = state
+        .command_sender
+        .as_mut()
+        .unwrap()
+        .try_push(Command::AddChain(strip))
+    {
+        strip.unregister(state.jack_client.as_ref().unwrap().as_client());
+        return Err(rack::Error::Other(format!(
+            "Error sending chain {}",
+            chain.name
+        )));
+    }
+    connections::restore(
+        state.jack_client.as_ref().unwrap().as_client(),
+        &state.config.connections,
+        &prefix,
+    );
+    Ok(())
+}
+
+fn send_returns(state: &mut AppState, returns: &mut [LoadedReturn], chain_id: Uuid) -> Result<()> {
+    for aux_return in returns {
+        let buffer_size = state
+            .jack_client
+            .as_ref()
+            .unwrap()
+            .as_client()
+            .buffer_size();
+        send_waiting(
+            state,
+            Command::AddReturn(
+                AuxReturn::new(aux_return.id, buffer_size as usize),
+                chain_id,
+            ),
+        )
+        .map_err(|_| {
+            rack::Error::Other(format!("Error sending return chain {}", aux_return.name))
+        })?;
+        send_nodes(state, &mut aux_return.nodes, aux_return.id)?;
+    }
+    Ok(())
+}
+
+fn reassign_ids(nodes: &mut [ChainNode]) {
+    for node in nodes {
+        match node {
+            ChainNode::Plugin(plugin) => plugin.id = Uuid::new_v4(),
+            ChainNode::Split(split) => {
+                split.id = Uuid::new_v4();
+                for plugin in split.branches.iter_mut().flatten() {
+                    plugin.id = Uuid::new_v4();
+                }
+            }
+        }
+    }
+}
+
+/// Reads a session file upgraded to the current format, and the version it was saved with.
+fn read_session(path: &Path) -> Result<(Session, u32)> {
+    let content = std::fs::read_to_string(path)?;
+    let mut yaml = serde_yaml_ng::from_str(&content)
+        .map_err(|e| rack::Error::Other(format!("Incorrect YAML: {}", e)))?;
+    let version = migrate::migrate(&mut yaml);
+    let mut session = serde_yaml_ng::from_value::<Session>(yaml)
+        .map_err(|e| rack::Error::Other(format!("Incorrect YAML: {}", e)))?;
+
+    // Bundles refer to their impulse responses relative to the session
+    if let Some(dir) = path.parent() {
+        for plugin in session.plugins_mut() {
+            if let LoadedUnit::Convolver { ir } = &mut plugin.unit
+                && ir.is_relative()
+            {
+                *ir = dir.join(&*ir);
+            }
+        }
+    }
+    Ok((session, version))
+}
+
+fn load_session(state: &mut AppState, path: &Path) -> Result<Session> {
+    let (mut session, version) = read_session(path)?;
+
+    let old_ids: Vec<Uuid> = session.plugins_mut().map(|plugin| plugin.id).collect();
+    // Return chains keep their ids since the sends refer to them
+    reassign_ids(&mut session.plugins);
+    for aux_return in &mut session.returns {
+        reassign_ids(&mut aux_return.nodes);
+    }
+    for chain in &mut session.chains {
+        chain.id = Uuid::new_v4();
+        reassign_ids(&mut chain.nodes);
+        for aux_return in &mut chain.returns {
+            reassign_ids(&mut aux_return.nodes);
+        }
+    }
+    // Snapshots refer to the plugins by id
+    let ids: HashMap<Uuid, Uuid> = old_ids
+        .into_iter()
+        .zip(session.plugins_mut().map(|plugin| plugin.id))
+        .collect();
+    for snapshot in &mut session.snapshots {
+        snapshot.plugins = std::mem::take(&mut snapshot.plugins)
+            .into_iter()
+            .filter_map(|(id, saved)| ids.get(&id).map(|new_id| (*new_id, saved)))
+            .collect();
+    }
+    session
+        .midi_mappings
+        .retain_mut(|mapping| match &mut mapping.target {
+            MidiTarget::Param { plugin_id, .. } => ids
+                .get(plugin_id)
+                .map(|new_id| *plugin_id = *new_id)
+                .is_some(),
+            MidiTarget::Volume => true,
+        });
+    if let Some(units) = take_preload(state, path) {
+        state.preloaded = units
+            .into_iter()
+            .filter_map(|(id, unit)| ids.get(&id).map(|new_id| (*new_id, unit)))
+            .collect();
+    }
+    session.chains.truncate(MAX_CHAINS - 1);
+
+    if version > SESSION_VERSION {
+        log::error!(
+            "{} was saved by a newer version of Rake, so some of it may not be restored",
+            path.display()
+        );
+    }
+    // Older sessions didn't save the volume, so it stays as it is
+    if version == 0 {
+        session.volume = state.volume;
+    }
+
+    let sent = send_session(state, &mut session);
+    // Left over if the chains were cut short or sending failed
+    let collector = state.collector.as_ref().unwrap();
+    for (_, unit) in state.preloaded.drain() {
+        collector.dispose(unit);
+    }
+    sent?;
+    // Sessions saved before connections were don't change them
+    state.missing_ports.clear();
+    if !session.connections.is_empty() {
+        let client = state.jack_client.as_ref().unwrap().as_client();
+        state.missing_ports = connections::apply(client, &session.connections);
+    }
+    Ok(session)
+}
+
+/// Takes the units preloaded for a session file, waiting for them if they aren't done yet.
+/// Units initialized for another sample rate or buffer size are dropped.
+fn take_preload(state: &mut AppState, path: &Path) -> Option<HashMap<Uuid, Unit>> {
+    let preload = state.preload.take_if(|preload| preload.path == path)?;
+    let units = preload.units.join().ok()?;
+    let client = state.jack_client.as_ref().unwrap().as_client();
+    if preload.format == (client.sample_rate(), client.buffer_size() as usize) {
+        Some(units)
+    } else {
+        let collector = state.collector.as_ref().unwrap();
+        for unit in units.into_values() {
+            collector.dispose(unit);
+        }
+        None
+    }
+}
+
+/// Starts instantiating the plugins and impulse responses of the setlist's next entry in the
+/// background if it's a session, so switching to it doesn't wait for them.
+fn preload_next(state: &mut AppState) {
+    let next = state.setlist_position.map_or(0, |i| i + 1);
+    let Some(SetlistEntry::Session(path)) = state.config.setlist.get(next).cloned() else {
+        state.preload = None;
+        return;
+    };
+    if state
+        .preload
+        .as_ref()
+        .is_some_and(|preload| preload.path == path)
+    {
+        return;
+    }
+    let client = state.jack_client.as_ref().unwrap().as_client();
+    let format = (client.sample_rate(), client.buffer_size() as usize);
+    let session_path = path.clone();
+    let units = std::thread::spawn(move || preload_session(&session_path, format));
+    state.preload = Some(Preload {
+        path,
+        format,
+        units,
+    });
+}
+
+/// Instantiates the units of a session file, by the ids of their plugins there. Loopers are
+/// left out since they share their status with the GUI.
+fn preload_session(path: &Path, (sample_rate, buffer_size): (usize, usize)) -> HashMap<Uuid, Unit> {
+    let (mut session, _) = match read_session(path) {
+        Ok(session) => session,
+        Err(e) => {
+            log::error!("Error preloading {}: {}", path.display(), e);
+            return HashMap::new();
+        }
+    };
+    let scanner = match Scanner::new() {
+        Ok(scanner) => scanner,
+        Err(e) => {
+            log::error!("Error preloading {}: {}", path.display(), e);
+            return HashMap::new();
+        }
+    };
+    session
+        .plugins_mut()
+        .filter(|plugin| !matches!(plugin.unit, LoadedUnit::Looper { .. }))
+        .filter_map(|plugin| {
+            create_unit_at(&plugin.unit, &scanner, sample_rate, buffer_size)
+                .ok()
+                .map(|unit| (plugin.id, unit))
+        })
+        .collect()
+}
+
+/// Opens a setlist entry's session or recalls its snapshot, and preloads the next entry.
+fn go_to_entry(state: &mut AppState, i: usize) {
+    let Some(entry) = state.config.setlist.get(i).cloned() else {
+        return;
+    };
+    match entry {
+        SetlistEntry::Session(path) => {
+            if let Err(e) = open_session(state, path) {
+                log::error!("{}", e);
+            }
+        }
+        SetlistEntry::Snapshot(name) => recall_snapshot_named(state, &name),
+    }
+    state.setlist_position = Some(i);
+    preload_next(state);
+}
+
+fn recall_snapshot_named(state: &mut AppState, name: &str) {
+    match state
+        .snapshots
+        .iter()
+        .position(|snapshot| snapshot.name == name)
+    {
+        Some(snapshot) => recall_snapshot(state, snapshot),
+        None => log::error!("The session has no snapshot {}", name),
+    }
+}
+
+/// Switches to what a program change is mapped to. Unmapped program changes of bank 0 pick the
+/// setlist entry numbered by the program.
+fn program_change(state: &mut AppState, bank: u16, program: u8) {
+    let target = state
+        .config
+        .program_map
+        .iter()
+        .find(|mapping| mapping.bank == bank && mapping.program == program)
+        .map(|mapping| mapping.target.clone());
+    match target {
+        Some(ProgramTarget::Entry(i)) => go_to_entry(state, i),
+        Some(ProgramTarget::Snapshot(name)) => recall_snapshot_named(state, &name),
+        None if bank == 0 && (program as usize) < state.config.setlist.len() => {
+            go_to_entry(state, program as usize);
+        }
+        None => {}
+    }
+}
+
+/// Replaces everything in the processor with the chains of `session`. Plugins that can't be
+/// instantiated are removed from `session`.
+fn send_session(state: &mut AppState, session: &mut Session) -> Result<()> {
+    let _ = send_waiting(state, Command::ClearSession)
+        .map_err(|_| rack::Error::Other("Error sending command to clear session".to_string()))?;
+
+    send_waiting(state, Command::SetLimiter(session.limiter))
+        .map_err(|_| rack::Error::Other("Error sending command to set limiter".to_string()))?;
+
+    send_waiting(state, Command::SetDcBlocker(session.dc_blocker))
+        .map_err(|_| rack::Error::Other("Error sending command to set DC filter".to_string()))?;
+
+    send_waiting(state, Command::VolumeChange(volume_to_gain(session.volume)))
+        .map_err(|_| rack::Error::Other("Error sending command to change volume".to_string()))?;
+
+    for mapping in &session.midi_mappings {
+        send_waiting(state, Command::MapController(*mapping)).map_err(|_| {
+            rack::Error::Other("Error sending command to map controller".to_string())
+        })?;
+    }
+
+    send_returns(state, &mut session.returns, MAIN_CHAIN)?;
+    send_nodes(state, &mut session.plugins, MAIN_CHAIN)?;
+    for chain in &mut session.chains {
+        send_chain(state, chain)?;
+        send_returns(state, &mut chain.returns, chain.id)?;
+        send_nodes(state, &mut chain.nodes, chain.id)?;
+    }
+    Ok(())
+}
+
+/// Puts a session file first in the recent sessions.
+fn remember_session(state: &mut AppState, path: &Path) {
+    let recent = &mut state.config.recent_sessions;
+    recent.retain(|recent_path| recent_path != path);
+    recent.insert(0, path.to_path_buf());
+    recent.truncate(MAX_RECENT_SESSIONS);
+}
+
+/// Shows the chains and settings of a session that was loaded.
+fn show_session(state: &mut AppState, session: Session) {
+    state.chains.truncate(1);
+    state.chains[0].nodes = session.plugins;
+    state.chains[0].returns = session.returns;
+    state.chains.extend(session.chains);
+    state.selected_chain = 0;
+    state.selected_return = None;
+    state.load_target = None;
+    state.analyzer_tap = None;
+    state.compare.clear();
+    state.undo.clear();
+    state.redo.clear();
+    state.snapshots = session.snapshots;
+    state.snapshot = None;
+    state.midi_mappings = session.midi_mappings;
+    state.learn_target = None;
+    state.limiter = session.limiter;
+    state.dc_blocker = session.dc_blocker;
+    state.volume = session.volume;
+    state.saved_session = state.saved_form();
+}
+
+fn open_session(state: &mut AppState, path: PathBuf) -> Result<()> {
+    match load_session(state, &path) {
+        Ok(session) => {
+            show_session(state, session);
+            remember_session(state, &path);
+            state.session_path = path;
+            Ok(())
+        }
+        Err(e) => {
+            // Most likely moved or deleted
+            state
+                .config
+                .recent_sessions
+                .retain(|recent_path| *recent_path != path);
+            Err(rack::Error::Other(format!(
+                "Error loading {}: {}",
+                path.display(),
+                e
+            )))
+        }
+    }
+}
+
+fn session_yaml(state: &AppState) -> String {
+    serde_yaml_ng::to_string(&state.session()).unwrap()
+}
+
+fn save_session(state: &mut AppState, path: PathBuf) -> Result<()> {
+    let content = session_yaml(state);
+    match std::fs::write(&path, content) {
+        Ok(_) => {
+            remember_session(state, &path);
+            state.session_path = path;
+            state.saved_session = state.saved_form();
+            Ok(())
+        }
+        Err(e) => Err(rack::Error::Other(format!(
+            "Error writing {}: {}",
+            path.display(),
+            e
+        ))),
+    }
+}
+
+/// Saves the session under a new name, which it is saved under from then on.
+fn save_session_as(state: &mut AppState) {
+    if let Some(path) = FileDialog::new()
+        .set_directory(state.session_path.parent().unwrap_or_else(|| Path::new("")))
+        .add_filter("YAML", &["yaml"])
+        .set_file_name(".yaml")
+        .save_file()
+        && let Err(e) = save_session(state, path)
+    {
+        log::error!("{}", e);
+    }
+}
+
+/// Exports the session with the files it uses, to open on another machine.
+fn export_bundle(state: &AppState) {
+    if let Some(path) = FileDialog::new()
+        .set_directory(state.session_path.parent().unwrap_or_else(|| Path::new("")))
+        .add_filter("Zip", &["zip"])
+        .set_file_name(".zip")
+        .save_file()
+        && let Err(e) = bundle::export(&path, state.session())
+    {
+        log::error!("Error exporting {}: {}", path.display(), e);
+    }
+}
+
+/// Adds the plugins of a Carla project or a mod-host command script to the selected chain,
+/// leaving out those that aren't available here.
+fn import_chain(state: &mut AppState) {
+    let Some(path) = FileDialog::new()
+        .add_filter("Carla project", &["carxp"])
+        .add_filter("mod-host commands", &["txt"])
+        .add_filter("All files", &["*"])
+        .pick_file()
+    else {
+        return;
+    };
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) => {
+            log::error!("Error reading {}: {}", path.display(), e);
+            return;
+        }
+    };
+    let foreign_plugins = if path.extension().is_some_and(|ext| ext == "carxp") {
+        match import::carla(&content) {
+            Ok(plugins) => plugins,
+            Err(e) => {
+                log::error!("Error importing {}: {}", path.display(), e);
+                return;
+            }
+        }
+    } else {
+        import::mod_host(&content)
+    };
+
+    let chain_id = state.selected_id();
+    for foreign in foreign_plugins {
+        if state.is_full(chain_id, None) {
+            log::error!("Can't have more than {} plugins in a chain", MAX_SLOTS);
+            break;
+        }
+        let found = state
+            .scanned_plugins
+            .iter()
+            .find(|info| foreign.path.as_ref() == Some(&info.path))
+            .or_else(|| {
+                foreign.names.iter().find_map(|name| {
+                    state
+                        .scanned_plugins
+                        .iter()
+                        .find(|info| import::same_name(&info.name, name))
+                })
+            })
+            .cloned();
+        let Some(info) = found else {
+            let alert = format!("{} {} (not found)", foreign.name(), Fault::Missing);
+            log::error!("{}", alert);
+            state.alerts.push((Uuid::new_v4(), Fault::Missing, alert));
+            continue;
+        };
+        // Only to list the parameters, as send_plugin makes the instance that runs
+        let mut params = match create_instance(
+            state.plugin_scanner.as_ref().unwrap(),
+            &info,
+            state.jack_client.as_ref().unwrap().as_client(),
+        ) {
+            Ok(plugin_instance) => read_params(&plugin_instance),
+            Err(e) => {
+                log::error!("Error loading {}: {}", info, e);
+                continue;
+            }
+        };
+        for (param_info, value) in &mut params {
+            if let Some((_, foreign_value)) = foreign
+                .params
+                .iter()
+                .find(|(name, _)| import::same_name(name, &param_info.name))
+            {
+                *value = view::normalized_value(param_info, *foreign_value);
+            }
+        }
+
+        let plugin = LoadedPlugin {
+            bypass: foreign.bypass,
+            mix: foreign.mix.clamp(0.0, 1.0),
+            output_gain: gain_to_db(foreign.volume).clamp(-24.0, 24.0),
+            ..LoadedPlugin::new(
+                LoadedUnit::Plugin {
+                    info,
+                    dual_mono: false,
+                },
+                params,
+            )
+        };
+        match send_plugin(state, &plugin, chain_id, None, None) {
+            Ok(true) => {
+                record(state, Edit::Delete(plugin.id));
+                state
+                    .selected_nodes_mut()
+                    .push(ChainNode::Plugin(Box::new(plugin)));
+            }
+            Ok(false) => {}
+            Err(e) => log::error!("{}", e),
+        }
+    }
+}
+
+/// Carries out a request from `rake ctl`, OSC or the web API, returning the reply.
+fn handle_request(
+    state: &mut AppState,
+    request: api::Request,
+) -> std::result::Result<api::Reply, String> {
+    let done = |message: String| Ok(api::Reply::Done { message });
+    match request {
+        api::Request::Status => Ok(api::Reply::Status(status(state))),
+        api::Request::Load { path } => {
+            open_session(state, path.clone()).map_err(|e| e.to_string())?;
+            done(format!("Opened {}", path.display()))
+        }
+        api::Request::Bypass { plugin, bypass } => {
+            let id = find_plugin(state, &plugin)?;
+            let bypass =
+                bypass.unwrap_or_else(|| state.plugin_mut(id).is_some_and(|plugin| !plugin.bypass));
+            let _ = update(state, Message::SetBypass(id, bypass));
+            if state
+                .plugin_mut(id)
+                .is_some_and(|plugin| plugin.bypass != bypass)
+            {
+                return Err("Error sending command to set bypass".to_string());
+            }
+            done(format!("Bypass {}", if bypass { "on" } else { "off" }))
+        }
+        api::Request::Param {
+            plugin,
+            param,
+            value,
+        } => {
+            let id = find_plugin(state, &plugin)?;
+            let info = state
+                .plugin_mut(id)
+                .and_then(|plugin| {
+                    plugin
+                        .params
+                        .iter()
+                        .map(|(info, _)| info)
+                        .find(|info| match &param {
+                            api::ParamRef::Index(index) => info.index == *index,
+                            api::ParamRef::Name(name) => info.name.eq_ignore_ascii_case(name),
+                        })
+                        .cloned()
+                })
+                .ok_or_else(|| match &param {
+                    api::ParamRef::Index(index) => format!("No parameter {}", index),
+                    api::ParamRef::Name(name) => format!("No parameter {}", name),
+                })?;
+            // Values come in the parameter's own range, but are kept normalized
+            let value = view::normalized_value(&info, value);
+            let reply = format!("{} = {}", info.name, view::plain_value(&info, value));
+            let undo = apply_edit(
+                state,
+                Edit::Param {
+                    plugin_id: id,
+                    info,
+                    value,
+                },
+            )
+            .map_err(|e| e.to_string())?;
+            record(state, undo);
+            done(reply)
+        }
+        api::Request::Volume { volume } => {
+            let volume = volume.clamp(VOLUME_MIN_DB, VOLUME_MAX_DB);
+            let undo = apply_edit(state, Edit::Volume(volume)).map_err(|e| e.to_string())?;
+            record(state, undo);
+            done(format!("Volume {:+.1} dB", volume))
+        }
+        api::Request::Next | api::Request::Previous => {
+            let message = if matches!(request, api::Request::Next) {
+                Message::SetlistNext
+            } else {
+                Message::SetlistPrevious
+            };
+            let _ = update(state, message);
+            match state.setlist_position {
+                Some(i) => done(format!("{}. {}", i + 1, state.config.setlist[i])),
+                None => Err("No setlist entry".to_string()),
+            }
+        }
+    }
+}
+
+/// Carries out an OSC message. `/rake/plugins` lists the plugins and `/rake/params PLUGIN` a
+/// plugin's parameters; any other `/rake/COMMAND` takes the arguments of the `rake ctl` command
+/// and is answered with `/rake/reply` or `/rake/error`.
+fn osc_request(state: &mut AppState, message: osc::Message) -> Vec<osc::Message> {
+    let command = message
+        .address
+        .strip_prefix("/rake/")
+        .unwrap_or(&message.address);
+    let args: Vec<String> = message.args.iter().map(osc::Arg::to_string).collect();
+    let reply = match (command, &args[..]) {
+        ("plugins", []) => Ok(status(state)
+            .plugins()
+            .map(|plugin| {
+                osc::Message::new(
+                    "/rake/plugin",
+                    vec![
+                        osc::Arg::String(plugin.id.to_string()),
+                        osc::Arg::String(plugin.name.clone()),
+                        osc::Arg::Int(plugin.bypass as i32),
+                    ],
+                )
+            })
+            .collect()),
+        ("params", [plugin]) => find_plugin(state, plugin).map(|id| {
+            status(state)
+                .plugins()
+                .filter(|plugin| plugin.id == id)
+                .flat_map(|plugin| &plugin.params)
+                .map(|param| {
+                    osc::Message::new(
+                        "/rake/param",
+                        vec![
+                            osc::Arg::String(id.to_string()),
+                            osc::Arg::Int(param.index as i32),
+                            osc::Arg::Float(param.value),
+                            osc::Arg::String(param.name.clone()),
+                        ],
+                    )
+                })
+                .collect()
+        }),
+        _ => {
+            let words: Vec<String> = std::iter::once(command.to_string()).chain(args).collect();
+            api::Request::parse(&words)
+                .and_then(|request| handle_request(state, request))
+                .map(|reply| {
+                    vec![osc::Message::new(
+                        "/rake/reply",
+                        vec![osc::Arg::String(reply.to_string())],
+                    )]
+                })
+        }
+    };
+    reply.unwrap_or_else(|e| vec![osc::Message::new("/rake/error", vec![osc::Arg::String(e)])])
+}
+
+/// Sends the OSC and WebSocket clients every plugin's peak levels, and whatever changed since
+/// they were last sent, when their feedback is due.
+fn send_feedback(state: &mut AppState) {
+    let osc_due = state
+        .osc
+        .as_mut()
+        .is_some_and(|osc| osc.has_clients() && osc.feedback.due());
+    let web_due = state.web.as_mut().is_some_and(|web| {
+        web.welcome();
+        web.has_clients() && web.feedback.due()
+    });
+    if !osc_due && !web_due {
+        return;
+    }
+    let status = status(state);
+    let meters: Vec<api::Event> = status
+        .plugins()
+        .filter_map(|plugin| {
+            state
+                .levels
+                .get(&plugin.id)
+                .map(|(input, output, _)| api::Event::Meter {
+                    plugin: plugin.id,
+                    input: input.peak,
+                    output: output.peak,
+                })
+        })
+        .collect();
+    if osc_due {
+        let osc = state.osc.as_mut().unwrap();
+        let changes = osc.feedback.changes(&status);
+        for event in meters.iter().chain(&changes) {
+            osc.broadcast(&osc::event_message(event));
+        }
+    }
+    if web_due {
+        let web = state.web.as_mut().unwrap();
+        let mut events = web.feedback.changes(&status);
+        events.extend(meters);
+        web.broadcast(&events);
+    }
+}
+
+/// Finds a plugin by its id, or else by its label or name regardless of case.
+fn find_plugin(state: &mut AppState, plugin: &str) -> std::result::Result<Uuid, String> {
+    let id = Uuid::parse_str(plugin).ok();
+    state
+        .plugins_mut()
+        .find(|loaded| {
+            Some(loaded.id) == id
+                || loaded.label.eq_ignore_ascii_case(plugin)
+                || loaded.unit.name().eq_ignore_ascii_case(plugin)
+        })
+        .map(|loaded| loaded.id)
+        .ok_or_else(|| format!("No plugin {}", plugin))
+}
+
+/// The session, its chains and their plugins, for remote control.
+fn status(state: &AppState) -> api::Status {
+    let plugin_status = |plugin: &LoadedPlugin| api::PluginStatus {
+        id: plugin.id,
+        name: plugin.name(),
+        bypass: plugin.bypass,
+        params: plugin
+            .params
+            .iter()
+            .map(|(info, value)| api::ParamStatus {
+                index: info.index,
+                name: info.name.clone(),
+                value: view::plain_value(info, *value),
+                min: info.min,
+                max: info.max,
+                unit: info.unit.clone(),
+            })
+            .collect(),
+    };
+    let nodes_status = |nodes: &[ChainNode]| -> Vec<api::NodeStatus> {
+        nodes
+            .iter()
+            .map(|node| match node {
+                ChainNode::Plugin(plugin) => api::NodeStatus::Plugin(plugin_status(plugin)),
+                ChainNode::Split(split) => api::NodeStatus::Split {
+                    id: split.id,
+                    branches: split
+                        .branches
+                        .iter()
+                        .map(|branch| branch.iter().map(plugin_status).collect())
+                        .collect(),
+                },
+            })
+            .collect()
+    };
+    api::Status {
+        session: (!state.session_path.as_os_str().is_empty()).then(|| state.session_path.clone()),
+        setlist_entry: state
+            .setlist_position
+            .map(|i| format!("{}. {}", i + 1, state.config.setlist[i])),
+        snapshot: state.snapshot.map(|i| state.snapshots[i].name.clone()),
+        volume: state.volume,
+        chains: state
+            .chains
+            .iter()
+            .map(|chain| api::ChainStatus {
+                id: chain.id,
+                name: chain.name.clone(),
+                nodes: nodes_status(&chain.nodes),
+                returns: chain
+                    .returns
+                    .iter()
+                    .map(|aux_return| api::ChainStatus {
+                        id: aux_return.id,
+                        name: aux_return.name.clone(),
+                        nodes: nodes_status(&aux_return.nodes),
+                        returns: Vec::new(),
+                    })
+                    .collect(),
+            })
+            .collect(),
+    }
+}
+
+/// Directory of the session templates, which new sessions start from.
+fn templates_dir() -> Option<PathBuf> {
+    config::dir().map(|dir| dir.join("templates"))
+}
+
+/// Saves the session as a template. Templates are only ever saved this way, so saving the
+/// sessions started from them doesn't change them.
+fn save_template(state: &mut AppState) {
+    let Some(dir) = templates_dir() else {
+        return;
+    };
+    if let Err(e) = std::fs::create_dir_all(&dir) {
+        log::error!("Error creating {}: {}", dir.display(), e);
+        return;
+    }
+    let Some(path) = FileDialog::new()
+        .set_directory(&dir)
+        .add_filter("YAML", &["yaml"])
+        .set_file_name(".yaml")
+        .save_file()
+    else {
+        return;
+    };
+    let content = session_yaml(state);
+    match std::fs::write(&path, content) {
+        Ok(_) => state.templates = presets::yaml_names(&dir),
+        Err(e) => log::error!("Error writing {}: {}", path.display(), e),
+    }
+}
+
+/// Starts a new, unsaved session from a template.
+fn new_from_template(state: &mut AppState, name: &str) {
+    let Some(dir) = templates_dir() else {
+        return;
+    };
+    let path = dir.join(format!("{}.yaml", name));
+    match load_session(state, &path) {
+        Ok(session) => {
+            show_session(state, session);
+            state.session_path = PathBuf::new();
+        }
+        Err(e) => {
+            log::error!("Error loading {}: {}", path.display(), e);
+        }
+    }
+}
+
+fn update(state: &mut AppState, message: Message) -> Task<Message> {
+    state.collect_garbage();
+
+    match message {
+        Message::Scan => {
+            start_scan(state);
+            Task::none()
+        }
+        Message::LoadPlugin(info) => {
+            load_plugin(state, info, None);
+            Task::none()
+        }
+        Message::LoadPluginAt(info, index) => {
+            state.dragging = None;
+            load_plugin(state, info, Some(index));
+            Task::none()
+        }
+        Message::DragPlugin(info) => {
+            state.dragging = Some(info);
+            Task::none()
+        }
+        Message::EndDrag => {
+            state.dragging = None;
+            Task::none()
+        }
+        Message::LoadImpulseResponse => {
+            if let Some(path) = FileDialog::new().add_filter("WAV", &["wav"]).pick_file() {
+                let client = state.jack_client.as_ref().unwrap().as_client();
+                match Convolver::load(&path, client.sample_rate(), client.buffer_size() as usize) {
+                    Ok(convolver) => {
+                        let plugin =
+                            LoadedPlugin::new(LoadedUnit::Convolver { ir: path }, Vec::new());
+                        let slot = Slot::new(Unit::Convolver(Box::new(convolver)), plugin.id);
+                        add_slot(state, slot, plugin, None);
+                    }
+                    Err(e) => {
+                        log::error!("Error loading {}: {}", path.display(), e);
+                    }
+                }
+            }
+            Task::none()
+        }
+        Message::AddLooper => {
+            let plugin = LoadedPlugin::new(
+                LoadedUnit::Looper {
+                    loop_seconds: looper::LOOP_SECONDS,
+                    status: Arc::default(),
+                },
+                Vec::new(),
+            );
+            match create_unit(
+                &plugin.unit,
+                state.plugin_scanner.as_ref().unwrap(),
+                state.jack_client.as_ref().unwrap().as_client(),
+            ) {
+                Ok(unit) => {
+                    let slot = Slot::new(unit, plugin.id);
+                    add_slot(state, slot, plugin, None);
+                }
+                Err(e) => {
+                    log::error!("Error creating looper: {}", e);
+                }
+            }
+            Task::none()
+        }
+        Message::LooperAction(id, action) => {
+            if state
+                .command_sender
+                .as_mut()
+                .unwrap()
+                .try_push(Command::LooperAction(id, action))
+                .is_err()
+            {
+                log::error!("Error sending looper action");
+            }
+            Task::none()
+        }
+        Message::DeletePlugin(id) if state.plugin_mut(id).is_some() => {
+            edit(state, Edit::Delete(id));
+            Task::none()
+        }
+        Message::DeletePlugin(id) => {
+            match state
+                .command_sender
+                .as_mut()
+                .unwrap()
+                .try_push(Command::DeletePlugin(id))
+            {
+                Ok(_) => {
+                    state.remove_node(id);
+                    state.compare.remove(&id);
+                }
+                Err(_) => {
+                    log::error!("Error sending command to delete plugin");
+                }
+            }
+            Task::none()
+        }
+        Message::MovePluginUp(id) => {
+            edit(state, Edit::Move { id, up: true });
+            Task::none()
+        }
+        Message::MovePluginDown(id) => {
+            edit(state, Edit::Move { id, up: false });
+            Task::none()
+        }
+        Message::ParamChange(plugin_id, info, value) => {
+            if state.midi_learn {
+                state.learn_target = Some(MidiTarget::Param {
+                    plugin_id,
+                    index: info.index,
+                    min: info.min,
+                    max: info.max,
+                });
+            }
+            edit(
+                state,
+                Edit::Param {
+                    plugin_id,
+                    info,
+                    value,
+                },
+            );
+            Task::none()
+        }
+        Message::DragParam(plugin_id, info, value) => {
+            let value = if state.modifiers.shift() {
+                let (start, pointer) = match state.fine_drag {
+                    Some((id, index, start, pointer)) if id == plugin_id && index == info.index => {
+                        (start, pointer)
+                    }
+                    // The parameter starts from where it was rather than jumping to the pointer
+                    _ => {
+                        let start = param_value(state, plugin_id, info.index).unwrap_or(value);
+                        state.fine_drag = Some((plugin_id, info.index, start, value));
+                        (start, value)
+                    }
+                };
+                (start + (value - pointer) * FINE_DRAG_SCALE).clamp(0.0, 1.0)
+            } else {
+                state.fine_drag = None;
+                value
+            };
+            update(state, Message::ParamChange(plugin_id, info, value))
+        }
+        Message::ReleaseParam => {
+            state.fine_drag = None;
+            Task::none()
+        }
+        Message::EditParam(plugin_id, index) => {
+            let Some(plugin) = state.plugin_mut(plugin_id) else {
+                return Task::none();
+            };
+            let Some((info, value)) = plugin.params.iter().find(|(info, _)| info.index == index)
+            else {
+                return Task::none();
+            };
+            state.param_entry = Some(ParamEntry {
+                plugin_id,
+                index,
+                text: view::value_label(info, *value),
+                invalid: false,
+            });
+            Task::batch([
+                iced::widget::operation::focus(view::PARAM_INPUT),
+                iced::widget::operation::select_all(view::PARAM_INPUT),
+            ])
+        }
+        Message::ParamEntryChange(text) => {
+            if let Some(entry) = &mut state.param_entry {
+                entry.text = text;
+                entry.invalid = false;
+            }
+            Task::none()
+        }
+        Message::SubmitParamEntry => {
+            let Some(entry) = &mut state.param_entry else {
+                return Task::none();
+            };
+            let (plugin_id, index) = (entry.plugin_id, entry.index);
+            let text = entry.text.clone();
+            let Some(info) = state.plugin_mut(plugin_id).and_then(|plugin| {
+                plugin
+                    .params
+                    .iter()
+                    .find(|(info, _)| info.index == index)
+                    .map(|(info, _)| info.clone())
+            }) else {
+                state.param_entry = None;
+                return Task::none();
+            };
+            match view::parse_value(&info, &text) {
+                Some(value) => {
+                    state.param_entry = None;
+                    update(state, Message::ParamChange(plugin_id, info, value))
+                }
+                None => {
+                    if let Some(entry) = &mut state.param_entry {
+                        entry.invalid = true;
+                    }
+                    Task::none()
+                }
+            }
+        }
+        Message::CancelParamEntry => {
+            state.param_entry = None;
+            Task::none()
+        }
+        Message::ModifiersChanged(modifiers) => {
+            state.modifiers = modifiers;
+            Task::none()
+        }
+        Message::Undo => {
+            if let Some(undo) = state.undo.pop() {
+                match apply_edit(state, undo) {
+                    Ok(redo) => state.redo.push(redo),
+                    Err(e) => log::error!("Error undoing: {}", e),
+                }
+                state.last_edit = None;
+            }
+            Task::none()
+        }
+        Message::Redo => {
+            if let Some(redo) = state.redo.pop() {
+                match apply_edit(state, redo) {
+                    Ok(undo) => state.undo.push(undo),
+                    Err(e) => log::error!("Error redoing: {}", e),
+                }
+                state.last_edit = None;
+            }
+            Task::none()
+        }
+        Message::ToggleParams(plugin_id) => {
+            if let Some(plugin) = state.plugin_mut(plugin_id) {
+                plugin.hide_params = !plugin.hide_params;
+            }
+            Task::none()
+        }
+        Message::SetSlotColor(plugin_id, color) => {
+            if let Some(plugin) = state.plugin_mut(plugin_id) {
+                plugin.color = color;
+            }
+            Task::none()
+        }
+        Message::ToggleCollapsed(plugin_id) => {
+            if let Some(plugin) = state.plugin_mut(plugin_id) {
+                plugin.collapsed = !plugin.collapsed;
+            }
+            Task::none()
+        }
+        Message::StartRename(plugin_id) => {
+            if let Some(plugin) = state.plugin_mut(plugin_id) {
+                let label = plugin.label.clone();
+                state.renaming = Some((plugin_id, label));
+            }
+            iced::widget::operation::focus(view::RENAME_INPUT)
+        }
+        Message::RenameChange(label) => {
+            if let Some((_, renaming)) = &mut state.renaming {
+                *renaming = label;
+            }
+            Task::none()
+        }
+        Message::FinishRename => {
+            if let Some((plugin_id, label)) = state.renaming.take()
+                && let Some(plugin) = state.plugin_mut(plugin_id)
+            {
+                plugin.label = label.trim().to_string();
+            }
+            Task::none()
+        }
+        Message::SetParamPage(plugin_id, page) => {
+            if let Some(plugin) = state.plugin_mut(plugin_id) {
+                plugin.param_page = page;
+            }
+            Task::none()
+        }
+        Message::ParamFilterChange(plugin_id, filter) => {
+            if let Some(plugin) = state.plugin_mut(plugin_id) {
+                plugin.param_filter = filter;
+                plugin.param_page = 0;
+            }
+            Task::none()
+        }
+        Message::SetParamView(view) => {
+            state.config.param_view = view;
+            state.config.save();
+            Task::none()
+        }
+        Message::SetPluginParamView(plugin_id, view) => {
+            if let Some(plugin) = state.plugin_mut(plugin_id) {
+                plugin.param_view = Some(view);
+            }
+            Task::none()
+        }
+        Message::ToggleParamLock(plugin_id, index) => {
+            if let Some(plugin) = state.plugin_mut(plugin_id) {
+                if let Some(position) = plugin.locked.iter().position(|locked| *locked == index) {
+                    plugin.locked.remove(position);
+                } else {
+                    plugin.locked.push(index);
+                }
+            }
+            Task::none()
+        }
+        Message::RandomizeParams(plugin_id) => {
+            let Some(plugin) = state.plugin_mut(plugin_id) else {
+                return Task::none();
+            };
+            let values = plugin
+                .params
+                .iter()
+                .filter(|(info, _)| !plugin.locked.contains(&info.index))
+                .map(|(info, _)| {
+                    let value = if view::is_switch(info) {
+                        random_unit().round()
+                    } else {
+                        random_unit()
+                    };
+                    (info.clone(), value)
+                })
+                .collect();
+            edit(state, Edit::Params { plugin_id, values });
+            Task::none()
+        }
+        Message::NudgeParams(plugin_id) => {
+            let Some(plugin) = state.plugin_mut(plugin_id) else {
+                return Task::none();
+            };
+            // Switches would only flip at the ends of their range
+            let values = plugin
+                .params
+                .iter()
+                .filter(|(info, _)| !plugin.locked.contains(&info.index) && !view::is_switch(info))
+                .map(|(info, value)| {
+                    let nudge = (random_unit() * 2.0 - 1.0) * view::NUDGE_AMOUNT;
+                    (info.clone(), (value + nudge).clamp(0.0, 1.0))
+                })
+                .collect();
+            edit(state, Edit::Params { plugin_id, values });
+            Task::none()
+        }
+        Message::SetBypass(plugin_id, bypass) => {
+            match state
+                .command_sender
+                .as_mut()
+                .unwrap()
+                .try_push(Command::SetBypass(plugin_id, bypass))
+            {
+                Ok(_) => {
+                    if let Some(plugin) = state.plugin_mut(plugin_id) {
+                        plugin.bypass = bypass
+                    }
+                    if !bypass {
+                        state.alerts.retain(|(id, ..)| *id != plugin_id);
+                    }
+                }
+                Err(_) => {
+                    log::error!("Error sending command to set bypass");
+                }
+            }
+            Task::none()
+        }
+        Message::SelectPlugin(plugin_id) => {
+            state.selected_plugin = Some(plugin_id);
+            Task::none()
+        }
+        Message::SelectPreviousPlugin | Message::SelectNextPlugin => {
+            let plugins = state.shown_plugins();
+            let position = state
+                .selected_plugin
+                .and_then(|id| plugins.iter().position(|other| *other == id));
+            // Without a plugin selected, the first or last one is
+            let next = match (position, matches!(message, Message::SelectNextPlugin)) {
+                (Some(i), true) => (i + 1).min(plugins.len() - 1),
+                (Some(i), false) => i.saturating_sub(1),
+                (None, true) => 0,
+                (None, false) => plugins.len().saturating_sub(1),
+            };
+            if let Some(id) = plugins.get(next) {
+                state.selected_plugin = Some(*id);
+            }
+            Task::none()
+        }
+        Message::ToggleSelectedBypass => {
+            let Some(plugin_id) = state.selected_plugin else {
+                return Task::none();
+            };
+            match state.plugin_mut(plugin_id) {
+                Some(plugin) => {
+                    let bypass = !plugin.bypass;
+                    update(state, Message::SetBypass(plugin_id, bypass))
+                }
+                None => Task::none(),
+            }
+        }
+        Message::MixChange(plugin_id, mix) => {
+            match state
+                .command_sender
+                .as_mut()
+                .unwrap()
+                .try_push(Command::MixChange(plugin_id, mix))
+            {
+                Ok(_) => {
+                    if let Some(plugin) = state.plugin_mut(plugin_id) {
+                        plugin.mix = mix
+                    }
+                }
+                Err(_) => {
+                    log::error!("Error sending command to change mix");
+                }
+            }
+            Task::none()
+        }
+        Message::InputGainChange(plugin_id, db) => {
+            match state
+                .command_sender
+                .as_mut()
+                .unwrap()
+                .try_push(Command::InputGainChange(plugin_id, db_to_gain(db)))
+            {
+                Ok(_) => {
+                    if let Some(plugin) = state.plugin_mut(plugin_id) {
+                        plugin.input_gain = db
+                    }
+                }
+                Err(_) => {
+                    log::error!("Error sending command to change input gain");
+                }
+            }
+            Task::none()
+        }
+        Message::OutputGainChange(plugin_id, db) => {
+            match state
+                .command_sender
+                .as_mut()
+                .unwrap()
+                .try_push(Command::OutputGainChange(plugin_id, db_to_gain(db)))
+            {
+                Ok(_) => {
+                    if let Some(plugin) = state.plugin_mut(plugin_id) {
+                        plugin.output_gain = db
+                    }
+                }
+                Err(_) => {
+                    log::error!("Error sending command to change output gain");
+                }
+            }
+            Task::none()
+        }
+        Message::RoutingChange(plugin_id, routing) => {
+            match state
+                .command_sender
+                .as_mut()
+                .unwrap()
+                .try_push(Command::RoutingChange(plugin_id, routing))
+            {
+                Ok(_) => {
+                    if let Some(plugin) = state.plugin_mut(plugin_id) {
+                        plugin.routing = routing
+                    }
+                }
+                Err(_) => {
+                    log::error!("Error sending command to change routing");
+                }
+            }
+            Task::none()
+        }
+        Message::LoadUserPreset(plugin_id, name) => {
+            let Some(LoadedPlugin {
+                unit: LoadedUnit::Plugin { info, .. },
+                ..
+            }) = state.plugin_mut(plugin_id)
+            else {
+                return Task::none();
+            };
+            let result = presets::load(info, &name)
+                .and_then(|settings| apply_settings(state, plugin_id, settings));
+            if let Err(e) = result {
+                log::error!("Error loading preset {}: {}", name, e);
+            }
+            Task::none()
+        }
+        Message::FlipCompare(plugin_id) => {
+            let Some(plugin) = state.plugin_mut(plugin_id) else {
+                return Task::none();
+            };
+            let current = Settings {
+                params: plugin.params.clone(),
+            };
+            let on_b = plugin.on_b;
+            // The other side starts out as a copy of the first
+            let other = state
+                .compare
+                .get(&plugin_id)
+                .cloned()
+                .unwrap_or_else(|| current.clone());
+            match apply_settings(state, plugin_id, other) {
+                Ok(_) => {
+                    state.compare.insert(plugin_id, current);
+                    if let Some(plugin) = state.plugin_mut(plugin_id) {
+                        plugin.on_b = !on_b;
+                    }
+                }
+                Err(e) => {
+                    log::error!("Error switching to {}: {}", if on_b { "A" } else { "B" }, e);
+                }
+            }
+            Task::none()
+        }
+        Message::CopyCompare(plugin_id) => {
+            if let Some(plugin) = state.plugin_mut(plugin_id) {
+                let current = Settings {
+                    params: plugin.params.clone(),
+                };
+                state.compare.insert(plugin_id, current);
+            }
+            Task::none()
+        }
+        Message::SaveUserPreset(plugin_id) => {
+            let Some(LoadedPlugin {
+                unit: LoadedUnit::Plugin { info, .. },
+                ..
+            }) = state.plugin_mut(plugin_id)
+            else {
+                return Task::none();
+            };
+            let info = info.clone();
+            let Some(dir) = presets::dir(&info) else {
+                return Task::none();
+            };
+            if let Err(e) = std::fs::create_dir_all(&dir) {
+                log::error!("Error creating {}: {}", dir.display(), e);
+                return Task::none();
+            }
+            let Some(path) = FileDialog::new()
+                .set_directory(&dir)
+                .add_filter("YAML", &["yaml"])
+                .set_file_name(".yaml")
+                .save_file()
+            else {
+                return Task::none();
+            };
+            let Some(plugin) = state.plugin_mut(plugin_id) else {
+                return Task::none();
+            };
+            let settings = Settings {
+                params: plugin.params.clone(),
+            };
+            match presets::save(&path, &settings) {
+                Ok(_) => {
+                    if let Some(user_presets) = state.presets.get_mut(&info.unique_id) {
+                        *user_presets = presets::list(&info);
+                    }
+                }
+                Err(e) => {
+                    log::error!("Error writing {}: {}", path.display(), e);
+                }
+            }
+            Task::none()
+        }
+        Message::SetDualMono(plugin_id, dual_mono) => {
+            let Some(LoadedPlugin {
+                unit: LoadedUnit::Plugin { info, .. },
+                params,
+                ..
+            }) = state.plugin_mut(plugin_id)
+            else {
+                return Task::none();
+            };
+            let unit = LoadedUnit::Plugin {
+                info: info.clone(),
+                dual_mono,
+            };
+            let params = params.clone();
+            let slot = match create_unit(
+                &unit,
+                state.plugin_scanner.as_ref().unwrap(),
+                state.jack_client.as_ref().unwrap().as_client(),
+            ) {
+                Ok(new_unit) => Slot::new(new_unit, plugin_id),
+                Err(e) => {
+                    log::error!("Error creating plugin {}: {}", unit, e);
+                    return Task::none();
+                }
+            };
+            let command_sender = state.command_sender.as_mut().unwrap();
+            match command_sender.try_push(Command::ReplacePlugin(slot)) {
+                Ok(_) => {
+                    // The new instances start from their default parameters
+                    for (info, value) in params {
+                        if command_sender
+                            .try_push(Command::ParamChange(plugin_id, info, value))
+                            .is_err()
+                        {
+                            log::error!("Error sending command to change parameter");
+                        }
+                    }
+                    if let Some(plugin) = state.plugin_mut(plugin_id) {
+                        plugin.unit = unit;
+                    }
+                }
+                Err(_) => {
+                    log::error!("Error sending command to replace plugin");
+                }
+            }
+            Task::none()
+        }
+        Message::SetBuses(plugin_id, buses) => {
+            let Some(plugin) = state.plugin_mut(plugin_id).cloned() else {
+                return Task::none();
+            };
+            let mut sent = plugin.buses;
+            while sent < buses {
+                if let Err(e) = send_bus(state, &plugin, sent + 1) {
+                    log::error!("{}", e);
+                    break;
+                }
+                sent += 1;
+            }
+            while sent > buses {
+                if state
+                    .command_sender
+                    .as_mut()
+                    .unwrap()
+                    .try_push(Command::RemoveBus(plugin_id))
+                    .is_err()
+                {
+                    log::error!("Error sending command to remove output bus");
+                    break;
+                }
+                sent -= 1;
+            }
+            if let Some(plugin) = state.plugin_mut(plugin_id) {
+                plugin.buses = sent;
+            }
+            // The chain falls back to the main bus, which the GUI should show
+            if matches!(plugin.output_bus, OutputBus::Extra(n) if n > sent) {
+                return update(state, Message::SetOutputBus(plugin_id, OutputBus::Main));
+            }
+            Task::none()
+        }
+        Message::SetOutputBus(plugin_id, output_bus) => {
+            match state
+                .command_sender
+                .as_mut()
+                .unwrap()
+                .try_push(Command::SetOutputBus(plugin_id, output_bus))
+            {
+                Ok(_) => {
+                    if let Some(plugin) = state.plugin_mut(plugin_id) {
+                        plugin.output_bus = output_bus
+                    }
+                }
+                Err(_) => {
+                    log::error!("Error sending command to change output bus");
+                }
+            }
+            Task::none()
+        }
+        Message::AddSplit => {
+            let split = LoadedSplit {
+                id: Uuid::new_v4(),
+                branches: [Vec::new(), Vec::new()],
+                gains: [0.0, 0.0],
+            };
+            let chain_id = state.selected_id();
+            if state.is_full(chain_id, None) {
+                log::error!("Can't have more than {} plugins in a chain", MAX_SLOTS);
+                return Task::none();
+            }
+            let command = Command::AddSplit(Box::new(Split::new(split.id)), chain_id);
+            match state.command_sender.as_mut().unwrap().try_push(command) {
+                Ok(_) => {
+                    state.load_target = Some((split.id, 0));
+                    state.selected_nodes_mut().push(ChainNode::Split(split));
+                }
+                Err(_) => {
+                    log::error!("Error sending command to add split");
+                }
+            }
+            Task::none()
+        }
+        Message::SelectBranch(split_id, branch) => {
+            state.load_target = if state.load_target == Some((split_id, branch)) {
+                None
+            } else {
+                Some((split_id, branch))
+            };
+            Task::none()
+        }
+        Message::BranchGainChange(split_id, branch, db) => {
+            match state
+                .command_sender
+                .as_mut()
+                .unwrap()
+                .try_push(Command::BranchGainChange(split_id, branch, db_to_gain(db)))
+            {
+                Ok(_) => {
+                    if let Some(split) = state.split_mut(split_id) {
+                        split.gains[branch] = db
+                    }
+                }
+                Err(_) => {
+                    log::error!("Error sending command to change branch gain");
+                }
+            }
+            Task::none()
+        }
+        Message::SelectChain(i) => {
+            state.selected_chain = i;
+            state.selected_return = None;
+            state.load_target = None;
+            Task::none()
+        }
+        Message::SelectReturn(i) => {
+            state.selected_return = i;
+            state.load_target = None;
+            Task::none()
+        }
+        Message::ChainNameChange(name) => {
+            state.chain_name = name;
+            Task::none()
+        }
+        Message::AddChain => {
+            let name = state.chain_name.trim().to_string();
+            if name.is_empty() || state.chains.iter().any(|chain| chain.name == name) {
+                log::error!("Chain name must be unique and not empty");
+            } else if state.chains.len() == MAX_CHAINS {
+                log::error!("Can't have more than {} chains", MAX_CHAINS);
+            } else {
+                let chain = LoadedChain {
+                    id: Uuid::new_v4(),
+                    name,
+                    nodes: Vec::new(),
+                    returns: Vec::new(),
+                    mute: false,
+                    solo: false,
+                };
+                match send_chain(state, &chain) {
+                    Ok(_) => {
+                        state.chains.push(chain);
+                        state.selected_chain = state.chains.len() - 1;
+                        state.selected_return = None;
+                        state.load_target = None;
+                        state.chain_name.clear();
+                    }
+                    Err(e) => {
+                        log::error!("Error adding chain: {}", e);
+                    }
+                }
+            }
+            Task::none()
+        }
+        Message::DeleteChain(id) => {
+            match state
+                .command_sender
+                .as_mut()
+                .unwrap()
+                .try_push(Command::DeleteChain(id))
+            {
+                Ok(_) => {
+                    state.chains.retain(|chain| chain.id != id);
+                    state.selected_chain = 0;
+                    state.selected_return = None;
+                    state.load_target = None;
+                    if state.analyzer_tap == Some(id) {
+                        state.analyzer_tap = None;
+                    }
+                    if state.tuner_input == Some(id) {
+                        state.tuner_input = None;
+                    }
+                }
+                Err(_) => {
+                    log::error!("Error sending command to delete chain");
+                }
+            }
+            Task::none()
+        }
+        Message::SetMute(id, mute) => {
+            match state
+                .command_sender
+                .as_mut()
+                .unwrap()
+                .try_push(Command::SetMute(id, mute))
+            {
+                Ok(_) => {
+                    if let Some(chain) = state.chains.iter_mut().find(|chain| chain.id == id) {
+                        chain.mute = mute;
+                    }
+                }
+                Err(_) => {
+                    log::error!("Error sending command to mute chain");
+                }
+            }
+            Task::none()
+        }
+        Message::SetSolo(id, solo) => {
+            match state
+                .command_sender
+                .as_mut()
+                .unwrap()
+                .try_push(Command::SetSolo(id, solo))
+            {
+                Ok(_) => {
+                    if let Some(chain) = state.chains.iter_mut().find(|chain| chain.id == id) {
+                        chain.solo = solo;
+                    }
+                }
+                Err(_) => {
+                    log::error!("Error sending command to solo chain");
+                }
+            }
+            Task::none()
+        }
+        Message::AddReturn => {
+            let name = state.chain_name.trim().to_string();
+            let returns = &state.chains[state.selected_chain].returns;
+            if name.is_empty() || returns.iter().any(|aux_return| aux_return.name == name) {
+                log::error!("Return chain name must be unique and not empty");
+            } else if returns.len() == MAX_RETURNS {
+                log::error!("Can't have more than {} return chains", MAX_RETURNS);
+            } else {
+                let aux_return = LoadedReturn {
+                    id: Uuid::new_v4(),
+                    name,
+                    nodes: Vec::new(),
+                };
+                let buffer_size = state
+                    .jack_client
+                    .as_ref()
+                    .unwrap()
+                    .as_client()
+                    .buffer_size();
+                match state
+                    .command_sender
+                    .as_mut()
+                    .unwrap()
+                    .try_push(Command::AddReturn(
+                        AuxReturn::new(aux_return.id, buffer_size as usize),
+                        state.chains[state.selected_chain].id,
+                    )) {
+                    Ok(_) => {
+                        let returns = &mut state.chains[state.selected_chain].returns;
+                        returns.push(aux_return);
+                        state.selected_return = Some(returns.len() - 1);
+                        state.load_target = None;
+                        state.chain_name.clear();
+                    }
+                    Err(_) => {
+                        log::error!("Error sending command to add return chain");
+                    }
+                }
+            }
+            Task::none()
+        }
+        Message::DeleteReturn(id) => {
+            match state
+                .command_sender
+                .as_mut()
+                .unwrap()
+                .try_push(Command::DeleteReturn(id))
+            {
+                Ok(_) => {
+                    for chain in &mut state.chains {
+                        chain.returns.retain(|aux_return| aux_return.id != id);
+                    }
+                    for node in state.nodes_mut() {
+                        match node {
+                            ChainNode::Plugin(plugin) => {
+                                plugin.sends.retain(|(return_id, _)| *return_id != id)
+                            }
+                            ChainNode::Split(split) => {
+                                for plugin in split.branches.iter_mut().flatten() {
+                                    plugin.sends.retain(|(return_id, _)| *return_id != id);
+                                }
+                            }
+                        }
+                    }
+                    state.selected_return = None;
+                    state.load_target = None;
+                }
+                Err(_) => {
+                    log::error!("Error sending command to delete return chain");
+                }
+            }
+            Task::none()
+        }
+        Message::SendChange(plugin_id, return_id, level) => {
+            match state
+                .command_sender
+                .as_mut()
+                .unwrap()
+                .try_push(Command::SetSend(plugin_id, return_id, level))
+            {
+                Ok(_) => {
+                    if let Some(plugin) = state.plugin_mut(plugin_id) {
+                        if level == 0.0 {
+                            plugin.sends.retain(|(id, _)| *id != return_id);
+                        } else if let Some(send) =
+                            plugin.sends.iter_mut().find(|(id, _)| *id == return_id)
+                        {
+                            send.1 = level;
+                        } else {
+                            plugin.sends.push((return_id, level));
+                        }
+                    }
+                }
+                Err(_) => {
+                    log::error!("Error sending command to change aux send");
+                }
+            }
+            Task::none()
+        }
+        // Under a session manager closing the window only hides it
+        Message::AskConfirm(Confirm::Exit)
+            if state.nsm.as_ref().is_some_and(|nsm| nsm.optional_gui) =>
+        {
+            update(state, Message::Exit)
+        }
+        Message::AskConfirm(action) => {
+            let unsaved = !matches!(action, Confirm::DeletePlugin(_)) && state.unsaved();
+            let question = match &action {
+                Confirm::ClearSession => {
+                    "Clear the session? Every chain, snapshot and MIDI mapping is removed."
+                        .to_string()
+                }
+                Confirm::DeletePlugin(id) => format!(
+                    "Delete {}?",
+                    state
+                        .plugin_mut(*id)
+                        .map_or("the split".to_string(), |plugin| plugin.name().to_string())
+                ),
+                // Only asked about to save the changes first
+                _ if !unsaved => return update(state, action.message()),
+                Confirm::Open(_) => "Open another session?".to_string(),
+                Confirm::NewFromTemplate(name) => format!("Start a new session from {}?", name),
+                Confirm::Exit => "Quit Rake?".to_string(),
+            };
+            state.confirmation = Some(Confirmation {
+                action,
+                question,
+                unsaved,
+            });
+            Task::none()
+        }
+        Message::Confirmed(save) => {
+            let Some(confirmation) = state.confirmation.take() else {
+                return Task::none();
+            };
+            if save {
+                let saved = if state.session_path.exists() {
+                    let path = state.session_path.clone();
+                    save_session(state, path)
+                        .inspect_err(|e| log::error!("{}", e))
+                        .is_ok()
+                } else {
+                    save_session_as(state);
+                    !state.unsaved()
+                };
+                // Not saved, or the file dialog was cancelled
+                if !saved {
+                    return Task::none();
+                }
+            }
+            update(state, confirmation.action.message())
+        }
+        Message::CancelConfirm => {
+            state.confirmation = None;
+            Task::none()
+        }
+        Message::ToggleLog => {
+            state.log_open = !state.log_open;
+            state.log.unread = 0;
+            Task::none()
+        }
+        Message::ClearLog => {
+            state.log.clear();
+            Task::none()
+        }
+        Message::DismissNotification(id) => {
+            state.log.dismiss(id);
+            Task::none()
+        }
+        Message::ClearSession => {
+            match state
+                .command_sender
+                .as_mut()
+                .unwrap()
+                .try_push(Command::ClearSession)
+            {
+                Ok(_) => {
+                    state.chains.truncate(1);
+                    state.chains[0].nodes.clear();
+                    state.chains[0].returns.clear();
+                    state.analyzer_tap = None;
+                    state.selected_chain = 0;
+                    state.selected_return = None;
+                    state.load_target = None;
+                    state.snapshots.clear();
+                    state.snapshot = None;
+                    state.midi_mappings.clear();
+                    state.learn_target = None;
+                    state.saved_session = state.saved_form();
+                }
+                Err(_) => {
+                    log::error!("Error sending command to clear session");
+                }
+            }
+            Task::none()
+        }
+        Message::SaveSession => {
+            if state.session_path.exists() {
+                let path = state.session_path.clone();
+                if let Err(e) = save_session(state, path) {
+                    log::error!("{}", e);
+                }
+            } else {
+                save_session_as(state);
+            }
+            Task::none()
+        }
+        Message::SaveSessionAs => {
+            save_session_as(state);
+            Task::none()
+        }
+        Message::LoadSession => {
+            if let Some(path) = FileDialog::new()
+                .set_directory(state.session_path.parent().unwrap_or_else(|| Path::new("")))
+                .add_filter("YAML", &["yaml"])
+                .pick_file()
+                && let Err(e) = open_session(state, path)
+            {
+                log::error!("{}", e);
+            }
+            Task::none()
+        }
+        Message::OpenRecentSession(path) => {
+            if let Err(e) = open_session(state, path) {
+                log::error!("{}", e);
+            }
+            Task::none()
+        }
+        Message::SaveTemplate => {
+            save_template(state);
+            Task::none()
+        }
+        Message::ExportBundle => {
+            export_bundle(state);
+            Task::none()
+        }
+        Message::SnapshotNameChange(name) => {
+            state.snapshot_name = name;
+            Task::none()
+        }
+        Message::SaveSnapshot => {
+            save_snapshot(state);
+            Task::none()
+        }
+        Message::RecallSnapshot(i) => {
+            recall_snapshot(state, i);
+            Task::none()
+        }
+        Message::DeleteSnapshot(i) => {
+            if i < state.snapshots.len() {
+                state.snapshots.remove(i);
+                state.snapshot = match state.snapshot {
+                    Some(current) if current == i => None,
+                    Some(current) if current > i => Some(current - 1),
+                    current => current,
+                };
+            }
+            Task::none()
+        }
+        Message::PreviousSnapshot => {
+            // Without a snapshot recalled, the last one is
+            let previous = match state.snapshot {
+                Some(i) => i.checked_sub(1),
+                None => state.snapshots.len().checked_sub(1),
+            };
+            if let Some(i) = previous {
+                recall_snapshot(state, i);
+            }
+            Task::none()
+        }
+        Message::NextSnapshot => {
+            let next = state.snapshot.map_or(0, |i| i + 1);
+            if next < state.snapshots.len() {
+                recall_snapshot(state, next);
+            }
+            Task::none()
+        }
+        Message::SetlistNext => {
+            let next = state.setlist_position.map_or(0, |i| i + 1);
+            if next < state.config.setlist.len() {
+                go_to_entry(state, next);
+            }
+            Task::none()
+        }
+        Message::SetlistPrevious => {
+            if let Some(i) = state.setlist_position
+                && i > 0
+            {
+                go_to_entry(state, i - 1);
+            }
+            Task::none()
+        }
+        Message::GoToEntry(i) => {
+            go_to_entry(state, i);
+            Task::none()
+        }
+        Message::AddSessionToSetlist => {
+            if let Some(path) = FileDialog::new()
+                .set_directory(state.session_path.parent().unwrap_or_else(|| Path::new("")))
+                .add_filter("YAML", &["yaml"])
+                .pick_file()
+            {
+                state.config.setlist.push(SetlistEntry::Session(path));
+                preload_next(state);
+            }
+            Task::none()
+        }
+        Message::AddSnapshotToSetlist => {
+            if let Some(i) = state.snapshot {
+                let name = state.snapshots[i].name.clone();
+                state.config.setlist.push(SetlistEntry::Snapshot(name));
+            }
+            Task::none()
+        }
+        Message::RemoveFromSetlist(i) => {
+            if i < state.config.setlist.len() {
+                state.config.setlist.remove(i);
+                state.setlist_position = match state.setlist_position {
+                    Some(current) if current == i => None,
+                    Some(current) if current > i => Some(current - 1),
+                    current => current,
+                };
+                // Program changes stay mapped to the same entries
+                state
+                    .config
+                    .program_map
+                    .retain_mut(|mapping| match &mut mapping.target {
+                        ProgramTarget::Entry(entry) if *entry == i => false,
+                        ProgramTarget::Entry(entry) => {
+                            if *entry > i {
+                                *entry -= 1;
+                            }
+                            true
+                        }
+                        ProgramTarget::Snapshot(_) => true,
+                    });
+                preload_next(state);
+            }
+            Task::none()
+        }
+        Message::ToggleProgramMap => {
+            state.program_map_open = !state.program_map_open;
+            Task::none()
+        }
+        Message::ProgramBankChange(bank) => {
+            state.program_bank = bank;
+            Task::none()
+        }
+        Message::ProgramNumberChange(program) => {
+            state.program_number = program;
+            Task::none()
+        }
+        Message::MapProgram(target) => {
+            // Most foot controllers only send bank 0
+            let bank = match state.program_bank.trim() {
+                "" => Some(0),
+                bank => bank.parse::<u16>().ok(),
+            };
+            let program = state.program_number.trim().parse::<u8>().ok();
+            match (bank, program) {
+                (Some(bank), Some(program)) if bank < 1 << 14 && program < 128 => {
+                    let program_map = &mut state.config.program_map;
+                    program_map
+                        .retain(|mapping| mapping.bank != bank || mapping.program != program);
+                    program_map.push(ProgramMapping {
+                        bank,
+                        program,
+                        target,
+                    });
+                    program_map.sort_by_key(|mapping| (mapping.bank, mapping.program));
+                }
+                _ => log::error!("Banks go from 0 to 16383 and programs from 0 to 127"),
+            }
+            Task::none()
+        }
+        Message::UnmapProgram(i) => {
+            if i < state.config.program_map.len() {
+                state.config.program_map.remove(i);
+            }
+            Task::none()
+        }
+        Message::ImportChain => {
+            import_chain(state);
+            Task::none()
+        }
+        Message::NewFromTemplate(name) => {
+            new_from_template(state, &name);
+            Task::none()
+        }
+        Message::VolumeChange(volume) => {
+            if state.midi_learn {
+                state.learn_target = Some(MidiTarget::Volume);
+            }
+            edit(state, Edit::Volume(volume));
+            Task::none()
+        }
+        Message::SetLimiter(enabled) => {
+            match state
+                .command_sender
+                .as_mut()
+                .unwrap()
+                .try_push(Command::SetLimiter(enabled))
+            {
+                Ok(_) => {
+                    state.limiter = enabled;
+                }
+                Err(_) => {
+                    log::error!("Error sending command to set limiter");
+                }
+            }
+            Task::none()
+        }
+        Message::SetMetronome(enabled) => {
+            match state
+                .command_sender
+                .as_mut()
+                .unwrap()
+                .try_push(Command::SetMetronome(enabled))
+            {
+                Ok(_) => {
+                    state.metronome = enabled;
+                }
+                Err(_) => {
+                    log::error!("Error sending command to set metronome");
+                }
+            }
+            Task::none()
+        }
+        Message::MetronomeVolumeChange(db) => {
+            match state
+                .command_sender
+                .as_mut()
+                .unwrap()
+                .try_push(Command::MetronomeVolumeChange(db_to_gain(db)))
+            {
+                Ok(_) => {
+                    state.metronome_volume = db;
+                }
+                Err(_) => {
+                    log::error!("Error sending command to change metronome volume");
+                }
+            }
+            Task::none()
+        }
+        Message::SetClickSound(sound) => {
+            match state
+                .command_sender
+                .as_mut()
+                .unwrap()
+                .try_push(Command::SetClickSound(sound))
+            {
+                Ok(_) => {
+                    state.click_sound = sound;
+                }
+                Err(_) => {
+                    log::error!("Error sending command to set click sound");
+                }
+            }
+            Task::none()
+        }
+        Message::SetClickToMain(to_main) => {
+            match state
+                .command_sender
+                .as_mut()
+                .unwrap()
+                .try_push(Command::SetClickToMain(to_main))
+            {
+                Ok(_) => {
+                    state.click_to_main = to_main;
+                }
+                Err(_) => {
+                    log::error!("Error sending command to set click output");
+                }
+            }
+            Task::none()
+        }
+        Message::Tap => {
+            if state
+                .command_sender
+                .as_mut()
+                .unwrap()
+                .try_push(Command::Tap)
+                .is_err()
+            {
+                log::error!("Error sending tap");
+            }
+            Task::none()
+        }
+        Message::SetTapController(controller) => {
+            match state
+                .command_sender
+                .as_mut()
+                .unwrap()
+                .try_push(Command::SetTapController(controller.0))
+            {
+                Ok(_) => {
+                    state.tap_controller = controller;
+                }
+                Err(_) => {
+                    log::error!("Error sending command to set tap controller");
+                }
+            }
+            Task::none()
+        }
+        Message::SetAlsaMidiInput(choice) => {
+            if let Some(sequencer) = &mut state.sequencer {
+                match sequencer.connect(choice.0.as_deref()) {
+                    Ok(()) => state.config.alsa_midi_input = choice.0,
+                    Err(e) => {
+                        log::error!("{}", e);
+                        state.config.alsa_midi_input = None;
+                    }
+                }
+                state.config.save();
+            }
+            Task::none()
+        }
+        Message::SetLink(enabled) => {
+            state.link = None;
+            if enabled {
+                match link::Link::listen() {
+                    Ok(link) => state.link = Some(link),
+                    Err(e) => log::error!("Error listening for Link peers: {}", e),
+                }
+            }
+            state.config.link = state.link.is_some();
+            state.config.save();
+            Task::none()
+        }
+        Message::ToggleMidiLearn => {
+            match state
                 .command_sender
                 .as_mut()
                 .unwrap()
-                .try_push(Command::ParamChange(plugin.id, param.0.clone(), param.1))
-                .map_err(|_| {
-                    rack::Error::Other(format!(
-                        "Error sending parameter {} of {}",
-                        param.0.name, plugin.info
-                    ))
-                })?;
-        }
-    }
-    Ok(saved_plugins)
-}
-
-fn update(state: &mut AppState, message: Message) -> Task<Message> {
-    match message {
-        Message::Scan => {
-            match state.plugin_scanner.as_ref().unwrap().scan() {
-                Ok(plugins) => {
-                    state.scanned_plugins = plugins;
+                .try_push(Command::SetMidiLearn(!state.midi_learn))
+            {
+                Ok(_) => {
+                    state.midi_learn = !state.midi_learn;
+                    state.learn_target = None;
                 }
-                Err(e) => {
-                    eprintln!("Error scanning plugins: {}", e);
+                Err(_) => {
+                    log::error!("Error sending command to set MIDI learn");
                 }
             }
             Task::none()
         }
-        Message::LoadPlugin(info) => {
-            if let Ok(plugin_instance) = create_instance(
-                state.plugin_scanner.as_ref().unwrap(),
-                &info,
-                state.jack_client.as_ref().unwrap().as_client(),
-            ) {
-                let mut params = Vec::with_capacity(plugin_instance.parameter_count());
-                for i in 0..plugin_instance.parameter_count() {
-                    params.push((
-                        plugin_instance.parameter_info(i).unwrap(),
-                        plugin_instance.get_parameter(i).unwrap(),
-                    ));
+        Message::Unmap(controller) => {
+            match state
+                .command_sender
+                .as_mut()
+                .unwrap()
+                .try_push(Command::UnmapController(controller))
+            {
+                Ok(_) => {
+                    state
+                        .midi_mappings
+                        .retain(|mapping| mapping.controller != controller);
                 }
-
-                let plugin = LoadedPlugin {
-                    id: Uuid::new_v4(),
-                    info: info.clone(),
-                    params,
-                };
-
-                match state
-                    .command_sender
-                    .as_mut()
-                    .unwrap()
-                    .try_push(Command::LoadPlugin(plugin_instance, plugin.id))
-                {
-                    Ok(_) => {
-                        state.loaded_plugins.push(plugin);
-                    }
-
-                    Err(_) => {
-                        eprintln!("Error sending plugin: {}", info);
-                    }
+                Err(_) => {
+                    log::error!("Error sending command to unmap controller");
                 }
             }
             Task::none()
         }
-        Message::DeletePlugin(id) => {
+        Message::SetDcBlocker(enabled) => {
             match state
                 .command_sender
                 .as_mut()
                 .unwrap()
-                .try_push(Command::DeletePlugin(id))
+                .try_push(Command::SetDcBlocker(enabled))
             {
                 Ok(_) => {
-                    state.loaded_plugins.retain(|plugin| plugin.id != id);
+                    state.dc_blocker = enabled;
                 }
                 Err(_) => {
-                    eprintln!("Error sending command to delete plugin");
+                    log::error!("Error sending command to set DC filter");
                 }
             }
-            // TODO: Safely drop plugin instances
-            // if let Some(_plugin) = state.garbage_receiver.as_mut().unwrap().try_pop() {
-            // }
             Task::none()
         }
-        Message::MovePluginUp(id) => {
+        Message::SetMonitor(monitor) => {
             match state
                 .command_sender
                 .as_mut()
                 .unwrap()
-                .try_push(Command::MovePluginUp(id))
+                .try_push(Command::SetMonitor(monitor))
             {
                 Ok(_) => {
-                    if let Some(i) = state
-                        .loaded_plugins
-                        .iter()
-                        .position(|plugin| plugin.id == id)
-                    {
-                        state.loaded_plugins.swap(i - 1, i);
-                    }
+                    state.monitor = monitor;
                 }
                 Err(_) => {
-                    eprintln!("Error sending command to move plugin up");
+                    log::error!("Error sending command to set monitoring");
                 }
             }
             Task::none()
         }
-        Message::MovePluginDown(id) => {
+        // Esc dismisses a dialog rather than panicking
+        Message::TogglePanic if state.confirmation.is_some() || state.log_open => {
+            state.confirmation = None;
+            state.log_open = false;
+            Task::none()
+        }
+        Message::TogglePanic => {
             match state
                 .command_sender
                 .as_mut()
                 .unwrap()
-                .try_push(Command::MovePluginDown(id))
+                .try_push(Command::SetPanic(!state.panic))
             {
                 Ok(_) => {
-                    if let Some(i) = state
-                        .loaded_plugins
-                        .iter()
-                        .rposition(|plugin| plugin.id == id)
-                    {
-                        state.loaded_plugins.swap(i, i + 1);
-                    }
+                    state.panic = !state.panic;
                 }
                 Err(_) => {
-                    eprintln!("Error sending command to move plugin down");
+                    log::error!("Error sending command to toggle panic bypass");
                 }
             }
             Task::none()
         }
-        Message::ParamChange(plugin_id, param_info, value) => {
+        Message::ToggleMasterMute => {
             match state
                 .command_sender
                 .as_mut()
                 .unwrap()
-                .try_push(Command::ParamChange(plugin_id, param_info.clone(), value))
+                .try_push(Command::SetMasterMute(!state.master_mute))
             {
                 Ok(_) => {
-                    if let Some(plugin) = state
-                        .loaded_plugins
-                        .iter_mut()
-                        .find(|plugin| plugin.id == plugin_id)
-                    {
-                        plugin.params[param_info.index].1 = value
-                    }
+                    state.master_mute = !state.master_mute;
                 }
                 Err(_) => {
-                    eprintln!("Error sending parameter {}", param_info.name);
+                    log::error!("Error sending command to toggle master mute");
                 }
             }
             Task::none()
         }
-        Message::ClearSession => {
+        Message::ToggleShortcuts => {
+            state.shortcuts_open = !state.shortcuts_open;
+            Task::none()
+        }
+        Message::ResizePanes(event) => {
+            state.panes.0.resize(event.split, event.ratio);
+            state.config.browser_ratio = Some(event.ratio);
+            Task::none()
+        }
+        Message::SetAnalyzerTap(tap) => {
             match state
                 .command_sender
                 .as_mut()
                 .unwrap()
-                .try_push(Command::ClearSession)
+                .try_push(Command::SetAnalyzerTap(tap))
             {
                 Ok(_) => {
-                    state.loaded_plugins.clear();
+                    state.analyzer_tap = tap;
                 }
                 Err(_) => {
-                    eprintln!("Error sending command to clear session");
+                    log::error!("Error sending command to set analyzer tap");
                 }
             }
             Task::none()
         }
-        Message::SaveSession => {
-            let content = serde_yaml_ng::to_string(&state.loaded_plugins).unwrap();
-            if state.session_path.exists() {
-                if let Err(e) = std::fs::write(&state.session_path, content) {
-                    eprintln!("Error writing {}: {}", state.session_path.display(), e);
+        Message::SetTuner(chain_id) => {
+            match state
+                .command_sender
+                .as_mut()
+                .unwrap()
+                .try_push(Command::SetTuner(chain_id))
+            {
+                Ok(_) => {
+                    state.tuner_input = chain_id;
+                    state.pitch = None;
                 }
-            } else {
-                if let Some(path) = FileDialog::new()
-                    .add_filter("YAML", &["yaml"])
-                    .set_file_name(".yaml")
-                    .save_file()
-                {
-                    if let Err(e) = std::fs::write(&path, content) {
-                        eprintln!("Error writing {}: {}", path.display(), e);
-                    }
+                Err(_) => {
+                    log::error!("Error sending command to set tuner");
                 }
             }
             Task::none()
         }
-        Message::LoadSession => {
-            if let Some(path) = FileDialog::new()
-                .set_directory(state.session_path.parent().unwrap_or_else(|| Path::new("")))
-                .add_filter("YAML", &["yaml"])
-                .pick_file()
+        Message::ToggleRecording => {
+            if state.recording.is_some() {
+                match state
+                    .command_sender
+                    .as_mut()
+                    .unwrap()
+                    .try_push(Command::SetRecording(false))
+                {
+                    Ok(_) => {
+                        state.recorder.as_ref().unwrap().stop();
+                        state.recording = None;
+                    }
+                    Err(_) => {
+                        log::error!("Error sending command to stop recording");
+                    }
+                }
+            } else if let Some(path) = FileDialog::new()
+                .add_filter("WAV", &["wav"])
+                .add_filter("FLAC", &["flac"])
+                .set_file_name(".wav")
+                .save_file()
             {
-                match load_session(state, &path) {
-                    Ok(plugins) => {
-                        state.loaded_plugins = plugins;
-                        state.session_path = path;
+                let sample_rate = state
+                    .jack_client
+                    .as_ref()
+                    .unwrap()
+                    .as_client()
+                    .sample_rate();
+                let dry_path = state.record_dry.then(|| {
+                    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                    let extension = path.extension().unwrap_or("wav".as_ref());
+                    path.with_file_name(format!("{}-dry.{}", stem, extension.to_string_lossy()))
+                });
+                state
+                    .recorder
+                    .as_ref()
+                    .unwrap()
+                    .start(path.clone(), dry_path, sample_rate as u32);
+                match state
+                    .command_sender
+                    .as_mut()
+                    .unwrap()
+                    .try_push(Command::SetRecording(true))
+                {
+                    Ok(_) => {
+                        state.recording = Some(path);
                     }
-                    Err(e) => {
-                        eprintln!("Error loading {}: {}", path.display(), e)
+                    Err(_) => {
+                        state.recorder.as_ref().unwrap().stop();
+                        log::error!("Error sending command to start recording");
                     }
                 }
             }
             Task::none()
         }
-        Message::VolumeChange(volume) => {
+        Message::SetCategory(category) => {
+            state.category = category;
+            Task::none()
+        }
+        Message::ToggleFavorite(unique_id) => {
+            let favorites = &mut state.config.favorites;
+            match favorites.iter().position(|id| *id == unique_id) {
+                Some(i) => {
+                    favorites.remove(i);
+                }
+                None => favorites.push(unique_id),
+            }
+            Task::none()
+        }
+        Message::ToggleDetails(info) => {
+            if state
+                .details
+                .as_ref()
+                .is_some_and(|(unique_id, _)| *unique_id == info.unique_id)
+            {
+                state.details = None;
+                return Task::none();
+            }
+            // The parameters are only known once the plugin is loaded, so an instance is
+            // created just to count them
+            let parameter_count = create_instance(
+                state.plugin_scanner.as_ref().unwrap(),
+                &info,
+                state.jack_client.as_ref().unwrap().as_client(),
+            )
+            .map(|plugin| plugin.parameter_count())
+            .map_err(|e| log::error!("Error loading plugin {}: {}", info, e))
+            .ok();
+            state.details = Some((info.unique_id, parameter_count));
+            Task::none()
+        }
+        Message::FilterChanged(filter) => {
+            state.filter = filter;
+            Task::none()
+        }
+        Message::SetRecordDry(record_dry) => {
+            state.record_dry = record_dry;
+            Task::none()
+        }
+        Message::RenderFile => {
+            let Some(input) = FileDialog::new().add_filter("WAV", &["wav"]).pick_file() else {
+                return Task::none();
+            };
+            let Some(path) = FileDialog::new()
+                .add_filter("WAV", &["wav"])
+                .set_file_name(".wav")
+                .save_file()
+            else {
+                return Task::none();
+            };
+            let client = state.jack_client.as_ref().unwrap().as_client();
+            let render = match Render::load(&input, path.clone(), client.sample_rate()) {
+                Ok(render) => render,
+                Err(e) => {
+                    log::error!("Error loading {}: {}", input.display(), e);
+                    return Task::none();
+                }
+            };
             match state
                 .command_sender
                 .as_mut()
                 .unwrap()
-                .try_push(Command::VolumeChange(volume))
+                .try_push(Command::StartRender(Box::new(render)))
             {
                 Ok(_) => {
-                    state.volume = volume;
+                    set_freewheel(state.jack_client.as_ref().unwrap().as_client(), true);
+                    state.rendering = Some(path);
                 }
                 Err(_) => {
-                    eprintln!("Error sending command to change volume");
+                    log::error!("Error sending command to render file");
+                }
+            }
+            Task::none()
+        }
+        Message::SetConnection(port, input, choice) => {
+            let client = state.jack_client.as_ref().unwrap().as_client();
+            for (source, destination) in &state.config.connections {
+                if (input && *destination == port || !input && *source == port)
+                    && let Err(e) = client.disconnect_ports_by_name(source, destination)
+                {
+                    log::error!("Error disconnecting {} from {}: {}", source, destination, e);
+                }
+            }
+            if let Some(other) = choice.0 {
+                let (source, destination) = if input {
+                    (&other, &port)
+                } else {
+                    (&port, &other)
+                };
+                if let Err(e) = client.connect_ports_by_name(source, destination) {
+                    log::error!("Error connecting {} to {}: {}", source, destination, e);
                 }
             }
+            poll_connections(state);
+            state.config.save();
+            Task::none()
+        }
+        Message::ReplaceMissingPort(i, other) => {
+            let missing = state.missing_ports.remove(i);
+            let (source, destination) = if missing.input {
+                (&other, &missing.port)
+            } else {
+                (&missing.port, &other)
+            };
+            let client = state.jack_client.as_ref().unwrap().as_client();
+            if let Err(e) = client.connect_ports_by_name(source, destination) {
+                log::error!("Error connecting {} to {}: {}", source, destination, e);
+            }
+            poll_connections(state);
+            state.config.save();
+            Task::none()
+        }
+        Message::DismissMissingPorts => {
+            state.missing_ports.clear();
+            Task::none()
+        }
+        Message::DismissAlert(plugin_id) => {
+            state.alerts.retain(|(id, ..)| *id != plugin_id);
+            Task::none()
+        }
+        Message::Reconnect => {
+            try_reconnect(state);
+            Task::none()
+        }
+        Message::ResetXruns => {
+            state.notifications.xruns.store(0, Ordering::Relaxed);
             Task::none()
         }
+        Message::Frame => {
+            state.log.receive();
+            if state.log_open {
+                state.log.unread = 0;
+            }
+            if state.recording.is_some() && state.recorder.as_ref().is_some_and(Recorder::failed) {
+                let _ = update(state, Message::ToggleRecording);
+            }
+            if state.scanning.as_ref().is_some_and(JoinHandle::is_finished) {
+                match state.scanning.take().unwrap().join() {
+                    Ok(Ok(plugins)) => {
+                        (state.scanned_plugins, state.duplicates) =
+                            collapse_duplicates(plugins, state.config.preferred_format.as_deref());
+                    }
+                    Ok(Err(e)) => log::error!("Error scanning plugins: {}", e),
+                    Err(_) => log::error!("Plugin scan panicked"),
+                }
+            }
+            if state.notifications.shutdown.load(Ordering::Relaxed) && !state.disconnected {
+                log::error!("JACK server shut down");
+                state.disconnected = true;
+                state.last_reconnect = Some(Instant::now());
+            }
+            if state.disconnected {
+                if state
+                    .last_reconnect
+                    .is_none_or(|time| time.elapsed() >= RECONNECT_INTERVAL)
+                {
+                    try_reconnect(state);
+                }
+                return Task::none();
+            }
+            if state
+                .connections_polled
+                .is_none_or(|time| time.elapsed() >= CONNECTION_POLL_INTERVAL)
+            {
+                poll_connections(state);
+            }
+            if state
+                .levels_collected
+                .is_none_or(|time| time.elapsed() >= METER_INTERVAL)
+            {
+                state.collect_levels();
+                state.levels_collected = Some(Instant::now());
+            }
+            state.collect_faults();
+            state.collect_errors();
+            // Only the last program change counts
+            let mut program = None;
+            while let Some(received) = state.program_receiver.as_mut().unwrap().try_pop() {
+                program = Some(received);
+            }
+            if let Some((bank, program)) = program {
+                state.program_bank = bank.to_string();
+                state.program_number = program.to_string();
+                program_change(state, bank, program);
+            }
+            while let Some((controller, value)) =
+                state.controller_receiver.as_mut().unwrap().try_pop()
+            {
+                if let Some(target) = state.learn_target.take() {
+                    map_controller(state, MidiMapping { controller, target });
+                } else if let Some(mapping) = state
+                    .midi_mappings
+                    .iter()
+                    .find(|mapping| mapping.controller == controller)
+                    .copied()
+                {
+                    show_mapped(state, mapping, value);
+                }
+            }
+            while let Some(pending) = state
+                .requests
+                .as_ref()
+                .and_then(|requests| requests.try_recv().ok())
+            {
+                let reply = handle_request(state, pending.request.clone());
+                pending.reply(reply);
+            }
+            while let Some((message, from)) = state.osc.as_mut().and_then(osc::Server::receive) {
+                for reply in osc_request(state, message) {
+                    state.osc.as_ref().unwrap().send(from, &reply);
+                }
+            }
+            send_feedback(state);
+            if let Some(link) = &mut state.link {
+                link.poll(&state.tempo);
+            }
+            let nsm_task = nsm_requests(state);
+            if state.analyzer_tap.is_some() {
+                state.spectrum = state.analyzer.as_ref().unwrap().lock().unwrap().clone();
+            }
+            if state.tuner_input.is_some() {
+                state.pitch = *state.tuner.as_ref().unwrap().lock().unwrap();
+            }
+            if state
+                .notifications
+                .sample_rate_changed
+                .swap(false, Ordering::Relaxed)
+            {
+                let sample_rate = state
+                    .jack_client
+                    .as_ref()
+                    .unwrap()
+                    .as_client()
+                    .sample_rate();
+                // Plugins only take the sample rate when initialized, so every chain is
+                // sent again with new instances
+                if sample_rate != state.sample_rate {
+                    state.sample_rate = sample_rate;
+                    let mut session = state.session();
+                    match send_session(state, &mut session) {
+                        Ok(_) => state.keep_sent(session),
+                        Err(e) => {
+                            log::error!("Error re-creating plugins at {} Hz: {}", sample_rate, e);
+                        }
+                    }
+                }
+            }
+            nsm_task
+        }
+        // Under a session manager closing the window hides it, and Rake quits with the session
+        Message::Exit if state.nsm.as_ref().is_some_and(|nsm| nsm.optional_gui) => {
+            show_gui(state, false)
+        }
         Message::Exit => {
             let _ = state
                 .command_sender
                 .as_mut()
                 .unwrap()
                 .try_push(Command::Exit);
+            if let Some(recorder) = state.recorder.take() {
+                recorder.join();
+            }
+            if let Some(collector) = state.collector.take() {
+                collector.join();
+            }
+            if !state.disconnected {
+                poll_connections(state);
+            }
+            state.config.save();
+            if state.ctl_socket {
+                control::remove(&state.client_name);
+            }
             iced::exit()
         }
     }
 }
 
+/// Connects to JACK and replaces the GUI's ends of the channels to the processor.
+/// A previous client is dropped along with everything its processor held.
+fn connect(state: &mut AppState) -> std::result::Result<(), jack::Error> {
+    let processor::Engine {
+        client: active_client,
+        command_sender,
+        garbage_receiver,
+        meter_receiver,
+        fault_receiver,
+        error_receiver,
+        program_receiver,
+        controller_receiver,
+        alsa_midi_sender,
+        analyzer_receiver,
+        tuner_receiver,
+        recorder_receiver,
+        tempo,
+        notifications,
+    } = processor::initialize(&state.client_name)?;
+    let sample_rate = active_client.as_client().sample_rate();
+    if let Some(recorder) = state.recorder.take() {
+        recorder.join();
+    }
+    state.command_sender = Some(command_sender);
+    state.garbage_receiver = Some(garbage_receiver);
+    state.meter_receiver = Some(meter_receiver);
+    state.fault_receiver = Some(fault_receiver);
+    state.error_receiver = Some(error_receiver);
+    state.program_receiver = Some(program_receiver);
+    state.controller_receiver = Some(controller_receiver);
+    if let Some(sequencer) = &state.sequencer {
+        sequencer.set_output(alsa_midi_sender);
+    }
+    state.analyzer = Some(analyzer::spawn(analyzer_receiver, sample_rate));
+    state.tuner = Some(tuner::spawn(tuner_receiver, sample_rate));
+    state.recorder = Some(Recorder::spawn(recorder_receiver));
+    tempo.set_bpm(state.tempo.bpm());
+    state.tempo = tempo;
+    state.sample_rate = sample_rate;
+    state.notifications = notifications;
+    state.jack_client = Some(active_client);
+    Ok(())
+}
+
+/// Sends the output and metronome settings that aren't part of a session.
+fn send_settings(state: &mut AppState) {
+    let mut commands = vec![
+        Command::VolumeChange(volume_to_gain(state.volume)),
+        Command::SetMonitor(state.monitor),
+        Command::SetPanic(state.panic),
+        Command::SetMasterMute(state.master_mute),
+        Command::SetMetronome(state.metronome),
+        Command::MetronomeVolumeChange(db_to_gain(state.metronome_volume)),
+        Command::SetClickSound(state.click_sound),
+        Command::SetClickToMain(state.click_to_main),
+        Command::SetTapController(state.tap_controller.0),
+        Command::SetMidiLearn(state.midi_learn),
+    ];
+    for chain in &state.chains {
+        commands.push(Command::SetMute(chain.id, chain.mute));
+        commands.push(Command::SetSolo(chain.id, chain.solo));
+    }
+    for command in commands {
+        if state
+            .command_sender
+            .as_mut()
+            .unwrap()
+            .try_push(command)
+            .is_err()
+        {
+            log::error!("Error sending settings");
+            return;
+        }
+    }
+}
+
+/// Reconnects after the JACK server shut down, restoring the chains, settings and port
+/// connections. Stays disconnected if the server isn't back yet.
+fn try_reconnect(state: &mut AppState) {
+    state.last_reconnect = Some(Instant::now());
+    if let Err(e) = connect(state) {
+        log::error!("Error reconnecting to JACK: {}", e);
+        return;
+    }
+    state.disconnected = false;
+    state.recording = None;
+    state.rendering = None;
+    state.analyzer_tap = None;
+    state.tuner_input = None;
+    state.pitch = None;
+
+    let mut session = state.session();
+    match send_session(state, &mut session) {
+        Ok(_) => state.keep_sent(session),
+        Err(e) => log::error!("Error restoring chains: {}", e),
+    }
+    send_settings(state);
+
+    let client = state.jack_client.as_ref().unwrap().as_client();
+    connections::restore(client, &state.config.connections, "");
+}
+
+/// Carries out the requests of the session manager.
+fn nsm_requests(state: &mut AppState) -> Task<Message> {
+    let Some(requests) = state.nsm.as_ref().map(nsm::Nsm::receive) else {
+        return Task::none();
+    };
+    let mut tasks = Vec::new();
+    for request in requests {
+        match request {
+            nsm::Request::Open(open) => {
+                let result = nsm_open(state, open).map_err(|e| e.to_string());
+                state.nsm.as_ref().unwrap().reply(nsm::OPEN, result);
+            }
+            nsm::Request::Save => {
+                let path = state.session_path.clone();
+                let result = save_session(state, path).map_err(|e| e.to_string());
+                state.nsm.as_ref().unwrap().reply(nsm::SAVE, result);
+            }
+            nsm::Request::ShowGui => tasks.push(show_gui(state, true)),
+            nsm::Request::HideGui => tasks.push(show_gui(state, false)),
+        }
+    }
+    Task::batch(tasks)
+}
+
+/// Opens the session the session manager asks for, in a file at its path, or starts it empty
+/// if there's none yet. A new client id becomes the JACK client name.
+fn nsm_open(state: &mut AppState, open: nsm::Open) -> Result<()> {
+    if open.client_id != state.client_name {
+        state.client_name = open.client_id;
+        try_reconnect(state);
+    }
+    let mut path = open.path.into_os_string();
+    path.push(".yaml");
+    let path = PathBuf::from(path);
+    if path.exists() {
+        open_session(state, path)
+    } else {
+        let _ = update(state, Message::ClearSession);
+        save_session(state, path)
+    }
+}
+
+/// Shows or hides the window as the session manager asks, and tells it so.
+fn show_gui(state: &mut AppState, shown: bool) -> Task<Message> {
+    state.gui_hidden = !shown;
+    if let Some(nsm) = &state.nsm {
+        nsm.gui_shown(shown);
+    }
+    let mode = if shown {
+        window::Mode::Windowed
+    } else {
+        window::Mode::Hidden
+    };
+    window::latest().and_then(move |id| window::set_mode(id, mode))
+}
+
+/// Takes a snapshot of the connections of Rake's ports into the config. Connections of ports
+/// that don't exist right now, like those of chains that aren't loaded, are kept.
+fn poll_connections(state: &mut AppState) {
+    let client = state.jack_client.as_ref().unwrap().as_client();
+    let prefix = format!("{}:", client.name());
+    // Connections of other instances, under other client names, are left alone
+    state.config.connections.retain(|(source, destination)| {
+        let port = if source.starts_with(&prefix) {
+            source
+        } else if destination.starts_with(&prefix) {
+            destination
+        } else {
+            return true;
+        };
+        client.port_by_name(port).is_none()
+    });
+    state
+        .config
+        .connections
+        .extend(connections::current(client));
+
+    let others = |flags| {
+        client
+            .ports(None, Some("audio"), flags)
+            .into_iter()
+            .filter(|name| !name.starts_with(&prefix))
+            .collect()
+    };
+    state.sources = others(jack::PortFlags::IS_OUTPUT);
+    state.destinations = others(jack::PortFlags::IS_INPUT);
+    if let Some(sequencer) = &mut state.sequencer {
+        state.alsa_devices = sequencer.devices();
+        if let Some(device) = &state.config.alsa_midi_input {
+            let present = state.alsa_devices.contains(device);
+            // Unplugging the device ends the subscription, so it's made again once it's back
+            if present != sequencer.is_connected()
+                && let Err(e) = sequencer.connect(present.then_some(device.as_str()))
+            {
+                log::error!("{}", e);
+            }
+        }
+    }
+    state.connections_polled = Some(Instant::now());
+}
+
+/// Prefix of the JACK port names of a chain.
+fn port_prefix(chain: &LoadedChain) -> String {
+    if chain.id == MAIN_CHAIN {
+        String::new()
+    } else {
+        format!("{}_", chain.name)
+    }
+}
+
+/// Options given on the command line.
+#[derive(Default)]
+struct Args {
+    /// JACK client name from `--name NAME` or `--name=NAME`.
+    name: Option<String>,
+    /// Session from `--session PATH`, `--session=PATH` or just the path, as desktop files and
+    /// file managers pass it.
+    session: Option<PathBuf>,
+    /// Whether to run without the GUI, from `--headless`.
+    headless: bool,
+}
+
+impl Args {
+    fn parse() -> Self {
+        let mut parsed = Args::default();
+        let mut args = std::env::args_os().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.to_str() {
+                Some("--name") => {
+                    parsed.name = args.next().and_then(|name| name.into_string().ok())
+                }
+                Some("--session") => parsed.session = args.next().map(PathBuf::from),
+                Some("--headless") => parsed.headless = true,
+                Some(text) if text.starts_with("--name=") => {
+                    parsed.name = Some(text["--name=".len()..].to_string());
+                }
+                Some(text) if text.starts_with("--session=") => {
+                    parsed.session = Some(PathBuf::from(&text["--session=".len()..]));
+                }
+                Some(text) if text.starts_with("--") => eprintln!("Unknown argument {}", text),
+                _ => parsed.session = Some(PathBuf::from(arg)),
+            }
+        }
+        parsed
+    }
+}
+
+/// Client name from the command line, then the config, then the default.
+fn client_name(args: &Args, config: &Config) -> String {
+    args.name
+        .clone()
+        .or_else(|| config.client_name.clone())
+        .unwrap_or_else(|| DEFAULT_CLIENT_NAME.to_string())
+}
+
 fn boot() -> AppState {
-    let (active_client, command_sender, garbage_receiver) = processor::initialize();
+    let args = Args::parse();
+    let config = Config::load();
     let plugin_scanner = Some(Scanner::new().expect("Error creating plugin scanner"));
-    AppState {
-        scanned_plugins: plugin_scanner.as_ref().unwrap().scan().unwrap_or_else(|e| {
-            eprintln!("Error scanning plugins: {}", e);
-            Vec::new()
-        }),
+    let mut state = AppState {
         plugin_scanner,
-        volume: 1.0,
-        command_sender: Some(command_sender),
-        _garbage_receiver: Some(garbage_receiver),
-        jack_client: Some(active_client),
+        chains: vec![LoadedChain {
+            id: MAIN_CHAIN,
+            name: "Main".to_string(),
+            nodes: Vec::new(),
+            returns: Vec::new(),
+            mute: false,
+            solo: false,
+        }],
+        volume: 0.0,
+        panes: view::Panes::new(config.browser_ratio),
+        spectrum: vec![analyzer::MIN_DB; analyzer::BANDS],
+        collector: Some(Collector::spawn()),
+        templates: templates_dir()
+            .map(|dir| presets::yaml_names(&dir))
+            .unwrap_or_default(),
+        config,
         ..AppState::default()
+    };
+    // Launched by a session manager, which names the JACK client and opens the session
+    let nsm_session = match nsm::Nsm::announce(!args.headless) {
+        Some(Ok((nsm, open))) => {
+            state.nsm = Some(nsm);
+            Some(open)
+        }
+        Some(Err(e)) => {
+            log::error!("Error announcing Rake to the session manager: {}", e);
+            None
+        }
+        None => None,
+    };
+    state.client_name = match &nsm_session {
+        Some(open) => open.client_id.clone(),
+        None => client_name(&args, &state.config),
+    };
+    let (requests, request_receiver) = mpsc::channel();
+    state.requests = Some(request_receiver);
+    match control::listen(&state.client_name, requests.clone()) {
+        Ok(()) => state.ctl_socket = true,
+        Err(e) => log::error!("Error listening for rake ctl: {}", e),
+    }
+    if let Some(port) = state.config.osc_port {
+        let address = state
+            .config
+            .osc_address
+            .unwrap_or(Ipv4Addr::LOCALHOST.into());
+        match osc::Server::listen((address, port).into()) {
+            Ok(server) => state.osc = Some(server),
+            Err(e) => log::error!("Error starting OSC server on port {}: {}", port, e),
+        }
+    }
+    if state.config.link {
+        match link::Link::listen() {
+            Ok(link) => state.link = Some(link),
+            Err(e) => log::error!("Error listening for Link peers: {}", e),
+        }
+    }
+    if let Some(port) = state.config.web_port {
+        if state.config.web_token.is_none() {
+            state.config.web_token = Some(Uuid::new_v4().simple().to_string());
+            state.config.save();
+        }
+        let address = state
+            .config
+            .web_address
+            .unwrap_or(Ipv4Addr::LOCALHOST.into());
+        let token = state.config.web_token.clone().unwrap_or_default();
+        match web::Server::listen((address, port).into(), token, requests) {
+            Ok(server) => state.web = Some(server),
+            Err(e) => log::error!("Error starting web API on port {}: {}", port, e),
+        }
+    }
+    match sequencer::Sequencer::open(&state.client_name) {
+        Ok(mut sequencer) => {
+            if let Some(device) = &state.config.alsa_midi_input
+                && let Err(e) = sequencer.connect(Some(device))
+            {
+                log::error!("{}", e);
+            }
+            state.alsa_devices = sequencer.devices();
+            state.sequencer = Some(sequencer);
+        }
+        Err(e) => log::error!("Error opening the ALSA sequencer: {}", e),
+    }
+    if let Err(e) = connect(&mut state) {
+        let message = format!(
+            "Error connecting to JACK: {}\n\nRake needs a running JACK server, or PipeWire with \
+             its JACK support (pipewire-jack).",
+            e
+        );
+        eprintln!("{}", message);
+        // Started from a desktop launcher there's no terminal to read it in
+        if !args.headless {
+            rfd::MessageDialog::new()
+                .set_level(rfd::MessageLevel::Error)
+                .set_title("Rake")
+                .set_description(message)
+                .show();
+        }
+        if state.ctl_socket {
+            control::remove(&state.client_name);
+        }
+        std::process::exit(1);
+    }
+    let client = state.jack_client.as_ref().unwrap().as_client();
+    let prefix = format!("{}:", client.name());
+    if !state
+        .config
+        .connections
+        .iter()
+        .any(|(source, destination)| {
+            source.starts_with(&prefix) || destination.starts_with(&prefix)
+        })
+    {
+        state
+            .config
+            .connections
+            .extend(connections::default(client));
+    }
+    connections::restore(client, &state.config.connections, "");
+    start_scan(&mut state);
+    if let Some(open) = nsm_session {
+        let result = nsm_open(&mut state, open).map_err(|e| e.to_string());
+        if let Err(e) = &result {
+            log::error!("{}", e);
+        }
+        state.nsm.as_ref().unwrap().reply(nsm::OPEN, result);
+    } else if let Some(path) = args.session {
+        // Kept in the recent sessions, so made independent of the working directory
+        if let Err(e) = open_session(&mut state, std::path::absolute(&path).unwrap_or(path)) {
+            log::error!("{}", e);
+        }
     }
+    preload_next(&mut state);
+    state.saved_session = state.saved_form();
+    state
 }
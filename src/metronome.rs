@@ -0,0 +1,170 @@
+use jack::TransportStatePosition;
+use std::fmt;
+
+/// Beats per bar while no JACK timebase master provides a time signature.
+const DEFAULT_BEATS_PER_BAR: usize = 4;
+
+const CLICK_SECONDS: f32 = 0.03;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ClickSound {
+    #[default]
+    Beep,
+    Woodblock,
+    Noise,
+}
+
+impl ClickSound {
+    pub const ALL: [ClickSound; 3] = [ClickSound::Beep, ClickSound::Woodblock, ClickSound::Noise];
+
+    /// Renders the click, with the accented version used on the first beat of a bar.
+    fn render(self, sample_rate: usize, accent: bool) -> Vec<f32> {
+        let length = (CLICK_SECONDS * sample_rate as f32) as usize;
+        let (frequency, decay_seconds) = match (self, accent) {
+            (ClickSound::Beep, false) => (1000.0, 0.008),
+            (ClickSound::Beep, true) => (1500.0, 0.008),
+            (ClickSound::Woodblock, false) => (2500.0, 0.003),
+            (ClickSound::Woodblock, true) => (3200.0, 0.003),
+            (ClickSound::Noise, _) => (0.0, 0.002),
+        };
+        let amplitude = if accent { 0.8 } else { 0.5 };
+        let mut noise: u32 = 0x12345678;
+        (0..length)
+            .map(|i| {
+                let time = i as f32 / sample_rate as f32;
+                let envelope = amplitude * (-time / decay_seconds).exp();
+                let wave = if self == ClickSound::Noise {
+                    noise = noise.wrapping_mul(1664525).wrapping_add(1013904223);
+                    (noise >> 8) as f32 / (1 << 23) as f32 - 1.0
+                } else {
+                    (2.0 * std::f32::consts::PI * frequency * time).sin()
+                };
+                envelope * wave
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for ClickSound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Transport position in beats, with the tempo and beats per bar it was computed with.
+pub struct Beats {
+    pub position: f64,
+    pub bpm: f64,
+    pub beats_per_bar: usize,
+}
+
+impl Beats {
+    /// Reads the beat position from the transport's bar, beat and tick, falling back to the
+    /// transport frame at `fallback_bpm` in 4/4 when no timebase master provides them.
+    pub fn from_transport(
+        transport: &TransportStatePosition,
+        sample_rate: usize,
+        fallback_bpm: f64,
+    ) -> Self {
+        match transport.pos.bbt() {
+            Some(bbt) => Beats {
+                position: (bbt.bar - 1) as f64 * bbt.sig_num as f64
+                    + (bbt.beat - 1) as f64
+                    + bbt.tick as f64 / bbt.ticks_per_beat,
+                bpm: bbt.bpm,
+                beats_per_bar: (bbt.sig_num as usize).max(1),
+            },
+            None => Beats {
+                position: transport.pos.frame() as f64 / sample_rate as f64 * fallback_bpm / 60.0,
+                bpm: fallback_bpm,
+                beats_per_bar: DEFAULT_BEATS_PER_BAR,
+            },
+        }
+    }
+}
+
+/// Click generator following the JACK transport.
+pub struct Metronome {
+    pub enabled: bool,
+    pub gain: f32,
+    pub sound: ClickSound,
+    /// Whether the click is also mixed into the main output.
+    pub to_main: bool,
+    /// Normal and accented click of every sound.
+    clicks: Vec<[Vec<f32>; 2]>,
+    /// Accent and read position of the click that is sounding.
+    click: Option<(usize, usize)>,
+    /// Last beat clicked, so a beat isn't clicked twice across cycles.
+    last_beat: Option<i64>,
+}
+
+impl Metronome {
+    pub fn new(sample_rate: usize) -> Self {
+        Metronome {
+            enabled: false,
+            gain: 1.0,
+            sound: ClickSound::default(),
+            to_main: false,
+            clicks: ClickSound::ALL
+                .iter()
+                .map(|sound| {
+                    [
+                        sound.render(sample_rate, false),
+                        sound.render(sample_rate, true),
+                    ]
+                })
+                .collect(),
+            click: None,
+            last_beat: None,
+        }
+    }
+
+    /// Writes this cycle's click to `out`, given the transport position while it's rolling.
+    pub fn process(&mut self, out: &mut [f32], beats: Option<&Beats>, sample_rate: usize) {
+        out.fill(0.0);
+        let beats = beats.filter(|_| self.enabled);
+        match beats {
+            // Relocating backwards starts counting again. The margin covers the position being
+            // rounded down to whole ticks
+            Some(beats)
+                if self
+                    .last_beat
+                    .is_some_and(|last| beats.position < last as f64 - 0.01) =>
+            {
+                self.last_beat = None;
+            }
+            Some(_) => (),
+            None => self.last_beat = None,
+        }
+        if !self.enabled {
+            self.click = None;
+            return;
+        }
+
+        let clicks = &self.clicks[self.sound as usize];
+        for (i, sample) in out.iter_mut().enumerate() {
+            if let Some(beats) = beats {
+                let step = beats.bpm / 60.0 / sample_rate as f64;
+                let position = beats.position + step * i as f64;
+                let beat = position.floor() as i64;
+                // Without a previous beat, only click right at the start of one, not partway through
+                let starts = match self.last_beat {
+                    Some(last) => beat > last,
+                    None => position - (beat as f64) < step,
+                };
+                if starts {
+                    let accent = beat.rem_euclid(beats.beats_per_bar as i64) == 0;
+                    self.click = Some((accent as usize, 0));
+                    self.last_beat = Some(beat);
+                }
+            }
+            if let Some((accent, position)) = &mut self.click {
+                *sample = clicks[*accent][*position] * self.gain;
+                *position += 1;
+                if *position == clicks[*accent].len() {
+                    self.click = None;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use crate::processor::{VOLUME_MAX_DB, VOLUME_MIN_DB};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Most events kept per process cycle, so the event buffer never reallocates.
+pub const MAX_EVENTS: usize = 256;
+
+/// Most controllers mapped at once, so the mapping table never reallocates.
+pub const MAX_MAPPINGS: usize = 128;
+
+/// What a controller mapped with MIDI learn moves.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum MidiTarget {
+    /// A plugin's parameter by index, over its range.
+    Param {
+        plugin_id: Uuid,
+        index: usize,
+        min: f32,
+        max: f32,
+    },
+    /// The master volume, from silence to its maximum.
+    Volume,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct MidiMapping {
+    pub controller: u8,
+    pub target: MidiTarget,
+}
+
+impl MidiMapping {
+    /// Value of the target for a controller value: a parameter value, or the volume in dB.
+    pub fn value(&self, value: u8) -> f32 {
+        let position = value as f32 / 127.0;
+        match self.target {
+            MidiTarget::Param { min, max, .. } => min + (max - min) * position,
+            MidiTarget::Volume => VOLUME_MIN_DB + (VOLUME_MAX_DB - VOLUME_MIN_DB) * position,
+        }
+    }
+}
+
+/// A control change from a MIDI input, on any channel.
+#[derive(Clone, Copy)]
+pub struct ControlChange {
+    /// Frame within the cycle.
+    pub time: u32,
+    pub controller: u8,
+    pub value: u8,
+}
+
+/// Control change of a raw MIDI message, which is all the process thread acts on.
+pub fn control_change(raw: &jack::RawMidi) -> Option<ControlChange> {
+    match *raw.bytes {
+        [status, controller, value] if status & 0xF0 == 0xB0 => Some(ControlChange {
+            time: raw.time,
+            controller,
+            value,
+        }),
+        _ => None,
+    }
+}
+
+/// Controllers selecting the high and low 7 bits of the bank of the next program change.
+const BANK_SELECT_MSB: u8 = 0;
+const BANK_SELECT_LSB: u8 = 32;
+
+/// Updates `bank` from a raw bank select message. Returns whether it was one, in which case it
+/// isn't taken as a control change.
+pub fn bank_select(raw: &jack::RawMidi, bank: &mut u16) -> bool {
+    match *raw.bytes {
+        [status, BANK_SELECT_MSB, value] if status & 0xF0 == 0xB0 => {
+            *bank = ((value as u16) << 7) | (*bank & 0x7F);
+            true
+        }
+        [status, BANK_SELECT_LSB, value] if status & 0xF0 == 0xB0 => {
+            *bank = (*bank & !0x7F) | value as u16;
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Program number of a raw program change message, which picks the setlist entry.
+pub fn program_change(raw: &jack::RawMidi) -> Option<u8> {
+    match *raw.bytes {
+        [status, program] if status & 0xF0 == 0xC0 => Some(program),
+        _ => None,
+    }
+}
@@ -0,0 +1,38 @@
+use serde_yaml_ng::{Mapping, Value};
+
+/// Upgrades from each version of the session format to the next, starting with version 0.
+/// Raising `SESSION_VERSION` needs a step here for sessions saved before.
+const STEPS: [fn(&mut Mapping); crate::SESSION_VERSION as usize] = [to_v1, to_v2, to_v3];
+
+/// Upgrades the YAML of a session to the current format, one version at a time, so older
+/// sessions keep loading. Returns the version the session was saved with.
+pub fn migrate(session: &mut Value) -> u32 {
+    // Sessions used to be saved as a bare list of plugins
+    if let Value::Sequence(plugins) = session {
+        let mut mapping = Mapping::new();
+        mapping.insert("plugins".into(), Value::Sequence(std::mem::take(plugins)));
+        *session = Value::Mapping(mapping);
+    }
+    // Anything else isn't a session, which reading it will report
+    let Value::Mapping(mapping) = session else {
+        return 0;
+    };
+    let version = mapping
+        .get("version")
+        .and_then(Value::as_u64)
+        .map_or(0, |version| version as u32);
+    for step in STEPS.iter().skip(version as usize) {
+        step(mapping);
+    }
+    version
+}
+
+/// Version 1 added the volume and the connections. Both can be left out, and the volume of
+/// older sessions is left as it is when they're loaded.
+fn to_v1(_session: &mut Mapping) {}
+
+/// Version 2 added snapshots, which older sessions have none of.
+fn to_v2(_session: &mut Mapping) {}
+
+/// Version 3 added MIDI mappings, which older sessions have none of.
+fn to_v3(_session: &mut Mapping) {}
@@ -0,0 +1,196 @@
+use crate::log;
+use crate::osc::{self, Arg, Message};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Environment variable with the OSC URL of the session manager that launched Rake.
+const URL_VAR: &str = "NSM_URL";
+
+const API_VERSION_MAJOR: i32 = 1;
+const API_VERSION_MINOR: i32 = 2;
+
+/// Time the session manager has to accept Rake and open its session.
+const OPEN_TIMEOUT: Duration = Duration::from_secs(10);
+
+const ANNOUNCE: &str = "/nsm/server/announce";
+pub const OPEN: &str = "/nsm/client/open";
+pub const SAVE: &str = "/nsm/client/save";
+const SHOW_GUI: &str = "/nsm/client/show_optional_gui";
+const HIDE_GUI: &str = "/nsm/client/hide_optional_gui";
+
+/// Code of errors without a more specific one.
+const ERROR_GENERAL: i32 = -1;
+
+/// Session the session manager asks Rake to open.
+pub struct Open {
+    /// Path to keep Rake's state under, without an extension.
+    pub path: PathBuf,
+    /// Name Rake's JACK client takes.
+    pub client_id: String,
+}
+
+pub enum Request {
+    Open(Open),
+    Save,
+    ShowGui,
+    HideGui,
+}
+
+/// Connection to a New Session Manager server, which opens and saves Rake's session and shows
+/// and hides its window.
+pub struct Nsm {
+    socket: UdpSocket,
+    server: SocketAddr,
+    /// Whether the session manager shows and hides the window, rather than Rake closing with it.
+    pub optional_gui: bool,
+}
+
+impl Nsm {
+    /// Announces Rake to the session manager in `NSM_URL`, if it was launched by one, and waits
+    /// for it to open a session. `gui` is whether Rake has a window to show and hide.
+    pub fn announce(gui: bool) -> Option<std::io::Result<(Self, Open)>> {
+        let url = std::env::var(URL_VAR).ok()?;
+        Some(Self::connect(&url, gui))
+    }
+
+    fn connect(url: &str, gui: bool) -> std::io::Result<(Self, Open)> {
+        let address = url
+            .strip_prefix("osc.udp://")
+            .map(|address| address.trim_end_matches('/'))
+            .ok_or_else(|| std::io::Error::other(format!("Incorrect {} {}", URL_VAR, url)))?;
+        let server = address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| std::io::Error::other(format!("No address for {}", address)))?;
+        let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+        let mut nsm = Nsm {
+            socket,
+            server,
+            optional_gui: false,
+        };
+        let executable = std::env::args()
+            .next()
+            .and_then(|arg| {
+                PathBuf::from(arg)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+            })
+            .unwrap_or_else(|| "rake".to_string());
+        nsm.send(Message::new(
+            ANNOUNCE,
+            vec![
+                Arg::String("Rake".to_string()),
+                Arg::String(
+                    if gui {
+                        ":switch:optional-gui:"
+                    } else {
+                        ":switch:"
+                    }
+                    .to_string(),
+                ),
+                Arg::String(executable),
+                Arg::Int(API_VERSION_MAJOR),
+                Arg::Int(API_VERSION_MINOR),
+                Arg::Int(std::process::id() as i32),
+            ],
+        ));
+
+        let deadline = Instant::now() + OPEN_TIMEOUT;
+        let mut buffer = [0; 65536];
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    "The session manager didn't open a session",
+                ));
+            }
+            nsm.socket.set_read_timeout(Some(left))?;
+            let size = nsm.socket.recv(&mut buffer)?;
+            for message in osc::decode(&buffer[..size]).unwrap_or_default() {
+                match (message.address.as_str(), &message.args[..]) {
+                    ("/reply", [Arg::String(path), _, _, Arg::String(capabilities), ..])
+                        if path == ANNOUNCE =>
+                    {
+                        nsm.optional_gui = gui && capabilities.contains(":optional-gui:");
+                    }
+                    ("/error", [Arg::String(path), _, Arg::String(e), ..]) if path == ANNOUNCE => {
+                        return Err(std::io::Error::other(format!(
+                            "The session manager refused Rake: {}",
+                            e
+                        )));
+                    }
+                    (OPEN, [Arg::String(path), _, Arg::String(client_id), ..]) => {
+                        nsm.socket.set_nonblocking(true)?;
+                        if nsm.optional_gui {
+                            nsm.gui_shown(true);
+                        }
+                        let open = Open {
+                            path: PathBuf::from(path),
+                            client_id: client_id.clone(),
+                        };
+                        return Ok((nsm, open));
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Takes the requests that came in since the last call.
+    pub fn receive(&self) -> Vec<Request> {
+        let mut requests = Vec::new();
+        let mut buffer = [0; 65536];
+        while let Ok(size) = self.socket.recv(&mut buffer) {
+            for message in osc::decode(&buffer[..size]).unwrap_or_default() {
+                requests.push(match (message.address.as_str(), &message.args[..]) {
+                    (OPEN, [Arg::String(path), _, Arg::String(client_id), ..]) => {
+                        Request::Open(Open {
+                            path: PathBuf::from(path),
+                            client_id: client_id.clone(),
+                        })
+                    }
+                    (SAVE, _) => Request::Save,
+                    (SHOW_GUI, _) => Request::ShowGui,
+                    (HIDE_GUI, _) => Request::HideGui,
+                    _ => continue,
+                });
+            }
+        }
+        requests
+    }
+
+    /// Answers the request to `path`, e.g. `OPEN`.
+    pub fn reply(&self, path: &str, result: Result<(), String>) {
+        self.send(match result {
+            Ok(()) => Message::new(
+                "/reply",
+                vec![Arg::String(path.to_string()), Arg::String("OK".to_string())],
+            ),
+            Err(e) => Message::new(
+                "/error",
+                vec![
+                    Arg::String(path.to_string()),
+                    Arg::Int(ERROR_GENERAL),
+                    Arg::String(e),
+                ],
+            ),
+        });
+    }
+
+    pub fn gui_shown(&self, shown: bool) {
+        let path = if shown {
+            "/nsm/client/gui_is_shown"
+        } else {
+            "/nsm/client/gui_is_hidden"
+        };
+        self.send(Message::new(path, Vec::new()));
+    }
+
+    fn send(&self, message: Message) {
+        if let Err(e) = self.socket.send_to(&osc::encode(&message), self.server) {
+            log::error!("Error sending to the session manager: {}", e);
+        }
+    }
+}
@@ -0,0 +1,321 @@
+use crate::api::{Event, Feedback};
+use crate::log;
+use std::fmt;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::mpsc::{self, Receiver};
+
+/// Most addresses sent feedback, the ones heard from last.
+const MAX_CLIENTS: usize = 8;
+
+/// Argument of an OSC message, of the types Rake sends and understands.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Arg {
+    Int(i32),
+    Float(f32),
+    String(String),
+}
+
+impl fmt::Display for Arg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Arg::Int(value) => write!(f, "{}", value),
+            Arg::Float(value) => write!(f, "{}", value),
+            Arg::String(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub address: String,
+    pub args: Vec<Arg>,
+}
+
+impl Message {
+    pub fn new(address: &str, args: Vec<Arg>) -> Self {
+        Message {
+            address: address.to_string(),
+            args,
+        }
+    }
+}
+
+/// Reads the messages of a packet, taking those of bundles out. Time tags are ignored, so
+/// bundled messages take effect right away.
+pub fn decode(packet: &[u8]) -> Option<Vec<Message>> {
+    let mut messages = Vec::new();
+    decode_into(packet, &mut messages)?;
+    Some(messages)
+}
+
+fn decode_into(packet: &[u8], messages: &mut Vec<Message>) -> Option<()> {
+    if let Some(elements) = packet.strip_prefix(b"#bundle\0") {
+        let mut reader = Reader(elements.get(8..)?);
+        while !reader.0.is_empty() {
+            let size = u32::from_be_bytes(reader.word()?) as usize;
+            decode_into(reader.0.get(..size)?, messages)?;
+            reader.0 = &reader.0[size..];
+        }
+        return Some(());
+    }
+    let mut reader = Reader(packet);
+    let address = reader.string()?;
+    let tags = reader.string()?;
+    let mut args = Vec::new();
+    for tag in tags.strip_prefix(',')?.chars() {
+        args.push(match tag {
+            'i' => Arg::Int(i32::from_be_bytes(reader.word()?)),
+            'f' => Arg::Float(f32::from_be_bytes(reader.word()?)),
+            's' => Arg::String(reader.string()?),
+            'T' => Arg::Int(1),
+            'F' => Arg::Int(0),
+            _ => return None,
+        });
+    }
+    messages.push(Message { address, args });
+    Some(())
+}
+
+/// Reads the big-endian words and padded strings of a packet.
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn word(&mut self) -> Option<[u8; 4]> {
+        let word = self.0.get(..4)?.try_into().ok()?;
+        self.0 = &self.0[4..];
+        Some(word)
+    }
+
+    /// A string, which ends with at least one null byte and is padded to a whole word.
+    fn string(&mut self) -> Option<String> {
+        let end = self.0.iter().position(|byte| *byte == 0)?;
+        let string = String::from_utf8(self.0[..end].to_vec()).ok()?;
+        self.0 = self.0.get((end / 4 + 1) * 4..)?;
+        Some(string)
+    }
+}
+
+pub fn encode(message: &Message) -> Vec<u8> {
+    let mut packet = Vec::new();
+    write_string(&mut packet, &message.address);
+    let tags: String = std::iter::once(',')
+        .chain(message.args.iter().map(|arg| match arg {
+            Arg::Int(_) => 'i',
+            Arg::Float(_) => 'f',
+            Arg::String(_) => 's',
+        }))
+        .collect();
+    write_string(&mut packet, &tags);
+    for arg in &message.args {
+        match arg {
+            Arg::Int(value) => packet.extend(value.to_be_bytes()),
+            Arg::Float(value) => packet.extend(value.to_be_bytes()),
+            Arg::String(value) => write_string(&mut packet, value),
+        }
+    }
+    packet
+}
+
+fn write_string(packet: &mut Vec<u8>, string: &str) {
+    packet.extend(string.as_bytes());
+    packet.extend(std::iter::repeat_n(0, 4 - string.len() % 4));
+}
+
+/// Feedback message for an event, under the address of the command that sets the same value.
+pub fn event_message(event: &Event) -> Message {
+    match event {
+        Event::Meter {
+            plugin,
+            input,
+            output,
+        } => Message::new(
+            "/rake/meter",
+            vec![
+                Arg::String(plugin.to_string()),
+                Arg::Float(*input),
+                Arg::Float(*output),
+            ],
+        ),
+        Event::Param {
+            plugin,
+            index,
+            value,
+        } => Message::new(
+            "/rake/param",
+            vec![
+                Arg::String(plugin.to_string()),
+                Arg::Int(*index as i32),
+                Arg::Float(*value),
+            ],
+        ),
+        Event::Bypass { plugin, bypass } => Message::new(
+            "/rake/bypass",
+            vec![Arg::String(plugin.to_string()), Arg::Int(*bypass as i32)],
+        ),
+        Event::Volume { volume } => Message::new("/rake/volume", vec![Arg::Float(*volume)]),
+        Event::Chains => Message::new("/rake/chains", Vec::new()),
+    }
+}
+
+/// OSC server on a UDP port, whose packets are read in the background.
+pub struct Server {
+    socket: UdpSocket,
+    receiver: Receiver<(Message, SocketAddr)>,
+    /// Addresses that sent messages, which are sent feedback.
+    clients: Vec<SocketAddr>,
+    pub feedback: Feedback,
+}
+
+impl Server {
+    pub fn listen(address: SocketAddr) -> std::io::Result<Self> {
+        let socket = UdpSocket::bind(address)?;
+        let reader = socket.try_clone()?;
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut buffer = [0; 65536];
+            loop {
+                let (size, from) = match reader.recv_from(&mut buffer) {
+                    Ok(received) => received,
+                    Err(e) => {
+                        log::error!("Error receiving OSC: {}", e);
+                        continue;
+                    }
+                };
+                let Some(messages) = decode(&buffer[..size]) else {
+                    log::error!("Incorrect OSC packet from {}", from);
+                    continue;
+                };
+                for message in messages {
+                    if sender.send((message, from)).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        Ok(Server {
+            socket,
+            receiver,
+            clients: Vec::new(),
+            feedback: Feedback::default(),
+        })
+    }
+
+    /// Takes the next message received. A new sender is sent feedback from then on, starting
+    /// with every value.
+    pub fn receive(&mut self) -> Option<(Message, SocketAddr)> {
+        let (message, from) = self.receiver.try_recv().ok()?;
+        if !self.clients.contains(&from) {
+            if self.clients.len() == MAX_CLIENTS {
+                self.clients.remove(0);
+            }
+            self.clients.push(from);
+            self.feedback.reset();
+        }
+        Some((message, from))
+    }
+
+    pub fn has_clients(&self) -> bool {
+        !self.clients.is_empty()
+    }
+
+    pub fn send(&self, to: SocketAddr, message: &Message) {
+        if let Err(e) = self.socket.send_to(&encode(message), to) {
+            log::error!("Error sending OSC to {}: {}", to, e);
+        }
+    }
+
+    /// Sends a message to every address that sent one.
+    pub fn broadcast(&self, message: &Message) {
+        for client in &self.clients {
+            self.send(*client, message);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bundle(elements: &[Vec<u8>]) -> Vec<u8> {
+        let mut packet = b"#bundle\0".to_vec();
+        // Time tag for right away
+        packet.extend(1_u64.to_be_bytes());
+        for element in elements {
+            packet.extend((element.len() as u32).to_be_bytes());
+            packet.extend(element);
+        }
+        packet
+    }
+
+    #[test]
+    fn strings_are_padded() {
+        let packet = encode(&Message::new(
+            "/rake",
+            vec![Arg::String("abcd".to_string())],
+        ));
+        assert_eq!(packet, b"/rake\0\0\0,s\0\0abcd\0\0\0\0");
+        assert_eq!(
+            encode(&Message::new("/abc", Vec::new())),
+            b"/abc\0\0\0\0,\0\0\0"
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let message = Message::new(
+            "/rake/param",
+            vec![
+                Arg::String("Reverb".to_string()),
+                Arg::Int(-3),
+                Arg::Float(0.25),
+                Arg::String(String::new()),
+            ],
+        );
+        assert_eq!(decode(&encode(&message)), Some(vec![message]));
+    }
+
+    #[test]
+    fn booleans_are_ints() {
+        let packet = b"/rake/bypass\0\0\0\0,sT\0Reverb\0\0";
+        assert_eq!(
+            decode(packet),
+            Some(vec![Message::new(
+                "/rake/bypass",
+                vec![Arg::String("Reverb".to_string()), Arg::Int(1)]
+            )])
+        );
+    }
+
+    #[test]
+    fn bundles() {
+        let next = Message::new("/rake/next", Vec::new());
+        let volume = Message::new("/rake/volume", vec![Arg::Float(-6.0)]);
+        let packet = bundle(&[encode(&next), bundle(&[encode(&volume)]), encode(&next)]);
+        assert_eq!(decode(&packet), Some(vec![next.clone(), volume, next]));
+        assert_eq!(decode(&bundle(&[])), Some(Vec::new()));
+    }
+
+    #[test]
+    fn truncated_packets() {
+        let packet = bundle(&[encode(&Message::new(
+            "/rake/meter",
+            vec![Arg::String("id".to_string()), Arg::Float(0.5), Arg::Int(2)],
+        ))]);
+        // Cut after the time tag it's an empty bundle, which is fine
+        for length in (0..packet.len()).filter(|length| *length != 16) {
+            assert_eq!(decode(&packet[..length]), None, "{} bytes", length);
+        }
+        // An element longer than the rest of the bundle
+        let mut packet = bundle(&[encode(&Message::new("/rake/next", Vec::new()))]);
+        packet[19] += 4;
+        assert_eq!(decode(&packet), None);
+    }
+
+    #[test]
+    fn incorrect_packets() {
+        // No type tags, an unknown type and a string that isn't UTF-8
+        assert_eq!(decode(b"/rake\0\0\0"), None);
+        assert_eq!(decode(b"/rake\0\0\0,d\0\0\0\0\0\0\0\0\0\0"), None);
+        assert_eq!(decode(b"/rake\0\0\0,s\0\0\xff\0\0\0"), None);
+    }
+}
@@ -0,0 +1,55 @@
+use rack::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// A plugin's parameters, as saved by the user under a name in a YAML file of its own.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Settings {
+    pub params: Vec<(ParameterInfo, f32)>,
+}
+
+/// Directory of the user presets of a plugin, under `presets` in Rake's config directory.
+pub fn dir(info: &PluginInfo) -> Option<PathBuf> {
+    let name = info.name.replace(['/', '\\'], "_");
+    crate::config::dir().map(|dir| dir.join("presets").join(name))
+}
+
+/// Names of a plugin's user presets, in alphabetical order.
+pub fn list(info: &PluginInfo) -> Vec<String> {
+    match dir(info) {
+        Some(dir) => yaml_names(&dir),
+        None => Vec::new(),
+    }
+}
+
+/// Names of the YAML files in a directory without the extension, in alphabetical order.
+pub fn yaml_names(dir: &Path) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "yaml")
+        })
+        .filter_map(|path| Some(path.file_stem()?.to_string_lossy().into_owned()))
+        .collect();
+    names.sort();
+    names
+}
+
+pub fn load(info: &PluginInfo, name: &str) -> Result<Settings> {
+    let path = dir(info)
+        .ok_or_else(|| Error::Other("No config directory".to_string()))?
+        .join(format!("{}.yaml", name));
+    let content = std::fs::read_to_string(&path)?;
+    serde_yaml_ng::from_str(&content)
+        .map_err(|e| Error::Other(format!("Incorrect YAML in {}: {}", path.display(), e)))
+}
+
+pub fn save(path: &Path, settings: &Settings) -> Result<()> {
+    let content = serde_yaml_ng::to_string(settings).unwrap();
+    std::fs::write(path, content)?;
+    Ok(())
+}
@@ -1,183 +1,1201 @@
-use jack::{AudioIn, AudioOut, Client, ClientOptions, ProcessHandler};
+use crate::chain::{
+    AuxReturn, Buffers, Bus, Chain, Fault, MAX_EXTRA_BUSES, MAX_RETURNS, Midi, Node, OutputBus,
+    PARAM_RAMP_SECONDS, ProcessError, Slot, Split, Unit,
+};
+use crate::dsp::{DcBlocker, Level, Limiter, Routing, flush_denormals, mix_down};
+use crate::log;
+use crate::looper::LooperAction;
+use crate::metronome::{Beats, ClickSound, Metronome};
+use crate::midi::{self, ControlChange, MidiMapping, MidiTarget};
+use crate::recorder;
+use crate::render::Render;
+use crate::sequencer::RawMessage;
+use crate::tempo::{TapTempo, Tempo};
+use jack::{AudioIn, AudioOut, Client, ClientOptions, MidiIn, ProcessHandler, TransportState};
 use rack::prelude::*;
-use ringbuf::traits::{Consumer, Producer, Split};
+use ringbuf::traits::{Consumer, Observer, Producer, Split as _};
 use ringbuf::{HeapCons, HeapProd, HeapRb};
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 
 pub enum Command {
-    LoadPlugin(Plugin, Uuid),
+    LoadPlugin(Slot, Uuid),
+    LoadPluginIntoBranch(Slot, Uuid, usize),
+    /// Puts a slot at an index of a chain, or of a split branch if one is given.
+    InsertPlugin(Slot, Uuid, Option<(Uuid, usize)>, usize),
+    AddSplit(Box<Split>, Uuid),
+    BranchGainChange(Uuid, usize, f32),
     DeletePlugin(Uuid),
+    /// Swaps the unit of the slot with the same id, keeping the slot's settings.
+    ReplacePlugin(Slot),
+    AddBus(Uuid, Bus),
+    RemoveBus(Uuid),
+    SetOutputBus(Uuid, OutputBus),
     MovePluginUp(Uuid),
     MovePluginDown(Uuid),
     ParamChange(Uuid, ParameterInfo, f32),
+    SetBypass(Uuid, bool),
+    MixChange(Uuid, f32),
+    InputGainChange(Uuid, f32),
+    OutputGainChange(Uuid, f32),
+    RoutingChange(Uuid, Routing),
+    SetSend(Uuid, Uuid, f32),
+    LooperAction(Uuid, LooperAction),
+    AddChain(Strip),
+    DeleteChain(Uuid),
+    SetMute(Uuid, bool),
+    SetSolo(Uuid, bool),
+    AddReturn(AuxReturn, Uuid),
+    DeleteReturn(Uuid),
     ClearSession,
     VolumeChange(f32),
+    SetLimiter(bool),
+    SetDcBlocker(bool),
+    SetMonitor(Monitor),
+    SetPanic(bool),
+    SetMasterMute(bool),
+    SetAnalyzerTap(Option<Uuid>),
+    SetTuner(Option<Uuid>),
+    SetRecording(bool),
+    StartRender(Box<Render>),
+    SetMetronome(bool),
+    MetronomeVolumeChange(f32),
+    SetClickSound(ClickSound),
+    SetClickToMain(bool),
+    Tap,
+    SetTapController(Option<u8>),
+    /// Maps a controller, replacing any mapping of the same controller or target.
+    MapController(MidiMapping),
+    UnmapController(u8),
+    /// Whether every control change is reported to the GUI, which is learning a controller.
+    SetMidiLearn(bool),
     Exit,
 }
 
-pub struct Processor {
+/// Takes a raw message from a MIDI input: program changes, with the bank selected before them,
+/// go to the GUI, and control changes to the mappings and loopers while the event buffer has
+/// room.
+fn receive_midi(
+    raw: &jack::RawMidi,
+    events: &mut Vec<ControlChange>,
+    program_sender: &mut HeapProd<(u16, u8)>,
+    bank: &mut u16,
+) {
+    if let Some(program) = midi::program_change(raw) {
+        // Only the last one counts, so one that doesn't fit can be dropped
+        let _ = program_sender.try_push((*bank, program));
+    } else if midi::bank_select(raw, bank) {
+        // Only picks the bank of the program changes that follow
+    } else if events.len() < midi::MAX_EVENTS
+        && let Some(event) = midi::control_change(raw)
+    {
+        events.push(event);
+    }
+}
+
+/// Process cycles over which a parameter change is ramped.
+fn param_ramp_cycles(client: &Client) -> usize {
+    (PARAM_RAMP_SECONDS * client.sample_rate() as f32 / client.buffer_size() as f32)
+        .ceil()
+        .max(1.0) as usize
+}
+
+pub fn db_to_gain(db: f32) -> f32 {
+    10.0_f32.powf(db / 20.0)
+}
+
+pub fn gain_to_db(gain: f32) -> f32 {
+    20.0 * gain.log10()
+}
+
+/// Master volume at or below this level is treated as silence.
+pub const VOLUME_MIN_DB: f32 = -60.0;
+pub const VOLUME_MAX_DB: f32 = 12.0;
+
+/// Time over which master volume changes are ramped.
+const VOLUME_RAMP_SECONDS: f32 = 0.02;
+
+/// Time over which chains fade in and out when muted or soloed.
+const MUTE_FADE_SECONDS: f32 = 0.01;
+
+/// Time over which plugins fade in when inserted, out when deleted, and out and back in
+/// when moved.
+const SLOT_FADE_SECONDS: f32 = 0.01;
+
+/// Share of a cycle's time that may be spent applying commands from the GUI.
+const COMMAND_BUDGET: f32 = 0.1;
+
+/// Smoothed share of a cycle above which a slot is bypassed before it causes xruns.
+const WATCHDOG_LOAD: f32 = 0.8;
+
+pub fn volume_to_gain(db: f32) -> f32 {
+    if db <= VOLUME_MIN_DB {
+        0.0
+    } else {
+        db_to_gain(db)
+    }
+}
+
+/// What is heard at the outputs of every chain.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Monitor {
+    #[default]
+    Processed,
+    /// Like `Processed`, but silent instead of passing the input through when no plugin ran.
+    WetOnly,
+    /// The raw input, without running the chains.
+    Dry,
+}
+
+impl Monitor {
+    pub const ALL: [Monitor; 3] = [Monitor::Processed, Monitor::WetOnly, Monitor::Dry];
+}
+
+impl fmt::Display for Monitor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Monitor::Processed => "Processed",
+            Monitor::WetOnly => "Wet Only",
+            Monitor::Dry => "Dry",
+        })
+    }
+}
+
+/// Id of the chain that uses the unprefixed `in_*`/`out_*` ports.
+pub const MAIN_CHAIN: Uuid = Uuid::nil();
+
+/// Maximum number of chains, so the process thread never grows its list of strips.
+pub const MAX_CHAINS: usize = 16;
+
+/// Audio ports of one chain.
+pub struct ChainPorts {
     left_in: jack::Port<AudioIn>,
     right_in: jack::Port<AudioIn>,
     left_out: jack::Port<AudioOut>,
     right_out: jack::Port<AudioOut>,
-    loaded_plugins: Vec<(Plugin, Uuid)>,
+}
+
+impl ChainPorts {
+    /// Registers `<prefix>in_left`, `<prefix>in_right`, `<prefix>out_left` and `<prefix>out_right`.
+    pub fn register(client: &Client, prefix: &str) -> std::result::Result<Self, jack::Error> {
+        Ok(ChainPorts {
+            left_in: client.register_port(&format!("{}in_left", prefix), AudioIn::default())?,
+            right_in: client.register_port(&format!("{}in_right", prefix), AudioIn::default())?,
+            left_out: client.register_port(&format!("{}out_left", prefix), AudioOut::default())?,
+            right_out: client
+                .register_port(&format!("{}out_right", prefix), AudioOut::default())?,
+        })
+    }
+
+    pub fn unregister(self, client: &Client) {
+        for result in [
+            client.unregister_port(self.left_in),
+            client.unregister_port(self.right_in),
+            client.unregister_port(self.left_out),
+            client.unregister_port(self.right_out),
+        ] {
+            if let Err(e) = result {
+                log::error!("Error unregistering port: {}", e);
+            }
+        }
+    }
+}
+
+/// A chain together with its ports and output processing.
+pub struct Strip {
+    pub id: Uuid,
+    ports: ChainPorts,
+    chain: Chain,
+    returns: Vec<AuxReturn>,
+    mute: bool,
+    solo: bool,
+    /// Gain of the mute/solo fade, from 0 to 1.
+    fade: f32,
+    limiter: Limiter,
+    l_dc_blocker: DcBlocker,
+    r_dc_blocker: DcBlocker,
+}
+
+impl Strip {
+    pub fn new(id: Uuid, ports: ChainPorts, sample_rate: usize) -> Self {
+        Strip {
+            id,
+            ports,
+            chain: Chain::default(),
+            returns: Vec::with_capacity(MAX_RETURNS),
+            mute: false,
+            solo: false,
+            fade: 1.0,
+            limiter: Limiter::new(sample_rate),
+            l_dc_blocker: DcBlocker::new(sample_rate),
+            r_dc_blocker: DcBlocker::new(sample_rate),
+        }
+    }
+
+    /// Unregisters the ports of a strip that the process thread is done with. Returns its
+    /// chain and return chains, which still hold its plugins.
+    pub fn unregister(mut self, client: &Client) -> (Chain, Vec<AuxReturn>) {
+        for chain in self.chains_mut() {
+            chain.unregister_buses(client);
+        }
+        self.ports.unregister(client);
+        (self.chain, self.returns)
+    }
+
+    /// The strip's own chain followed by its return chains.
+    fn chains_mut(&mut self) -> impl Iterator<Item = &mut Chain> {
+        std::iter::once(&mut self.chain).chain(
+            self.returns
+                .iter_mut()
+                .map(|aux_return| &mut aux_return.chain),
+        )
+    }
+}
+
+/// Things removed in the process thread that have to be released in the GUI thread.
+pub enum Garbage {
+    Slot(Slot),
+    Bus(Bus),
+    Strip(Strip),
+    Return(AuxReturn),
+    Split(Box<Split>),
+    /// A finished render, to be saved by the GUI thread.
+    Render(Box<Render>),
+}
+
+/// JACK notifications the GUI acts on.
+#[derive(Default)]
+pub struct Notifications {
+    /// Set when JACK reports a sample rate, cleared by the GUI once it has checked it.
+    pub sample_rate_changed: AtomicBool,
+    /// Set when the JACK server shuts down or disconnects the client.
+    pub shutdown: AtomicBool,
+    /// Xruns since start or since the GUI last reset the count.
+    pub xruns: AtomicUsize,
+}
+
+/// Receives JACK notifications, which arrive outside the process thread.
+pub struct Notifier(Arc<Notifications>);
+
+impl jack::NotificationHandler for Notifier {
+    unsafe fn shutdown(&mut self, _: jack::ClientStatus, _: &str) {
+        self.0.shutdown.store(true, Ordering::Relaxed);
+    }
+
+    fn sample_rate(&mut self, _: &jack::Client, _: jack::Frames) -> jack::Control {
+        self.0.sample_rate_changed.store(true, Ordering::Relaxed);
+        jack::Control::Continue
+    }
+
+    fn xrun(&mut self, _: &jack::Client) -> jack::Control {
+        self.0.xruns.fetch_add(1, Ordering::Relaxed);
+        jack::Control::Continue
+    }
+}
+
+pub struct Processor {
+    midi_in: jack::Port<MidiIn>,
+    midi: Midi,
+    metronome_out: jack::Port<AudioOut>,
+    metronome: Metronome,
+    tempo: Arc<Tempo>,
+    tap_tempo: TapTempo,
+    /// MIDI controller that triggers tap tempo.
+    tap_controller: Option<u8>,
+    /// Controllers that move parameters or the volume instead of reaching the plugins.
+    midi_mappings: Vec<MidiMapping>,
+    midi_learn: bool,
+    /// Controller numbers and values of mapped control changes, and of every one during MIDI
+    /// learn, for the GUI.
+    controller_sender: HeapProd<(u8, u8)>,
+    strips: Vec<Strip>,
     volume: f32,
+    volume_target: f32,
+    volume_step: f32,
+    volume_ramp: usize,
+    /// Master volume for each sample of the current cycle, shared by all strips.
+    volume_gains: Vec<f32>,
+    /// Whether the master output is muted, and the gain fading it to or from silence.
+    master_mute: bool,
+    master_mute_gain: f32,
+    limiter_enabled: bool,
+    dc_blocker_enabled: bool,
+    monitor: Monitor,
+    /// Whether every chain is bypassed in an emergency, and how far the outputs have faded
+    /// from the processed signal to the dry input, from 0 to 1.
+    panic: bool,
+    panic_fade: f32,
+    flush_denormals: bool,
     command_receiver: HeapCons<Command>,
-    garbage_sender: HeapProd<(Plugin, Uuid)>,
-    l_vec: Vec<f32>,
-    r_vec: Vec<f32>,
+    garbage_sender: HeapProd<Garbage>,
+    /// Input and output levels of every slot and chain, sent each cycle.
+    meter_sender: HeapProd<(Uuid, Level, Level, f32)>,
+    /// Slots bypassed by the process thread and why.
+    fault_sender: HeapProd<(Uuid, Fault)>,
+    /// Errors for the GUI to log, with the slot or chain they concern.
+    error_sender: HeapProd<(Uuid, ProcessError)>,
+    /// Program changes received on the MIDI input with the bank selected before them, for the
+    /// GUI to pick the setlist entry or snapshot.
+    program_sender: HeapProd<(u16, u8)>,
+    bank: u16,
+    /// MIDI read from an ALSA sequencer device, merged into the JACK MIDI input.
+    alsa_midi: HeapCons<RawMessage>,
+    /// Slot or chain whose output is sent to the spectrum analyzer.
+    analyzer_tap: Option<Uuid>,
+    analyzer_sender: HeapProd<f32>,
+    /// Chain whose dry input is sent to the tuner.
+    tuner_input: Option<Uuid>,
+    tuner_sender: HeapProd<f32>,
+    /// Whether the main output is sent to the recorder.
+    recording: bool,
+    /// Output and dry input of the main chain, interleaved in frames of `recorder::FRAME`.
+    recorder_sender: HeapProd<f32>,
+    /// File being rendered through the main chain in place of its input.
+    render: Option<Box<Render>>,
+    buffers: Buffers,
 }
 
-impl ProcessHandler for Processor {
-    fn process(&mut self, client: &jack::Client, scope: &jack::ProcessScope) -> jack::Control {
-        match self.command_receiver.try_pop() {
-            Some(Command::LoadPlugin(plugin, id)) => {
-                self.loaded_plugins.push((plugin, id));
-            }
-            Some(Command::DeletePlugin(id)) => {
-                if let Some(i) = self
-                    .loaded_plugins
-                    .iter()
-                    .rposition(|plugin| plugin.1 == id)
-                {
-                    if let Err(e) = self.garbage_sender.try_push(self.loaded_plugins.remove(i)) {
-                        eprintln!("Error removing plugin {}", e.0.info())
-                    }
+/// Hands a removed plugin to the GUI thread so it isn't dropped in the process callback.
+fn dispose(
+    garbage_sender: &mut HeapProd<Garbage>,
+    error_sender: &mut HeapProd<(Uuid, ProcessError)>,
+    slot: Slot,
+) {
+    let id = slot.id;
+    if garbage_sender.try_push(Garbage::Slot(slot)).is_err() {
+        let _ = error_sender.try_push((id, ProcessError::RemovePlugin));
+    }
+}
+
+/// Hands a removed bus to the GUI thread, which also unregisters its ports.
+fn dispose_bus(
+    garbage_sender: &mut HeapProd<Garbage>,
+    error_sender: &mut HeapProd<(Uuid, ProcessError)>,
+    bus: Bus,
+) {
+    if garbage_sender.try_push(Garbage::Bus(bus)).is_err() {
+        let _ = error_sender.try_push((Uuid::nil(), ProcessError::RemoveBus));
+    }
+}
+
+/// Hands a removed strip to the GUI thread, which also unregisters its ports.
+fn dispose_strip(
+    garbage_sender: &mut HeapProd<Garbage>,
+    error_sender: &mut HeapProd<(Uuid, ProcessError)>,
+    strip: Strip,
+) {
+    let id = strip.id;
+    if garbage_sender.try_push(Garbage::Strip(strip)).is_err() {
+        let _ = error_sender.try_push((id, ProcessError::RemoveChain));
+    }
+}
+
+/// Hands back a split that didn't fit in its chain, since it owns its branches' delay lines.
+fn dispose_split(
+    garbage_sender: &mut HeapProd<Garbage>,
+    error_sender: &mut HeapProd<(Uuid, ProcessError)>,
+    split: Box<Split>,
+) {
+    let id = split.id;
+    if garbage_sender.try_push(Garbage::Split(split)).is_err() {
+        let _ = error_sender.try_push((id, ProcessError::RemoveSplit));
+    }
+}
+
+fn dispose_return(
+    garbage_sender: &mut HeapProd<Garbage>,
+    error_sender: &mut HeapProd<(Uuid, ProcessError)>,
+    aux_return: AuxReturn,
+) {
+    let id = aux_return.id;
+    if garbage_sender
+        .try_push(Garbage::Return(aux_return))
+        .is_err()
+    {
+        let _ = error_sender.try_push((id, ProcessError::RemoveReturn));
+    }
+}
+
+impl Processor {
+    fn dispose(&mut self, slot: Slot) {
+        dispose(&mut self.garbage_sender, &mut self.error_sender, slot);
+    }
+
+    /// Whether the chains run this cycle. They don't while monitoring dry, nor once the panic
+    /// fade is done, in case a plugin is what went wrong.
+    fn chains_running(&self) -> bool {
+        self.monitor != Monitor::Dry && !(self.panic && self.panic_fade == 1.0)
+    }
+
+    /// Fades a plugin out before moving it, so the chain doesn't glitch. Splits and plugins
+    /// in chains that aren't running are moved right away.
+    fn move_node(&mut self, id: Uuid, up: bool, fade_step: f32) {
+        let running = self.chains_running();
+        match self.slot_mut(id) {
+            Some(slot) if running => slot.start_move(up, fade_step),
+            _ => {
+                for chain in self.chains_mut() {
+                    chain.move_node(id, up);
                 }
             }
-            Some(Command::MovePluginUp(id)) => {
-                if let Some(i) = self.loaded_plugins.iter().position(|plugin| plugin.1 == id) {
-                    self.loaded_plugins.swap(i - 1, i);
-                }
+        }
+    }
+
+    /// Ramps the master volume to `gain`.
+    fn set_volume(&mut self, gain: f32, sample_rate: usize) {
+        self.volume_ramp = ((VOLUME_RAMP_SECONDS * sample_rate as f32) as usize).max(1);
+        self.volume_target = gain;
+        self.volume_step = (gain - self.volume) / self.volume_ramp as f32;
+    }
+
+    /// Moves the targets of mapped controllers, whose control changes are taken out of the
+    /// events passed on to the loopers.
+    fn apply_mappings(&mut self, client: &Client) {
+        let mut i = 0;
+        while i < self.midi.events.len() {
+            let ControlChange {
+                controller, value, ..
+            } = self.midi.events[i];
+            let mapping = self
+                .midi_mappings
+                .iter()
+                .find(|mapping| mapping.controller == controller)
+                .copied();
+            if mapping.is_some() || self.midi_learn {
+                // The GUI only shows these, so one that doesn't fit can be dropped
+                let _ = self.controller_sender.try_push((controller, value));
             }
-            Some(Command::MovePluginDown(id)) => {
-                if let Some(i) = self
-                    .loaded_plugins
-                    .iter()
-                    .rposition(|plugin| plugin.1 == id)
-                {
-                    self.loaded_plugins.swap(i, i + 1);
+            let Some(mapping) = mapping else {
+                i += 1;
+                continue;
+            };
+            match mapping.target {
+                MidiTarget::Param {
+                    plugin_id, index, ..
+                } => {
+                    let cycles = param_ramp_cycles(client);
+                    if let Some(slot) = self.slot_mut(plugin_id) {
+                        slot.start_ramp(index, mapping.value(value), cycles);
+                    }
+                }
+                MidiTarget::Volume => {
+                    self.set_volume(volume_to_gain(mapping.value(value)), client.sample_rate());
                 }
             }
-            Some(Command::ParamChange(plugin_id, param_info, value)) => {
-                if let Some(plugin) = self
-                    .loaded_plugins
+            self.midi.events.remove(i);
+        }
+    }
+
+    fn chains_mut(&mut self) -> impl Iterator<Item = &mut Chain> {
+        self.strips.iter_mut().flat_map(Strip::chains_mut)
+    }
+
+    fn slot_mut(&mut self, id: Uuid) -> Option<&mut Slot> {
+        self.chains_mut().find_map(|chain| chain.slot_mut(id))
+    }
+
+    fn split_mut(&mut self, id: Uuid) -> Option<&mut Split> {
+        self.chains_mut().find_map(|chain| chain.split_mut(id))
+    }
+
+    /// Finds a strip's chain or a return chain by id.
+    fn chain_mut(&mut self, id: Uuid) -> Option<&mut Chain> {
+        self.strips.iter_mut().find_map(|strip| {
+            if strip.id == id {
+                Some(&mut strip.chain)
+            } else {
+                strip
+                    .returns
                     .iter_mut()
-                    .find(|plugin| plugin.1 == plugin_id)
-                {
-                    if let Err(e) = plugin.0.set_parameter(param_info.index, value) {
-                        eprintln!(
-                            "Error setting parameter {} of {}: {}",
-                            param_info.name,
-                            plugin.0.info(),
-                            e
-                        )
-                    }
+                    .find(|aux_return| aux_return.id == id)
+                    .map(|aux_return| &mut aux_return.chain)
+            }
+        })
+    }
+}
+
+impl ProcessHandler for Processor {
+    /// Called between cycles when the JACK period changes, so buffers can be reallocated here.
+    fn buffer_size(&mut self, client: &jack::Client, size: jack::Frames) -> jack::Control {
+        let size = size as usize;
+        self.buffers = Buffers::new(size);
+        self.volume_gains = vec![self.volume; size];
+        for strip in &mut self.strips {
+            for aux_return in &mut strip.returns {
+                aux_return.set_buffer_size(size);
+            }
+            for chain in strip.chains_mut() {
+                for slot in chain.slots_mut() {
+                    slot.set_buffer_size(client.sample_rate(), size);
                 }
             }
-            Some(Command::ClearSession) => {
-                for i in (0..self.loaded_plugins.len()).rev() {
-                    if let Err(e) = self.garbage_sender.try_push(self.loaded_plugins.remove(i)) {
-                        eprintln!("Error removing plugin {}", e.0.info())
+        }
+        jack::Control::Continue
+    }
+
+    fn process(&mut self, client: &jack::Client, scope: &jack::ProcessScope) -> jack::Control {
+        if self.flush_denormals {
+            flush_denormals();
+        }
+
+        // Commands are setters, so a burst of slider moves ends on the latest value. Whatever
+        // doesn't fit in the budget waits for the next cycle
+        let command_deadline = Instant::now()
+            + Duration::from_secs_f32(
+                COMMAND_BUDGET * scope.n_frames() as f32 / client.sample_rate() as f32,
+            );
+        let slot_fade_step = 1.0 / (SLOT_FADE_SECONDS * client.sample_rate() as f32);
+        while let Some(command) = self.command_receiver.try_pop() {
+            match command {
+                Command::LoadPlugin(mut slot, chain_id) => match self.chain_mut(chain_id) {
+                    Some(chain) if chain.nodes.len() < chain.nodes.capacity() => {
+                        slot.start_fade_in(slot_fade_step);
+                        chain.nodes.push(Node::Plugin(slot));
+                    }
+                    _ => self.dispose(slot),
+                },
+                Command::LoadPluginIntoBranch(mut slot, split_id, branch) => {
+                    match self.split_mut(split_id) {
+                        Some(split)
+                            if split.branches[branch].len() < split.branches[branch].capacity() =>
+                        {
+                            slot.start_fade_in(slot_fade_step);
+                            split.branches[branch].push(slot);
+                        }
+                        _ => self.dispose(slot),
+                    }
+                }
+                Command::InsertPlugin(mut slot, chain_id, branch, index) => {
+                    slot.start_fade_in(slot_fade_step);
+                    match branch {
+                        Some((split_id, branch)) => match self.split_mut(split_id) {
+                            Some(split)
+                                if split.branches[branch].len()
+                                    < split.branches[branch].capacity() =>
+                            {
+                                let slots = &mut split.branches[branch];
+                                slots.insert(index.min(slots.len()), slot);
+                            }
+                            _ => self.dispose(slot),
+                        },
+                        None => match self.chain_mut(chain_id) {
+                            Some(chain) if chain.nodes.len() < chain.nodes.capacity() => {
+                                let index = index.min(chain.nodes.len());
+                                chain.nodes.insert(index, Node::Plugin(slot));
+                            }
+                            _ => self.dispose(slot),
+                        },
+                    }
+                }
+                Command::AddSplit(split, chain_id) => match self.chain_mut(chain_id) {
+                    Some(chain) if chain.nodes.len() < chain.nodes.capacity() => {
+                        chain.nodes.push(Node::Split(*split));
+                    }
+                    _ => dispose_split(&mut self.garbage_sender, &mut self.error_sender, split),
+                },
+                Command::BranchGainChange(split_id, branch, gain) => {
+                    if let Some(split) = self.split_mut(split_id) {
+                        split.gains[branch] = gain;
+                    }
+                }
+                Command::DeletePlugin(id) => {
+                    // A slot in chains that aren't running would never fade out
+                    let running = self.chains_running();
+                    match self.slot_mut(id) {
+                        Some(slot) if running => slot.start_removal(slot_fade_step),
+                        _ => {
+                            let garbage_sender = &mut self.garbage_sender;
+                            let error_sender = &mut self.error_sender;
+                            for chain in self.strips.iter_mut().flat_map(Strip::chains_mut) {
+                                chain
+                                    .remove(id, |slot| dispose(garbage_sender, error_sender, slot));
+                            }
+                        }
+                    }
+                }
+                Command::ReplacePlugin(slot) => {
+                    let garbage = match self.slot_mut(slot.id) {
+                        Some(old) => slot.replace(old),
+                        None => slot,
+                    };
+                    dispose(&mut self.garbage_sender, &mut self.error_sender, garbage);
+                }
+                Command::AddBus(id, bus) => match self.slot_mut(id) {
+                    Some(slot) if slot.buses.len() < MAX_EXTRA_BUSES => slot.buses.push(bus),
+                    _ => dispose_bus(&mut self.garbage_sender, &mut self.error_sender, bus),
+                },
+                Command::RemoveBus(id) => {
+                    if let Some(bus) = self.slot_mut(id).and_then(|slot| slot.buses.pop()) {
+                        dispose_bus(&mut self.garbage_sender, &mut self.error_sender, bus);
+                    }
+                }
+                Command::SetOutputBus(id, output_bus) => {
+                    if let Some(slot) = self.slot_mut(id) {
+                        slot.output_bus = output_bus;
+                    }
+                }
+                Command::MovePluginUp(id) => self.move_node(id, true, slot_fade_step),
+                Command::MovePluginDown(id) => self.move_node(id, false, slot_fade_step),
+                Command::ParamChange(plugin_id, param_info, value) => {
+                    let cycles = param_ramp_cycles(client);
+                    if let Some(slot) = self.slot_mut(plugin_id) {
+                        slot.start_ramp(param_info.index, value, cycles);
+                    }
+                }
+                Command::SetBypass(id, bypass) => {
+                    if let Some(slot) = self.slot_mut(id) {
+                        slot.bypass = bypass;
+                    }
+                }
+                Command::MixChange(id, mix) => {
+                    if let Some(slot) = self.slot_mut(id) {
+                        slot.mix = mix;
+                    }
+                }
+                Command::InputGainChange(id, gain) => {
+                    if let Some(slot) = self.slot_mut(id) {
+                        slot.input_gain = gain;
+                    }
+                }
+                Command::OutputGainChange(id, gain) => {
+                    if let Some(slot) = self.slot_mut(id) {
+                        slot.output_gain = gain;
+                    }
+                }
+                Command::RoutingChange(id, routing) => {
+                    if let Some(slot) = self.slot_mut(id) {
+                        slot.routing = routing;
+                    }
+                }
+                Command::SetSend(id, return_id, level) => {
+                    if let Some(slot) = self.slot_mut(id) {
+                        slot.set_send(return_id, level);
+                    }
+                }
+                Command::LooperAction(id, action) => {
+                    if let Some(slot) = self.slot_mut(id)
+                        && let Unit::Looper(looper) = &mut slot.unit
+                    {
+                        looper.trigger(action);
+                    }
+                }
+                Command::AddChain(strip) => {
+                    if self.strips.len() < self.strips.capacity() {
+                        self.strips.push(strip);
+                    } else {
+                        dispose_strip(&mut self.garbage_sender, &mut self.error_sender, strip);
+                    }
+                }
+                Command::DeleteChain(id) => {
+                    if id != MAIN_CHAIN
+                        && let Some(i) = self.strips.iter().position(|strip| strip.id == id)
+                    {
+                        let strip = self.strips.remove(i);
+                        dispose_strip(&mut self.garbage_sender, &mut self.error_sender, strip);
+                        if self.analyzer_tap == Some(id) {
+                            self.analyzer_tap = None;
+                        }
+                    }
+                }
+                Command::SetMute(id, mute) => {
+                    if let Some(strip) = self.strips.iter_mut().find(|strip| strip.id == id) {
+                        strip.mute = mute;
+                    }
+                }
+                Command::SetSolo(id, solo) => {
+                    if let Some(strip) = self.strips.iter_mut().find(|strip| strip.id == id) {
+                        strip.solo = solo;
+                    }
+                }
+                Command::AddReturn(aux_return, chain_id) => {
+                    match self.strips.iter_mut().find(|strip| strip.id == chain_id) {
+                        Some(strip) if strip.returns.len() < strip.returns.capacity() => {
+                            strip.returns.push(aux_return);
+                        }
+                        _ => dispose_return(
+                            &mut self.garbage_sender,
+                            &mut self.error_sender,
+                            aux_return,
+                        ),
+                    }
+                }
+                Command::DeleteReturn(id) => {
+                    for strip in &mut self.strips {
+                        if let Some(i) = strip
+                            .returns
+                            .iter()
+                            .position(|aux_return| aux_return.id == id)
+                        {
+                            let aux_return = strip.returns.remove(i);
+                            dispose_return(
+                                &mut self.garbage_sender,
+                                &mut self.error_sender,
+                                aux_return,
+                            );
+                            break;
+                        }
+                    }
+                }
+                Command::SetTuner(chain_id) => {
+                    self.tuner_input = chain_id;
+                }
+                Command::SetRecording(recording) => {
+                    self.recording = recording;
+                }
+                Command::StartRender(render) => {
+                    self.render = Some(render);
+                }
+                Command::SetMetronome(enabled) => {
+                    self.metronome.enabled = enabled;
+                }
+                Command::MetronomeVolumeChange(gain) => {
+                    self.metronome.gain = gain;
+                }
+                Command::SetClickSound(sound) => {
+                    self.metronome.sound = sound;
+                }
+                Command::SetClickToMain(to_main) => {
+                    self.metronome.to_main = to_main;
+                }
+                Command::Tap => {
+                    if let Some(bpm) = self
+                        .tap_tempo
+                        .tap(scope.last_frame_time(), client.sample_rate())
+                    {
+                        self.tempo.set_bpm(bpm);
+                    }
+                }
+                Command::SetTapController(controller) => {
+                    self.tap_controller = controller;
+                }
+                Command::MapController(mapping) => {
+                    self.midi_mappings.retain(|other| {
+                        other.controller != mapping.controller && other.target != mapping.target
+                    });
+                    if self.midi_mappings.len() < self.midi_mappings.capacity() {
+                        self.midi_mappings.push(mapping);
+                    }
+                }
+                Command::UnmapController(controller) => {
+                    self.midi_mappings
+                        .retain(|mapping| mapping.controller != controller);
+                }
+                Command::SetMidiLearn(learn) => {
+                    self.midi_learn = learn;
+                }
+                Command::ClearSession => {
+                    self.analyzer_tap = None;
+                    self.midi_mappings.clear();
+                    while self.strips.len() > 1 {
+                        let strip = self.strips.pop().unwrap();
+                        dispose_strip(&mut self.garbage_sender, &mut self.error_sender, strip);
                     }
+                    let garbage_sender = &mut self.garbage_sender;
+                    let error_sender = &mut self.error_sender;
+                    while let Some(aux_return) = self.strips[0].returns.pop() {
+                        dispose_return(garbage_sender, error_sender, aux_return);
+                    }
+                    self.strips[0]
+                        .chain
+                        .clear(|slot| dispose(garbage_sender, error_sender, slot));
+                }
+                Command::VolumeChange(gain) => {
+                    self.set_volume(gain, client.sample_rate());
+                }
+                Command::SetLimiter(enabled) => {
+                    self.limiter_enabled = enabled;
+                }
+                Command::SetDcBlocker(enabled) => {
+                    self.dc_blocker_enabled = enabled;
+                }
+                Command::SetMonitor(monitor) => {
+                    self.monitor = monitor;
+                }
+                Command::SetPanic(panic) => {
+                    self.panic = panic;
+                }
+                Command::SetMasterMute(mute) => {
+                    self.master_mute = mute;
+                }
+                Command::SetAnalyzerTap(tap) => {
+                    self.analyzer_tap = tap;
+                    for chain in self.chains_mut() {
+                        for slot in chain.slots_mut() {
+                            slot.tap = tap == Some(slot.id);
+                        }
+                    }
+                }
+                Command::Exit => {
+                    return jack::Control::Quit;
                 }
             }
-            Some(Command::VolumeChange(volume)) => {
-                self.volume = volume;
+            if Instant::now() >= command_deadline {
+                break;
+            }
+        }
+
+        self.midi.events.clear();
+        for raw in self.midi_in.iter(scope) {
+            receive_midi(
+                &raw,
+                &mut self.midi.events,
+                &mut self.program_sender,
+                &mut self.bank,
+            );
+        }
+        // ALSA messages have no time within the cycle, so they take effect at its start
+        while let Some((bytes, len)) = self.alsa_midi.try_pop() {
+            let raw = jack::RawMidi {
+                time: 0,
+                bytes: &bytes[..len],
+            };
+            receive_midi(
+                &raw,
+                &mut self.midi.events,
+                &mut self.program_sender,
+                &mut self.bank,
+            );
+        }
+        self.apply_mappings(client);
+        for event in &self.midi.events {
+            if Some(event.controller) == self.tap_controller
+                && event.value >= 64
+                && let Some(bpm) = self.tap_tempo.tap(
+                    scope.last_frame_time().wrapping_add(event.time),
+                    client.sample_rate(),
+                )
+            {
+                self.tempo.set_bpm(bpm);
             }
-            Some(Command::Exit) => {
-                return jack::Control::Quit;
+        }
+
+        let mute_step = 1.0 / (MUTE_FADE_SECONDS * client.sample_rate() as f32);
+        for gain in &mut self.volume_gains {
+            if self.volume_ramp > 0 {
+                self.volume_ramp -= 1;
+                self.volume = if self.volume_ramp == 0 {
+                    self.volume_target
+                } else {
+                    self.volume + self.volume_step
+                };
             }
-            None => (),
+            self.master_mute_gain = if self.master_mute {
+                (self.master_mute_gain - mute_step).max(0.0)
+            } else {
+                (self.master_mute_gain + mute_step).min(1.0)
+            };
+            *gain = self.volume * self.master_mute_gain;
         }
 
-        let l_in = self.left_in.as_slice(scope);
-        let r_in = self.right_in.as_slice(scope);
-        let l_out = self.left_out.as_mut_slice(scope);
-        let r_out = self.right_out.as_mut_slice(scope);
+        // Tempo and position for this cycle. rack has no way to pass them on to plugins yet,
+        // so only the metronome follows them
+        let beats = client
+            .transport()
+            .query()
+            .ok()
+            .filter(|transport| transport.state == TransportState::Rolling)
+            .map(|transport| {
+                Beats::from_transport(&transport, client.sample_rate(), self.tempo.bpm())
+            });
+        let run_chains = self.chains_running();
+        let click = self.metronome_out.as_mut_slice(scope);
+        self.metronome
+            .process(click, beats.as_ref(), client.sample_rate());
 
-        l_out.copy_from_slice(l_in);
-        r_out.copy_from_slice(r_in);
+        self.buffers.tapped = false;
+        let soloed = self.strips.iter().any(|strip| strip.solo);
+        let fade_step = 1.0 / (MUTE_FADE_SECONDS * client.sample_rate() as f32);
+        // Slot loads are given as a fraction of the time available for a cycle
+        let cycle_seconds = scope.n_frames() as f32 / client.sample_rate() as f32;
+        let panic_step = if self.panic { fade_step } else { -fade_step };
 
-        self.l_vec.copy_from_slice(l_in);
-        self.r_vec.copy_from_slice(r_in);
+        for strip in &mut self.strips {
+            let l_in = strip.ports.left_in.as_slice(scope);
+            let r_in = strip.ports.right_in.as_slice(scope);
+            let l_out = strip.ports.left_out.as_mut_slice(scope);
+            let r_out = strip.ports.right_out.as_mut_slice(scope);
+
+            l_out.copy_from_slice(l_in);
+            r_out.copy_from_slice(r_in);
+            if strip.id == MAIN_CHAIN
+                && let Some(render) = &mut self.render
+            {
+                render.read(l_out, r_out);
+            }
+
+            if self.tuner_input == Some(strip.id) {
+                self.tuner_sender
+                    .push_iter(l_in.iter().zip(r_in).map(|(l, r)| (l + r) * 0.5));
+            }
 
-        for plugin in &mut self.loaded_plugins {
-            match plugin.0.process(
-                &[self.l_vec.as_mut_slice(), self.r_vec.as_mut_slice()],
-                &mut [l_out, r_out],
-                client.buffer_size() as usize,
-            ) {
-                Ok(_) => {
-                    self.l_vec.copy_from_slice(l_out);
-                    self.r_vec.copy_from_slice(r_out);
+            if run_chains {
+                for aux_return in &mut strip.returns {
+                    aux_return.clear();
                 }
-                Err(e) => {
-                    eprintln!("Plugin {} failed to process: {}", plugin.0.info(), e)
+                let processed = strip.chain.process(
+                    l_out,
+                    r_out,
+                    &mut self.buffers,
+                    &mut self.midi,
+                    &mut strip.returns,
+                );
+                if !processed && self.monitor == Monitor::WetOnly {
+                    l_out.fill(0.0);
+                    r_out.fill(0.0);
+                }
+                for aux_return in &mut strip.returns {
+                    aux_return.process(l_out, r_out, &mut self.buffers, &mut self.midi);
+                }
+
+                if self.panic || self.panic_fade > 0.0 {
+                    let mut panic_fade = self.panic_fade;
+                    for ((l, r), (dry_l, dry_r)) in l_out
+                        .iter_mut()
+                        .zip(r_out.iter_mut())
+                        .zip(l_in.iter().zip(r_in))
+                    {
+                        panic_fade = (panic_fade + panic_step).clamp(0.0, 1.0);
+                        *l += (dry_l - *l) * panic_fade;
+                        *r += (dry_r - *r) * panic_fade;
+                    }
                 }
             }
-        }
 
-        for sample in l_out {
-            *sample = *sample * self.volume * self.volume;
+            if self.dc_blocker_enabled {
+                strip.l_dc_blocker.process(l_out);
+                strip.r_dc_blocker.process(r_out);
+            }
+
+            let audible = if soloed { strip.solo } else { !strip.mute };
+            for ((l, r), gain) in l_out
+                .iter_mut()
+                .zip(r_out.iter_mut())
+                .zip(self.volume_gains.iter())
+            {
+                strip.fade = if audible {
+                    (strip.fade + fade_step).min(1.0)
+                } else {
+                    (strip.fade - fade_step).max(0.0)
+                };
+                *l *= gain * strip.fade;
+                *r *= gain * strip.fade;
+            }
+
+            if self.metronome.to_main && strip.id == MAIN_CHAIN {
+                for ((l, r), sample) in l_out.iter_mut().zip(r_out.iter_mut()).zip(click.iter()) {
+                    *l += sample;
+                    *r += sample;
+                }
+            }
+
+            if self.limiter_enabled {
+                strip.limiter.process(l_out, r_out);
+            }
+
+            if strip.id == MAIN_CHAIN
+                && let Some(render) = &mut self.render
+                && render.write(l_out, r_out)
+            {
+                let render = self.render.take().unwrap();
+                if self
+                    .garbage_sender
+                    .try_push(Garbage::Render(render))
+                    .is_err()
+                {
+                    let _ = self
+                        .error_sender
+                        .try_push((MAIN_CHAIN, ProcessError::FinishRender));
+                }
+            }
+
+            if self.recording && strip.id == MAIN_CHAIN {
+                // A whole cycle is dropped rather than part of it so the channels stay interleaved
+                if self.recorder_sender.vacant_len() >= l_out.len() * recorder::FRAME {
+                    self.recorder_sender.push_iter(
+                        l_out
+                            .iter()
+                            .zip(r_out.iter())
+                            .zip(l_in.iter().zip(r_in))
+                            .flat_map(|((l, r), (dry_l, dry_r))| [*l, *r, *dry_l, *dry_r]),
+                    );
+                } else {
+                    let _ = self
+                        .error_sender
+                        .try_push((MAIN_CHAIN, ProcessError::RecorderFull));
+                }
+            }
+
+            let _ = self.meter_sender.try_push((
+                strip.id,
+                Level::measure(l_in, r_in),
+                Level::measure(l_out, r_out),
+                0.0,
+            ));
+
+            if self.analyzer_tap == Some(strip.id) {
+                mix_down(&mut self.buffers.tap, l_out, r_out);
+                self.buffers.tapped = true;
+            }
+
+            // Readings are dropped while the GUI isn't keeping up
+            for chain in strip.chains_mut() {
+                for slot in chain.slots_mut() {
+                    slot.write_buses(scope);
+                    let load = slot.process_seconds / cycle_seconds;
+                    // Cycles have no deadline while rendering in freewheel mode
+                    if load > WATCHDOG_LOAD && self.render.is_none() {
+                        slot.fault = Some(Fault::Overload);
+                    }
+                    if let Some(fault) = slot.fault.take() {
+                        slot.bypass = true;
+                        slot.process_seconds = 0.0;
+                        if self.fault_sender.try_push((slot.id, fault)).is_err() {
+                            let _ = self
+                                .error_sender
+                                .try_push((slot.id, ProcessError::ReportFault(fault)));
+                        }
+                    }
+                    if let Some(error) = slot.error.take() {
+                        let _ = self.error_sender.try_push((slot.id, error));
+                    }
+                    let _ = self.meter_sender.try_push((
+                        slot.id,
+                        slot.input_level,
+                        slot.output_level,
+                        load,
+                    ));
+                }
+                chain.finish_transitions(|slot| {
+                    dispose(&mut self.garbage_sender, &mut self.error_sender, slot)
+                });
+            }
         }
-        for sample in r_out {
-            *sample = *sample * self.volume * self.volume;
+
+        self.panic_fade = (self.panic_fade + panic_step * scope.n_frames() as f32).clamp(0.0, 1.0);
+
+        if self.buffers.tapped {
+            self.analyzer_sender.push_slice(&self.buffers.tap);
         }
 
         jack::Control::Continue
     }
 }
 
-pub fn initialize() -> (
-    jack::AsyncClient<(), Processor>,
-    HeapProd<Command>,
-    HeapCons<(Plugin, Uuid)>,
-) {
-    let (client, _status) = Client::new("Rake", ClientOptions::NO_START_SERVER).unwrap();
+/// The activated client, and the other ends of the queues and shared state of its processor.
+pub struct Engine {
+    pub client: jack::AsyncClient<Notifier, Processor>,
+    pub command_sender: HeapProd<Command>,
+    /// Plugins and buffers removed by the processor, to be dropped off the audio thread.
+    pub garbage_receiver: HeapCons<Garbage>,
+    /// Input and output levels of every slot and chain.
+    pub meter_receiver: HeapCons<(Uuid, Level, Level, f32)>,
+    pub fault_receiver: HeapCons<(Uuid, Fault)>,
+    pub error_receiver: HeapCons<(Uuid, ProcessError)>,
+    /// Program changes received on the MIDI input, with the bank selected before them.
+    pub program_receiver: HeapCons<(u16, u8)>,
+    /// Controller numbers and values received on the MIDI input.
+    pub controller_receiver: HeapCons<(u8, u8)>,
+    pub alsa_midi_sender: HeapProd<RawMessage>,
+    pub analyzer_receiver: HeapCons<f32>,
+    pub tuner_receiver: HeapCons<f32>,
+    /// Output and dry input of the main chain, interleaved in frames of `recorder::FRAME`.
+    pub recorder_receiver: HeapCons<f32>,
+    pub tempo: Arc<Tempo>,
+    pub notifications: Arc<Notifications>,
+}
+
+/// Connects to the JACK server as `name` and starts processing.
+pub fn initialize(name: &str) -> std::result::Result<Engine, jack::Error> {
+    let (client, _status) = Client::new(name, ClientOptions::NO_START_SERVER)?;
     let (command_sender, command_receiver) = HeapRb::<Command>::new(512).split();
-    let (garbage_sender, garbage_receiver) = HeapRb::<(Plugin, Uuid)>::new(128).split();
+    let (garbage_sender, garbage_receiver) = HeapRb::<Garbage>::new(128).split();
+    let (meter_sender, meter_receiver) = HeapRb::<(Uuid, Level, Level, f32)>::new(4096).split();
+    let (fault_sender, fault_receiver) = HeapRb::<(Uuid, Fault)>::new(64).split();
+    let (error_sender, error_receiver) = HeapRb::<(Uuid, ProcessError)>::new(64).split();
+    let (program_sender, program_receiver) = HeapRb::<(u16, u8)>::new(64).split();
+    let (controller_sender, controller_receiver) = HeapRb::<(u8, u8)>::new(256).split();
+    let (alsa_midi_sender, alsa_midi) = HeapRb::<RawMessage>::new(256).split();
+    let (analyzer_sender, analyzer_receiver) = HeapRb::<f32>::new(16384).split();
+    let (tuner_sender, tuner_receiver) = HeapRb::<f32>::new(16384).split();
+    // Four seconds of frames in case the disk stalls
+    let (recorder_sender, recorder_receiver) =
+        HeapRb::<f32>::new(client.sample_rate() * 4 * recorder::FRAME).split();
+
+    let tempo = Arc::new(Tempo::default());
+    let notifications = Arc::new(Notifications::default());
+
+    let mut strips = Vec::with_capacity(MAX_CHAINS);
+    strips.push(Strip::new(
+        MAIN_CHAIN,
+        ChainPorts::register(&client, "")?,
+        client.sample_rate(),
+    ));
 
     let plugin_processor = Processor {
-        left_in: client.register_port("in_left", AudioIn::default()).unwrap(),
-        right_in: client
-            .register_port("in_right", AudioIn::default())
-            .unwrap(),
-        left_out: client
-            .register_port("out_left", AudioOut::default())
-            .unwrap(),
-        right_out: client
-            .register_port("out_right", AudioOut::default())
-            .unwrap(),
-        loaded_plugins: Vec::new(),
+        midi_in: client.register_port("midi_in", MidiIn::default())?,
+        midi: Midi {
+            events: Vec::with_capacity(midi::MAX_EVENTS),
+        },
+        metronome_out: client.register_port("metronome_out", AudioOut::default())?,
+        metronome: Metronome::new(client.sample_rate()),
+        tempo: tempo.clone(),
+        tap_tempo: TapTempo::default(),
+        tap_controller: None,
+        midi_mappings: Vec::with_capacity(midi::MAX_MAPPINGS),
+        midi_learn: false,
+        controller_sender,
+        strips,
         volume: 1.0,
+        volume_target: 1.0,
+        volume_step: 0.0,
+        volume_ramp: 0,
+        volume_gains: vec![1.0; client.buffer_size() as usize],
+        master_mute: false,
+        master_mute_gain: 1.0,
+        limiter_enabled: false,
+        dc_blocker_enabled: false,
+        monitor: Monitor::Processed,
+        panic: false,
+        panic_fade: 0.0,
+        // Setting RAKE_NO_FTZ keeps denormals, e.g. to check whether a plugin relies on them
+        flush_denormals: std::env::var_os("RAKE_NO_FTZ").is_none(),
         command_receiver,
         garbage_sender,
-        l_vec: vec![0.0; client.buffer_size() as usize],
-        r_vec: vec![0.0; client.buffer_size() as usize],
+        meter_sender,
+        fault_sender,
+        error_sender,
+        program_sender,
+        bank: 0,
+        alsa_midi,
+        analyzer_tap: None,
+        analyzer_sender,
+        tuner_input: None,
+        tuner_sender,
+        recording: false,
+        recorder_sender,
+        render: None,
+        buffers: Buffers::new(client.buffer_size() as usize),
     };
 
-    let active_client = client.activate_async((), plugin_processor).unwrap();
-
-    let input_ports = active_client
-        .as_client()
-        .ports(None, None, jack::PortFlags::IS_OUTPUT);
-    let output_ports = active_client
-        .as_client()
-        .ports(None, None, jack::PortFlags::IS_INPUT);
-
-    let _ = active_client
-        .as_client()
-        .connect_ports_by_name(&input_ports[0], &format!("Rake:in_left"));
-    let _ = active_client
-        .as_client()
-        .connect_ports_by_name(&input_ports[0], &format!("Rake:in_right"));
-    let _ = active_client
-        .as_client()
-        .connect_ports_by_name(&format!("Rake:out_left"), &output_ports[0]);
-    let _ = active_client
-        .as_client()
-        .connect_ports_by_name(&format!("Rake:out_right"), &output_ports[1]);
-
-    (active_client, command_sender, garbage_receiver)
+    let active_client = client.activate_async(Notifier(notifications.clone()), plugin_processor)?;
+
+    Ok(Engine {
+        client: active_client,
+        command_sender,
+        garbage_receiver,
+        meter_receiver,
+        fault_receiver,
+        error_receiver,
+        program_receiver,
+        controller_receiver,
+        alsa_midi_sender,
+        analyzer_receiver,
+        tuner_receiver,
+        recorder_receiver,
+        tempo,
+        notifications,
+    })
 }
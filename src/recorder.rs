@@ -0,0 +1,264 @@
+use crate::flac::FlacWriter;
+use crate::log;
+use crate::wav::WavWriter;
+use ringbuf::HeapCons;
+use ringbuf::traits::{Consumer, Observer};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Samples per frame in the ring: output left and right, then input left and right.
+pub const FRAME: usize = 4;
+
+enum Control {
+    /// Starts writing the output, and optionally the input, with the given sample rate.
+    Start(PathBuf, Option<PathBuf>, u32),
+    Stop,
+}
+
+/// Handle to the thread that writes the recorded output to disk.
+pub struct Recorder {
+    control_sender: Sender<Control>,
+    thread: JoinHandle<()>,
+    /// Set by the thread when a take couldn't be created or written and was ended.
+    failed: Arc<AtomicBool>,
+}
+
+impl Recorder {
+    pub fn spawn(mut receiver: HeapCons<f32>) -> Self {
+        let (control_sender, control_receiver) = mpsc::channel();
+        let failed = Arc::new(AtomicBool::new(false));
+        let thread_failed = failed.clone();
+        let thread =
+            std::thread::spawn(move || run(&mut receiver, control_receiver, &thread_failed));
+        Recorder {
+            control_sender,
+            thread,
+            failed,
+        }
+    }
+
+    /// Whether a take was ended by an error since this was last asked, so the GUI stops
+    /// recording.
+    pub fn failed(&self) -> bool {
+        self.failed.swap(false, Ordering::Relaxed)
+    }
+
+    pub fn start(&self, path: PathBuf, dry_path: Option<PathBuf>, sample_rate: u32) {
+        let _ = self
+            .control_sender
+            .send(Control::Start(path, dry_path, sample_rate));
+    }
+
+    pub fn stop(&self) {
+        let _ = self.control_sender.send(Control::Stop);
+    }
+
+    /// Finishes the current file and waits for the thread to exit.
+    pub fn join(self) {
+        self.stop();
+        drop(self.control_sender);
+        let _ = self.thread.join();
+    }
+}
+
+/// Writer of a recorded file, in the format its extension names.
+enum Writer {
+    Wav(WavWriter),
+    Flac(FlacWriter),
+}
+
+impl Writer {
+    fn create(path: &Path, sample_rate: u32) -> std::io::Result<Self> {
+        if path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("flac"))
+        {
+            FlacWriter::create(path, sample_rate).map(Writer::Flac)
+        } else {
+            WavWriter::create(path, sample_rate).map(Writer::Wav)
+        }
+    }
+
+    /// Whether `samples` more samples fit in the file, which FLAC has no practical limit on.
+    fn has_room(&self, samples: usize) -> bool {
+        match self {
+            Writer::Wav(writer) => writer.has_room(samples),
+            Writer::Flac(_) => true,
+        }
+    }
+
+    fn write(&mut self, samples: &[f32]) -> std::io::Result<()> {
+        match self {
+            Writer::Wav(writer) => writer.write(samples),
+            Writer::Flac(writer) => writer.write(samples),
+        }
+    }
+
+    fn finish(self) -> std::io::Result<()> {
+        match self {
+            Writer::Wav(writer) => writer.finish(),
+            Writer::Flac(writer) => writer.finish(),
+        }
+    }
+}
+
+/// Files of the current recording.
+struct Take {
+    wet: Writer,
+    path: PathBuf,
+    /// Unprocessed input, written frame for frame alongside the output.
+    dry: Option<(Writer, PathBuf)>,
+    /// Paths the take was started with, numbered for each further file it continues in.
+    base: (PathBuf, Option<PathBuf>),
+    sample_rate: u32,
+    /// Number of the current file of the take, counted from 1.
+    part: u32,
+}
+
+impl Take {
+    fn create(path: PathBuf, dry_path: Option<PathBuf>, sample_rate: u32) -> Option<Self> {
+        Self::create_part((path, dry_path), sample_rate, 1)
+    }
+
+    fn create_part(base: (PathBuf, Option<PathBuf>), sample_rate: u32, part: u32) -> Option<Self> {
+        let path = numbered(&base.0, part);
+        let wet = Writer::create(&path, sample_rate)
+            .inspect_err(|e| log::error!("Error creating {}: {}", path.display(), e))
+            .ok()?;
+        let dry = base.1.as_deref().and_then(|dry_path| {
+            let dry_path = numbered(dry_path, part);
+            Writer::create(&dry_path, sample_rate)
+                .inspect_err(|e| log::error!("Error creating {}: {}", dry_path.display(), e))
+                .ok()
+                .map(|dry| (dry, dry_path))
+        });
+        Some(Take {
+            wet,
+            path,
+            dry,
+            base,
+            sample_rate,
+            part,
+        })
+    }
+
+    /// Splits frames of output and input samples between the two files.
+    fn write(&mut self, frames: &[f32], samples: &mut Vec<f32>) -> std::io::Result<()> {
+        // WAV files can't grow past 4 GiB, so a long take continues in the next numbered file
+        let count = frames.len() / FRAME * 2;
+        if !self.wet.has_room(count)
+            || self
+                .dry
+                .as_ref()
+                .is_some_and(|(dry, _)| !dry.has_room(count))
+        {
+            let next = Take::create_part(self.base.clone(), self.sample_rate, self.part + 1)
+                .ok_or_else(|| std::io::Error::other("couldn't continue the take in a new file"))?;
+            std::mem::replace(self, next).finish();
+        }
+        samples.clear();
+        samples.extend(
+            frames
+                .chunks_exact(FRAME)
+                .flat_map(|frame| [frame[0], frame[1]]),
+        );
+        self.wet.write(samples)?;
+        if let Some((dry, _)) = &mut self.dry {
+            samples.clear();
+            samples.extend(
+                frames
+                    .chunks_exact(FRAME)
+                    .flat_map(|frame| [frame[2], frame[3]]),
+            );
+            dry.write(samples)?;
+        }
+        Ok(())
+    }
+
+    fn finish(self) {
+        if let Err(e) = self.wet.finish() {
+            log::error!("Error writing {}: {}", self.path.display(), e);
+        }
+        if let Some((dry, dry_path)) = self.dry
+            && let Err(e) = dry.finish()
+        {
+            log::error!("Error writing {}: {}", dry_path.display(), e);
+        }
+    }
+}
+
+/// Path of the `part`th file of a take, which from the second file on has the number added to
+/// its name.
+fn numbered(path: &Path, part: u32) -> PathBuf {
+    if part == 1 {
+        return path.to_path_buf();
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    match path.extension() {
+        Some(extension) => {
+            path.with_file_name(format!("{}-{}.{}", stem, part, extension.to_string_lossy()))
+        }
+        None => path.with_file_name(format!("{}-{}", stem, part)),
+    }
+}
+
+/// Pops whole frames, at most as many as fit in `chunk`, so the channels stay interleaved.
+fn pop_frames(receiver: &mut HeapCons<f32>, chunk: &mut [f32]) -> usize {
+    let available = receiver.occupied_len().min(chunk.len()) / FRAME * FRAME;
+    receiver.pop_slice(&mut chunk[..available])
+}
+
+fn run(receiver: &mut HeapCons<f32>, control_receiver: Receiver<Control>, failed: &AtomicBool) {
+    let mut take: Option<Take> = None;
+    let mut chunk = vec![0.0; 4096 * FRAME];
+    let mut samples = Vec::with_capacity(4096 * 2);
+    loop {
+        match control_receiver.try_recv() {
+            Ok(Control::Start(path, dry_path, sample_rate)) => {
+                // Anything left over from before the take isn't part of it
+                receiver.clear();
+                take = Take::create(path, dry_path, sample_rate);
+                if take.is_none() {
+                    failed.store(true, Ordering::Relaxed);
+                }
+            }
+            Ok(Control::Stop) => {
+                if let Some(mut current) = take.take() {
+                    loop {
+                        let count = pop_frames(receiver, &mut chunk);
+                        if count == 0 {
+                            break;
+                        }
+                        if let Err(e) = current.write(&chunk[..count], &mut samples) {
+                            log::error!("Error writing {}: {}", current.path.display(), e);
+                            break;
+                        }
+                    }
+                    current.finish();
+                }
+            }
+            Err(TryRecvError::Empty) => (),
+            Err(TryRecvError::Disconnected) => return,
+        }
+
+        let count = pop_frames(receiver, &mut chunk);
+        if count == 0 {
+            std::thread::sleep(Duration::from_millis(10));
+            continue;
+        }
+        if let Some(current) = &mut take
+            && let Err(e) = current.write(&chunk[..count], &mut samples)
+        {
+            log::error!("Error writing {}: {}", current.path.display(), e);
+            // Finishing the headers keeps what was recorded before the error readable
+            if let Some(current) = take.take() {
+                current.finish();
+            }
+            failed.store(true, Ordering::Relaxed);
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use crate::dsp::resample;
+use crate::wav::{self, WavWriter};
+use std::path::{Path, PathBuf};
+
+/// Silence processed after the input so reverb and delay tails make it into the file.
+const TAIL_SECONDS: f32 = 2.0;
+
+/// A file being rendered through the main chain while JACK runs in freewheel mode.
+/// All buffers are allocated before it's sent to the process thread.
+pub struct Render {
+    pub path: PathBuf,
+    input: [Vec<f32>; 2],
+    output: [Vec<f32>; 2],
+    /// Input frames read and output frames written so far.
+    read: usize,
+    written: usize,
+}
+
+impl Render {
+    /// Reads `input` and converts it to the given sample rate, to be rendered to `path`.
+    /// Mono files are rendered to both channels.
+    pub fn load(input: &Path, path: PathBuf, sample_rate: usize) -> std::io::Result<Self> {
+        let wav = wav::read(input)?;
+        let mut channels = wav
+            .channels
+            .iter()
+            .map(|samples| resample(samples, wav.sample_rate as usize, sample_rate));
+        let left = channels.next().unwrap_or_default();
+        let right = channels.next().unwrap_or_else(|| left.clone());
+        let length = left.len() + (TAIL_SECONDS * sample_rate as f32) as usize;
+        Ok(Render {
+            path,
+            input: [left, right],
+            output: [vec![0.0; length], vec![0.0; length]],
+            read: 0,
+            written: 0,
+        })
+    }
+
+    /// Fills `left` and `right` with the next input frames, padded with silence at the end.
+    pub fn read(&mut self, left: &mut [f32], right: &mut [f32]) {
+        let frames = left.len();
+        for (out, input) in [left, right].into_iter().zip(&self.input) {
+            let start = self.read.min(input.len());
+            let end = (self.read + out.len()).min(input.len());
+            out[..end - start].copy_from_slice(&input[start..end]);
+            out[end - start..].fill(0.0);
+        }
+        self.read += frames;
+    }
+
+    /// Stores the processed frames. Returns true once the whole file is rendered.
+    pub fn write(&mut self, left: &[f32], right: &[f32]) -> bool {
+        let count = left.len().min(self.output[0].len() - self.written);
+        self.output[0][self.written..self.written + count].copy_from_slice(&left[..count]);
+        self.output[1][self.written..self.written + count].copy_from_slice(&right[..count]);
+        self.written += count;
+        self.written == self.output[0].len()
+    }
+
+    pub fn save(&self, sample_rate: u32) -> std::io::Result<()> {
+        let mut writer = WavWriter::create(&self.path, sample_rate)?;
+        let frames = self.output[0]
+            .iter()
+            .zip(&self.output[1])
+            .flat_map(|(left, right)| [*left, *right])
+            .collect::<Vec<_>>();
+        writer.write(&frames)?;
+        writer.finish()
+    }
+}
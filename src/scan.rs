@@ -0,0 +1,63 @@
+use rack::prelude::*;
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+/// First argument of the child process that scans for plugins.
+pub const SCAN_ARG: &str = "--scan";
+
+/// Time after which a scan is taken to hang and the child process is killed.
+const SCAN_TIMEOUT: Duration = Duration::from_secs(120);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Scans for plugins in a child process, so a plugin that crashes or hangs while being scanned
+/// doesn't take Rake down with it. Blocks until the scan is done.
+pub fn scan() -> Result<Vec<PluginInfo>> {
+    let output = std::env::temp_dir().join(format!("rake-scan-{}.yaml", std::process::id()));
+    let mut child = Command::new(std::env::current_exe()?)
+        .arg(SCAN_ARG)
+        .arg(&output)
+        .spawn()?;
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if start.elapsed() >= SCAN_TIMEOUT {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(rack::Error::Other(format!(
+                "Plugin scan didn't finish within {} seconds",
+                SCAN_TIMEOUT.as_secs()
+            )));
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    };
+    if !status.success() {
+        return Err(rack::Error::Other(format!(
+            "Plugin scan failed: {}",
+            status
+        )));
+    }
+    let content = std::fs::read_to_string(&output)?;
+    let _ = std::fs::remove_file(&output);
+    serde_yaml_ng::from_str(&content).map_err(|e| rack::Error::Other(e.to_string()))
+}
+
+/// Runs in the child process: scans and writes the plugins found to `output`.
+pub fn run(output: &Path) -> ! {
+    let plugins = match Scanner::new().and_then(|scanner| scanner.scan()) {
+        Ok(plugins) => plugins,
+        Err(e) => {
+            eprintln!("Error scanning plugins: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let content = serde_yaml_ng::to_string(&plugins).unwrap();
+    if let Err(e) = std::fs::write(output, content) {
+        eprintln!("Error writing {}: {}", output.display(), e);
+        std::process::exit(1);
+    }
+    std::process::exit(0);
+}
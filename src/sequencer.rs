@@ -0,0 +1,155 @@
+use crate::log;
+use alsa::Direction;
+use alsa::poll::Descriptors;
+use alsa::seq::{
+    Addr, ClientIter, MidiEvent, PortCap, PortInfo, PortIter, PortSubscribe, PortType, Seq,
+};
+use ringbuf::HeapProd;
+use ringbuf::traits::Producer;
+use std::ffi::CString;
+use std::sync::mpsc::{self, Sender, TryRecvError};
+
+/// Time the reader waits for events before checking for a new output, in milliseconds.
+const POLL_TIMEOUT_MS: i32 = 100;
+
+/// A short MIDI message and its length, from 1 to 3 bytes. Longer ones like SysEx are dropped.
+pub type RawMessage = ([u8; 3], usize);
+
+/// ALSA sequencer client whose input port takes MIDI from a device directly, for controllers
+/// that aren't bridged to JACK. Its messages are merged into the JACK MIDI input.
+pub struct Sequencer {
+    seq: Seq,
+    /// Rake's input port.
+    port: Addr,
+    /// Device port subscribed to the input port.
+    connected: Option<Addr>,
+    /// Passes the reader the processor's end of the ring after connecting to JACK.
+    outputs: Sender<HeapProd<RawMessage>>,
+}
+
+impl Sequencer {
+    /// Creates the client and its input port, and reads the port in the background.
+    pub fn open(client_name: &str) -> alsa::Result<Self> {
+        let name = CString::new(client_name).unwrap_or_default();
+        let reader = Seq::open(None, Some(Direction::Capture), true)?;
+        reader.set_client_name(&name)?;
+        let port = Addr {
+            client: reader.client_id()?,
+            port: reader.create_simple_port(
+                c"midi_in",
+                PortCap::WRITE | PortCap::SUBS_WRITE,
+                PortType::MIDI_GENERIC | PortType::APPLICATION,
+            )?,
+        };
+        let seq = Seq::open(None, None, false)?;
+        let (outputs, output_receiver) = mpsc::channel::<HeapProd<RawMessage>>();
+        let mut fds = (&reader, Some(Direction::Capture)).get()?;
+
+        std::thread::spawn(move || {
+            let decoder = match MidiEvent::new(16) {
+                Ok(decoder) => decoder,
+                Err(e) => {
+                    log::error!("Error creating ALSA MIDI decoder: {}", e);
+                    return;
+                }
+            };
+            decoder.enable_running_status(false);
+            let mut output = None;
+            loop {
+                match output_receiver.try_recv() {
+                    Ok(producer) => output = Some(producer),
+                    Err(TryRecvError::Empty) => {}
+                    // The sequencer was dropped
+                    Err(TryRecvError::Disconnected) => return,
+                }
+                if let Err(e) = alsa::poll::poll(&mut fds, POLL_TIMEOUT_MS) {
+                    log::error!("Error waiting for ALSA MIDI: {}", e);
+                    return;
+                }
+                let mut input = reader.input();
+                while input.event_input_pending(true).unwrap_or(0) > 0 {
+                    let Ok(mut event) = input.event_input() else {
+                        break;
+                    };
+                    let mut bytes = [0; 3];
+                    // Events other than MIDI messages, like subscriptions, don't decode
+                    if let Ok(len @ 1..) = decoder.decode(&mut bytes, &mut event)
+                        && let Some(output) = &mut output
+                    {
+                        let _ = output.try_push((bytes, len));
+                    }
+                }
+            }
+        });
+
+        Ok(Sequencer {
+            seq,
+            port,
+            connected: None,
+            outputs,
+        })
+    }
+
+    /// Sends the messages read to a new processor.
+    pub fn set_output(&self, producer: HeapProd<RawMessage>) {
+        let _ = self.outputs.send(producer);
+    }
+
+    /// Names of the MIDI ports of other clients that can be read, as `client:port`.
+    pub fn devices(&self) -> Vec<String> {
+        self.ports().into_iter().map(|(name, _)| name).collect()
+    }
+
+    fn ports(&self) -> Vec<(String, Addr)> {
+        let mut ports = Vec::new();
+        for client in ClientIter::new(&self.seq) {
+            // The system client only announces changes and timers
+            if client.get_client() == 0 || client.get_client() == self.port.client {
+                continue;
+            }
+            let client_name = client.get_name().unwrap_or_default().to_string();
+            for port in PortIter::new(&self.seq, client.get_client()) {
+                if port
+                    .get_capability()
+                    .contains(PortCap::READ | PortCap::SUBS_READ)
+                    && port.get_type().contains(PortType::MIDI_GENERIC)
+                {
+                    ports.push((port_name(&client_name, &port), port.addr()));
+                }
+            }
+        }
+        ports
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected.is_some()
+    }
+
+    /// Subscribes the input port to a device by name instead of the one it was, if any.
+    pub fn connect(&mut self, device: Option<&str>) -> Result<(), String> {
+        if let Some(connected) = self.connected.take() {
+            let _ = self.seq.unsubscribe_port(connected, self.port);
+        }
+        let Some(device) = device else {
+            return Ok(());
+        };
+        let sender = self
+            .ports()
+            .into_iter()
+            .find(|(name, _)| name == device)
+            .map(|(_, addr)| addr)
+            .ok_or_else(|| format!("No ALSA MIDI port {}", device))?;
+        let subscription = PortSubscribe::empty().map_err(|e| e.to_string())?;
+        subscription.set_sender(sender);
+        subscription.set_dest(self.port);
+        self.seq
+            .subscribe_port(&subscription)
+            .map_err(|e| format!("Error connecting {}: {}", device, e))?;
+        self.connected = Some(sender);
+        Ok(())
+    }
+}
+
+fn port_name(client_name: &str, port: &PortInfo) -> String {
+    format!("{}:{}", client_name, port.get_name().unwrap_or_default())
+}
@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+
+/// Entry of the setlist: a session file, or a snapshot of the session opened before it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SetlistEntry {
+    Session(PathBuf),
+    Snapshot(String),
+}
+
+/// Program change mapped to a setlist entry or a snapshot, in place of picking the entry
+/// numbered by the program.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProgramMapping {
+    pub bank: u16,
+    pub program: u8,
+    pub target: ProgramTarget,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ProgramTarget {
+    /// Setlist entry by position.
+    Entry(usize),
+    /// Snapshot of the session that is open, by name.
+    Snapshot(String),
+}
+
+impl fmt::Display for ProgramTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProgramTarget::Entry(i) => write!(f, "Setlist entry {}", i + 1),
+            ProgramTarget::Snapshot(name) => write!(f, "Snapshot {}", name),
+        }
+    }
+}
+
+impl fmt::Display for SetlistEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SetlistEntry::Session(path) => {
+                write!(
+                    f,
+                    "{}",
+                    path.file_stem().unwrap_or_default().to_string_lossy()
+                )
+            }
+            SetlistEntry::Snapshot(name) => write!(f, "{}", name),
+        }
+    }
+}
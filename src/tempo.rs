@@ -0,0 +1,82 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Tempo until one is tapped.
+pub const DEFAULT_BPM: f64 = 120.0;
+
+/// Taps averaged into the tempo.
+const MAX_TAPS: usize = 5;
+
+/// A longer pause between taps starts a new tempo.
+const TAP_TIMEOUT_SECONDS: f64 = 2.0;
+
+/// Tempo used while no JACK timebase master provides one, set by the process thread and read
+/// by the GUI.
+pub struct Tempo(AtomicU64);
+
+impl Default for Tempo {
+    fn default() -> Self {
+        Tempo(AtomicU64::new(DEFAULT_BPM.to_bits()))
+    }
+}
+
+impl Tempo {
+    pub fn bpm(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    pub fn set_bpm(&self, bpm: f64) {
+        self.0.store(bpm.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Works out a tempo from the intervals between the last few taps.
+#[derive(Default)]
+pub struct TapTempo {
+    /// JACK frame times of the taps, oldest first.
+    taps: [u32; MAX_TAPS],
+    count: usize,
+}
+
+impl TapTempo {
+    /// Registers a tap at the given frame time. Returns the tempo from the second tap on.
+    pub fn tap(&mut self, frame: u32, sample_rate: usize) -> Option<f64> {
+        let timeout = (TAP_TIMEOUT_SECONDS * sample_rate as f64) as u32;
+        // Frame times wrap around, so intervals are taken with wrapping subtraction
+        if self.count > 0 && frame.wrapping_sub(self.taps[self.count - 1]) > timeout {
+            self.count = 0;
+        }
+        if self.count == MAX_TAPS {
+            self.taps.rotate_left(1);
+            self.count -= 1;
+        }
+        self.taps[self.count] = frame;
+        self.count += 1;
+        if self.count < 2 {
+            return None;
+        }
+        let frames = frame.wrapping_sub(self.taps[0]) as f64 / (self.count - 1) as f64;
+        Some((60.0 * sample_rate as f64 / frames).clamp(20.0, 300.0))
+    }
+}
+
+/// MIDI controller that triggers tap tempo, if any.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TapController(pub Option<u8>);
+
+impl TapController {
+    pub fn all() -> Vec<TapController> {
+        std::iter::once(TapController(None))
+            .chain((0..128).map(|controller| TapController(Some(controller))))
+            .collect()
+    }
+}
+
+impl fmt::Display for TapController {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(controller) => write!(f, "CC {}", controller),
+            None => write!(f, "Off"),
+        }
+    }
+}
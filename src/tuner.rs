@@ -0,0 +1,95 @@
+use ringbuf::HeapCons;
+use ringbuf::traits::{Consumer, Observer};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const WINDOW_SIZE: usize = 4096;
+const HOP_SIZE: usize = WINDOW_SIZE / 2;
+const MIN_FREQUENCY: f32 = 40.0;
+const MAX_FREQUENCY: f32 = 1000.0;
+/// Windows quieter than this RMS level (about -50 dBFS) aren't analyzed.
+const MIN_RMS: f32 = 0.003;
+/// Threshold of the YIN cumulative mean normalized difference.
+const THRESHOLD: f32 = 0.15;
+
+const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/// Spawns the thread that detects the pitch of the samples sent to the tuner.
+pub fn spawn(mut receiver: HeapCons<f32>, sample_rate: usize) -> Arc<Mutex<Option<f32>>> {
+    let pitch = Arc::new(Mutex::new(None));
+    let shared = pitch.clone();
+
+    std::thread::spawn(move || {
+        let mut samples = vec![0.0; WINDOW_SIZE];
+        let mut difference = vec![0.0; WINDOW_SIZE / 2];
+        loop {
+            if receiver.occupied_len() < HOP_SIZE {
+                // The processor is gone, e.g. after the JACK server shut down
+                if !receiver.write_is_held() {
+                    return;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+                continue;
+            }
+            samples.copy_within(HOP_SIZE.., 0);
+            receiver.pop_slice(&mut samples[WINDOW_SIZE - HOP_SIZE..]);
+            *shared.lock().unwrap() = detect(&samples, &mut difference, sample_rate as f32);
+        }
+    });
+
+    pitch
+}
+
+/// Estimates the fundamental frequency with the YIN algorithm.
+fn detect(samples: &[f32], difference: &mut [f32], sample_rate: f32) -> Option<f32> {
+    let rms = (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt();
+    if rms < MIN_RMS {
+        return None;
+    }
+
+    let half = difference.len();
+    let min_lag = (sample_rate / MAX_FREQUENCY) as usize;
+    let max_lag = ((sample_rate / MIN_FREQUENCY) as usize).min(half - 1);
+
+    // Cumulative mean normalized difference
+    difference[0] = 1.0;
+    let mut sum = 0.0;
+    for lag in 1..=max_lag {
+        let d: f32 = (0..half)
+            .map(|i| samples[i] - samples[i + lag])
+            .map(|delta| delta * delta)
+            .sum();
+        sum += d;
+        difference[lag] = if sum > 0.0 { d * lag as f32 / sum } else { 1.0 };
+    }
+
+    let mut lag = (min_lag.max(2)..max_lag).find(|&lag| difference[lag] < THRESHOLD)?;
+    while lag + 1 < max_lag && difference[lag + 1] < difference[lag] {
+        lag += 1;
+    }
+
+    // Parabolic interpolation around the minimum
+    let (a, b, c) = (difference[lag - 1], difference[lag], difference[lag + 1]);
+    let denominator = a - 2.0 * b + c;
+    let offset = if denominator.abs() > f32::EPSILON {
+        0.5 * (a - c) / denominator
+    } else {
+        0.0
+    };
+    Some(sample_rate / (lag as f32 + offset))
+}
+
+/// Nearest note name with its octave, and the offset from it in cents.
+pub fn note(frequency: f32) -> (String, f32) {
+    let midi = 69.0 + 12.0 * (frequency / 440.0).log2();
+    let nearest = midi.round();
+    let index = nearest as i32;
+    let name = format!(
+        "{}{}",
+        NOTE_NAMES[index.rem_euclid(12) as usize],
+        index.div_euclid(12) - 1
+    );
+    (name, (midi - nearest) * 100.0)
+}
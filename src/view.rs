@@ -1,13 +1,113 @@
-use crate::Message;
-use iced::widget::{Column, Row, button, column, container, row, scrollable, slider, space, text};
-use iced::{Alignment, Color, Element, Length, Theme};
+use crate::chain::{Fault, MAX_EXTRA_BUSES, OutputBus};
+use crate::connections::PortChoice;
+use crate::dsp::{Level, Routing};
+use crate::knob::knob;
+use crate::looper::{LooperAction, LooperState};
+use crate::metronome::{Beats, ClickSound};
+use crate::midi::MidiTarget;
+use crate::processor::{MAIN_CHAIN, Monitor, VOLUME_MAX_DB, VOLUME_MIN_DB, gain_to_db};
+use crate::setlist::ProgramTarget;
+use crate::tempo::TapController;
+use crate::{
+    Category, ChainNode, Confirm, Confirmation, LoadedPlugin, LoadedReturn, LoadedSplit,
+    LoadedUnit, Message,
+};
+use crate::{analyzer, tuner};
+use iced::widget::{
+    Column, Row, button, center, column, container, mouse_area, opaque, pane_grid, pick_list,
+    progress_bar, row, scrollable, slider, space, stack, text, text_input, toggler,
+};
+use iced::{Alignment, Color, Element, Length, Theme, mouse};
+use jack::TransportState;
+use rack::prelude::{ParameterInfo, PluginInfo};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use uuid::Uuid;
+
+/// Id of the text input a slot is renamed in, focused when renaming starts.
+pub const RENAME_INPUT: &str = "rename";
+
+/// Id of the text input a parameter's value is typed in.
+pub const PARAM_INPUT: &str = "param";
+
+/// Levels at or below this are shown as silence on the meters.
+const METER_MIN_DB: f32 = -60.0;
 
 pub fn view(state: &crate::AppState) -> Element<'_, Message> {
+    // Nothing else is shown since the client can't be queried until it reconnects
+    if state.disconnected {
+        return container(
+            column![
+                text("The JACK server shut down. Rake reconnects as soon as it is back.").size(20),
+                button("Reconnect").on_press(Message::Reconnect),
+            ]
+            .spacing(15)
+            .align_x(Alignment::Center),
+        )
+        .style(|_theme: &Theme| container::Style {
+            background: Some(Color::from_rgb8(250, 220, 220).into()),
+            ..Default::default()
+        })
+        .center(Length::Fill)
+        .into();
+    }
+
+    let client = state.jack_client.as_ref().unwrap().as_client();
     let toolbar = row![
-        button("Open").on_press(Message::LoadSession),
+        button("Open").on_press(Message::AskConfirm(Confirm::Open(None))),
         button("Save").on_press(Message::SaveSession),
-        button("Clear").on_press(Message::ClearSession),
-        button("Rescan").on_press(Message::Scan),
+        button("Save As").on_press(Message::SaveSessionAs),
+        pick_list(
+            state
+                .config
+                .recent_sessions
+                .iter()
+                .cloned()
+                .map(SessionFile)
+                .collect::<Vec<_>>(),
+            None::<SessionFile>,
+            |session| Message::AskConfirm(Confirm::Open(Some(session.0))),
+        )
+        .placeholder("Recent"),
+        pick_list(state.templates.as_slice(), None::<String>, |name| {
+            Message::AskConfirm(Confirm::NewFromTemplate(name))
+        })
+        .placeholder("New from Template"),
+        button("Save Template").on_press(Message::SaveTemplate),
+        button("Export Bundle").on_press(Message::ExportBundle),
+        button("Import").on_press(Message::ImportChain),
+        button("Clear").on_press(Message::AskConfirm(Confirm::ClearSession)),
+        button(if state.scanning.is_some() {
+            "Scanning..."
+        } else {
+            "Rescan"
+        })
+        .on_press_maybe(state.scanning.is_none().then_some(Message::Scan)),
+        button("Split").on_press(Message::AddSplit),
+        button("IR").on_press(Message::LoadImpulseResponse),
+        button("Looper").on_press(Message::AddLooper),
+        button(if state.recording.is_some() {
+            "Stop Recording"
+        } else {
+            "Record"
+        })
+        .style(if state.recording.is_some() {
+            button::danger
+        } else {
+            button::primary
+        })
+        .on_press(Message::ToggleRecording),
+        toggler(state.record_dry)
+            .label("Dry")
+            .on_toggle_maybe(state.recording.is_none().then_some(Message::SetRecordDry)),
+        button(if state.rendering.is_some() {
+            "Rendering..."
+        } else {
+            "Render"
+        })
+        .on_press_maybe(state.rendering.is_none().then_some(Message::RenderFile)),
         space::horizontal().width(6),
         text(format!(
             "{}",
@@ -17,87 +117,416 @@ pub fn view(state: &crate::AppState) -> Element<'_, Message> {
                 .unwrap_or_default()
                 .to_string_lossy()
                 .into_owned()
-        ))
+        )),
+        space::horizontal(),
+        toggler(state.link.is_some())
+            .label("Follow Link Tempo")
+            .on_toggle(Message::SetLink),
+        text(match &state.link {
+            Some(link) => format!("{:.1} BPM from {} peers", state.tempo.bpm(), link.peers()),
+            None => format!("{:.1} BPM", state.tempo.bpm()),
+        }),
+        // Read every frame, so a new JACK period or rate shows up as soon as it takes effect
+        text(format!(
+            "{} frames @ {} Hz",
+            client.buffer_size(),
+            client.sample_rate()
+        )),
+        text(format!(
+            "Xruns: {}",
+            state.notifications.xruns.load(Ordering::Relaxed)
+        )),
+        button("Reset").on_press(Message::ResetXruns),
+        text("Parameters"),
+        pick_list(
+            &ParamView::ALL[..],
+            Some(state.config.param_view),
+            Message::SetParamView
+        ),
+        button(text(if state.log.unread > 0 {
+            format!("Log ({})", state.log.unread)
+        } else {
+            "Log".to_string()
+        }))
+        .style(if state.log.unread > 0 {
+            button::danger
+        } else {
+            button::secondary
+        })
+        .on_press(Message::ToggleLog),
+        button("Shortcuts (F1)")
+            .style(button::secondary)
+            .on_press(Message::ToggleShortcuts),
     ]
     .spacing(10)
     .align_y(Alignment::Center);
 
-    let mut scanned_list = column![].spacing(10);
-    for info in &state.scanned_plugins {
-        scanned_list = scanned_list.push(
-            container(
-                row![
-                    text(format!("{}", info)).width(223.0),
-                    button("+").on_press(Message::LoadPlugin(info.clone())),
-                ]
-                .spacing(10)
-                .padding(10)
-                .align_y(Alignment::Center),
-            )
-            .style(box_style),
+    let mut alerts = column![].spacing(5);
+    for (id, fault, alert) in &state.alerts {
+        let mut row = row![text(alert).color([0.8, 0.1, 0.1])]
+            .spacing(10)
+            .align_y(Alignment::Center);
+        // A plugin that panicked stays disabled until it's loaded again
+        if !matches!(fault, Fault::Panic | Fault::Missing) {
+            row = row.push(button("Re-enable").on_press(Message::SetBypass(*id, false)));
+        }
+        row = row.push(
+            button("Dismiss")
+                .style(button::secondary)
+                .on_press(Message::DismissAlert(*id)),
         );
+        alerts = alerts.push(row);
+    }
+    if !state.missing_ports.is_empty() {
+        alerts = alerts.push(
+            row![
+                text("Ports of this session that don't exist here").color([0.8, 0.1, 0.1]),
+                button("Dismiss")
+                    .style(button::secondary)
+                    .on_press(Message::DismissMissingPorts),
+            ]
+            .spacing(10)
+            .align_y(Alignment::Center),
+        );
+    }
+    for (i, missing) in state.missing_ports.iter().enumerate() {
+        let mut row = row![text(if missing.input {
+            format!("{} ← {}", missing.port, missing.other)
+        } else {
+            format!("{} → {}", missing.port, missing.other)
+        })]
+        .spacing(10)
+        .align_y(Alignment::Center);
+        // The pickers only list audio ports
+        if missing.audio {
+            let others = if missing.input {
+                &state.sources
+            } else {
+                &state.destinations
+            };
+            row = row.push(
+                pick_list(others.as_slice(), None::<&String>, move |other| {
+                    Message::ReplaceMissingPort(i, other.clone())
+                })
+                .placeholder("Connect Instead To"),
+            );
+        }
+        alerts = alerts.push(row);
     }
 
-    let mut plugin_chain = column![].spacing(15);
-    for (i, plugin) in state.loaded_plugins.iter().enumerate() {
-        let mut plugin_header: Row<'_, Message> = row![].spacing(10).align_y(Alignment::Center);
-        plugin_header = plugin_header.push(text(&plugin.info.name));
-        plugin_header = plugin_header.push(button("✕").on_press(Message::DeletePlugin(plugin.id)));
+    let mut chain_tabs = row![].spacing(10).align_y(Alignment::Center);
+    for (i, chain) in state.chains.iter().enumerate() {
+        chain_tabs = chain_tabs.push(
+            button(text(&chain.name))
+                .style(if i == state.selected_chain {
+                    button::primary
+                } else {
+                    button::secondary
+                })
+                .on_press(Message::SelectChain(i)),
+        );
+    }
+    let selected = &state.chains[state.selected_chain];
+    if selected.id != MAIN_CHAIN {
+        chain_tabs = chain_tabs.push(button("✕").on_press(Message::DeleteChain(selected.id)));
+    }
+    chain_tabs = chain_tabs.push(
+        toggler(selected.mute)
+            .label("Mute")
+            .on_toggle(|mute| Message::SetMute(selected.id, mute)),
+    );
+    chain_tabs = chain_tabs.push(
+        toggler(selected.solo)
+            .label("Solo")
+            .on_toggle(|solo| Message::SetSolo(selected.id, solo)),
+    );
+    let tuning = state.tuner_input == Some(selected.id);
+    chain_tabs = chain_tabs.push(
+        toggler(tuning)
+            .label("Tuner")
+            .on_toggle(|on| Message::SetTuner(on.then_some(selected.id))),
+    );
+    if tuning {
+        chain_tabs = chain_tabs.push(text(match state.pitch {
+            Some(frequency) => {
+                let (name, cents) = tuner::note(frequency);
+                format!("{} {:+.0} cents ({:.1} Hz)", name, cents, frequency)
+            }
+            None => "-".to_string(),
+        }));
+    }
+    chain_tabs = chain_tabs.push(space::horizontal().width(6));
+    chain_tabs = chain_tabs.push(
+        text_input("New chain", &state.chain_name)
+            .on_input(Message::ChainNameChange)
+            .on_submit(Message::AddChain)
+            .width(150),
+    );
+    chain_tabs = chain_tabs.push(button("Add Chain").on_press(Message::AddChain));
+    chain_tabs = chain_tabs.push(button("Add Return").on_press(Message::AddReturn));
 
-        if i != 0 {
-            plugin_header =
-                plugin_header.push(button("↑").on_press(Message::MovePluginUp(plugin.id)));
-        }
-        if i != state.loaded_plugins.len() - 1 {
-            plugin_header =
-                plugin_header.push(button("↓").on_press(Message::MovePluginDown(plugin.id)));
-        }
+    let mut snapshot_row = row![text("Snapshots").color([0.5, 0.5, 0.5])]
+        .spacing(10)
+        .align_y(Alignment::Center);
+    for (i, snapshot) in state.snapshots.iter().enumerate() {
+        snapshot_row = snapshot_row.push(
+            button(text(&snapshot.name))
+                .style(if state.snapshot == Some(i) {
+                    button::primary
+                } else {
+                    button::secondary
+                })
+                .on_press(Message::RecallSnapshot(i)),
+        );
+    }
+    if let Some(i) = state.snapshot {
+        snapshot_row = snapshot_row.push(button("✕").on_press(Message::DeleteSnapshot(i)));
+    }
+    snapshot_row = snapshot_row.push(
+        text_input("New snapshot", &state.snapshot_name)
+            .on_input(Message::SnapshotNameChange)
+            .on_submit(Message::SaveSnapshot)
+            .width(150),
+    );
+    snapshot_row = snapshot_row.push(button("Save Snapshot").on_press(Message::SaveSnapshot));
 
-        let mut param_controls: Column<'_, Message> = column![].spacing(10);
-        for param in &plugin.params {
-            param_controls = param_controls.push(row![
-                text(param.0.name.clone()).width(100.0),
-                text(format!("{:.2} ", param.1)),
-                slider(0.0..=1.0, param.1, |value|
-                    // TODO: denormalize parameter value
-                    // For VST3, it seems that min & max in ParameterInfo always gives 0.0 and 1.0
-                    // so currently there's no way to denormalize parameter value
-                    Message::ParamChange(
-                    plugin.id,
-                    param.0.clone(),
-                    value
-                ))
-                .step(0.01),
-            ]);
-        }
+    let mut setlist_row = row![
+        button(text("◀ Previous").size(24))
+            .padding(12)
+            .on_press_maybe(
+                state
+                    .setlist_position
+                    .is_some_and(|i| i > 0)
+                    .then_some(Message::SetlistPrevious)
+            ),
+        button(text("Next ▶").size(24)).padding(12).on_press_maybe(
+            (state.setlist_position.map_or(0, |i| i + 1) < state.config.setlist.len())
+                .then_some(Message::SetlistNext)
+        ),
+        text("Setlist").color([0.5, 0.5, 0.5]),
+    ]
+    .spacing(10)
+    .align_y(Alignment::Center);
+    for (i, entry) in state.config.setlist.iter().enumerate() {
+        setlist_row = setlist_row.push(
+            button(text(format!("{}. {}", i + 1, entry)))
+                .style(if state.setlist_position == Some(i) {
+                    button::primary
+                } else {
+                    button::secondary
+                })
+                .on_press(Message::GoToEntry(i)),
+        );
+    }
+    if let Some(i) = state.setlist_position {
+        setlist_row = setlist_row.push(button("✕").on_press(Message::RemoveFromSetlist(i)));
+    }
+    setlist_row = setlist_row.push(button("Add Session").on_press(Message::AddSessionToSetlist));
+    setlist_row = setlist_row.push(
+        button("Add Snapshot").on_press_maybe(
+            state
+                .snapshot
+                .is_some()
+                .then_some(Message::AddSnapshotToSetlist),
+        ),
+    );
+    setlist_row = setlist_row.push(
+        button("Program Changes")
+            .style(if state.program_map_open {
+                button::primary
+            } else {
+                button::secondary
+            })
+            .on_press(Message::ToggleProgramMap),
+    );
+    let mut setlist = column![setlist_row].spacing(15);
+    if state.program_map_open {
+        setlist = setlist.push(program_map_view(state));
+    }
 
-        plugin_chain = plugin_chain.push(
-            container(
-                column![plugin_header, param_controls]
-                    .spacing(15)
-                    .padding(15),
-            )
-            .style(box_style),
+    let mut midi_row = row![
+        text("MIDI").color([0.5, 0.5, 0.5]),
+        button(if state.midi_learn {
+            "Stop Learning"
+        } else {
+            "MIDI Learn"
+        })
+        .style(if state.midi_learn {
+            button::danger
+        } else {
+            button::primary
+        })
+        .on_press(Message::ToggleMidiLearn),
+    ]
+    .spacing(10)
+    .align_y(Alignment::Center);
+    if state.midi_learn {
+        midi_row = midi_row.push(text(match state.learn_target {
+            Some(target) => format!("Move a controller for {}", target_name(state, target)),
+            None => "Move a parameter or the master volume, then a controller".to_string(),
+        }));
+    }
+    if state.sequencer.is_some() {
+        let choices: Vec<PortChoice> = std::iter::once(None)
+            .chain(state.alsa_devices.iter().cloned().map(Some))
+            .map(PortChoice)
+            .collect();
+        midi_row = midi_row.push(text("ALSA"));
+        midi_row = midi_row.push(pick_list(
+            choices,
+            Some(PortChoice(state.config.alsa_midi_input.clone())),
+            Message::SetAlsaMidiInput,
+        ));
+    }
+    for mapping in &state.midi_mappings {
+        midi_row = midi_row.push(text(format!(
+            "CC {}: {}",
+            mapping.controller,
+            target_name(state, mapping.target)
+        )));
+        midi_row = midi_row.push(button("✕").on_press(Message::Unmap(mapping.controller)));
+    }
+
+    // Pickers connect each of the chain's ports to a single port of another client
+    let prefix = format!("{}:{}", client.name(), crate::port_prefix(selected));
+    let mut port_row = row![text("Ports").color([0.5, 0.5, 0.5])]
+        .spacing(10)
+        .align_y(Alignment::Center);
+    for (label, suffix, input) in [
+        ("In L", "in_left", true),
+        ("In R", "in_right", true),
+        ("Out L", "out_left", false),
+        ("Out R", "out_right", false),
+    ] {
+        let port = format!("{}{}", prefix, suffix);
+        let connected =
+            state
+                .config
+                .connections
+                .iter()
+                .find_map(|(source, destination)| match input {
+                    true if *destination == port => Some(source.clone()),
+                    false if *source == port => Some(destination.clone()),
+                    _ => None,
+                });
+        let others = if input {
+            &state.sources
+        } else {
+            &state.destinations
+        };
+        let choices: Vec<PortChoice> = std::iter::once(None)
+            .chain(others.iter().cloned().map(Some))
+            .map(PortChoice)
+            .collect();
+        port_row = port_row.push(text(label));
+        port_row = port_row.push(pick_list(
+            choices,
+            Some(PortChoice(connected)),
+            move |choice| Message::SetConnection(port.clone(), input, choice),
+        ));
+    }
+
+    let mut return_tabs = row![text("Returns").color([0.5, 0.5, 0.5])]
+        .spacing(10)
+        .align_y(Alignment::Center);
+    return_tabs = return_tabs.push(
+        button(text(&selected.name))
+            .style(if state.selected_return.is_none() {
+                button::primary
+            } else {
+                button::secondary
+            })
+            .on_press(Message::SelectReturn(None)),
+    );
+    for (i, aux_return) in selected.returns.iter().enumerate() {
+        return_tabs = return_tabs.push(
+            button(text(&aux_return.name))
+                .style(if state.selected_return == Some(i) {
+                    button::primary
+                } else {
+                    button::secondary
+                })
+                .on_press(Message::SelectReturn(Some(i))),
         );
     }
+    if let Some(i) = state.selected_return {
+        return_tabs =
+            return_tabs.push(button("✕").on_press(Message::DeleteReturn(selected.returns[i].id)));
+    }
 
-    container(
+    let content = container(
         column![
             toolbar,
-            row![
-                text(" Available").color([0.5, 0.5, 0.5]),
-                space::horizontal().width(233),
-                text("Active Chain").color([0.5, 0.5, 0.5]),
-            ],
-            row![
-                scrollable(scanned_list).spacing(8),
-                scrollable(plugin_chain).spacing(8),
-            ]
+            alerts,
+            chain_tabs,
+            snapshot_row,
+            setlist,
+            midi_row,
+            port_row,
+            return_tabs,
+            pane_grid(&state.panes.0, |_pane, pane, _maximized| {
+                let (title, list) = match pane {
+                    Pane::Browser => (
+                        format!(" Available ({})", state.scanned_plugins.len()),
+                        browser_view(state),
+                    ),
+                    Pane::Chain => (
+                        match &state.dragging {
+                            Some(info) => format!("Drop {} where it goes in the chain", info.name),
+                            None => "Active Chain".to_string(),
+                        },
+                        chain_view(state),
+                    ),
+                };
+                pane_grid::Content::new(scrollable(list).spacing(8))
+                    .title_bar(pane_grid::TitleBar::new(text(title).color([0.5, 0.5, 0.5])))
+            })
             .spacing(20)
+            .min_size(200)
+            .on_resize(10, Message::ResizePanes)
             .height(Length::Fill),
+            analyzer_view(state),
+            metronome_view(state),
+            chain_meters(state, selected.id),
             row![
-                text(format!("Master Volume: {:.2} ", state.volume)),
-                slider(0.0..=5.0, state.volume, Message::VolumeChange).step(0.01),
+                text(if state.volume <= VOLUME_MIN_DB {
+                    "Master Volume: -inf dB ".to_string()
+                } else {
+                    format!("Master Volume: {:+.1} dB ", state.volume)
+                }),
+                slider(
+                    VOLUME_MIN_DB..=VOLUME_MAX_DB,
+                    state.volume,
+                    Message::VolumeChange
+                )
+                .step(0.1),
+                space::horizontal().width(10),
+                toggler(state.master_mute)
+                    .label("Mute (M)")
+                    .on_toggle(|_| Message::ToggleMasterMute),
+                space::horizontal().width(10),
+                toggler(state.limiter)
+                    .label("Limiter")
+                    .on_toggle(Message::SetLimiter),
+                space::horizontal().width(10),
+                toggler(state.dc_blocker)
+                    .label("DC Filter")
+                    .on_toggle(Message::SetDcBlocker),
+                space::horizontal().width(10),
+                text("Monitor "),
+                pick_list(&Monitor::ALL[..], Some(state.monitor), Message::SetMonitor),
+                space::horizontal().width(10),
+                button(if state.panic {
+                    "Panic (Esc): bypassing all"
+                } else {
+                    "Panic (Esc)"
+                })
+                .style(if state.panic {
+                    button::danger
+                } else {
+                    button::secondary
+                })
+                .on_press(Message::TogglePanic),
             ]
             .align_y(Alignment::Center),
         ]
@@ -109,10 +538,1270 @@ pub fn view(state: &crate::AppState) -> Element<'_, Message> {
         ..Default::default()
     })
     .width(Length::Fill)
-    .height(Length::Fill)
+    .height(Length::Fill);
+
+    let mut layers = stack![content, notifications_view(state)];
+    if let Some(confirmation) = &state.confirmation {
+        layers = layers.push(confirmation_view(confirmation));
+    } else if state.log_open {
+        layers = layers.push(log_view(state));
+    } else if state.shortcuts_open {
+        layers = layers.push(shortcuts_view());
+    }
+    layers.into()
+}
+
+/// Errors that just came in, in the lower right corner, leaving the rest of the window to
+/// be clicked through.
+fn notifications_view(state: &crate::AppState) -> Element<'_, Message> {
+    let mut notifications = column![].spacing(10).width(400);
+    for entry in state.log.toasts() {
+        notifications = notifications.push(
+            container(
+                row![
+                    button(text(entry_text(entry)))
+                        .style(button::text)
+                        .width(Length::Fill)
+                        .on_press(Message::ToggleLog),
+                    button("✕")
+                        .style(button::text)
+                        .on_press(Message::DismissNotification(entry.id)),
+                ]
+                .align_y(Alignment::Center),
+            )
+            .padding(5)
+            .style(|theme: &Theme| {
+                let style = box_style(theme);
+                style.border(iced::Border {
+                    color: theme.palette().danger,
+                    width: 1.0,
+                    ..style.border
+                })
+            }),
+        );
+    }
+    container(notifications)
+        .align_right(Length::Fill)
+        .align_bottom(Length::Fill)
+        .padding(20)
+        .into()
+}
+
+/// Every error kept, newest first, to look back on ones that were missed.
+fn log_view(state: &crate::AppState) -> Element<'_, Message> {
+    let mut entries = column![].spacing(8);
+    for entry in state.log.entries() {
+        entries = entries.push(row![
+            text(age(entry.time.elapsed()))
+                .width(80)
+                .color([0.5, 0.5, 0.5]),
+            text(entry_text(entry)),
+        ]);
+    }
+    modal(
+        column![
+            row![
+                text("Log").size(20).width(Length::Fill),
+                button("Clear")
+                    .style(button::secondary)
+                    .on_press(Message::ClearLog),
+                button("Close").on_press(Message::ToggleLog),
+            ]
+            .spacing(10)
+            .align_y(Alignment::Center),
+            scrollable(entries).height(400).width(600),
+        ]
+        .spacing(15),
+    )
+}
+
+/// An error's message, with the number of times it came in a row.
+fn entry_text(entry: &crate::log::Entry) -> String {
+    if entry.count > 1 {
+        format!("{} (×{})", entry.message, entry.count)
+    } else {
+        entry.message.clone()
+    }
+}
+
+/// How long ago something happened, e.g. "5 min ago".
+fn age(elapsed: std::time::Duration) -> String {
+    match elapsed.as_secs() {
+        0..10 => "just now".to_string(),
+        seconds @ 10..60 => format!("{} s ago", seconds),
+        seconds @ 60..3600 => format!("{} min ago", seconds / 60),
+        seconds => format!("{} h ago", seconds / 3600),
+    }
+}
+
+/// Dialog asking to confirm an action that loses the session or part of it, offering to save
+/// unsaved changes first.
+fn confirmation_view(confirmation: &Confirmation) -> Element<'_, Message> {
+    let mut dialog = column![text(&confirmation.question).size(20)].spacing(15);
+    let buttons = if confirmation.unsaved {
+        dialog = dialog.push(text("The session has unsaved changes."));
+        row![
+            button("Save").on_press(Message::Confirmed(true)),
+            button("Don't Save")
+                .style(button::danger)
+                .on_press(Message::Confirmed(false)),
+        ]
+    } else {
+        row![
+            button(match confirmation.action {
+                Confirm::ClearSession => "Clear",
+                Confirm::DeletePlugin(_) => "Delete",
+                Confirm::Open(_) => "Open",
+                Confirm::NewFromTemplate(_) => "New",
+                Confirm::Exit => "Quit",
+            })
+            .style(button::danger)
+            .on_press(Message::Confirmed(false))
+        ]
+    };
+    dialog = dialog.push(
+        buttons
+            .push(
+                button("Cancel")
+                    .style(button::secondary)
+                    .on_press(Message::CancelConfirm),
+            )
+            .spacing(10),
+    );
+    modal(dialog)
+}
+
+/// Content over a dimmed window, which takes the clicks.
+fn modal<'a>(content: Column<'a, Message>) -> Element<'a, Message> {
+    opaque(
+        center(container(content).padding(20).style(box_style)).style(|_theme: &Theme| {
+            container::Style {
+                background: Some(Color::from_rgba8(0, 0, 0, 0.4).into()),
+                ..Default::default()
+            }
+        }),
+    )
+}
+
+/// Keys and what they do, as handled by `shortcut` in main.
+const SHORTCUTS: [(&str, &str); 12] = [
+    ("Ctrl+O", "Open a session"),
+    ("Ctrl+S", "Save the session"),
+    ("Ctrl+Shift+S", "Save the session as a new file"),
+    ("Ctrl+Z", "Undo"),
+    ("Ctrl+Shift+Z", "Redo"),
+    ("Ctrl+R", "Rescan plugins"),
+    ("↑ / ↓", "Select the previous or next plugin"),
+    ("B", "Bypass the selected plugin or enable it again"),
+    ("[ / ]", "Recall the previous or next snapshot"),
+    ("M", "Mute or unmute the master output"),
+    ("Esc", "Panic: bypass every chain, or close a dialog"),
+    ("F1 or ?", "Show or hide this list"),
+];
+
+/// Cheat sheet of the keyboard shortcuts.
+fn shortcuts_view<'a>() -> Element<'a, Message> {
+    let mut list = column![text("Keyboard Shortcuts").size(20)].spacing(8);
+    for (keys, action) in SHORTCUTS {
+        list = list.push(row![text(keys).width(140.0), text(action)]);
+    }
+    list = list.push(space::vertical().height(6));
+    list = list.push(button("Close").on_press(Message::ToggleShortcuts));
+    modal(list)
+}
+
+/// Search and the plugins found.
+fn browser_view(state: &crate::AppState) -> Column<'_, Message> {
+    let mut scanned_list = column![].spacing(10);
+    // Only the types of the plugins found are offered
+    let mut categories = vec![Category(None)];
+    for info in &state.scanned_plugins {
+        let category = Category(Some(info.plugin_type));
+        if !categories.contains(&category) {
+            categories.push(category);
+        }
+    }
+    scanned_list = scanned_list.push(
+        row![
+            text_input("Search", &state.filter)
+                .on_input(Message::FilterChanged)
+                .width(150),
+            pick_list(categories, Some(state.category), Message::SetCategory),
+        ]
+        .spacing(10),
+    );
+    let mut available: Vec<&PluginInfo> = state
+        .scanned_plugins
+        .iter()
+        .filter(|info| {
+            state
+                .category
+                .0
+                .is_none_or(|plugin_type| info.plugin_type == plugin_type)
+                && (fuzzy_match(&state.filter, &info.name)
+                    || fuzzy_match(&state.filter, &info.manufacturer))
+        })
+        .collect();
+    // Favorites first, then the most recently loaded, then the rest in scan order
+    let favorite = |info: &PluginInfo| state.config.favorites.contains(&info.unique_id);
+    available.sort_by_key(|info| {
+        (
+            !favorite(info),
+            state
+                .config
+                .recent
+                .iter()
+                .position(|id| *id == info.unique_id)
+                .unwrap_or(usize::MAX),
+        )
+    });
+    for info in available {
+        let mut entry = column![
+            row![
+                mouse_area(text("⠿").color([0.5, 0.5, 0.5]))
+                    .interaction(mouse::Interaction::Grab)
+                    .on_press(Message::DragPlugin(info.clone())),
+                button(if favorite(info) { "★" } else { "☆" })
+                    .style(button::text)
+                    .on_press(Message::ToggleFavorite(info.unique_id.clone())),
+                button(text(format!("{}", info)))
+                    .style(button::text)
+                    .width(Length::Fill)
+                    .on_press(Message::ToggleDetails(info.clone())),
+                button("+").on_press(Message::LoadPlugin(info.clone())),
+            ]
+            .spacing(10)
+            .align_y(Alignment::Center),
+        ]
+        .spacing(5)
+        .padding(10);
+        if let Some((_, parameter_count)) = state
+            .details
+            .as_ref()
+            .filter(|(unique_id, _)| *unique_id == info.unique_id)
+        {
+            let duplicates = state
+                .duplicates
+                .get(&info.unique_id)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            entry = entry.push(plugin_details(info, *parameter_count, duplicates));
+        }
+        scanned_list = scanned_list.push(container(entry).style(box_style));
+    }
+    scanned_list
+}
+
+/// Slots of the chain or return chain shown.
+fn chain_view(state: &crate::AppState) -> Column<'_, Message> {
+    let selected = &state.chains[state.selected_chain];
+    // Sends are only offered from the chain itself, not from its return chains
+    let (nodes, returns) = match state.selected_return {
+        Some(i) => (&selected.returns[i].nodes, &[][..]),
+        None => (&selected.nodes, &selected.returns[..]),
+    };
+
+    let mut plugin_chain = column![].spacing(15);
+    for (i, node) in nodes.iter().enumerate() {
+        if let Some(info) = &state.dragging {
+            plugin_chain = plugin_chain.push(drop_zone(info, i));
+        }
+        let first = i == 0;
+        let last = i == nodes.len() - 1;
+        plugin_chain = plugin_chain.push(match node {
+            ChainNode::Plugin(plugin) => plugin_view(plugin, returns, state, first, last),
+            ChainNode::Split(split) => split_view(split, returns, state, first, last),
+        });
+    }
+    if let Some(info) = &state.dragging {
+        plugin_chain = plugin_chain.push(drop_zone(info, nodes.len()));
+    }
+    plugin_chain
+}
+
+fn plugin_view<'a>(
+    plugin: &'a LoadedPlugin,
+    returns: &'a [LoadedReturn],
+    state: &'a crate::AppState,
+    first: bool,
+    last: bool,
+) -> Element<'a, Message> {
+    let selected = state.selected_plugin == Some(plugin.id);
+    let mut plugin_header: Row<'_, Message> = row![].spacing(10).align_y(Alignment::Center);
+    plugin_header = plugin_header.push(
+        button(if plugin.collapsed { "▸" } else { "▾" })
+            .style(button::secondary)
+            .on_press(Message::ToggleCollapsed(plugin.id)),
+    );
+    match &state.renaming {
+        Some((id, label)) if *id == plugin.id => {
+            plugin_header = plugin_header.push(
+                text_input(&plugin.unit.name(), label)
+                    .id(RENAME_INPUT)
+                    .on_input(Message::RenameChange)
+                    .on_submit(Message::FinishRename)
+                    .width(200),
+            );
+            plugin_header = plugin_header.push(button("OK").on_press(Message::FinishRename));
+        }
+        _ => {
+            plugin_header = plugin_header.push(
+                button(text(plugin.name()))
+                    .style(button::text)
+                    .padding(0)
+                    .on_press(Message::SelectPlugin(plugin.id)),
+            );
+            plugin_header = plugin_header.push(
+                button("✎")
+                    .style(button::secondary)
+                    .on_press(Message::StartRename(plugin.id)),
+            );
+        }
+    }
+    plugin_header = plugin_header
+        .push(button("✕").on_press(Message::AskConfirm(Confirm::DeletePlugin(plugin.id))));
+    plugin_header = plugin_header.push(pick_list(
+        &SlotColor::ALL[..],
+        Some(plugin.color),
+        |color| Message::SetSlotColor(plugin.id, color),
+    ));
+    plugin_header = plugin_header.push(pick_list(
+        &Routing::ALL[..],
+        Some(plugin.routing),
+        |routing| Message::RoutingChange(plugin.id, routing),
+    ));
+    let mut preset_controls: Row<'_, Message> = row![].spacing(10).align_y(Alignment::Center);
+    if let LoadedUnit::Plugin { info, .. } = &plugin.unit {
+        if let Some(user) = state.presets.get(&info.unique_id)
+            && !user.is_empty()
+        {
+            preset_controls = preset_controls.push(
+                pick_list(&user[..], None::<String>, |name| {
+                    Message::LoadUserPreset(plugin.id, name)
+                })
+                .placeholder("User preset"),
+            );
+        }
+        preset_controls = preset_controls
+            .push(button("Save Preset").on_press(Message::SaveUserPreset(plugin.id)))
+            .push(
+                toggler(plugin.on_b)
+                    .label(if plugin.on_b { "B" } else { "A" })
+                    .on_toggle(|_| Message::FlipCompare(plugin.id)),
+            )
+            .push(
+                button(if plugin.on_b {
+                    "Copy B→A"
+                } else {
+                    "Copy A→B"
+                })
+                .on_press(Message::CopyCompare(plugin.id)),
+            );
+    }
+    if let LoadedUnit::Plugin { dual_mono, .. } = plugin.unit {
+        plugin_header = plugin_header.push(
+            toggler(dual_mono)
+                .label("Dual mono")
+                .on_toggle(|dual_mono| Message::SetDualMono(plugin.id, dual_mono)),
+        );
+        if !dual_mono {
+            // Counted with the main bus, as shown in the bus names
+            let counts: Vec<usize> = (1..=MAX_EXTRA_BUSES + 1).collect();
+            plugin_header = plugin_header.push(text("Buses"));
+            plugin_header =
+                plugin_header.push(pick_list(counts, Some(plugin.buses + 1), |count| {
+                    Message::SetBuses(plugin.id, count - 1)
+                }));
+        }
+        if !dual_mono && plugin.buses > 0 {
+            let output_buses: Vec<OutputBus> = std::iter::once(OutputBus::Main)
+                .chain((1..=plugin.buses).map(OutputBus::Extra))
+                .chain([OutputBus::Downmix])
+                .collect();
+            plugin_header = plugin_header.push(pick_list(
+                output_buses,
+                Some(plugin.output_bus),
+                |output_bus| Message::SetOutputBus(plugin.id, output_bus),
+            ));
+        }
+    }
+    plugin_header = plugin_header.push(
+        toggler(plugin.bypass)
+            .label("Bypass")
+            .on_toggle(|bypass| Message::SetBypass(plugin.id, bypass)),
+    );
+
+    if !first {
+        plugin_header = plugin_header.push(button("↑").on_press(Message::MovePluginUp(plugin.id)));
+    }
+    if !last {
+        plugin_header =
+            plugin_header.push(button("↓").on_press(Message::MovePluginDown(plugin.id)));
+    }
+
+    let (input_level, output_level, load) =
+        state.levels.get(&plugin.id).copied().unwrap_or_default();
+    let [input_hold, output_hold] = peak_holds(state, plugin.id);
+    let meters = column![
+        meter_view("In", input_level, input_hold),
+        meter_view("Out", output_level, output_hold),
+        text(format!("DSP {:.1}%", load * 100.0)),
+    ]
+    .spacing(5);
+    if plugin.collapsed {
+        return slot_box(plugin, column![plugin_header, meters], selected);
+    }
+
+    let mut looper_controls: Column<'_, Message> = column![].spacing(10);
+    if let LoadedUnit::Looper { status, .. } = &plugin.unit {
+        let looper_state = status.state();
+        let mut buttons = row![text(looper_state.to_string()).width(100.0)].spacing(10);
+        for action in LooperAction::ALL {
+            let active = matches!(
+                (action, looper_state),
+                (LooperAction::Record, LooperState::Recording)
+                    | (LooperAction::Overdub, LooperState::Overdubbing)
+                    | (LooperAction::Play, LooperState::Playing)
+            );
+            buttons = buttons.push(
+                button(text(action.to_string()))
+                    .style(if active {
+                        button::danger
+                    } else {
+                        button::secondary
+                    })
+                    .on_press(Message::LooperAction(plugin.id, action)),
+            );
+        }
+        looper_controls = looper_controls
+            .push(buttons)
+            .push(progress_bar(0.0..=1.0, status.progress()).girth(6));
+    }
+
+    let input_gain_control = row![
+        text("Input").width(100.0),
+        text(format!("{:+.1} dB ", plugin.input_gain)),
+        slider(-24.0..=24.0, plugin.input_gain, |db| {
+            Message::InputGainChange(plugin.id, db)
+        })
+        .step(0.1),
+    ];
+
+    let output_gain_control = row![
+        text("Output").width(100.0),
+        text(format!("{:+.1} dB ", plugin.output_gain)),
+        slider(-24.0..=24.0, plugin.output_gain, |db| {
+            Message::OutputGainChange(plugin.id, db)
+        })
+        .step(0.1),
+    ];
+
+    let mix_control = row![
+        text("Dry/Wet").width(100.0),
+        text(format!("{:.0}% ", plugin.mix * 100.0)),
+        slider(0.0..=1.0, plugin.mix, |mix| Message::MixChange(
+            plugin.id, mix
+        ))
+        .step(0.01),
+    ];
+
+    let mut send_controls: Column<'_, Message> = column![].spacing(10);
+    for aux_return in returns {
+        let level = plugin
+            .sends
+            .iter()
+            .find(|(id, _)| *id == aux_return.id)
+            .map_or(0.0, |(_, level)| *level);
+        send_controls = send_controls.push(row![
+            text(format!("Send {}", aux_return.name)).width(100.0),
+            text(format!("{:.0}% ", level * 100.0)),
+            slider(0.0..=1.0, level, |level| {
+                Message::SendChange(plugin.id, aux_return.id, level)
+            })
+            .step(0.01),
+        ]);
+    }
+
+    let mut param_controls: Column<'_, Message> = column![].spacing(10);
+    let filter = plugin.param_filter.to_lowercase();
+    let matching: Vec<_> = plugin
+        .params
+        .iter()
+        .filter(|(info, _)| info.name.to_lowercase().contains(&filter))
+        .collect();
+    let pages = matching.len().div_ceil(PARAMS_PER_PAGE);
+    if !plugin.params.is_empty() {
+        let mut params_header: Row<'_, Message> = row![
+            button(text(format!(
+                "{} Parameters ({})",
+                if plugin.hide_params { "▸" } else { "▾" },
+                if filter.is_empty() {
+                    plugin.params.len().to_string()
+                } else {
+                    format!("{} of {}", matching.len(), plugin.params.len())
+                }
+            )))
+            .on_press(Message::ToggleParams(plugin.id))
+        ]
+        .spacing(10)
+        .align_y(Alignment::Center);
+        if pages > 1 && !plugin.hide_params {
+            let page = plugin.param_page.min(pages - 1);
+            params_header =
+                params_header
+                    .push(button("‹").on_press_maybe(
+                        (page > 0).then(|| Message::SetParamPage(plugin.id, page - 1)),
+                    ))
+                    .push(text(format!("Page {}/{}", page + 1, pages)))
+                    .push(button("›").on_press_maybe(
+                        (page + 1 < pages).then(|| Message::SetParamPage(plugin.id, page + 1)),
+                    ));
+        }
+        if !plugin.hide_params {
+            params_header = params_header
+                .push(
+                    text_input("Filter parameters", &plugin.param_filter)
+                        .on_input(move |filter| Message::ParamFilterChange(plugin.id, filter))
+                        .width(160),
+                )
+                .push(space().width(Length::Fill))
+                .push(pick_list(
+                    &ParamView::ALL[..],
+                    Some(plugin.param_view.unwrap_or(state.config.param_view)),
+                    move |view| Message::SetPluginParamView(plugin.id, view),
+                ))
+                .push(button("Randomize").on_press(Message::RandomizeParams(plugin.id)))
+                .push(
+                    button(text(format!("Nudge ±{:.0}%", NUDGE_AMOUNT * 100.0)))
+                        .on_press(Message::NudgeParams(plugin.id)),
+                );
+        }
+        param_controls = param_controls.push(params_header);
+    }
+    let shown = if plugin.hide_params {
+        &[][..]
+    } else {
+        let start = plugin.param_page.min(pages.saturating_sub(1)) * PARAMS_PER_PAGE;
+        &matching[start..(start + PARAMS_PER_PAGE).min(matching.len())]
+    };
+    let knobs = plugin.param_view.unwrap_or(state.config.param_view) == ParamView::Knobs;
+    let mut knob_cells: Row<'_, Message> = row![].spacing(15);
+    for &param in shown {
+        let locked = plugin.locked.contains(&param.0.index);
+        // Locked parameters are left alone by Randomize and Nudge
+        let lock = button(if locked { "🔒" } else { "🔓" })
+            .style(if locked {
+                button::primary
+            } else {
+                button::text
+            })
+            .padding(2)
+            .on_press(Message::ToggleParamLock(plugin.id, param.0.index));
+        let switch = is_switch(&param.0).then(|| {
+            toggler(param.1 >= 0.5).on_toggle(|on| {
+                Message::ParamChange(plugin.id, param.0.clone(), if on { 1.0 } else { 0.0 })
+            })
+        });
+        if knobs {
+            let name = row![lock, text(param.0.name.clone()).size(12)]
+                .spacing(2)
+                .align_y(Alignment::Center);
+            let cell = match switch {
+                Some(switch) => column![name, switch],
+                None => column![
+                    name,
+                    knob(param.1, |value| {
+                        Message::ParamChange(plugin.id, param.0.clone(), value)
+                    }),
+                    param_value_view(state, plugin, param),
+                ],
+            };
+            knob_cells = knob_cells.push(
+                cell.spacing(5)
+                    .width(KNOB_CELL_WIDTH)
+                    .align_x(Alignment::Center),
+            );
+            continue;
+        }
+        if let Some(switch) = switch {
+            param_controls =
+                param_controls.push(row![lock, text(param.0.name.clone()).width(100.0), switch]);
+            continue;
+        }
+        param_controls = param_controls.push(
+            row![
+                lock,
+                text(param.0.name.clone()).width(100.0),
+                param_value_view(state, plugin, param)
+            ]
+            .spacing(5)
+            .align_y(Alignment::Center)
+            .push(
+                // Plugins take normalized values, so only the label is denormalized
+                slider(0.0..=1.0, param.1, |value| {
+                    Message::DragParam(plugin.id, param.0.clone(), value)
+                })
+                .step(0.001)
+                .on_release(Message::ReleaseParam),
+            ),
+        );
+    }
+    if knobs && !shown.is_empty() {
+        param_controls = param_controls.push(knob_cells.wrap().vertical_spacing(15));
+    }
+
+    slot_box(
+        plugin,
+        column![
+            plugin_header,
+            preset_controls,
+            meters,
+            looper_controls,
+            column![input_gain_control, mix_control, output_gain_control].spacing(10),
+            send_controls,
+            param_controls
+        ],
+        selected,
+    )
+}
+
+/// A parameter's value, clicked to type one, or the input it's being typed in.
+fn param_value_view<'a>(
+    state: &'a crate::AppState,
+    plugin: &LoadedPlugin,
+    param: &(ParameterInfo, f32),
+) -> Element<'a, Message> {
+    match &state.param_entry {
+        Some(entry) if entry.plugin_id == plugin.id && entry.index == param.0.index => {
+            let mut entry_row = row![
+                text_input(&value_label(&param.0, param.1), &entry.text)
+                    .id(PARAM_INPUT)
+                    .on_input(Message::ParamEntryChange)
+                    .on_submit(Message::SubmitParamEntry)
+                    .width(90.0),
+                button("✕").on_press(Message::CancelParamEntry),
+            ]
+            .spacing(5)
+            .align_y(Alignment::Center);
+            if entry.invalid {
+                entry_row = entry_row.push(
+                    text(format!(
+                        "Enter a number from {} to {}",
+                        param.0.min.min(param.0.max),
+                        param.0.max.max(param.0.min)
+                    ))
+                    .color([0.8, 0.1, 0.1]),
+                );
+            }
+            entry_row.into()
+        }
+        _ => button(text(value_label(&param.0, param.1)))
+            .style(button::text)
+            .padding(0)
+            .width(90.0)
+            .on_press(Message::EditParam(plugin.id, param.0.index))
+            .into(),
+    }
+}
+
+/// Box around a slot's controls under a strip of its color tag, outlined when the slot is
+/// selected.
+fn slot_box<'a>(
+    plugin: &LoadedPlugin,
+    content: Column<'a, Message>,
+    selected: bool,
+) -> Element<'a, Message> {
+    let mut body = column![];
+    if let Some(color) = plugin.color.color() {
+        body = body.push(container(space()).width(Length::Fill).height(8).style(
+            move |_theme: &Theme| container::Style {
+                background: Some(color.into()),
+                border: iced::Border {
+                    radius: iced::border::top(10.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        ));
+    }
+    container(body.push(content.spacing(15).padding(15)))
+        .style(move |theme| {
+            let style = box_style(theme);
+            if selected {
+                // Outlined as the target of the keyboard shortcuts
+                style.border(iced::Border {
+                    color: theme.palette().primary,
+                    width: 2.0,
+                    ..style.border
+                })
+            } else {
+                style
+            }
+        })
+        .into()
+}
+
+/// Place in the chain that a plugin being dragged is loaded at when released over it.
+fn drop_zone<'a>(info: &PluginInfo, index: usize) -> Element<'a, Message> {
+    mouse_area(
+        container(text("Drop here").color([0.5, 0.5, 0.5]))
+            .center_x(Length::Fill)
+            .padding(10)
+            .style(|theme: &Theme| container::Style {
+                border: iced::Border {
+                    color: theme.palette().primary,
+                    width: 1.0,
+                    radius: 10.0.into(),
+                },
+                ..Default::default()
+            }),
+    )
+    .interaction(mouse::Interaction::Grabbing)
+    .on_release(Message::LoadPluginAt(info.clone(), index))
     .into()
 }
 
+/// Share of the width the plugin browser takes unless it was resized.
+const BROWSER_RATIO: f32 = 0.3;
+
+/// Side of the split between the plugin browser and the chain.
+pub enum Pane {
+    Browser,
+    Chain,
+}
+
+/// Split between the plugin browser and the chain, resized by dragging the space between them.
+pub struct Panes(pub pane_grid::State<Pane>);
+
+impl Panes {
+    /// Gives the browser a share of the width, or the default share.
+    pub fn new(ratio: Option<f32>) -> Self {
+        Panes(pane_grid::State::with_configuration(
+            pane_grid::Configuration::Split {
+                axis: pane_grid::Axis::Vertical,
+                ratio: ratio.unwrap_or(BROWSER_RATIO),
+                a: Box::new(pane_grid::Configuration::Pane(Pane::Browser)),
+                b: Box::new(pane_grid::Configuration::Pane(Pane::Chain)),
+            },
+        ))
+    }
+}
+
+impl Default for Panes {
+    fn default() -> Self {
+        Panes::new(None)
+    }
+}
+
+/// Tag that groups slots by color, shown as a strip along the top of each.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SlotColor {
+    #[default]
+    None,
+    Red,
+    Orange,
+    Yellow,
+    Green,
+    Blue,
+    Purple,
+}
+
+impl SlotColor {
+    pub const ALL: [SlotColor; 7] = [
+        SlotColor::None,
+        SlotColor::Red,
+        SlotColor::Orange,
+        SlotColor::Yellow,
+        SlotColor::Green,
+        SlotColor::Blue,
+        SlotColor::Purple,
+    ];
+
+    fn color(self) -> Option<Color> {
+        match self {
+            SlotColor::None => None,
+            SlotColor::Red => Some(Color::from_rgb8(230, 70, 70)),
+            SlotColor::Orange => Some(Color::from_rgb8(240, 150, 50)),
+            SlotColor::Yellow => Some(Color::from_rgb8(230, 200, 50)),
+            SlotColor::Green => Some(Color::from_rgb8(80, 180, 90)),
+            SlotColor::Blue => Some(Color::from_rgb8(70, 130, 220)),
+            SlotColor::Purple => Some(Color::from_rgb8(150, 90, 200)),
+        }
+    }
+}
+
+impl fmt::Display for SlotColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SlotColor::None => "No color",
+            SlotColor::Red => "Red",
+            SlotColor::Orange => "Orange",
+            SlotColor::Yellow => "Yellow",
+            SlotColor::Green => "Green",
+            SlotColor::Blue => "Blue",
+            SlotColor::Purple => "Purple",
+        })
+    }
+}
+
+/// Way parameters are shown, for every plugin or just one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ParamView {
+    #[default]
+    Sliders,
+    /// Knobs in a grid, which fit many more parameters on screen.
+    Knobs,
+}
+
+impl ParamView {
+    pub const ALL: [ParamView; 2] = [ParamView::Sliders, ParamView::Knobs];
+}
+
+impl fmt::Display for ParamView {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ParamView::Sliders => "Sliders",
+            ParamView::Knobs => "Knobs",
+        })
+    }
+}
+
+/// Width of a knob with its parameter's name and value.
+const KNOB_CELL_WIDTH: f32 = 110.0;
+
+/// Number of parameters shown at once, so plugins with hundreds of them stay navigable.
+const PARAMS_PER_PAGE: usize = 20;
+
+/// Largest share of its range that nudging moves a parameter either way.
+pub const NUDGE_AMOUNT: f32 = 0.05;
+
+/// A normalized parameter value in the parameter's own range. rack has no value-to-string
+/// conversion, and VST3 parameters report a range of 0.0 to 1.0, so for them it's unchanged.
+pub fn plain_value(info: &ParameterInfo, value: f32) -> f32 {
+    info.min + value * (info.max - info.min)
+}
+
+/// Normalized value of a number in a parameter's own range, clamped to the range.
+pub fn normalized_value(info: &ParameterInfo, value: f32) -> f32 {
+    if info.max == info.min {
+        return 0.0;
+    }
+    ((value - info.min) / (info.max - info.min)).clamp(0.0, 1.0)
+}
+
+/// Normalized value of a number typed in a parameter's own range, with or without its unit,
+/// e.g. "350" or "350 Hz". Numbers outside the range aren't taken.
+pub fn parse_value(info: &ParameterInfo, text: &str) -> Option<f32> {
+    let text = text.trim();
+    let number: f32 = text
+        .strip_suffix(info.unit.as_str())
+        .unwrap_or(text)
+        .trim()
+        .parse()
+        .ok()?;
+    if !(info.min.min(info.max)..=info.max.max(info.min)).contains(&number) {
+        return None;
+    }
+    Some(normalized_value(info, number))
+}
+
+/// Words in the names of on/off parameters.
+const SWITCH_WORDS: [&str; 10] = [
+    "bypass", "on/off", "enable", "enabled", "active", "mute", "solo", "invert", "switch", "toggle",
+];
+
+/// Whether a parameter is an on/off switch, shown as a toggler rather than a slider. rack doesn't
+/// report step counts, so this goes by the parameter's name.
+pub fn is_switch(info: &ParameterInfo) -> bool {
+    let name = info.name.to_lowercase();
+    name.split(|c: char| !c.is_alphanumeric() && c != '/')
+        .any(|word| SWITCH_WORDS.contains(&word))
+}
+
+/// The plain value with as many decimals as its range needs, followed by the unit, e.g. "-6.0 dB".
+/// rack doesn't report step counts, so the precision is taken from the range alone.
+pub fn value_label(info: &ParameterInfo, value: f32) -> String {
+    let range = (info.max - info.min).abs();
+    let decimals = if range >= 100.0 {
+        0
+    } else if range >= 10.0 {
+        1
+    } else {
+        2
+    };
+    let value = plain_value(info, value);
+    if info.unit.is_empty() {
+        format!("{:.*}", decimals, value)
+    } else {
+        format!("{:.*} {}", decimals, value, info.unit)
+    }
+}
+
+fn split_view<'a>(
+    split: &'a LoadedSplit,
+    returns: &'a [LoadedReturn],
+    state: &'a crate::AppState,
+    first: bool,
+    last: bool,
+) -> Element<'a, Message> {
+    let mut split_header: Row<'_, Message> = row![].spacing(10).align_y(Alignment::Center);
+    split_header = split_header.push(text("Parallel Split"));
+    split_header = split_header
+        .push(button("✕").on_press(Message::AskConfirm(Confirm::DeletePlugin(split.id))));
+    if !first {
+        split_header = split_header.push(button("↑").on_press(Message::MovePluginUp(split.id)));
+    }
+    if !last {
+        split_header = split_header.push(button("↓").on_press(Message::MovePluginDown(split.id)));
+    }
+
+    let mut branches = row![].spacing(15);
+    for (i, (branch, name)) in split.branches.iter().zip(["A", "B"]).enumerate() {
+        let target = button(if state.load_target == Some((split.id, i)) {
+            "Adding here"
+        } else {
+            "Add here"
+        })
+        .on_press(Message::SelectBranch(split.id, i));
+
+        let mut branch_column = column![
+            row![text(format!("Branch {}", name)), target]
+                .spacing(10)
+                .align_y(Alignment::Center),
+            row![
+                text(format!("{:+.1} dB ", split.gains[i])),
+                slider(-24.0..=24.0, split.gains[i], move |db| {
+                    Message::BranchGainChange(split.id, i, db)
+                })
+                .step(0.1),
+            ],
+        ]
+        .spacing(10)
+        .width(Length::FillPortion(1));
+
+        for (j, plugin) in branch.iter().enumerate() {
+            branch_column = branch_column.push(plugin_view(
+                plugin,
+                returns,
+                state,
+                j == 0,
+                j == branch.len() - 1,
+            ));
+        }
+        branches = branches.push(branch_column);
+    }
+
+    container(column![split_header, branches].spacing(15).padding(15))
+        .style(box_style)
+        .into()
+}
+
+/// An entry of the recent sessions list.
+#[derive(Clone, PartialEq)]
+struct SessionFile(PathBuf);
+
+impl fmt::Display for SessionFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.display())
+    }
+}
+
+/// Program changes mapped to setlist entries and snapshots, and the bank and program to map
+/// next, which the last program change received fills in.
+fn program_map_view(state: &crate::AppState) -> Row<'_, Message> {
+    let targets: Vec<ProgramTarget> = (0..state.config.setlist.len())
+        .map(ProgramTarget::Entry)
+        .chain(
+            state
+                .snapshots
+                .iter()
+                .map(|snapshot| ProgramTarget::Snapshot(snapshot.name.clone())),
+        )
+        .collect();
+    let mut program_row = row![
+        text("Bank"),
+        text_input("0", &state.program_bank)
+            .on_input(Message::ProgramBankChange)
+            .width(70),
+        text("Program"),
+        text_input("0", &state.program_number)
+            .on_input(Message::ProgramNumberChange)
+            .width(60),
+        pick_list(targets, None::<ProgramTarget>, Message::MapProgram).placeholder("Map to"),
+    ]
+    .spacing(10)
+    .align_y(Alignment::Center);
+    for (i, mapping) in state.config.program_map.iter().enumerate() {
+        let target = match &mapping.target {
+            ProgramTarget::Entry(entry) => match state.config.setlist.get(*entry) {
+                Some(name) => format!("{}. {}", entry + 1, name),
+                None => mapping.target.to_string(),
+            },
+            ProgramTarget::Snapshot(_) => mapping.target.to_string(),
+        };
+        program_row = program_row.push(text(format!(
+            "{}:{} → {}",
+            mapping.bank, mapping.program, target
+        )));
+        program_row = program_row.push(button("✕").on_press(Message::UnmapProgram(i)));
+    }
+    program_row
+}
+
+/// Name of the control a MIDI controller is mapped to.
+fn target_name(state: &crate::AppState, target: MidiTarget) -> String {
+    let MidiTarget::Param {
+        plugin_id, index, ..
+    } = target
+    else {
+        return "Master Volume".to_string();
+    };
+    let plugin = state
+        .chains
+        .iter()
+        .flat_map(|chain| {
+            std::iter::once(&chain.nodes)
+                .chain(chain.returns.iter().map(|aux_return| &aux_return.nodes))
+        })
+        .flatten()
+        .flat_map(|node| match node {
+            ChainNode::Plugin(plugin) => vec![plugin.as_ref()],
+            ChainNode::Split(split) => split.branches.iter().flatten().collect(),
+        })
+        .find(|plugin| plugin.id == plugin_id);
+    // Kept for a deleted plugin, in case it's brought back by undo
+    let Some(plugin) = plugin else {
+        return "Deleted plugin".to_string();
+    };
+    match plugin.params.iter().find(|(info, _)| info.index == index) {
+        Some((info, _)) => format!("{} {}", plugin.name(), info.name),
+        None => plugin.name(),
+    }
+}
+
+/// An entry of the analyzer tap list.
+#[derive(Clone, PartialEq)]
+struct Tap {
+    id: Option<Uuid>,
+    name: String,
+}
+
+impl fmt::Display for Tap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+/// Vendor, version, type, format, path and parameter count of an available plugin, and the
+/// other places it was found.
+fn plugin_details<'a>(
+    info: &'a PluginInfo,
+    parameter_count: Option<usize>,
+    duplicates: &'a [PathBuf],
+) -> Column<'a, Message> {
+    let format = info
+        .path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_uppercase())
+        .unwrap_or_default();
+    column![
+        text(format!("Vendor: {}", info.manufacturer)),
+        text(format!("Version: {}", info.version)),
+        text(format!("Type: {:?}", info.plugin_type)),
+        text(format!("Format: {}", format)),
+        text(format!("Path: {}", info.path.display())),
+        text(match parameter_count {
+            Some(count) => format!("Parameters: {}", count),
+            None => "Parameters: unknown, the plugin failed to load".to_string(),
+        }),
+    ]
+    .extend(
+        duplicates
+            .iter()
+            .map(|path| text(format!("Also found at: {}", path.display())).into()),
+    )
+    .spacing(2)
+}
+
+/// Tap selection, followed by the spectrum of the tapped signal when there is one.
+fn analyzer_view(state: &crate::AppState) -> Column<'_, Message> {
+    let mut taps = vec![Tap {
+        id: None,
+        name: "Off".to_string(),
+    }];
+    for chain in &state.chains {
+        taps.push(Tap {
+            id: Some(chain.id),
+            name: format!("{} Output", chain.name),
+        });
+        let nodes = chain.nodes.iter().chain(
+            chain
+                .returns
+                .iter()
+                .flat_map(|aux_return| &aux_return.nodes),
+        );
+        for node in nodes {
+            let plugins: Vec<&LoadedPlugin> = match node {
+                ChainNode::Plugin(plugin) => vec![plugin],
+                ChainNode::Split(split) => split.branches.iter().flatten().collect(),
+            };
+            for plugin in plugins {
+                taps.push(Tap {
+                    id: Some(plugin.id),
+                    name: format!("{} / {}", chain.name, plugin.name()),
+                });
+            }
+        }
+    }
+    let selected = taps
+        .iter()
+        .find(|tap| tap.id == state.analyzer_tap)
+        .cloned();
+
+    let mut analyzer = column![
+        row![
+            text("Analyzer "),
+            pick_list(taps, selected, |tap| Message::SetAnalyzerTap(tap.id)),
+        ]
+        .align_y(Alignment::Center)
+    ]
+    .spacing(10);
+
+    if state.analyzer_tap.is_some() {
+        let mut bands = row![].spacing(2).height(120);
+        for level in &state.spectrum {
+            bands = bands.push(
+                progress_bar(analyzer::MIN_DB..=0.0, *level)
+                    .vertical()
+                    .girth(Length::Fill),
+            );
+        }
+        analyzer = analyzer.push(bands);
+    }
+    analyzer
+}
+
+fn metronome_view(state: &crate::AppState) -> Row<'_, Message> {
+    let client = state.jack_client.as_ref().unwrap().as_client();
+    let position = match client.transport().query() {
+        Ok(transport) if transport.state == TransportState::Rolling => {
+            let beats = Beats::from_transport(&transport, client.sample_rate(), state.tempo.bpm());
+            let beat = beats.position.floor() as usize;
+            format!(
+                "{:.1} BPM  {}.{}",
+                beats.bpm,
+                beat / beats.beats_per_bar + 1,
+                beat % beats.beats_per_bar + 1
+            )
+        }
+        Ok(_) => format!("{:.1} BPM  Transport stopped", state.tempo.bpm()),
+        Err(_) => String::new(),
+    };
+
+    row![
+        toggler(state.metronome)
+            .label("Metronome")
+            .on_toggle(Message::SetMetronome),
+        space::horizontal().width(10),
+        text(format!("{:+.1} dB ", state.metronome_volume)),
+        slider(
+            -40.0..=0.0,
+            state.metronome_volume,
+            Message::MetronomeVolumeChange
+        )
+        .step(0.1)
+        .width(150),
+        pick_list(
+            &ClickSound::ALL[..],
+            Some(state.click_sound),
+            Message::SetClickSound
+        ),
+        toggler(state.click_to_main)
+            .label("Click in Main Output")
+            .on_toggle(Message::SetClickToMain),
+        space::horizontal().width(10),
+        button("Tap").on_press(Message::Tap),
+        text("MIDI "),
+        pick_list(
+            TapController::all(),
+            Some(state.tap_controller),
+            Message::SetTapController
+        ),
+        space::horizontal().width(10),
+        text(position),
+    ]
+    .spacing(10)
+    .align_y(Alignment::Center)
+}
+
+/// Input and output meters of the chain shown.
+fn chain_meters(state: &crate::AppState, chain_id: Uuid) -> Row<'_, Message> {
+    let (input_level, output_level, _) = state.levels.get(&chain_id).copied().unwrap_or_default();
+    let [input_hold, output_hold] = peak_holds(state, chain_id);
+    row![
+        meter_view("Chain In", input_level, input_hold).width(Length::Fill),
+        meter_view("Chain Out", output_level, output_hold).width(Length::Fill),
+    ]
+    .spacing(20)
+}
+
+/// Held input and output peaks of a plugin or chain.
+fn peak_holds(state: &crate::AppState, id: Uuid) -> [f32; 2] {
+    state
+        .peak_holds
+        .get(&id)
+        .map_or([0.0; 2], |holds| holds.map(|(peak, _)| peak))
+}
+
+/// Peak level as a bar with a mark at the held peak, followed by the held peak and RMS levels in
+/// dBFS.
+fn meter_view(name: &str, level: Level, hold: f32) -> Row<'_, Message> {
+    let peak = gain_to_db(level.peak);
+    let hold = gain_to_db(hold.max(level.peak));
+    let rms = gain_to_db(level.rms);
+    let format_db = |db: f32| {
+        if db <= METER_MIN_DB {
+            "-inf".to_string()
+        } else {
+            format!("{:.1}", db)
+        }
+    };
+    // Where the mark is along the bar, in thousandths
+    let offset = ((hold.max(METER_MIN_DB) - METER_MIN_DB) / -METER_MIN_DB).min(1.0) * 1000.0;
+    let mut bar = stack![progress_bar(METER_MIN_DB..=0.0, peak.max(METER_MIN_DB)).girth(8)]
+        .width(Length::Fill);
+    if hold > METER_MIN_DB {
+        bar = bar.push(row![
+            space().width(Length::FillPortion(offset as u16)),
+            container(space())
+                .width(2)
+                .height(8)
+                .style(|_theme: &Theme| container::Style {
+                    background: Some(Color::from_rgb8(200, 40, 40).into()),
+                    ..Default::default()
+                }),
+            space().width(Length::FillPortion(1000 - offset as u16)),
+        ]);
+    }
+    row![
+        text(name).width(100.0),
+        bar,
+        text(format!(" {} / {} dB", format_db(hold), format_db(rms))).width(130.0),
+    ]
+    .align_y(Alignment::Center)
+}
+
+/// Whether the characters of `query` appear in `text` in order, ignoring case and spaces in
+/// `query`, so "vverb" finds "ValhallaVintageVerb".
+fn fuzzy_match(query: &str, text: &str) -> bool {
+    let mut chars = text.chars().flat_map(char::to_lowercase);
+    query
+        .chars()
+        .flat_map(char::to_lowercase)
+        .filter(|c| !c.is_whitespace())
+        .all(|q| chars.any(|c| c == q))
+}
+
 fn box_style(_theme: &Theme) -> container::Style {
     container::Style {
         background: Some(Color::WHITE.into()),
@@ -0,0 +1,215 @@
+use std::fs::File;
+use std::io::{BufWriter, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Largest data chunk whose RIFF size, which counts the 50 bytes of header after it, still
+/// fits in 32 bits.
+const MAX_DATA_SIZE: u64 = u32::MAX as u64 - 50;
+
+/// Writes interleaved stereo 32-bit float WAV files.
+pub struct WavWriter {
+    file: BufWriter<File>,
+    /// Bytes of samples written so far.
+    data_size: u64,
+}
+
+impl WavWriter {
+    pub fn create(path: &Path, sample_rate: u32) -> std::io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        let block_align = 2 * 4;
+        file.write_all(b"RIFF")?;
+        // Sizes are filled in by finish
+        file.write_all(&0_u32.to_le_bytes())?;
+        file.write_all(b"WAVEfmt ")?;
+        file.write_all(&18_u32.to_le_bytes())?;
+        // WAVE_FORMAT_IEEE_FLOAT
+        file.write_all(&3_u16.to_le_bytes())?;
+        file.write_all(&2_u16.to_le_bytes())?;
+        file.write_all(&sample_rate.to_le_bytes())?;
+        file.write_all(&(sample_rate * block_align).to_le_bytes())?;
+        file.write_all(&(block_align as u16).to_le_bytes())?;
+        file.write_all(&32_u16.to_le_bytes())?;
+        file.write_all(&0_u16.to_le_bytes())?;
+        file.write_all(b"fact")?;
+        file.write_all(&4_u32.to_le_bytes())?;
+        file.write_all(&0_u32.to_le_bytes())?;
+        file.write_all(b"data")?;
+        file.write_all(&0_u32.to_le_bytes())?;
+        Ok(WavWriter { file, data_size: 0 })
+    }
+
+    /// Whether `samples` more samples fit before the file reaches the 4 GiB limit of WAV.
+    pub fn has_room(&self, samples: usize) -> bool {
+        (samples as u64)
+            .checked_mul(4)
+            .and_then(|size| self.data_size.checked_add(size))
+            .is_some_and(|size| size <= MAX_DATA_SIZE)
+    }
+
+    pub fn write(&mut self, samples: &[f32]) -> std::io::Result<()> {
+        if !self.has_room(samples.len()) {
+            return Err(Error::new(
+                ErrorKind::FileTooLarge,
+                "WAV files can't be larger than 4 GiB",
+            ));
+        }
+        for sample in samples {
+            self.file.write_all(&sample.to_le_bytes())?;
+        }
+        self.data_size += samples.len() as u64 * 4;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> std::io::Result<()> {
+        // write keeps the size within MAX_DATA_SIZE
+        let data_size = u32::try_from(self.data_size)
+            .map_err(|_| Error::new(ErrorKind::FileTooLarge, "WAV data too large"))?;
+        let frames = data_size / (2 * 4);
+        // Header up to the data chunk is 58 bytes, the RIFF size excludes its first 8
+        self.file.seek(SeekFrom::Start(4))?;
+        self.file.write_all(&(50 + data_size).to_le_bytes())?;
+        self.file.seek(SeekFrom::Start(46))?;
+        self.file.write_all(&frames.to_le_bytes())?;
+        self.file.seek(SeekFrom::Start(54))?;
+        self.file.write_all(&data_size.to_le_bytes())?;
+        self.file.flush()
+    }
+}
+
+/// Samples of a WAV file, one vector per channel.
+pub struct Wav {
+    pub channels: Vec<Vec<f32>>,
+    pub sample_rate: u32,
+}
+
+/// Reads a PCM (16, 24 or 32-bit) or float (32 or 64-bit) WAV file.
+pub fn read(path: &Path) -> std::io::Result<Wav> {
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err(Error::new(ErrorKind::InvalidData, "not a WAV file"));
+    }
+
+    let mut format = None;
+    let mut data = None;
+    let mut position = 12;
+    while position + 8 <= bytes.len() {
+        let id = &bytes[position..position + 4];
+        let size = u32::from_le_bytes(bytes[position + 4..position + 8].try_into().unwrap());
+        let start = position + 8;
+        let end = (start + size as usize).min(bytes.len());
+        match id {
+            b"fmt " if end - start >= 16 => format = Some(&bytes[start..end]),
+            b"data" => data = Some(&bytes[start..end]),
+            _ => (),
+        }
+        // Chunks are padded to an even size
+        position = start + size as usize + (size as usize & 1);
+    }
+    let (Some(format), Some(data)) = (format, data) else {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "missing fmt or data chunk",
+        ));
+    };
+
+    let mut tag = u16::from_le_bytes([format[0], format[1]]);
+    let channel_count = u16::from_le_bytes([format[2], format[3]]) as usize;
+    let sample_rate = u32::from_le_bytes(format[4..8].try_into().unwrap());
+    let block_align = u16::from_le_bytes([format[12], format[13]]);
+    let bits = u16::from_le_bytes([format[14], format[15]]);
+    // WAVE_FORMAT_EXTENSIBLE keeps the actual format at the start of the sub-format GUID
+    if tag == 0xfffe && format.len() >= 26 {
+        tag = u16::from_le_bytes([format[24], format[25]]);
+    }
+    let decode: fn(&[u8]) -> f32 = match (tag, bits) {
+        (1, 16) => |b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0,
+        (1, 24) => |b| i32::from_le_bytes([0, b[0], b[1], b[2]]) as f32 / 2147483648.0,
+        (1, 32) => |b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2147483648.0,
+        (3, 32) => |b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+        (3, 64) => |b| f64::from_le_bytes(b[..8].try_into().unwrap()) as f32,
+        _ => {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                format!("unsupported format {} with {} bits", tag, bits),
+            ));
+        }
+    };
+    if channel_count == 0 {
+        return Err(Error::new(ErrorKind::InvalidData, "no channels"));
+    }
+    if sample_rate == 0 {
+        return Err(Error::new(ErrorKind::InvalidData, "zero sample rate"));
+    }
+    if block_align == 0 {
+        return Err(Error::new(ErrorKind::InvalidData, "zero block alignment"));
+    }
+
+    let sample_size = bits as usize / 8;
+    let mut channels =
+        vec![Vec::with_capacity(data.len() / sample_size / channel_count); channel_count];
+    for frame in data.chunks_exact(sample_size * channel_count) {
+        for (channel, sample) in channels.iter_mut().zip(frame.chunks_exact(sample_size)) {
+            channel.push(decode(sample));
+        }
+    }
+    Ok(Wav {
+        channels,
+        sample_rate,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("rake-test-{}-{}.wav", std::process::id(), name))
+    }
+
+    #[test]
+    fn header_sizes() {
+        let path = temp_path("header");
+        let mut writer = WavWriter::create(&path, 48000).unwrap();
+        writer.write(&[0.5; 6]).unwrap();
+        writer.finish().unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let field =
+            |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        assert_eq!(bytes.len(), 58 + 24);
+        assert_eq!(field(4), bytes.len() as u32 - 8);
+        assert_eq!(&bytes[38..42], b"fact");
+        assert_eq!(field(46), 3);
+        assert_eq!(&bytes[50..54], b"data");
+        assert_eq!(field(54), 24);
+    }
+
+    #[test]
+    fn room_up_to_max_data_size() {
+        let path = temp_path("room");
+        let mut writer = WavWriter::create(&path, 48000).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        writer.data_size = MAX_DATA_SIZE - 8;
+        assert!(writer.has_room(2));
+        assert!(!writer.has_room(3));
+        assert!(!writer.has_room(usize::MAX));
+        assert!(writer.write(&[0.0; 3]).is_err());
+        writer.data_size = MAX_DATA_SIZE;
+        assert!(writer.has_room(0));
+        assert!(!writer.has_room(1));
+    }
+
+    #[test]
+    fn write_and_read() {
+        let path = temp_path("round-trip");
+        let samples = [0.0, -1.0, 0.25, 0.5, -0.125, 1.0];
+        let mut writer = WavWriter::create(&path, 44100).unwrap();
+        writer.write(&samples).unwrap();
+        writer.finish().unwrap();
+        let wav = read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(wav.sample_rate, 44100);
+        assert_eq!(wav.channels, [[0.0, 0.25, -0.125], [-1.0, 0.5, 1.0]]);
+    }
+}
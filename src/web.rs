@@ -0,0 +1,352 @@
+use crate::api::{self, Event, Feedback, Pending, Reply, Request};
+use crate::log;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tungstenite::handshake::derive_accept_key;
+use tungstenite::protocol::Role;
+use tungstenite::{Message, WebSocket};
+
+/// Time a WebSocket waits for a message before sending the events that came in meanwhile.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Longest request line or header read.
+const MAX_LINE: u64 = 8192;
+
+/// Most headers read from a request, more than browsers and HTTP clients send.
+const MAX_HEADERS: usize = 64;
+
+/// Largest request body read, which is plenty for any request.
+const MAX_BODY: usize = 1 << 16;
+
+/// Page served at `/` for editing the rig from a phone or tablet, using the WebSocket.
+const PAGE: &str = include_str!("web.html");
+
+/// Event senders of the connected WebSockets.
+type Clients = Arc<Mutex<Vec<Sender<String>>>>;
+
+/// Web API on a TCP port. Requests are posted as JSON to `/api`, the status is at
+/// `/api/status`, a WebSocket at `/ws` takes the same requests and sends events, and `/` is a
+/// page for editing the chains with them. All but the page need the token from the config.
+pub struct Server {
+    clients: Clients,
+    /// Whether a WebSocket connected since the last feedback.
+    joined: Arc<AtomicBool>,
+    pub feedback: Feedback,
+}
+
+impl Server {
+    pub fn listen(
+        address: SocketAddr,
+        token: String,
+        requests: Sender<Pending>,
+    ) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let token = Arc::new(token);
+        let clients = Clients::default();
+        let joined = Arc::new(AtomicBool::new(false));
+        let server = Server {
+            clients: clients.clone(),
+            joined: joined.clone(),
+            feedback: Feedback::default(),
+        };
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        log::error!("Error accepting web connection: {}", e);
+                        continue;
+                    }
+                };
+                let requests = requests.clone();
+                let clients = clients.clone();
+                let joined = joined.clone();
+                let token = token.clone();
+                std::thread::spawn(move || {
+                    if let Err(e) = serve(stream, &token, &requests, &clients, &joined) {
+                        log::error!("Error serving web client: {}", e);
+                    }
+                });
+            }
+        });
+        Ok(server)
+    }
+
+    pub fn has_clients(&self) -> bool {
+        !self.clients.lock().unwrap().is_empty()
+    }
+
+    /// Starts the feedback over if a WebSocket connected, so it's sent every value.
+    pub fn welcome(&mut self) {
+        if self.joined.swap(false, Ordering::Relaxed) {
+            self.feedback.reset();
+        }
+    }
+
+    /// Sends events to every WebSocket as one JSON array, forgetting those that closed.
+    pub fn broadcast(&self, events: &[Event]) {
+        if events.is_empty() {
+            return;
+        }
+        let json = serde_json::to_string(events).unwrap();
+        self.clients
+            .lock()
+            .unwrap()
+            .retain(|client| client.send(json.clone()).is_ok());
+    }
+}
+
+struct HttpRequest {
+    method: String,
+    path: String,
+    /// Headers with lowercase names.
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    /// Whether the body is longer than `MAX_BODY`, so it wasn't read.
+    oversized: bool,
+}
+
+impl HttpRequest {
+    fn read(reader: &mut BufReader<TcpStream>) -> std::io::Result<Self> {
+        let mut line = String::new();
+        reader.by_ref().take(MAX_LINE).read_line(&mut line)?;
+        let mut words = line.split_whitespace();
+        let (Some(method), Some(path)) = (words.next(), words.next()) else {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                "Incorrect HTTP request",
+            ));
+        };
+        let (method, path) = (method.to_string(), path.to_string());
+        let mut headers = Vec::new();
+        loop {
+            line.clear();
+            if reader.by_ref().take(MAX_LINE).read_line(&mut line)? == 0
+                || line.trim_end().is_empty()
+            {
+                break;
+            }
+            if headers.len() == MAX_HEADERS {
+                return Err(std::io::Error::new(
+                    ErrorKind::InvalidData,
+                    "Too many HTTP headers",
+                ));
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+            }
+        }
+        let mut request = HttpRequest {
+            method,
+            path,
+            headers,
+            body: Vec::new(),
+            oversized: false,
+        };
+        let length = request
+            .header("content-length")
+            .and_then(|length| length.parse().ok())
+            .unwrap_or(0);
+        if length > MAX_BODY {
+            request.oversized = true;
+        } else {
+            request.body = vec![0; length];
+            reader.read_exact(&mut request.body)?;
+        }
+        Ok(request)
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Whether the request gives `token` as `Authorization: Bearer <token>`, or in the query as
+    /// `token=<token>` since browsers can't add headers to WebSockets.
+    fn authorized(&self, token: &str) -> bool {
+        let given = self
+            .header("authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .or_else(|| {
+                let (_, query) = self.path.split_once('?')?;
+                query
+                    .split('&')
+                    .find_map(|pair| pair.strip_prefix("token="))
+            });
+        // Compared in constant time so the token can't be guessed from how long it takes
+        given.is_some_and(|given| {
+            given.len() == token.len()
+                && given
+                    .bytes()
+                    .zip(token.bytes())
+                    .fold(0, |difference, (a, b)| difference | (a ^ b))
+                    == 0
+        })
+    }
+
+    /// Whether the request comes from a page served by Rake itself, or from outside a browser,
+    /// which doesn't send an `Origin`.
+    fn same_origin(&self) -> bool {
+        let Some(origin) = self.header("origin") else {
+            return true;
+        };
+        let host = self.header("host").unwrap_or_default();
+        origin
+            .strip_prefix("http://")
+            .or_else(|| origin.strip_prefix("https://"))
+            .is_some_and(|origin_host| origin_host.eq_ignore_ascii_case(host))
+    }
+}
+
+fn serve(
+    stream: TcpStream,
+    token: &str,
+    requests: &Sender<Pending>,
+    clients: &Clients,
+    joined: &AtomicBool,
+) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let request = HttpRequest::read(&mut reader)?;
+    let mut stream = stream;
+    let path = request.path.split('?').next().unwrap_or_default();
+    match (request.method.as_str(), path) {
+        ("GET", "/ws" | "/api/status") | ("POST", "/api") if !request.authorized(token) => respond(
+            &mut stream,
+            "401 Unauthorized",
+            "text/plain",
+            b"Missing or wrong token",
+        ),
+        _ if request.oversized => respond(
+            &mut stream,
+            "413 Content Too Large",
+            "text/plain",
+            b"Request body too large",
+        ),
+        // Pages elsewhere could otherwise use the browser's WebSocket to reach the rig
+        ("GET", "/ws") if !request.same_origin() => respond(
+            &mut stream,
+            "403 Forbidden",
+            "text/plain",
+            b"WebSocket from another origin",
+        ),
+        ("GET", "/ws") => {
+            let Some(key) = request.header("sec-websocket-key") else {
+                return respond(
+                    &mut stream,
+                    "400 Bad Request",
+                    "text/plain",
+                    b"Not a WebSocket",
+                );
+            };
+            write!(
+                stream,
+                "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+                 Sec-WebSocket-Accept: {}\r\n\r\n",
+                derive_accept_key(key.as_bytes())
+            )?;
+            // Reads time out so events are sent while the client is quiet
+            stream.set_read_timeout(Some(POLL_INTERVAL))?;
+            let socket = WebSocket::from_partially_read(
+                stream,
+                reader.buffer().to_vec(),
+                Role::Server,
+                None,
+            );
+            websocket(socket, requests, clients, joined)
+        }
+        ("GET", "/" | "/index.html") => respond(
+            &mut stream,
+            "200 OK",
+            "text/html; charset=utf-8",
+            PAGE.as_bytes(),
+        ),
+        ("GET", "/api/status") => respond_json(&mut stream, api::ask(requests, Request::Status)),
+        ("POST", "/api") => {
+            let reply = serde_json::from_slice::<Request>(&request.body)
+                .map_err(|e| format!("Incorrect request: {}", e))
+                .and_then(|request| api::ask(requests, request));
+            respond_json(&mut stream, reply)
+        }
+        _ => respond(&mut stream, "404 Not Found", "text/plain", b"Not found"),
+    }
+}
+
+/// Answers the requests of a WebSocket and sends it events until it closes.
+fn websocket(
+    mut socket: WebSocket<TcpStream>,
+    requests: &Sender<Pending>,
+    clients: &Clients,
+    joined: &AtomicBool,
+) -> std::io::Result<()> {
+    let (events, event_receiver) = mpsc::channel();
+    clients.lock().unwrap().push(events);
+    joined.store(true, Ordering::Relaxed);
+    loop {
+        match socket.read() {
+            Ok(Message::Text(text)) => {
+                let reply = serde_json::from_str::<Request>(&text)
+                    .map_err(|e| format!("Incorrect request: {}", e))
+                    .and_then(|request| api::ask(requests, request));
+                socket
+                    .send(Message::text(reply_json(reply)))
+                    .map_err(std::io::Error::other)?;
+            }
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(e))
+                if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed) => {
+                return Ok(());
+            }
+            Err(e) => return Err(std::io::Error::other(e)),
+        }
+        while let Ok(json) = event_receiver.try_recv() {
+            socket
+                .send(Message::text(json))
+                .map_err(std::io::Error::other)?;
+        }
+    }
+}
+
+/// A reply as JSON, with errors as `{"reply": "error", "message": ...}`.
+fn reply_json(reply: Result<Reply, String>) -> String {
+    match reply {
+        Ok(reply) => serde_json::to_string(&reply).unwrap(),
+        Err(e) => serde_json::json!({ "reply": "error", "message": e }).to_string(),
+    }
+}
+
+fn respond_json(stream: &mut TcpStream, reply: Result<Reply, String>) -> std::io::Result<()> {
+    let status = if reply.is_ok() {
+        "200 OK"
+    } else {
+        "400 Bad Request"
+    };
+    respond(
+        stream,
+        status,
+        "application/json",
+        reply_json(reply).as_bytes(),
+    )
+}
+
+fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(body)
+}